@@ -0,0 +1,916 @@
+//! A-normal form, and an optimizer over it.
+//!
+//! `lower` turns a typechecked program into an `Expr` in which the operands
+//! of every application and `if` are atoms: literals or variables. Every
+//! other intermediate result is bound to a name by a `let`. Each binder gets
+//! a fresh `Name::Unique`, so no name is bound twice in a program, and
+//! passes can move code into any scope where the names it uses are bound
+//! without worrying about capture.
+//!
+//! Printed, the IR reads
+//!
+//! ```text
+//! let t$2 = fn(x$1 : int) {
+//!   x$1
+//! };
+//! t$2(1)
+//! ```
+//!
+//! `optimize` runs the passes below until the program stops changing. They
+//! treat free names as `globals` primitives. Those are total but for `div`
+//! and `mod`, which fail on a divisor of zero, so a computation in a
+//! well-typed program has no effect besides its value unless it might
+//! divide by zero: the passes duplicate computations freely, and drop the
+//! ones that cannot fail.
+
+use ast::{Loc, AST};
+use env::TypeEnv;
+use eval;
+use globals;
+use names;
+use names::Name;
+use query;
+use typecheck;
+use typecheck::{NodeTypes, TypeError};
+use types::Type;
+
+use num_bigint::BigInt;
+use num_traits::Zero;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// How many times `optimize` runs its passes before it gives up on reaching
+/// a fixed point.
+const MAX_ROUNDS: usize = 16;
+
+/// `inline_small` inlines functions whose bodies are at most this `size`.
+const INLINE_SIZE: usize = 6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Atom<'a> {
+    Int(BigInt),
+    Bool(bool),
+    Var(Name<'a>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Comp<'a> {
+    Atom(Atom<'a>),
+    Apply(Atom<'a>, Atom<'a>),
+    /// A function of a parameter of the given type.
+    Lambda(Name<'a>, Rc<Type<'a>>, Box<Expr<'a>>),
+    If(Atom<'a>, Box<Expr<'a>>, Box<Expr<'a>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr<'a> {
+    Let(Name<'a>, Comp<'a>, Box<Expr<'a>>),
+    /// The computation whose value is the expression's.
+    Tail(Comp<'a>),
+}
+
+fn wrap<'a>(binds: Vec<(Name<'a>, Comp<'a>)>, body: Expr<'a>) -> Expr<'a> {
+    binds.into_iter().rev().fold(body, |body, (name, comp)| {
+        Expr::Let(name, comp, Box::new(body))
+    })
+}
+
+/// `expr`, binding its value to `name` in `rest`.
+fn splice<'a>(expr: Expr<'a>, name: Name<'a>, rest: Expr<'a>) -> Expr<'a> {
+    match expr {
+        Expr::Let(bound, comp, body) => Expr::Let(bound, comp, Box::new(splice(*body, name, rest))),
+        Expr::Tail(comp) => Expr::Let(name, comp, Box::new(rest)),
+    }
+}
+
+/// The name a unique name was made from.
+fn base<'a>(name: &Name<'a>) -> Name<'a> {
+    match *name {
+        Name::Unique(ref inner, _) => base(inner),
+        _ => name.clone(),
+    }
+}
+
+/// Fresh unique names, numbered after every one in use.
+struct Fresh {
+    next: i32,
+}
+
+impl Fresh {
+    fn after(expr: &Expr) -> Fresh {
+        fn expr_max(expr: &Expr, max: &mut i32) {
+            match *expr {
+                Expr::Let(ref name, ref comp, ref body) => {
+                    name_max(name, max);
+                    comp_max(comp, max);
+                    expr_max(body, max);
+                }
+                Expr::Tail(ref comp) => comp_max(comp, max),
+            }
+        }
+        fn comp_max(comp: &Comp, max: &mut i32) {
+            match *comp {
+                Comp::Lambda(ref param, _, ref body) => {
+                    name_max(param, max);
+                    expr_max(body, max);
+                }
+                Comp::If(_, ref cons, ref alt) => {
+                    expr_max(cons, max);
+                    expr_max(alt, max);
+                }
+                Comp::Atom(_) | Comp::Apply(..) => {}
+            }
+        }
+        fn name_max(name: &Name, max: &mut i32) {
+            if let Name::Unique(_, i) = *name {
+                *max = (*max).max(i);
+            }
+        }
+        let mut max = 0;
+        expr_max(expr, &mut max);
+        Fresh { next: max }
+    }
+
+    fn name<'a>(&mut self, like: &Name<'a>) -> Name<'a> {
+        self.next += 1;
+        Name::Unique(Box::new(base(like)), self.next)
+    }
+}
+
+struct Lower<'a, 't> {
+    types: &'t NodeTypes<'a>,
+    fresh: Fresh,
+    /// Source names in scope and the unique names they were given,
+    /// innermost last.
+    scope: Vec<(Name<'a>, Name<'a>)>,
+}
+
+impl<'a, 't> Lower<'a, 't> {
+    fn comp(
+        &mut self,
+        ast: &AST<'a>,
+        binds: &mut Vec<(Name<'a>, Comp<'a>)>,
+    ) -> Result<Comp<'a>, TypeError<'a>> {
+        let comp = match *ast {
+            AST::Integer(_, ref val) => Comp::Atom(Atom::Int(val.clone())),
+            AST::Boolean(_, val) => Comp::Atom(Atom::Bool(val)),
+            AST::Variable(_, ref name) => {
+                let name = match self.scope.iter().rev().find(|&(from, _)| from == name) {
+                    Some((_, to)) => to.clone(),
+                    None => name.clone(),
+                };
+                Comp::Atom(Atom::Var(name))
+            }
+            AST::Application(_, ref func, ref arg) => {
+                let func = self.atom(func, binds)?;
+                let arg = self.atom(arg, binds)?;
+                Comp::Apply(func, arg)
+            }
+            AST::Abstraction(_, ref param, ref body) => {
+                let name = match *query::binder(param) {
+                    AST::Variable(_, ref name) => name.clone(),
+                    _ => panic!("anf: malformed parameter"),
+                };
+                let ty = self.types.get(ast).expect("anf: untyped abstraction");
+                let ty = match *ty {
+                    Type::Function(ref dom, _) => Rc::clone(dom),
+                    _ => panic!("anf: abstraction of type {}", ty),
+                };
+                let param = self.fresh.name(&name);
+                self.scope.push((name, param.clone()));
+                let body = self.expr(body)?;
+                self.scope.pop();
+                Comp::Lambda(param, ty, Box::new(body))
+            }
+            AST::If(_, ref cond, ref cons, ref alt) => {
+                let cond = self.atom(cond, binds)?;
+                Comp::If(cond, Box::new(self.expr(cons)?), Box::new(self.expr(alt)?))
+            }
+            AST::Ascription(_, ref expr, _) => return self.comp(expr, binds),
+            AST::TyName(ref loc, _)
+            | AST::TyFn(ref loc, _, _)
+            | AST::TyApp(ref loc, _, _)
+            | AST::TyQual(ref loc, _, _) => {
+                return Err(TypeError::Generic(loc.clone(), "a type is not an expression"))
+            }
+        };
+        Ok(comp)
+    }
+
+    fn atom(
+        &mut self,
+        ast: &AST<'a>,
+        binds: &mut Vec<(Name<'a>, Comp<'a>)>,
+    ) -> Result<Atom<'a>, TypeError<'a>> {
+        match self.comp(ast, binds)? {
+            Comp::Atom(atom) => Ok(atom),
+            comp => {
+                let name = self.fresh.name(&names::ident("t"));
+                binds.push((name.clone(), comp));
+                Ok(Atom::Var(name))
+            }
+        }
+    }
+
+    fn expr(&mut self, ast: &AST<'a>) -> Result<Expr<'a>, TypeError<'a>> {
+        let mut binds = Vec::new();
+        let tail = self.comp(ast, &mut binds)?;
+        Ok(wrap(binds, Expr::Tail(tail)))
+    }
+}
+
+/// Typecheck `ast` against `env`, and lower it to A-normal form.
+pub fn lower<'a>(env: &Rc<TypeEnv<'a>>, ast: &AST<'a>) -> Result<Expr<'a>, TypeError<'a>> {
+    let (_, types) = typecheck::typecheck_nodes(env, ast)?;
+    lower_typed(ast, &types)
+}
+
+/// Lower `ast` for `optimize`, which assumes that every free name is a
+/// primitive, checking it with `typecheck::typecheck_primitive`.
+pub fn lower_primitive<'a>(ast: &AST<'a>) -> Result<Expr<'a>, TypeError<'a>> {
+    let (_, types) = typecheck::typecheck_primitive(ast)?;
+    lower_typed(ast, &types)
+}
+
+fn lower_typed<'a>(ast: &AST<'a>, types: &NodeTypes<'a>) -> Result<Expr<'a>, TypeError<'a>> {
+    let mut lower = Lower {
+        types,
+        fresh: Fresh { next: 0 },
+        scope: Vec::new(),
+    };
+    lower.expr(ast)
+}
+
+/// The number of computations in `expr`, including those in the bodies of
+/// its functions and branches.
+pub fn size(expr: &Expr) -> usize {
+    fn comp_size(comp: &Comp) -> usize {
+        match *comp {
+            Comp::Atom(_) | Comp::Apply(..) => 1,
+            Comp::Lambda(_, _, ref body) => 1 + size(body),
+            Comp::If(_, ref cons, ref alt) => 1 + size(cons) + size(alt),
+        }
+    }
+    match *expr {
+        Expr::Let(_, ref comp, ref body) => comp_size(comp) + size(body),
+        Expr::Tail(ref comp) => comp_size(comp),
+    }
+}
+
+/// Rewrite every `Expr` nested in `comp` with `f`.
+fn map_comp<'a, F>(comp: Comp<'a>, f: &mut F) -> Comp<'a>
+where
+    F: FnMut(Expr<'a>) -> Expr<'a>,
+{
+    match comp {
+        Comp::Lambda(param, ty, body) => Comp::Lambda(param, ty, Box::new(f(*body))),
+        Comp::If(cond, cons, alt) => Comp::If(cond, Box::new(f(*cons)), Box::new(f(*alt))),
+        comp => comp,
+    }
+}
+
+/// What `fold_constants` knows about a variable.
+enum Known<'a> {
+    Atom(Atom<'a>),
+    /// A primitive applied to fewer arguments than it takes.
+    Partial(&'static str, Vec<Atom<'a>>),
+}
+
+struct Folder<'a> {
+    prims: Vec<globals::Primitive>,
+    known: HashMap<Name<'a>, Known<'a>>,
+}
+
+impl<'a> Folder<'a> {
+    fn atom(&self, atom: Atom<'a>) -> Atom<'a> {
+        match atom {
+            Atom::Var(name) => match self.known.get(&name) {
+                Some(Known::Atom(atom)) => atom.clone(),
+                _ => Atom::Var(name),
+            },
+            atom => atom,
+        }
+    }
+
+    /// The primitive `func(arg)` applies and all its arguments so far.
+    fn spine(
+        &self,
+        func: &Atom<'a>,
+        arg: &Atom<'a>,
+    ) -> Option<(&globals::Primitive, Vec<Atom<'a>>)> {
+        let name = match *func {
+            Atom::Var(ref name) => name,
+            _ => return None,
+        };
+        let (prim, mut args) = match (self.known.get(name), name) {
+            (Some(Known::Partial(prim, args)), _) => (*prim, args.clone()),
+            (None, Name::Ident(ident)) => (*ident, Vec::new()),
+            _ => return None,
+        };
+        let prim = self.prims.iter().find(|p| p.name == prim)?;
+        args.push(arg.clone());
+        Some((prim, args))
+    }
+
+    fn expr(&mut self, expr: Expr<'a>) -> Expr<'a> {
+        match expr {
+            Expr::Let(name, comp, body) => match self.comp(comp) {
+                Comp::Atom(atom) => {
+                    self.known.insert(name, Known::Atom(atom));
+                    self.expr(*body)
+                }
+                comp => {
+                    if let Comp::Apply(ref func, ref arg) = comp {
+                        if let Some((prim, args)) = self.spine(func, arg) {
+                            if args.len() < prim.arity() {
+                                self.known
+                                    .insert(name.clone(), Known::Partial(prim.name, args));
+                            }
+                        }
+                    }
+                    Expr::Let(name, comp, Box::new(self.expr(*body)))
+                }
+            },
+            Expr::Tail(comp) => Expr::Tail(self.comp(comp)),
+        }
+    }
+
+    fn comp(&mut self, comp: Comp<'a>) -> Comp<'a> {
+        match comp {
+            Comp::Atom(atom) => Comp::Atom(self.atom(atom)),
+            Comp::Apply(func, arg) => {
+                let func = self.atom(func);
+                let arg = self.atom(arg);
+                match self.apply(&func, &arg) {
+                    Some(atom) => Comp::Atom(atom),
+                    None => Comp::Apply(func, arg),
+                }
+            }
+            Comp::If(cond, cons, alt) => {
+                let cond = self.atom(cond);
+                Comp::If(cond, Box::new(self.expr(*cons)), Box::new(self.expr(*alt)))
+            }
+            comp => map_comp(comp, &mut |expr| self.expr(expr)),
+        }
+    }
+
+    /// The value of `func(arg)`, if it completes a primitive's arguments
+    /// and they are all literals.
+    fn apply(&self, func: &Atom<'a>, arg: &Atom<'a>) -> Option<Atom<'a>> {
+        let (prim, args) = self.spine(func, arg)?;
+        if args.len() != prim.arity() {
+            return None;
+        }
+        let mut vals = Vec::new();
+        for arg in args {
+            vals.push(match arg {
+                Atom::Int(val) => eval::Value::Integer(val),
+                Atom::Bool(val) => eval::Value::Boolean(val),
+                Atom::Var(_) => return None,
+            });
+        }
+        match (prim.func)(&vals) {
+            Ok(eval::Value::Integer(val)) => Some(Atom::Int(val)),
+            Ok(eval::Value::Boolean(val)) => Some(Atom::Bool(val)),
+            _ => None,
+        }
+    }
+}
+
+/// Apply primitives to literal arguments (`add(1, 2)` is `3`), and
+/// substitute variables bound to atoms for their values.
+pub fn fold_constants(expr: Expr) -> Expr {
+    let mut folder = Folder {
+        prims: globals::primitives(),
+        known: HashMap::new(),
+    };
+    folder.expr(expr)
+}
+
+/// Replace `if`s on literal conditions with the branch they take.
+pub fn fold_ifs(expr: Expr) -> Expr {
+    match expr {
+        Expr::Let(name, Comp::If(Atom::Bool(cond), cons, alt), body) => {
+            let taken = fold_ifs(if cond { *cons } else { *alt });
+            splice(taken, name, fold_ifs(*body))
+        }
+        Expr::Let(name, comp, body) => Expr::Let(
+            name,
+            map_comp(comp, &mut fold_ifs),
+            Box::new(fold_ifs(*body)),
+        ),
+        Expr::Tail(Comp::If(Atom::Bool(cond), cons, alt)) => {
+            fold_ifs(if cond { *cons } else { *alt })
+        }
+        Expr::Tail(comp) => Expr::Tail(map_comp(comp, &mut fold_ifs)),
+    }
+}
+
+/// How often each variable is used in `expr`, and how often as the function
+/// of an application.
+fn uses<'a>(
+    expr: &Expr<'a>,
+    all: &mut HashMap<Name<'a>, usize>,
+    calls: &mut HashMap<Name<'a>, usize>,
+) {
+    let atom = |atom: &Atom<'a>, all: &mut HashMap<Name<'a>, usize>| {
+        if let Atom::Var(ref name) = *atom {
+            *all.entry(name.clone()).or_insert(0) += 1;
+        }
+    };
+    let comp = match *expr {
+        Expr::Let(_, ref comp, ref body) => {
+            uses(body, all, calls);
+            comp
+        }
+        Expr::Tail(ref comp) => comp,
+    };
+    match *comp {
+        Comp::Atom(ref a) => atom(a, all),
+        Comp::Apply(ref func, ref arg) => {
+            atom(func, all);
+            atom(arg, all);
+            if let Atom::Var(ref name) = *func {
+                *calls.entry(name.clone()).or_insert(0) += 1;
+            }
+        }
+        Comp::Lambda(_, _, ref body) => uses(body, all, calls),
+        Comp::If(ref cond, ref cons, ref alt) => {
+            atom(cond, all);
+            uses(cons, all, calls);
+            uses(alt, all, calls);
+        }
+    }
+}
+
+/// Inlines calls of some let-bound functions.
+struct Inliner<'a, P> {
+    /// Whether to inline a function with the given name and body.
+    pick: P,
+    /// Functions being inlined, by name.
+    inline: HashMap<Name<'a>, (Name<'a>, Expr<'a>)>,
+    /// Whether to remove a function's binding once it is picked, rather than
+    /// copying its body to each call.
+    moving: bool,
+    fresh: Fresh,
+}
+
+impl<'a, P> Inliner<'a, P>
+where
+    P: FnMut(&Name<'a>, &Expr<'a>) -> bool,
+{
+    /// `func(arg)`, with the function's body in place of the call if it is
+    /// being inlined.
+    fn call(&mut self, func: Atom<'a>, arg: Atom<'a>) -> Result<Expr<'a>, Comp<'a>> {
+        let name = match func {
+            Atom::Var(ref name) if self.inline.contains_key(name) => name.clone(),
+            func => return Err(Comp::Apply(func, arg)),
+        };
+        let (param, body) = if self.moving {
+            self.inline.remove(&name).unwrap()
+        } else {
+            let (ref param, ref body) = self.inline[&name];
+            let mut renames = HashMap::new();
+            let fresh = self.fresh.name(param);
+            renames.insert(param.clone(), fresh.clone());
+            (fresh, rename(body, &mut renames, &mut self.fresh))
+        };
+        let body = self.expr(body);
+        Ok(Expr::Let(param, Comp::Atom(arg), Box::new(body)))
+    }
+
+    fn expr(&mut self, expr: Expr<'a>) -> Expr<'a> {
+        match expr {
+            Expr::Let(name, comp, body) => match self.comp(comp) {
+                Comp::Lambda(param, ty, lambda_body) => {
+                    if (self.pick)(&name, &lambda_body) {
+                        self.inline
+                            .insert(name.clone(), (param.clone(), (*lambda_body).clone()));
+                        if self.moving {
+                            return self.expr(*body);
+                        }
+                    }
+                    Expr::Let(
+                        name,
+                        Comp::Lambda(param, ty, lambda_body),
+                        Box::new(self.expr(*body)),
+                    )
+                }
+                Comp::Apply(func, arg) => match self.call(func, arg) {
+                    Ok(inlined) => {
+                        let rest = self.expr(*body);
+                        splice(inlined, name, rest)
+                    }
+                    Err(comp) => Expr::Let(name, comp, Box::new(self.expr(*body))),
+                },
+                comp => Expr::Let(name, comp, Box::new(self.expr(*body))),
+            },
+            Expr::Tail(Comp::Apply(func, arg)) => match self.call(func, arg) {
+                Ok(inlined) => inlined,
+                Err(comp) => Expr::Tail(comp),
+            },
+            Expr::Tail(comp) => Expr::Tail(self.comp(comp)),
+        }
+    }
+
+    fn comp(&mut self, comp: Comp<'a>) -> Comp<'a> {
+        map_comp(comp, &mut |expr| self.expr(expr))
+    }
+}
+
+/// A copy of `expr` with fresh names for everything it binds, and its free
+/// variables renamed by `renames`.
+fn rename<'a>(
+    expr: &Expr<'a>,
+    renames: &mut HashMap<Name<'a>, Name<'a>>,
+    fresh: &mut Fresh,
+) -> Expr<'a> {
+    fn atom<'a>(atom: &Atom<'a>, renames: &HashMap<Name<'a>, Name<'a>>) -> Atom<'a> {
+        match *atom {
+            Atom::Var(ref name) => Atom::Var(renames.get(name).unwrap_or(name).clone()),
+            ref atom => atom.clone(),
+        }
+    }
+    fn comp<'a>(
+        comp: &Comp<'a>,
+        renames: &mut HashMap<Name<'a>, Name<'a>>,
+        fresh: &mut Fresh,
+    ) -> Comp<'a> {
+        match *comp {
+            Comp::Atom(ref a) => Comp::Atom(atom(a, renames)),
+            Comp::Apply(ref func, ref arg) => Comp::Apply(atom(func, renames), atom(arg, renames)),
+            Comp::Lambda(ref param, ref ty, ref body) => {
+                let new = fresh.name(param);
+                renames.insert(param.clone(), new.clone());
+                Comp::Lambda(new, Rc::clone(ty), Box::new(rename(body, renames, fresh)))
+            }
+            Comp::If(ref cond, ref cons, ref alt) => Comp::If(
+                atom(cond, renames),
+                Box::new(rename(cons, renames, fresh)),
+                Box::new(rename(alt, renames, fresh)),
+            ),
+        }
+    }
+    match *expr {
+        Expr::Let(ref name, ref c, ref body) => {
+            let c = comp(c, renames, fresh);
+            let new = fresh.name(name);
+            renames.insert(name.clone(), new.clone());
+            Expr::Let(new, c, Box::new(rename(body, renames, fresh)))
+        }
+        Expr::Tail(ref c) => Expr::Tail(comp(c, renames, fresh)),
+    }
+}
+
+/// Beta-reduce calls of functions that are used once, as the function of a
+/// call, such as immediately applied `fn`s.
+pub fn beta(expr: Expr) -> Expr {
+    let (mut all, mut calls) = (HashMap::new(), HashMap::new());
+    uses(&expr, &mut all, &mut calls);
+    let mut inliner = Inliner {
+        pick: |name: &Name, _: &Expr| all.get(name) == Some(&1) && calls.get(name) == Some(&1),
+        inline: HashMap::new(),
+        moving: true,
+        fresh: Fresh::after(&expr),
+    };
+    inliner.expr(expr)
+}
+
+/// Inline every call of functions whose bodies are at most `INLINE_SIZE`.
+pub fn inline_small(expr: Expr) -> Expr {
+    let mut inliner = Inliner {
+        pick: |_: &Name, body: &Expr| size(body) <= INLINE_SIZE,
+        inline: HashMap::new(),
+        moving: false,
+        fresh: Fresh::after(&expr),
+    };
+    inliner.expr(expr)
+}
+
+/// Whether `name` is used in `expr`.
+fn occurs(name: &Name, expr: &Expr) -> bool {
+    let (mut all, mut calls) = (HashMap::new(), HashMap::new());
+    uses(expr, &mut all, &mut calls);
+    all.contains_key(name)
+}
+
+/// Which computations of a program might fail, by dividing by zero.
+struct Failures<'a> {
+    prims: Vec<globals::Primitive>,
+    /// Each variable bound to a partial application of a primitive, with
+    /// the primitive and how many arguments it has been given.
+    partial: HashMap<Name<'a>, (&'static str, usize)>,
+}
+
+impl<'a> Failures<'a> {
+    /// The primitive `func` is, or partially applies, and how many
+    /// arguments it has been given.
+    fn callee(&self, func: &Atom<'a>) -> Option<(&globals::Primitive, usize)> {
+        let (prim, args) = match *func {
+            Atom::Var(ref name) => match (self.partial.get(name), name) {
+                (Some(&(prim, args)), _) => (prim, args),
+                (None, &Name::Ident(prim)) => (prim, 0),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let prim = self.prims.iter().find(|p| p.name == prim)?;
+        Some((prim, args))
+    }
+
+    /// Record the partial applications of primitives `expr` binds.
+    fn find(&mut self, expr: &Expr<'a>) {
+        let comp = match *expr {
+            Expr::Let(ref name, ref comp, _) => {
+                if let Comp::Apply(ref func, _) = *comp {
+                    if let Some((prim, args)) = self.callee(func) {
+                        if args + 1 < prim.arity() {
+                            let partial = (prim.name, args + 1);
+                            self.partial.insert(name.clone(), partial);
+                        }
+                    }
+                }
+                comp
+            }
+            Expr::Tail(ref comp) => comp,
+        };
+        match *comp {
+            Comp::Lambda(_, _, ref body) => self.find(body),
+            Comp::If(_, ref cons, ref alt) => {
+                self.find(cons);
+                self.find(alt);
+            }
+            Comp::Atom(_) | Comp::Apply(..) => {}
+        }
+        if let Expr::Let(_, _, ref body) = *expr {
+            self.find(body);
+        }
+    }
+
+    /// Whether computing `comp` might fail: whether it completes a call of
+    /// `div` or `mod` without a nonzero literal divisor, or calls a function
+    /// that is not a primitive.
+    fn comp(&self, comp: &Comp<'a>) -> bool {
+        match *comp {
+            Comp::Atom(_) | Comp::Lambda(..) => false,
+            Comp::If(_, ref cons, ref alt) => self.expr(cons) || self.expr(alt),
+            Comp::Apply(ref func, ref arg) => match self.callee(func) {
+                Some((prim, args)) if args + 1 == prim.arity() && ["div", "mod"].contains(&prim.name) => {
+                    match *arg {
+                        Atom::Int(ref divisor) => divisor.is_zero(),
+                        _ => true,
+                    }
+                }
+                Some(_) => false,
+                None => true,
+            },
+        }
+    }
+
+    fn expr(&self, expr: &Expr<'a>) -> bool {
+        match *expr {
+            Expr::Let(_, ref comp, ref body) => self.comp(comp) || self.expr(body),
+            Expr::Tail(ref comp) => self.comp(comp),
+        }
+    }
+
+    fn eliminate(&self, expr: Expr<'a>) -> Expr<'a> {
+        match expr {
+            Expr::Let(name, comp, body) => {
+                let body = self.eliminate(*body);
+                if occurs(&name, &body) || self.comp(&comp) {
+                    Expr::Let(name, map_comp(comp, &mut |expr| self.eliminate(expr)), Box::new(body))
+                } else {
+                    body
+                }
+            }
+            Expr::Tail(comp) => Expr::Tail(map_comp(comp, &mut |expr| self.eliminate(expr))),
+        }
+    }
+}
+
+/// Remove bindings of variables that are never used, unless computing them
+/// might fail.
+pub fn eliminate_dead(expr: Expr) -> Expr {
+    let mut failures = Failures {
+        prims: globals::primitives(),
+        partial: HashMap::new(),
+    };
+    failures.find(&expr);
+    failures.eliminate(expr)
+}
+
+/// Run every pass until the program stops changing.
+pub fn optimize(mut expr: Expr) -> Expr {
+    for _ in 0..MAX_ROUNDS {
+        let before = expr.clone();
+        expr = fold_constants(expr);
+        expr = fold_ifs(expr);
+        expr = beta(expr);
+        expr = inline_small(expr);
+        expr = eliminate_dead(expr);
+        if expr == before {
+            break;
+        }
+    }
+    expr
+}
+
+/// Read `expr` back as an `AST` whose nodes are all at `loc`, binding each
+/// `let` with an applied `fn`, so the IR can be run by `eval`.
+pub fn to_ast<'a>(expr: &Expr<'a>, loc: &Loc<'a>) -> AST<'a> {
+    let var = |name: &Name<'a>| AST::Variable(loc.clone(), name.clone());
+    let atom = |atom: &Atom<'a>| match *atom {
+        Atom::Int(ref val) => AST::Integer(loc.clone(), val.clone()),
+        Atom::Bool(val) => AST::Boolean(loc.clone(), val),
+        Atom::Var(ref name) => var(name),
+    };
+    let comp = |comp: &Comp<'a>| match *comp {
+        Comp::Atom(ref a) => atom(a),
+        Comp::Apply(ref func, ref arg) => {
+            AST::Application(loc.clone(), Box::new(atom(func)), Box::new(atom(arg)))
+        }
+        Comp::Lambda(ref param, _, ref body) => AST::Abstraction(
+            loc.clone(),
+            Box::new(var(param)),
+            Box::new(to_ast(body, loc)),
+        ),
+        Comp::If(ref cond, ref cons, ref alt) => AST::If(
+            loc.clone(),
+            Box::new(atom(cond)),
+            Box::new(to_ast(cons, loc)),
+            Box::new(to_ast(alt, loc)),
+        ),
+    };
+    match *expr {
+        Expr::Let(ref name, ref bound, ref body) => {
+            let func = AST::Abstraction(
+                loc.clone(),
+                Box::new(var(name)),
+                Box::new(to_ast(body, loc)),
+            );
+            AST::Application(loc.clone(), Box::new(func), Box::new(comp(bound)))
+        }
+        Expr::Tail(ref c) => comp(c),
+    }
+}
+
+impl<'a> fmt::Display for Atom<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Atom::Int(ref val) => val.fmt(f),
+            Atom::Bool(val) => val.fmt(f),
+            Atom::Var(ref name) => name.fmt(f),
+        }
+    }
+}
+
+fn write_indent(f: &mut fmt::Formatter, indent: usize) -> Result<(), fmt::Error> {
+    for _ in 0..indent {
+        f.write_str("  ")?;
+    }
+    Ok(())
+}
+
+fn write_comp(f: &mut fmt::Formatter, comp: &Comp, indent: usize) -> Result<(), fmt::Error> {
+    match *comp {
+        Comp::Atom(ref atom) => write!(f, "{}", atom),
+        Comp::Apply(ref func, ref arg) => write!(f, "{}({})", func, arg),
+        Comp::Lambda(ref param, ref ty, ref body) => {
+            writeln!(f, "fn({} : {}) {{", param, ty)?;
+            write_expr(f, body, indent + 1)?;
+            write_indent(f, indent)?;
+            f.write_str("}")
+        }
+        Comp::If(ref cond, ref cons, ref alt) => {
+            writeln!(f, "if {} {{", cond)?;
+            write_expr(f, cons, indent + 1)?;
+            write_indent(f, indent)?;
+            writeln!(f, "}} else {{")?;
+            write_expr(f, alt, indent + 1)?;
+            write_indent(f, indent)?;
+            f.write_str("}")
+        }
+    }
+}
+
+fn write_expr(f: &mut fmt::Formatter, mut expr: &Expr, indent: usize) -> Result<(), fmt::Error> {
+    while let Expr::Let(ref name, ref comp, ref body) = *expr {
+        write_indent(f, indent)?;
+        write!(f, "let {} = ", name)?;
+        write_comp(f, comp, indent)?;
+        writeln!(f, ";")?;
+        expr = body;
+    }
+    if let Expr::Tail(ref comp) = *expr {
+        write_indent(f, indent)?;
+        write_comp(f, comp, indent)?;
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+impl<'a> fmt::Display for Expr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write_expr(f, self, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser;
+
+    fn lowered(src: &str) -> Expr<'_> {
+        let ast = parser::parse("test", src).unwrap();
+        lower(&globals::global_env(), &ast).unwrap()
+    }
+
+    fn optimized(src: &str) -> String {
+        optimize(lowered(src)).to_string()
+    }
+
+    #[test]
+    fn test_lower() {
+        assert_eq!(
+            lowered("(fn(x) { add(x, 1) })(2)").to_string(),
+            "let t$3 = fn(x$1 : int) {\n  let t$2 = add(x$1);\n  t$2(1)\n};\nt$3(2)\n"
+        );
+        assert_eq!(
+            lowered("fn(c : bool) { if not(c) { 1 } else { 2 } }").to_string(),
+            "fn(c$1 : bool) {\n  let t$2 = not(c$1);\n  if t$2 {\n    1\n  } else {\n    2\n  }\n}\n"
+        );
+        // Parameters that shadow globals get names of their own.
+        assert_eq!(
+            lowered("fn(add : int) { add }").to_string(),
+            "fn(add$1 : int) {\n  add$1\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_lower_errors() {
+        let list = parser::parse("test", "head([1])").unwrap();
+        assert!(lower(&globals::global_env(), &list).is_ok());
+        assert_eq!(lower_primitive(&list).unwrap_err().kind(), "Unsupported");
+        let one = parser::parse("test", "1").unwrap();
+        let (_, types) = typecheck::typecheck_nodes(&globals::global_env(), &one).unwrap();
+        let ty = parser::parse_type("test", "int").unwrap();
+        assert_eq!(lower_typed(&ty, &types).unwrap_err().kind(), "Generic");
+    }
+
+    #[test]
+    fn test_passes() {
+        assert_eq!(
+            fold_constants(lowered("add(1, 2)")).to_string(),
+            "let t$1 = add(1);\n3\n"
+        );
+        assert_eq!(
+            fold_ifs(lowered("if true { 1 } else { 2 }")).to_string(),
+            "1\n"
+        );
+        assert_eq!(
+            beta(lowered("(fn(x : int) { x })(1)")).to_string(),
+            "let x$1 = 1;\nx$1\n"
+        );
+        // `beta` binds `y` to `add(x, 1)`, and nothing uses either.
+        assert_eq!(
+            eliminate_dead(beta(lowered(
+                "fn(x : int) { (fn(y : int) { x })(add(x, 1)) }"
+            )))
+            .to_string(),
+            "fn(x$1 : int) {\n  x$1\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_optimize() {
+        assert_eq!(optimized("add(1, 2)"), "3\n");
+        assert_eq!(optimized("(fn(x){x})(1)"), "1\n");
+        assert_eq!(optimized("if lt(1, 2) { 10 } else { 20 }"), "10\n");
+        assert_eq!(
+            optimized("fn(x : int) { (fn(y : int) { x })(add(x, 1)) }"),
+            "fn(x$1 : int) {\n  x$1\n}\n"
+        );
+        // Unused divisions are kept unless they cannot divide by zero.
+        assert_eq!(
+            optimized("fn(x : int) { (fn(y : int) { x })(div(x, 2)) }"),
+            "fn(x$1 : int) {\n  x$1\n}\n"
+        );
+        assert_eq!(
+            optimized("fn(x : int) { (fn(y : int) { 1 })(mod(1, x)) }"),
+            "fn(x$1 : int) {\n  let t$4 = mod(1);\n  let t$5 = t$4(x$1);\n  1\n}\n"
+        );
+        assert_eq!(optimized("(fn(y : int) { 1 })(div(1, 0))"), "let t$3 = div(1);\nlet t$4 = t$3(0);\n1\n");
+        // `double` is small, so both calls are inlined and its binding dies.
+        assert_eq!(
+            optimized(
+                "(fn(double : int -> int) { fn(x : int) { add(double(x), double(2)) } })\
+                 (fn(y : int) { mul(y, 2) })"
+            ),
+            "fn(x$2 : int) {\n  let t$11 = mul(x$2);\n  let t$3 = t$11(2);\n  \
+             let t$4 = add(t$3);\n  t$4(4)\n}\n"
+        );
+    }
+}
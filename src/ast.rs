@@ -1,5 +1,8 @@
 use names::Name;
 
+use num_bigint::BigInt;
+use stacker;
+
 #[derive(Clone, Debug)]
 pub struct Loc<'a> {
     pub file: &'a str,
@@ -7,10 +10,43 @@ pub struct Loc<'a> {
     pub end: u32,
 }
 
+/// A human-readable source position. Lines and columns are 1-based, and
+/// columns count characters rather than bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// Translate a byte offset into `src` into a line and column.
+pub fn position(src: &str, offset: u32) -> Position {
+    let offset = (offset as usize).min(src.len());
+    let mut pos = Position { line: 1, column: 1 };
+    for ch in src[..offset].chars() {
+        if ch == '\n' {
+            pos.line += 1;
+            pos.column = 1;
+        } else {
+            pos.column += 1;
+        }
+    }
+    pos
+}
+
+impl<'a> Loc<'a> {
+    pub fn start(&self, src: &str) -> Position {
+        position(src, self.begin)
+    }
+
+    pub fn end(&self, src: &str) -> Position {
+        position(src, self.end)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum AST<'a> {
     Variable(Loc<'a>, Name<'a>),
-    Integer(Loc<'a>, i64),
+    Integer(Loc<'a>, BigInt),
     Boolean(Loc<'a>, bool),
 
     Application(Loc<'a>, Box<AST<'a>>, Box<AST<'a>>),
@@ -21,6 +57,10 @@ pub enum AST<'a> {
 
     TyName(Loc<'a>, Name<'a>),
     TyFn(Loc<'a>, Box<AST<'a>>, Box<AST<'a>>),
+    /// A type constructor applied to arguments: `list<int>`.
+    TyApp(Loc<'a>, Name<'a>, Vec<Box<AST<'a>>>),
+    /// A type with class constraints on its variables: `Eq a => a -> bool`.
+    TyQual(Loc<'a>, Vec<(Name<'a>, Box<AST<'a>>)>, Box<AST<'a>>),
 }
 
 impl<'a> AST<'a> {
@@ -35,6 +75,130 @@ impl<'a> AST<'a> {
             AST::If(ref loc, _, _, _) => loc,
             AST::TyName(ref loc, _) => loc,
             AST::TyFn(ref loc, _, _) => loc,
+            AST::TyApp(ref loc, _, _) => loc,
+            AST::TyQual(ref loc, _, _) => loc,
         }.clone()
     }
+
+    /// A short, stable name for the node's constructor.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            AST::Variable(..) => "var",
+            AST::Integer(..) => "int",
+            AST::Boolean(..) => "bool",
+            AST::Application(..) => "apply",
+            AST::Abstraction(..) => "lambda",
+            AST::Ascription(..) => "ascribe",
+            AST::If(..) => "if",
+            AST::TyName(..) => "tyname",
+            AST::TyFn(..) => "tyfn",
+            AST::TyApp(..) => "tyapp",
+            AST::TyQual(..) => "tyqual",
+        }
+    }
+
+    pub fn children(&self) -> Vec<&AST<'a>> {
+        match *self {
+            AST::Application(_, ref l, ref r)
+            | AST::Abstraction(_, ref l, ref r)
+            | AST::Ascription(_, ref l, ref r)
+            | AST::TyFn(_, ref l, ref r) => vec![l, r],
+            AST::If(_, ref cond, ref cons, ref alt) => vec![cond, cons, alt],
+            AST::TyApp(_, _, ref args) => args.iter().map(|arg| &**arg).collect(),
+            AST::TyQual(_, ref constraints, ref ty) => constraints
+                .iter()
+                .map(|(_, var)| &**var)
+                .chain(Some(&**ty))
+                .collect(),
+            AST::Variable(..) | AST::Integer(..) | AST::Boolean(..) | AST::TyName(..) => vec![],
+        }
+    }
+}
+
+/// Find a node of `ast` deeper than `max`. The walk uses an explicit stack,
+/// so that it is safe on trees too deep to check recursively.
+pub fn too_deep<'a, 'b>(ast: &'b AST<'a>, max: usize) -> Option<&'b AST<'a>> {
+    let mut stack = vec![(ast, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        if depth > max {
+            return Some(node);
+        }
+        for child in node.children() {
+            stack.push((child, depth + 1));
+        }
+    }
+    None
+}
+
+/// How much stack `grow` leaves for each level of a recursive walk.
+const RED_ZONE: usize = 128 * 1024;
+
+/// How much more stack `grow` allocates when less than `RED_ZONE` is left.
+const STACK_GROWTH: usize = 4 * 1024 * 1024;
+
+/// Run `f`, one level of a recursive walk, on a new stack segment if the
+/// current one is nearly used up. A walk that recurses through `grow` is
+/// bounded by the depth it allows rather than by the thread's stack.
+pub fn grow<R, F: FnOnce() -> R>(f: F) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_GROWTH, f)
+}
+
+/// Free `ast` without recursing, so that trees too deep to drop normally can
+/// still be thrown away.
+pub fn dispose(ast: Box<AST>) {
+    let mut stack = vec![ast];
+    while let Some(node) = stack.pop() {
+        match *node {
+            AST::Application(_, l, r)
+            | AST::Abstraction(_, l, r)
+            | AST::Ascription(_, l, r)
+            | AST::TyFn(_, l, r) => stack.extend(vec![l, r]),
+            AST::If(_, cond, cons, alt) => stack.extend(vec![cond, cons, alt]),
+            AST::TyApp(_, _, args) => stack.extend(args),
+            AST::TyQual(_, constraints, ty) => {
+                stack.extend(constraints.into_iter().map(|(_, var)| var));
+                stack.push(ty);
+            }
+            AST::Variable(..) | AST::Integer(..) | AST::Boolean(..) | AST::TyName(..) => {}
+        }
+    }
+}
+
+/// A declaration at the top level of a file.
+#[derive(Clone, Debug)]
+pub enum Item<'a> {
+    /// `import "path" as alias`
+    Import(Loc<'a>, &'a str, Name<'a>),
+    /// `use module::{name, ...}`, with the location of each name.
+    Use(Loc<'a>, Name<'a>, Vec<(Loc<'a>, Name<'a>)>),
+    /// `let name = value;`. A type on the name is an `Ascription` of the
+    /// value.
+    Let(Loc<'a>, Name<'a>, Box<AST<'a>>),
+    /// `class Name var { method : type; ... }`
+    Class(Loc<'a>, Name<'a>, Name<'a>, Vec<Method<'a>>),
+    /// `instance Class type { method = value; ... }`
+    Instance(Loc<'a>, Name<'a>, Box<AST<'a>>, Vec<Method<'a>>),
+}
+
+/// A method of a class, with its type, or of an instance, with its value.
+pub type Method<'a> = (Loc<'a>, Name<'a>, Box<AST<'a>>);
+
+impl<'a> Item<'a> {
+    pub fn loc(&self) -> Loc<'a> {
+        match *self {
+            Item::Import(ref loc, ..)
+            | Item::Use(ref loc, ..)
+            | Item::Let(ref loc, ..)
+            | Item::Class(ref loc, ..)
+            | Item::Instance(ref loc, ..) => loc.clone(),
+        }
+    }
+}
+
+/// A whole file: its declarations, and the expression it evaluates to, if
+/// it is a program rather than a library.
+#[derive(Clone, Debug)]
+pub struct Module<'a> {
+    pub items: Vec<Item<'a>>,
+    pub body: Option<Box<AST<'a>>>,
 }
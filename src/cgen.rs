@@ -0,0 +1,323 @@
+//! Compiling Gollum to C.
+//!
+//! Programs are lambda-lifted by `lift`, and each lifted function becomes a
+//! C function over the closure it belongs to. The output is a single C file
+//! with no dependencies beyond the C standard library: the runtime in
+//! `runtime.c`, which implements values and the `globals` primitives, is
+//! copied into it. Running the compiled program prints the program's value
+//! the way the interpreter does.
+//!
+//! Closures are a code pointer and an array of captured values, in the order
+//! of the lifted function's `captures`. Each primitive becomes a chain of
+//! closures, one per argument, and a call with all of a primitive's
+//! arguments calls its `rt_` implementation directly.
+//!
+//! Calls in tail position return to `rt_apply`'s loop rather than nesting,
+//! so loops written with tail recursion run in constant C stack.
+
+use ast::AST;
+use globals;
+use lift;
+use lift::{Expr, Program};
+use names::Name;
+use typecheck::TypeError;
+
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
+
+use std::fmt::Write;
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// The arity of the primitive `name` refers to, if it is one.
+fn primitive_arity(prims: &[globals::Primitive], name: &Name) -> Option<usize> {
+    match *name {
+        Name::Ident(ident) => prims.iter().find(|p| p.name == ident).map(|p| p.arity()),
+        _ => None,
+    }
+}
+
+fn int_literal(val: &BigInt) -> String {
+    match val.to_i32() {
+        Some(small) => format!("rt_int({})", small),
+        None => {
+            let (sign, limbs) = val.to_u32_digits();
+            let limbs: Vec<String> = limbs.iter().map(|limb| format!("{:#x}u", limb)).collect();
+            format!(
+                "rt_int_limbs({}, {}, (const uint32_t[]){{{}}})",
+                (sign == Sign::Minus) as i32,
+                limbs.len(),
+                limbs.join(", ")
+            )
+        }
+    }
+}
+
+/// The head of an application spine and its arguments, in order.
+fn spine<'e, 'a>(mut expr: &'e Expr<'a>) -> (&'e Expr<'a>, Vec<&'e Expr<'a>>) {
+    let mut args = Vec::new();
+    while let Expr::Apply(ref func, ref arg) = *expr {
+        args.push(&**arg);
+        expr = func;
+    }
+    args.reverse();
+    (expr, args)
+}
+
+struct Gen<'p, 'a: 'p> {
+    program: &'p Program<'a>,
+    prims: Vec<globals::Primitive>,
+    out: String,
+    indent: usize,
+    temps: usize,
+}
+
+impl<'p, 'a> Gen<'p, 'a> {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("t{}", self.temps)
+    }
+
+    /// Bind the value of `c` to a fresh temporary.
+    fn bind(&mut self, c: &str) -> String {
+        let t = self.temp();
+        self.line(&format!("V {} = {};", t, c));
+        t
+    }
+
+    /// Emit statements that compute `expr`, and return a C expression for
+    /// its value that is safe to evaluate more than once.
+    fn expr(&mut self, expr: &Expr<'a>) -> String {
+        match *expr {
+            Expr::Int(ref val) => {
+                let lit = int_literal(val);
+                self.bind(&lit)
+            }
+            Expr::Bool(val) => if val { "RT_TRUE" } else { "RT_FALSE" }.to_string(),
+            Expr::Param(_) => "arg".to_string(),
+            Expr::Env(i, ref name) => format!("self->env[{}] /* {} */", i, name),
+            Expr::Global(ref name) => format!("(V)&g_{}", name),
+            Expr::Closure(index, ref values) => {
+                let values: Vec<String> = values.iter().map(|val| self.expr(val)).collect();
+                let env = if values.is_empty() {
+                    "NULL".to_string()
+                } else {
+                    format!("(V[]){{{}}}", values.join(", "))
+                };
+                let name = &self.program.functions[index].name;
+                let make = format!("rt_make({}, NULL, {}, {})", name, values.len(), env);
+                self.bind(&make)
+            }
+            Expr::Apply(ref func, ref arg) => {
+                if let Some(call) = self.saturated(expr) {
+                    return self.bind(&call);
+                }
+                let func = self.expr(func);
+                let arg = self.expr(arg);
+                self.bind(&format!("rt_apply({}, {})", func, arg))
+            }
+            Expr::If(ref cond, ref cons, ref alt) => {
+                let cond = self.expr(cond);
+                let t = self.temp();
+                self.line(&format!("V {};", t));
+                self.line(&format!("if (rt_truthy({})) {{", cond));
+                self.indent += 1;
+                let val = self.expr(cons);
+                self.line(&format!("{} = {};", t, val));
+                self.indent -= 1;
+                self.line("} else {");
+                self.indent += 1;
+                let val = self.expr(alt);
+                self.line(&format!("{} = {};", t, val));
+                self.indent -= 1;
+                self.line("}");
+                t
+            }
+        }
+    }
+
+    /// If `expr` applies a primitive to all its arguments, emit the
+    /// arguments and return a direct call to the primitive.
+    fn saturated(&mut self, expr: &Expr<'a>) -> Option<String> {
+        let name = self.saturated_head(expr)?;
+        let (_, args) = spine(expr);
+        let args: Vec<String> = args.into_iter().map(|arg| self.expr(arg)).collect();
+        Some(format!("rt_{}({})", name, args.join(", ")))
+    }
+
+    /// The primitive `expr` applies to all its arguments, if any.
+    fn saturated_head(&self, expr: &Expr<'a>) -> Option<Name<'a>> {
+        let (head, args) = spine(expr);
+        match *head {
+            Expr::Global(ref name) if primitive_arity(&self.prims, name) == Some(args.len()) => {
+                Some(name.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Emit statements that return the value of `expr` from the current
+    /// function, making calls in tail position through `tc`.
+    fn tail(&mut self, expr: &Expr<'a>) {
+        match *expr {
+            Expr::Apply(ref func, ref arg) if self.saturated_head(expr).is_none() => {
+                let func = self.expr(func);
+                let arg = self.expr(arg);
+                self.line(&format!("tc->fn = (struct closure *){};", func));
+                self.line(&format!("tc->arg = {};", arg));
+                self.line("return NULL;");
+            }
+            Expr::If(ref cond, ref cons, ref alt) => {
+                let cond = self.expr(cond);
+                self.line(&format!("if (rt_truthy({})) {{", cond));
+                self.indent += 1;
+                self.tail(cons);
+                self.indent -= 1;
+                self.line("} else {");
+                self.indent += 1;
+                self.tail(alt);
+                self.indent -= 1;
+                self.line("}");
+            }
+            _ => {
+                let val = self.expr(expr);
+                self.line(&format!("return {};", val));
+            }
+        }
+    }
+
+    /// The closures that make up each primitive: `p_NAME_K` takes the K-th
+    /// argument, and `g_NAME` is the primitive before it has any.
+    fn primitives(&mut self) {
+        let prims: Vec<(&'static str, usize)> =
+            self.prims.iter().map(|p| (p.name, p.arity())).collect();
+        for (name, arity) in prims {
+            // Later stages first, so that each is declared before the stage
+            // that builds closures of it.
+            for k in (1..=arity).rev() {
+                self.line(&format!(
+                    "static V p_{}_{}(struct closure *self, V arg, struct tail *tc) {{",
+                    name, k
+                ));
+                self.indent += 1;
+                self.line("(void)tc;");
+                let mut args: Vec<String> =
+                    (0..k - 1).map(|i| format!("self->env[{}]", i)).collect();
+                args.push("arg".to_string());
+                if k == arity {
+                    self.line(&format!("return rt_{}({});", name, args.join(", ")));
+                } else {
+                    self.line(&format!(
+                        "return rt_make(p_{}_{}, \"{}\", {}, (V[]){{{}}});",
+                        name,
+                        k + 1,
+                        name,
+                        k,
+                        args.join(", ")
+                    ));
+                }
+                self.indent -= 1;
+                self.line("}");
+            }
+            self.line(&format!(
+                "static struct closure g_{} = {{T_CLOSURE, \"{}\", p_{}_1}};",
+                name, name, name
+            ));
+            self.line("");
+        }
+    }
+
+    fn program(&mut self) {
+        self.out.push_str(RUNTIME);
+        self.line("");
+        self.primitives();
+
+        let program = self.program;
+        for func in &program.functions {
+            self.line(&format!(
+                "static V {}(struct closure *self, V arg, struct tail *tc);",
+                func.name
+            ));
+        }
+        self.line("");
+        for func in &program.functions {
+            self.line(&format!("/* {} : {} */", func.param, func.ty));
+            self.line(&format!(
+                "static V {}(struct closure *self, V arg, struct tail *tc) {{",
+                func.name
+            ));
+            self.indent += 1;
+            self.line("(void)self;");
+            self.line("(void)arg;");
+            self.tail(&func.body);
+            self.indent -= 1;
+            self.line("}");
+            self.line("");
+        }
+
+        self.line(&format!("/* main : {} */", program.ty));
+        self.line("int main(void) {");
+        self.indent += 1;
+        let val = self.expr(&program.main);
+        self.line(&format!("rt_print({});", val));
+        self.line("putchar('\\n');");
+        self.line("return 0;");
+        self.indent -= 1;
+        self.line("}");
+    }
+}
+
+/// Typecheck `ast`, which may use only the primitives, and compile it to
+/// a C program that prints its value.
+pub fn compile<'a>(ast: &AST<'a>) -> Result<String, TypeError<'a>> {
+    let program = lift::lift_primitive(ast)?;
+    let mut gen = Gen {
+        program: &program,
+        prims: globals::primitives(),
+        out: String::new(),
+        indent: 0,
+        temps: 0,
+    };
+    gen.program();
+    let mut out = String::new();
+    writeln!(out, "/* Generated by iron-golem. */").unwrap();
+    out.push_str(&gen.out);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser;
+
+    fn compiled(src: &str) -> String {
+        compile(&parser::parse("test", src).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let c = compiled("fn(f : int -> int, x : int) { f(x) }");
+        assert!(c.contains("    tc->fn = (struct closure *)self->env[0] /* f */;\n    tc->arg = arg;\n    return NULL;\n"));
+        // A call to a primitive with all its arguments is made directly.
+        let c = compiled("fn(x : int) { add(x, 1) }");
+        assert!(c.contains("rt_add(arg, t"));
+        assert!(!c.contains("return NULL;\n}\n\n/* main"));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(int_literal(&BigInt::from(-5)), "rt_int(-5)");
+        assert_eq!(
+            int_literal(&-BigInt::from(1u64 << 32)),
+            "rt_int_limbs(1, 2, (const uint32_t[]){0x0u, 0x1u})"
+        );
+    }
+}
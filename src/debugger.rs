@@ -0,0 +1,251 @@
+//! An interactive debugger over the small-step evaluator.
+//!
+//! The debugger reads one command per line:
+//!
+//! - `break LINE[:COL]`: stop before evaluating an expression that starts
+//!   there. Without a column, any expression starting on the line matches.
+//! - `delete N`: remove breakpoint `N`.
+//! - `step`: run to the next step, entering calls.
+//! - `next`: run to the next step, running calls to completion.
+//! - `finish`: run until the current call returns.
+//! - `continue`: run until a breakpoint or the end of the program.
+//! - `env`: print the variables in scope.
+//! - `where`: print the calls in progress.
+//! - `quit`
+//!
+//! Every command but `break` and `delete` may be abbreviated to its first
+//! letter. The session ends when the program does.
+
+use ast::{Loc, AST};
+use diagnostics::Diagnostic;
+use eval::{Env, EvalError, Limits};
+use step;
+use step::{Kind, Step, Stepper};
+
+use std::io;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+const HELP: &str =
+    "commands: break LINE[:COL], delete N, step, next, finish, continue, env, where, quit";
+
+struct Breakpoint {
+    line: u32,
+    column: Option<u32>,
+}
+
+/// Why the debugger stopped running the program.
+enum Stop<'a> {
+    Step(Step<'a>),
+    Breakpoint(usize, &'a AST<'a>),
+    Done,
+    Error(EvalError<'a>),
+}
+
+struct Debugger<'s, 'a: 's, W> {
+    src: &'s str,
+    stepper: Stepper<'a>,
+    breakpoints: Vec<Option<Breakpoint>>,
+    /// Where the last breakpoint stopped, until the program next steps, so
+    /// that the expressions nested at the same place do not stop again.
+    stopped_at: Option<Loc<'a>>,
+    output: W,
+}
+
+impl<'s, 'a, W: Write> Debugger<'s, 'a, W> {
+    fn position(&self, loc: &Loc) -> String {
+        let pos = loc.start(self.src);
+        format!("{}:{}:{}", loc.file, pos.line, pos.column)
+    }
+
+    /// The breakpoint, if any, at the start of `ast`.
+    fn breakpoint(&self, ast: &AST<'a>) -> Option<usize> {
+        let pos = ast.loc().start(self.src);
+        self.breakpoints.iter().position(|bp| match *bp {
+            Some(ref bp) => bp.line == pos.line && bp.column.is_none_or(|col| col == pos.column),
+            None => false,
+        })
+    }
+
+    /// Run until `done` accepts a step, or something else stops the
+    /// program. `done` is not consulted for breakpoints.
+    fn run<F>(&mut self, breakpoints: bool, mut done: F) -> Stop<'a>
+    where
+        F: FnMut(&Step<'a>) -> bool,
+    {
+        loop {
+            if let Some(ast) = self.stepper.focus() {
+                let here = ast.loc();
+                let again = self
+                    .stopped_at
+                    .as_ref()
+                    .is_some_and(|loc| loc.begin == here.begin && loc.file == here.file);
+                if breakpoints && !again {
+                    if let Some(n) = self.breakpoint(ast) {
+                        self.stopped_at = Some(here);
+                        return Stop::Breakpoint(n + 1, ast);
+                    }
+                }
+            }
+            match self.stepper.advance() {
+                Ok(Some(step)) => {
+                    self.stopped_at = None;
+                    if done(&step) {
+                        return Stop::Step(step);
+                    }
+                }
+                Ok(None) if self.stepper.value().is_some() => return Stop::Done,
+                Ok(None) => {}
+                Err(e) => return Stop::Error(e),
+            }
+        }
+    }
+
+    /// Report a stop. Returns whether the session should go on.
+    fn report(&mut self, stop: Stop<'a>) -> io::Result<bool> {
+        match stop {
+            Stop::Step(step) => {
+                writeln!(self.output, "{}: {}", self.position(&step.loc), step.kind)?;
+                writeln!(self.output, "{}", step::highlight(self.src, &step.loc))?;
+            }
+            Stop::Breakpoint(n, ast) => {
+                writeln!(
+                    self.output,
+                    "breakpoint {} at {}",
+                    n,
+                    self.position(&ast.loc())
+                )?;
+                writeln!(self.output, "{}", step::highlight(self.src, &ast.loc()))?;
+            }
+            Stop::Done => {
+                writeln!(self.output, "value: {}", self.stepper.value().unwrap())?;
+                return Ok(false);
+            }
+            Stop::Error(e) => {
+                writeln!(self.output, "{}", Diagnostic::eval(self.src, &e))?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Carry out one command. Returns whether the session should go on.
+    fn command(&mut self, line: &str) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let cmd = match words.next() {
+            Some(cmd) => cmd,
+            None => return Ok(true),
+        };
+        let arg = words.next();
+        match cmd {
+            "break" => match arg.and_then(parse_location) {
+                Some(bp) => {
+                    self.breakpoints.push(Some(bp));
+                    writeln!(
+                        self.output,
+                        "breakpoint {} at {}",
+                        self.breakpoints.len(),
+                        arg.unwrap()
+                    )?;
+                }
+                None => writeln!(self.output, "usage: break LINE[:COL]")?,
+            },
+            "delete" => match arg.and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n >= 1 && n <= self.breakpoints.len() => self.breakpoints[n - 1] = None,
+                _ => writeln!(self.output, "usage: delete N")?,
+            },
+            "step" | "s" => {
+                let stop = self.run(false, |_| true);
+                return self.report(stop);
+            }
+            "next" | "n" => {
+                let depth = self.stepper.depth();
+                let stop = match self.run(true, |_| true) {
+                    Stop::Step(Step {
+                        kind: Kind::Apply(..),
+                        ..
+                    }) => self.run(true, |step| match step.kind {
+                        Kind::Return(_) => step.depth == depth,
+                        _ => false,
+                    }),
+                    stop => stop,
+                };
+                return self.report(stop);
+            }
+            "finish" | "f" => {
+                let depth = self.stepper.depth();
+                let stop = self.run(true, |step| match step.kind {
+                    Kind::Return(_) => step.depth < depth,
+                    _ => false,
+                });
+                return self.report(stop);
+            }
+            "continue" | "c" => {
+                let stop = self.run(true, |_| false);
+                return self.report(stop);
+            }
+            "env" | "e" => {
+                let env: &Rc<Env> = self.stepper.env();
+                let locals = env.locals();
+                if locals.is_empty() {
+                    writeln!(self.output, "no local variables")?;
+                }
+                for (name, val) in locals {
+                    writeln!(self.output, "{} = {}", name, val)?;
+                }
+            }
+            "where" | "w" => {
+                let calls = self.stepper.calls();
+                if let Some(ast) = self.stepper.focus() {
+                    writeln!(self.output, "at {}", self.position(&ast.loc()))?;
+                }
+                for (i, loc) in calls.iter().enumerate() {
+                    writeln!(self.output, "#{} called from {}", i, self.position(loc))?;
+                }
+            }
+            "quit" | "q" => return Ok(false),
+            _ => writeln!(self.output, "{}", HELP)?,
+        }
+        Ok(true)
+    }
+}
+
+fn parse_location(arg: &str) -> Option<Breakpoint> {
+    let mut parts = arg.splitn(2, ':');
+    let line = parts.next()?.parse().ok()?;
+    let column = match parts.next() {
+        Some(col) => Some(col.parse().ok()?),
+        None => None,
+    };
+    Some(Breakpoint { line, column })
+}
+
+/// Debug `ast` under `limits`, reading commands from `input` until the
+/// program finishes, the user quits, or the input ends.
+pub fn run<'a, R: BufRead, W: Write>(
+    src: &str,
+    env: &Rc<Env<'a>>,
+    ast: &'a AST<'a>,
+    limits: &Limits,
+    input: R,
+    output: W,
+) -> io::Result<()> {
+    let mut debugger = Debugger {
+        src,
+        stepper: Stepper::new(env, ast, limits),
+        breakpoints: Vec::new(),
+        stopped_at: None,
+        output,
+    };
+    writeln!(
+        debugger.output,
+        "stopped at {}",
+        debugger.position(&ast.loc())
+    )?;
+    for line in input.lines() {
+        if !debugger.command(&line?)? {
+            break;
+        }
+    }
+    debugger.output.flush()
+}
@@ -0,0 +1,188 @@
+//! Errors from every phase, in one owned form.
+//!
+//! `ParseError`, `TypeError` and `EvalError` borrow from the source they
+//! describe. A `Diagnostic` copies out everything a caller needs to report
+//! one, so it can outlive the source and be passed around as a
+//! `std::error::Error`.
+
+use ast;
+use ast::Position;
+use eval::{EvalError, Failure};
+use module::ModuleError;
+use nbe;
+use parser::ParseError;
+use typecheck::TypeError;
+
+use std::error;
+use std::fmt;
+
+/// The phase of processing a diagnostic came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Parse,
+    Type,
+    Module,
+    Runtime,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(match *self {
+            Phase::Parse => "parse",
+            Phase::Type => "type",
+            Phase::Module => "module",
+            Phase::Runtime => "runtime",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub phase: Phase,
+    /// The name of the error's variant, such as `Mismatch`.
+    pub kind: &'static str,
+    pub message: String,
+    pub file: String,
+    /// The byte range of the source the diagnostic refers to.
+    pub span: (usize, usize),
+    pub start: Position,
+    pub end: Position,
+    /// For a runtime error, the calls in progress, innermost first.
+    pub trace: Vec<TraceFrame>,
+}
+
+/// A call in a runtime error's stack trace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceFrame {
+    /// The variable the called function was bound to, if any.
+    pub name: Option<String>,
+    pub file: String,
+    pub span: (usize, usize),
+    pub start: Position,
+}
+
+/// How many calls of a stack trace `Display` shows.
+const TRACE_LIMIT: usize = 20;
+
+impl Diagnostic {
+    fn new(
+        src: &str,
+        phase: Phase,
+        kind: &'static str,
+        message: String,
+        loc: &ast::Loc,
+    ) -> Diagnostic {
+        Diagnostic {
+            phase,
+            kind,
+            message,
+            file: loc.file.to_string(),
+            span: (loc.begin as usize, loc.end as usize),
+            start: loc.start(src),
+            end: loc.end(src),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn parse(src: &str, err: &ParseError) -> Diagnostic {
+        Diagnostic::new(src, Phase::Parse, err.kind(), err.to_string(), &err.loc())
+    }
+
+    pub fn typecheck(src: &str, err: &TypeError) -> Diagnostic {
+        Diagnostic::new(src, Phase::Type, err.kind(), err.to_string(), &err.loc())
+    }
+
+    /// An error loading a module. `src` is the text of the file the error
+    /// is in.
+    pub fn module(src: &str, err: &ModuleError) -> Diagnostic {
+        match *err {
+            ModuleError::Parse(ref err) => Diagnostic::parse(src, err),
+            ModuleError::Type(ref err) => Diagnostic::typecheck(src, err),
+            _ => Diagnostic::new(src, Phase::Module, err.kind(), err.to_string(), &err.loc()),
+        }
+    }
+
+    pub fn eval(src: &str, err: &EvalError) -> Diagnostic {
+        Diagnostic::new(src, Phase::Runtime, err.kind(), err.to_string(), &err.loc())
+    }
+
+    /// A limit normalization ran into, which is reported like the runtime
+    /// error evaluation would give.
+    pub fn normalize(src: &str, err: &nbe::Error) -> Diagnostic {
+        Diagnostic::new(src, Phase::Runtime, err.kind(), err.to_string(), &err.loc())
+    }
+
+    /// A runtime error, with its stack trace.
+    pub fn failure(src: &str, failure: &Failure) -> Diagnostic {
+        let mut diag = Diagnostic::eval(src, &failure.error);
+        diag.trace = failure
+            .trace
+            .iter()
+            .map(|frame| TraceFrame {
+                name: frame.name.as_ref().map(|name| name.to_string()),
+                file: frame.loc.file.to_string(),
+                span: (frame.loc.begin as usize, frame.loc.end as usize),
+                start: frame.loc.start(src),
+            })
+            .collect();
+        diag
+    }
+}
+
+/// `FILE:LINE:COL: PHASE error: MESSAGE`, followed by an indented line for
+/// each call in the stack trace.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "{}:{}:{}: {} error: {}",
+            self.file, self.start.line, self.start.column, self.phase, self.message
+        )?;
+        for frame in self.trace.iter().take(TRACE_LIMIT) {
+            write!(f, "\n    in ")?;
+            if let Some(ref name) = frame.name {
+                write!(f, "{} at ", name)?;
+            }
+            write!(
+                f,
+                "{}:{}:{}",
+                frame.file, frame.start.line, frame.start.column
+            )?;
+        }
+        if self.trace.len() > TRACE_LIMIT {
+            write!(f, "\n    ... {} more calls", self.trace.len() - TRACE_LIMIT)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Diagnostic {}
+
+/// Everything that went wrong with a program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diag: Diagnostic) -> Diagnostics {
+        Diagnostics {
+            diagnostics: vec![diag],
+        }
+    }
+}
+
+/// One diagnostic after another, each starting on a new line.
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (i, diag) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            diag.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Diagnostics {}
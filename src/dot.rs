@@ -0,0 +1,231 @@
+//! Graphviz renderings of ASTs and of the typechecker's constraint graph.
+
+use ast::AST;
+use names::Name;
+use typecheck::{Constraints, TypeError};
+use types::Type;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn role(parent: &AST, i: usize) -> String {
+    let roles: &[&str] = match *parent {
+        AST::Application(..) => &["fn", "arg"],
+        AST::Abstraction(..) => &["param", "body"],
+        AST::Ascription(..) => &["expr", "type"],
+        AST::If(..) => &["cond", "then", "else"],
+        AST::TyFn(..) => &["dom", "range"],
+        AST::TyApp(..) => return "arg".to_string(),
+        // Each constrained type is labelled with its class.
+        AST::TyQual(_, ref constraints, _) => match constraints.get(i) {
+            Some((class, _)) => return class.to_string(),
+            None => return "type".to_string(),
+        },
+        _ => &[],
+    };
+    roles.get(i).cloned().unwrap_or("").to_string()
+}
+
+fn ast_node(out: &mut String, src: &str, ast: &AST, next: &mut usize) -> usize {
+    let id = *next;
+    *next += 1;
+
+    let loc = ast.loc();
+    let begin = loc.start(src);
+    let mut label = ast.kind().to_string();
+    match *ast {
+        AST::Variable(_, ref name) | AST::TyName(_, ref name) | AST::TyApp(_, ref name, _) => {
+            write!(label, " {}", name).unwrap()
+        }
+        AST::Integer(_, ref val) => write!(label, " {}", val).unwrap(),
+        AST::Boolean(_, val) => write!(label, " {}", val).unwrap(),
+        _ => {}
+    }
+    write!(label, "\n{}:{}", begin.line, begin.column).unwrap();
+    writeln!(out, "  n{} [label=\"{}\"];", id, escape(&label)).unwrap();
+
+    for (i, child) in ast.children().into_iter().enumerate() {
+        let child_id = ast_node(out, src, child, next);
+        writeln!(out, "  n{} -> n{} [label=\"{}\"];", id, child_id, role(ast, i)).unwrap();
+    }
+    id
+}
+
+/// Render `ast` as a Graphviz tree, one node per AST node.
+pub fn ast_dot(src: &str, ast: &AST) -> String {
+    let mut out = String::new();
+    out.push_str("digraph ast {\n  node [shape=box];\n");
+    ast_node(&mut out, src, ast, &mut 0);
+    out.push_str("}\n");
+    out
+}
+
+fn type_vars<'a>(ty: &Type<'a>, vars: &mut Vec<Name<'a>>) {
+    match *ty {
+        Type::Primitive(_) => {}
+        Type::Variable(ref name) => {
+            if !vars.contains(name) {
+                vars.push(name.clone())
+            }
+        }
+        Type::Function(ref dom, ref range) => {
+            type_vars(dom, vars);
+            type_vars(range, vars);
+        }
+        Type::App(_, ref args) => {
+            for arg in args {
+                type_vars(arg, vars);
+            }
+        }
+        Type::ForAll(_, ref body) => type_vars(body, vars),
+        Type::Qualified(ref constraints, ref body) => {
+            type_vars(body, vars);
+            for (_, ty) in constraints {
+                type_vars(ty, vars);
+            }
+        }
+    }
+}
+
+struct ConstraintGraph {
+    out: String,
+    ids: HashMap<String, usize>,
+}
+
+impl ConstraintGraph {
+    fn var(&mut self, name: &Name) -> usize {
+        self.node(format!("{}", name), "ellipse")
+    }
+
+    fn node(&mut self, label: String, shape: &str) -> usize {
+        let key = format!("{}:{}", shape, label);
+        if let Some(&id) = self.ids.get(&key) {
+            return id;
+        }
+        let id = self.ids.len();
+        self.ids.insert(key, id);
+        writeln!(self.out, "  t{} [shape={}, label=\"{}\"];", id, shape, escape(&label)).unwrap();
+        id
+    }
+
+    /// Add a node for `ty`, linking a compound type to the variables that
+    /// appear in it.
+    fn ty(&mut self, ty: &Rc<Type>) -> usize {
+        if let Type::Variable(ref name) = **ty {
+            return self.var(name);
+        }
+        let fresh = !self.ids.contains_key(&format!("box:{}", ty));
+        let id = self.node(ty.to_string(), "box");
+        if fresh {
+            let mut vars = Vec::new();
+            type_vars(ty, &mut vars);
+            for var in vars {
+                let var_id = self.var(&var);
+                writeln!(self.out, "  t{} -> t{} [style=dashed, arrowhead=none];", id, var_id).unwrap();
+            }
+        }
+        id
+    }
+}
+
+/// Render the solutions `unify` found as a graph from type variables to the
+/// types they were solved to. If checking failed with a `Mismatch`, the two
+/// types that could not be unified are joined by a highlighted edge.
+pub fn constraints_dot(src: &str, constraints: &Constraints) -> String {
+    let mut graph = ConstraintGraph {
+        out: String::new(),
+        ids: HashMap::new(),
+    };
+    graph.out.push_str("digraph constraints {\n");
+
+    for (i, (var, ty)) in constraints.solutions.iter().enumerate() {
+        let from = graph.var(var);
+        let to = graph.ty(ty);
+        writeln!(graph.out, "  t{} -> t{} [label=\"{}\"];", from, to, i + 1).unwrap();
+    }
+
+    match constraints.error {
+        Some(TypeError::Mismatch(ref loc, ref left, ref right)) => {
+            let from = graph.ty(left);
+            let to = graph.ty(right);
+            let pos = loc.start(src);
+            writeln!(
+                graph.out,
+                "  t{} -> t{} [color=red, penwidth=2, dir=both, label=\"mismatch at {}:{}\"];",
+                from, to, pos.line, pos.column
+            ).unwrap();
+        }
+        Some(ref err) => {
+            writeln!(graph.out, "  label=\"{}\";", escape(&format!("{:?}", err))).unwrap();
+        }
+        None => {}
+    }
+
+    graph.out.push_str("}\n");
+    graph.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globals;
+    use parser;
+    use typecheck;
+
+    #[test]
+    fn test_constraints_no_instance() {
+        // `add` is a `Num` method, so adding a `bool` fails for want of an
+        // instance rather than with a mismatch.
+        let src = "fn(x) { add(x, true) }";
+        let ast = parser::parse("t.gol", src).unwrap();
+        let constraints = typecheck::constraints(&globals::global_env(), &ast);
+        let dot = constraints_dot(src, &constraints);
+        assert!(dot.contains("label=\"x$1\""), "{}", dot);
+        assert!(!dot.contains("color=red"), "{}", dot);
+        assert!(dot.contains("label=\"NoInstance("), "{}", dot);
+        assert!(dot.contains("Type(\\\"Num\\\"), Primitive(Type(\\\"bool\\\"))"), "{}", dot);
+    }
+
+    #[test]
+    fn test_constraints_mismatch() {
+        let src = "fn(x) { min(x, true) }";
+        let ast = parser::parse("t.gol", src).unwrap();
+        let constraints = typecheck::constraints(&globals::global_env(), &ast);
+        let dot = constraints_dot(src, &constraints);
+        assert!(dot.contains("label=\"x$1\""), "{}", dot);
+        assert!(dot.contains("color=red"), "{}", dot);
+        assert!(dot.contains("mismatch at 1:9"), "{}", dot);
+    }
+
+    #[test]
+    fn test_ast_dot() {
+        let src = "f(x)";
+        let ast = parser::parse("t.gol", src).unwrap();
+        assert_eq!(
+            ast_dot(src, &ast),
+            "digraph ast {\n  node [shape=box];\n  \
+             n0 [label=\"apply\\n1:1\"];\n  \
+             n1 [label=\"var f\\n1:1\"];\n  \
+             n0 -> n1 [label=\"fn\"];\n  \
+             n2 [label=\"var x\\n1:3\"];\n  \
+             n0 -> n2 [label=\"arg\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_ast_dot_types() {
+        let src = "fn(f : Eq a => list<a>) { f }";
+        let ast = parser::parse("t.gol", src).unwrap();
+        let dot = ast_dot(src, &ast);
+        assert!(dot.contains("n3 [label=\"tyqual\\n1:8\"];"), "{}", dot);
+        assert!(dot.contains("n3 -> n4 [label=\"Eq\"];"), "{}", dot);
+        assert!(dot.contains("n5 [label=\"tyapp list\\n1:16\"];"), "{}", dot);
+        assert!(dot.contains("n5 -> n6 [label=\"arg\"];"), "{}", dot);
+        assert!(dot.contains("n3 -> n5 [label=\"type\"];"), "{}", dot);
+    }
+}
@@ -0,0 +1,349 @@
+//! Machine-readable dumps of parsed and typechecked programs.
+//!
+//! Both the JSON and the S-expression forms share a schema. Every node has a
+//! `kind`, one of:
+//!
+//! | kind      | fields    | children                         |
+//! |-----------|-----------|----------------------------------|
+//! | `var`     | `name`    |                                  |
+//! | `int`     | `value`   |                                  |
+//! | `bool`    | `value`   |                                  |
+//! | `apply`   |           | function, argument               |
+//! | `lambda`  |           | parameter, body                  |
+//! | `ascribe` |           | expression, type                 |
+//! | `if`      |           | condition, then, else            |
+//! | `tyname`  | `name`    |                                  |
+//! | `tyfn`    |           | domain, range                    |
+//! | `tyapp`   | `name`    | arguments                        |
+//! | `tyqual`  | `classes` | constrained types, then the body |
+//!
+//! A `lambda` parameter is either a `var` or an `ascribe` of a `var`. A
+//! `tyqual` has one constrained type for each of its `classes`, in order, so
+//! `Eq a, Num b => a -> b` has `classes` `Eq` and `Num` and the children `a`,
+//! `b` and `a -> b`.
+//!
+//! JSON nodes are objects with `kind`, `loc`, the fields above, and a
+//! `children` array. `loc` holds the `file` and the `begin` and `end` of the
+//! node, each an object with a byte `offset` and a 1-based `line` and
+//! `column`. The top-level document is
+//!
+//! ```text
+//! { "version": 2, "file": ..., "ast": <node> }
+//! ```
+//!
+//! Integer `value`s are arbitrary-precision, and are written as strings of
+//! decimal digits so that consumers do not round them.
+//!
+//! `typed-json` documents additionally carry the program's `type`, and every
+//! expression node carries its inferred `type`, rendered as a string in
+//! surface syntax. Type variables the program leaves undetermined are named
+//! `a`, `b`, and so on, in the order they first appear, and each name stands
+//! for the same variable throughout the document. Nodes that are type syntax
+//! (`tyname`, `tyfn`, `tyapp`, `tyqual`) or lambda parameters have no `type`.
+//!
+//! S-expressions take the form `(kind (loc FILE LINE COL LINE COL) FIELD...
+//! CHILD...)`, where names and files are strings, `classes` is a list of
+//! strings, and booleans are the symbols `true` and `false`.
+
+use ast;
+use ast::AST;
+use names::Name;
+use typecheck::NodeTypes;
+use types;
+use types::Type;
+
+use serde_json::{Map, Value};
+
+use std::fmt::Write;
+use std::rc::Rc;
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+fn json_position(src: &str, offset: u32) -> Value {
+    let pos = ast::position(src, offset);
+    json!({
+        "offset": offset,
+        "line": pos.line,
+        "column": pos.column,
+    })
+}
+
+fn json_loc(src: &str, loc: &ast::Loc) -> Value {
+    json!({
+        "file": loc.file,
+        "begin": json_position(src, loc.begin),
+        "end": json_position(src, loc.end),
+    })
+}
+
+/// The types of a `typed-json` document's nodes, in surface syntax.
+struct Surface<'t, 'a: 't> {
+    types: &'t NodeTypes<'a>,
+    /// The undetermined type variables, in the order they first appear.
+    vars: Vec<Rc<Type<'a>>>,
+    /// The name each of `vars` is printed as.
+    names: Vec<String>,
+}
+
+impl<'t, 'a> Surface<'t, 'a> {
+    fn new(ast: &AST<'a>, ty: &Rc<Type<'a>>, types: &'t NodeTypes<'a>) -> Surface<'t, 'a> {
+        let mut vars = Vec::new();
+        let mut collect = |ty: &Rc<Type<'a>>| {
+            types::map_vars(ty, &mut |var| {
+                if !vars.contains(&var) {
+                    vars.push(Rc::clone(&var));
+                }
+                var
+            });
+        };
+        collect(ty);
+        let mut stack = vec![ast];
+        while let Some(node) = stack.pop() {
+            if let Some(ty) = types.get(node) {
+                collect(&ty);
+            }
+            stack.extend(node.children().into_iter().rev());
+        }
+        let names = (0..vars.len())
+            .map(|i| {
+                let letter = (b'a' + (i % 26) as u8) as char;
+                if i < 26 {
+                    letter.to_string()
+                } else {
+                    format!("{}{}", letter, i / 26)
+                }
+            })
+            .collect();
+        Surface { types, vars, names }
+    }
+
+    fn render(&self, ty: &Rc<Type<'a>>) -> String {
+        let ty = types::map_vars(ty, &mut |var| {
+            match self.vars.iter().position(|seen| *seen == var) {
+                Some(i) => Rc::new(Type::Variable(Name::Type(&self.names[i]))),
+                None => var,
+            }
+        });
+        ty.to_string()
+    }
+
+    fn get(&self, node: &AST<'a>) -> Option<String> {
+        self.types.get(node).map(|ty| self.render(&ty))
+    }
+}
+
+fn json_node(src: &str, ast: &AST, types: Option<&Surface>, is_param: bool) -> Value {
+    let mut obj = Map::new();
+    obj.insert("kind".to_string(), json!(ast.kind()));
+    obj.insert("loc".to_string(), json_loc(src, &ast.loc()));
+    match *ast {
+        AST::Variable(_, ref name) | AST::TyName(_, ref name) | AST::TyApp(_, ref name, _) => {
+            obj.insert("name".to_string(), json!(name.to_string()));
+        }
+        AST::Integer(_, ref val) => {
+            obj.insert("value".to_string(), json!(val.to_string()));
+        }
+        AST::Boolean(_, val) => {
+            obj.insert("value".to_string(), json!(val));
+        }
+        AST::TyQual(_, ref constraints, _) => {
+            let classes: Vec<String> = constraints
+                .iter()
+                .map(|(class, _)| class.to_string())
+                .collect();
+            obj.insert("classes".to_string(), json!(classes));
+        }
+        _ => {}
+    }
+    if let Some(types) = types {
+        if !is_param {
+            if let Some(ty) = types.get(ast) {
+                obj.insert("type".to_string(), json!(ty));
+            }
+        }
+    }
+    let children: Vec<Value> = ast
+        .children()
+        .into_iter()
+        .enumerate()
+        .map(|(i, child)| {
+            let param = match *ast {
+                AST::Abstraction(..) => i == 0,
+                _ => is_param,
+            };
+            json_node(src, child, types, param)
+        })
+        .collect();
+    obj.insert("children".to_string(), Value::Array(children));
+    Value::Object(obj)
+}
+
+/// Render `ast` as an untyped JSON document.
+pub fn ast_json(src: &str, ast: &AST) -> Value {
+    json!({
+        "version": SCHEMA_VERSION,
+        "file": ast.loc().file,
+        "ast": json_node(src, ast, None, false),
+    })
+}
+
+/// Render `ast`, along with the result of typechecking it, as a JSON
+/// document.
+pub fn typed_json<'a>(src: &str, ast: &AST<'a>, ty: &Rc<Type<'a>>, types: &NodeTypes<'a>) -> Value {
+    let surface = Surface::new(ast, ty, types);
+    json!({
+        "version": SCHEMA_VERSION,
+        "file": ast.loc().file,
+        "type": surface.render(ty),
+        "ast": json_node(src, ast, Some(&surface), false),
+    })
+}
+
+fn sexp_string(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn sexp_node(out: &mut String, src: &str, ast: &AST) {
+    let loc = ast.loc();
+    let (begin, end) = (loc.start(src), loc.end(src));
+    write!(out, "({} (loc ", ast.kind()).unwrap();
+    sexp_string(out, loc.file);
+    write!(
+        out,
+        " {} {} {} {})",
+        begin.line, begin.column, end.line, end.column
+    ).unwrap();
+    match *ast {
+        AST::Variable(_, ref name) | AST::TyName(_, ref name) | AST::TyApp(_, ref name, _) => {
+            out.push(' ');
+            sexp_string(out, &name.to_string());
+        }
+        AST::Integer(_, ref val) => write!(out, " {}", val).unwrap(),
+        AST::Boolean(_, val) => write!(out, " {}", val).unwrap(),
+        AST::TyQual(_, ref constraints, _) => {
+            out.push_str(" (");
+            for (i, (class, _)) in constraints.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                sexp_string(out, &class.to_string());
+            }
+            out.push(')');
+        }
+        _ => {}
+    }
+    for child in ast.children() {
+        out.push(' ');
+        sexp_node(out, src, child);
+    }
+    out.push(')');
+}
+
+/// Render `ast` as an S-expression.
+pub fn ast_sexp(src: &str, ast: &AST) -> String {
+    let mut out = String::new();
+    sexp_node(&mut out, src, ast);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globals;
+    use parser;
+    use typecheck;
+
+    #[test]
+    fn test_sexp() {
+        let src = "add(1,\n x)";
+        let ast = parser::parse("t.gol", src).unwrap();
+        assert_eq!(
+            ast_sexp(src, &ast),
+            "(apply (loc \"t.gol\" 1 1 2 4) \
+             (apply (loc \"t.gol\" 1 1 1 6) \
+             (var (loc \"t.gol\" 1 1 1 4) \"add\") \
+             (int (loc \"t.gol\" 1 5 1 6) 1)) \
+             (var (loc \"t.gol\" 2 2 2 3) \"x\"))"
+        );
+    }
+
+    #[test]
+    fn test_type_application() {
+        let src = "fn(x : list<int>) { x }";
+        let ast = parser::parse("t.gol", src).unwrap();
+        assert_eq!(
+            ast_sexp(src, &ast),
+            "(lambda (loc \"t.gol\" 1 1 1 24) \
+             (ascribe (loc \"t.gol\" 1 4 1 17) \
+             (var (loc \"t.gol\" 1 4 1 5) \"x\") \
+             (tyapp (loc \"t.gol\" 1 8 1 17) \"list\" \
+             (tyname (loc \"t.gol\" 1 13 1 16) \"int\"))) \
+             (var (loc \"t.gol\" 1 21 1 22) \"x\"))"
+        );
+        let ty = &ast_json(src, &ast)["ast"]["children"][0]["children"][1];
+        assert_eq!(ty["kind"], json!("tyapp"));
+        assert_eq!(ty["name"], json!("list"));
+        assert_eq!(ty["children"][0]["name"], json!("int"));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let src = "fn(f : Eq a, Num b => a -> b) { f }";
+        let ast = parser::parse("t.gol", src).unwrap();
+        assert!(ast_sexp(src, &ast).contains(
+            "(tyqual (loc \"t.gol\" 1 8 1 29) (\"Eq\" \"Num\") \
+             (tyname (loc \"t.gol\" 1 11 1 12) \"a\") \
+             (tyname (loc \"t.gol\" 1 18 1 19) \"b\") \
+             (tyfn "
+        ));
+        let ty = &ast_json(src, &ast)["ast"]["children"][0]["children"][1];
+        assert_eq!(ty["kind"], json!("tyqual"));
+        assert_eq!(ty["classes"], json!(["Eq", "Num"]));
+        assert_eq!(ty["children"][1]["name"], json!("b"));
+        assert_eq!(ty["children"][2]["kind"], json!("tyfn"));
+    }
+
+    #[test]
+    fn test_typed_json() {
+        let src = "fn(x : int) { add(x, 1) }";
+        let ast = parser::parse("t.gol", src).unwrap();
+        let (ty, types) = typecheck::typecheck_nodes(&globals::global_env(), &ast).unwrap();
+        let doc = typed_json(src, &ast, &ty, &types);
+        assert_eq!(doc["version"], json!(SCHEMA_VERSION));
+        assert_eq!(doc["type"], json!("int -> int"));
+        let lambda = &doc["ast"];
+        assert_eq!(lambda["kind"], json!("lambda"));
+        assert_eq!(lambda["type"], json!("int -> int"));
+        assert_eq!(lambda["loc"]["end"]["column"], json!(26));
+        let param = &lambda["children"][0];
+        assert_eq!(param["kind"], json!("ascribe"));
+        assert!(param.get("type").is_none());
+        assert!(param["children"][1].get("type").is_none());
+        let body = &lambda["children"][1];
+        assert_eq!(body["type"], json!("int"));
+        assert_eq!(body["children"][1]["value"], json!("1"));
+    }
+
+    #[test]
+    fn test_type_variables() {
+        let src = "fn(f, x) { f(x) }";
+        let ast = parser::parse("t.gol", src).unwrap();
+        let (ty, types) = typecheck::typecheck_nodes(&globals::global_env(), &ast).unwrap();
+        let doc = typed_json(src, &ast, &ty, &types);
+        assert_eq!(doc["type"], json!("(a -> b) -> a -> b"));
+        let inner = &doc["ast"]["children"][1];
+        assert_eq!(inner["type"], json!("a -> b"));
+        let call = &inner["children"][1];
+        assert_eq!(call["type"], json!("b"));
+        assert_eq!(call["children"][1]["type"], json!("a"));
+    }
+}
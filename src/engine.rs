@@ -0,0 +1,524 @@
+//! Embedding Gollum in a Rust program.
+//!
+//! An `Engine` holds the bindings a program can see: the prelude from
+//! `globals`, plus any Rust functions and values the host registers. Each
+//! registered binding gets a Gollum type derived from its Rust type, so the
+//! typechecker and the evaluator always agree on it:
+//!
+//! ```
+//! # use iron_golem::engine::Engine;
+//! let mut engine = Engine::new();
+//! let scale = 3;
+//! engine
+//!     .register_fn("scale", move |x: i64| x * scale)
+//!     .register_fn("clamp", |x: i64, lo: i64, hi: i64| x.max(lo).min(hi))
+//!     .register_value("limit", 10i64);
+//! ```
+//!
+//! `check` and `eval` run a program against those bindings. Their results
+//! and errors are owned, and do not borrow from the program's source:
+//!
+//! ```
+//! # use iron_golem::engine::{Engine, Value};
+//! # let mut engine = Engine::new();
+//! # engine.register_value("limit", 10i64);
+//! assert_eq!(engine.check("mul(limit, 2)").unwrap().to_string(), "int");
+//! assert_eq!(engine.eval("mul(limit, 2)").unwrap().as_i64(), Some(20));
+//! assert!(engine.eval("not(limit)").is_err());
+//! ```
+//!
+//! Arguments and results convert between Gollum values and Rust types with
+//! `FromValue` and `IntoValue`. An argument that does not fit its Rust type
+//! stops evaluation with `Trap::OutOfRange`, and a host function that
+//! returns `Err` stops it with `Trap::Host`.
+
+use diagnostics::{Diagnostic, Diagnostics};
+use env::TypeEnv;
+use eval;
+use eval::{Apply, BuiltinFn, Trap};
+use globals;
+use names;
+use parser;
+use typecheck;
+use types;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use std::fmt;
+use std::rc::Rc;
+
+/// The file name diagnostics report for programs passed to `check` and
+/// `eval`.
+pub const INPUT: &str = "<input>";
+
+/// A program's type, independent of its source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    Primitive(String),
+    Variable(String),
+    Function(Box<Type>, Box<Type>),
+    App(String, Vec<Type>),
+    ForAll(Vec<String>, Box<Type>),
+    Qualified(Vec<(String, Type)>, Box<Type>),
+}
+
+impl<'a> From<&'a types::Type<'a>> for Type {
+    fn from(ty: &'a types::Type<'a>) -> Type {
+        match *ty {
+            types::Type::Primitive(ref name) => Type::Primitive(name.to_string()),
+            types::Type::Variable(ref name) => Type::Variable(name.to_string()),
+            types::Type::Function(ref dom, ref range) => {
+                Type::Function(Box::new(Type::from(&**dom)), Box::new(Type::from(&**range)))
+            }
+            types::Type::App(ref con, ref args) => Type::App(
+                con.to_string(),
+                args.iter().map(|arg| Type::from(&**arg)).collect(),
+            ),
+            types::Type::ForAll(ref vars, ref body) => Type::ForAll(
+                vars.iter().map(|var| var.to_string()).collect(),
+                Box::new(Type::from(&**body)),
+            ),
+            types::Type::Qualified(ref constraints, ref body) => Type::Qualified(
+                constraints
+                    .iter()
+                    .map(|(class, ty)| (class.to_string(), Type::from(&**ty)))
+                    .collect(),
+                Box::new(Type::from(&**body)),
+            ),
+        }
+    }
+}
+
+/// The same syntax as `types::Type`.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Type::Primitive(ref name) | Type::Variable(ref name) => f.write_str(name),
+            Type::Function(ref dom, ref range) => match **dom {
+                Type::Function(..) | Type::ForAll(..) | Type::Qualified(..) => {
+                    write!(f, "({}) -> {}", dom, range)
+                }
+                _ => write!(f, "{} -> {}", dom, range),
+            },
+            Type::App(ref con, ref args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}<{}>", con, args.join(", "))
+            }
+            Type::ForAll(ref vars, ref body) => write!(f, "forall {}. {}", vars.join(" "), body),
+            Type::Qualified(ref constraints, ref body) => {
+                let constraints: Vec<String> = constraints
+                    .iter()
+                    .map(|(class, ty)| format!("{} {}", class, ty))
+                    .collect();
+                write!(f, "{} => {}", constraints.join(", "), body)
+            }
+        }
+    }
+}
+
+/// The result of evaluating a program, independent of its source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Integer(BigInt),
+    Boolean(bool),
+    List(Vec<Value>),
+    Option(Option<Box<Value>>),
+    /// Functions cannot be called once they leave the engine; this holds
+    /// how one prints.
+    Function(String),
+}
+
+impl Value {
+    pub fn as_int(&self) -> Option<&BigInt> {
+        match *self {
+            Value::Integer(ref i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// The value as an `i64`, if it is an integer that fits in one.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_int().and_then(|i| i.to_i64())
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> From<eval::Value<'a>> for Value {
+    fn from(val: eval::Value<'a>) -> Value {
+        match val {
+            eval::Value::Integer(i) => Value::Integer(i),
+            eval::Value::Boolean(b) => Value::Boolean(b),
+            eval::Value::List(ref list) => {
+                Value::List(list.iter().map(|val| Value::from(val.clone())).collect())
+            }
+            eval::Value::Option(ref val) => {
+                Value::Option(val.as_ref().map(|val| Box::new(Value::from((**val).clone()))))
+            }
+            other => Value::Function(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Value::Integer(ref i) => i.fmt(f),
+            Value::Boolean(b) => b.fmt(f),
+            Value::List(ref vals) => {
+                let vals: Vec<String> = vals.iter().map(|val| val.to_string()).collect();
+                write!(f, "[{}]", vals.join(", "))
+            }
+            Value::Option(Some(ref val)) => write!(f, "some({})", val),
+            Value::Option(None) => f.write_str("none"),
+            Value::Function(ref text) => f.write_str(text),
+        }
+    }
+}
+
+/// A Rust type that Gollum values can be converted to.
+pub trait FromValue: Sized {
+    /// The Gollum type of values that convert to `Self`.
+    fn ty() -> Rc<types::Type<'static>>;
+    fn from_value(val: &eval::Value) -> Result<Self, Trap>;
+}
+
+/// A Rust type that can be converted to a Gollum value.
+pub trait IntoValue {
+    /// The Gollum type of the values `Self` converts to.
+    fn ty() -> Rc<types::Type<'static>>;
+    fn into_value<'v>(self) -> eval::Value<'v>;
+}
+
+impl FromValue for i64 {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::integer()
+    }
+
+    fn from_value(val: &eval::Value) -> Result<i64, Trap> {
+        match *val {
+            eval::Value::Integer(ref i) => i.to_i64().ok_or(Trap::OutOfRange),
+            _ => Err(Trap::BadArgument),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::integer()
+    }
+
+    fn into_value<'v>(self) -> eval::Value<'v> {
+        eval::Value::Integer(BigInt::from(self))
+    }
+}
+
+impl FromValue for BigInt {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::integer()
+    }
+
+    fn from_value(val: &eval::Value) -> Result<BigInt, Trap> {
+        match *val {
+            eval::Value::Integer(ref i) => Ok(i.clone()),
+            _ => Err(Trap::BadArgument),
+        }
+    }
+}
+
+impl IntoValue for BigInt {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::integer()
+    }
+
+    fn into_value<'v>(self) -> eval::Value<'v> {
+        eval::Value::Integer(self)
+    }
+}
+
+impl FromValue for bool {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::bool()
+    }
+
+    fn from_value(val: &eval::Value) -> Result<bool, Trap> {
+        match *val {
+            eval::Value::Boolean(b) => Ok(b),
+            _ => Err(Trap::BadArgument),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::bool()
+    }
+
+    fn into_value<'v>(self) -> eval::Value<'v> {
+        eval::Value::Boolean(self)
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::list(T::ty())
+    }
+
+    fn from_value(val: &eval::Value) -> Result<Vec<T>, Trap> {
+        match *val {
+            eval::Value::List(ref list) => list.iter().map(T::from_value).collect(),
+            _ => Err(Trap::BadArgument),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::list(T::ty())
+    }
+
+    fn into_value<'v>(self) -> eval::Value<'v> {
+        let list = self
+            .into_iter()
+            .rev()
+            .fold(eval::List::nil(), |tail, val| eval::List::cons(val.into_value(), tail));
+        eval::Value::List(list)
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::option(T::ty())
+    }
+
+    fn from_value(val: &eval::Value) -> Result<Option<T>, Trap> {
+        match *val {
+            eval::Value::Option(ref val) => val.as_ref().map(|val| T::from_value(val)).transpose(),
+            _ => Err(Trap::BadArgument),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn ty() -> Rc<types::Type<'static>> {
+        globals::option(T::ty())
+    }
+
+    fn into_value<'v>(self) -> eval::Value<'v> {
+        eval::Value::Option(self.map(|val| Rc::new(val.into_value())))
+    }
+}
+
+/// What a host function may return: a value, or a `Result` whose error
+/// becomes a `Trap::Host`.
+pub trait HostResult {
+    fn ty() -> Rc<types::Type<'static>>;
+    fn into_result<'v>(self) -> Result<eval::Value<'v>, Trap>;
+}
+
+macro_rules! host_result {
+    ($($t:ty),*) => {
+        $(
+            impl HostResult for $t {
+                fn ty() -> Rc<types::Type<'static>> {
+                    <$t as IntoValue>::ty()
+                }
+
+                fn into_result<'v>(self) -> Result<eval::Value<'v>, Trap> {
+                    Ok(self.into_value())
+                }
+            }
+        )*
+    };
+}
+
+host_result!(i64, BigInt, bool);
+
+impl<T: IntoValue> HostResult for Vec<T> {
+    fn ty() -> Rc<types::Type<'static>> {
+        <Vec<T> as IntoValue>::ty()
+    }
+
+    fn into_result<'v>(self) -> Result<eval::Value<'v>, Trap> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue> HostResult for Option<T> {
+    fn ty() -> Rc<types::Type<'static>> {
+        <Option<T> as IntoValue>::ty()
+    }
+
+    fn into_result<'v>(self) -> Result<eval::Value<'v>, Trap> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue, E: fmt::Display> HostResult for Result<T, E> {
+    fn ty() -> Rc<types::Type<'static>> {
+        T::ty()
+    }
+
+    fn into_result<'v>(self) -> Result<eval::Value<'v>, Trap> {
+        self.map(T::into_value)
+            .map_err(|e| Trap::Host(e.to_string()))
+    }
+}
+
+/// A Rust closure that can be called from Gollum. `Args` is the tuple of
+/// its argument types; it exists only to tell the implementations for
+/// different arities apart.
+pub trait HostFn<Args>: 'static {
+    /// The Gollum type of the function.
+    fn ty() -> Rc<types::Type<'static>>;
+    fn arity() -> usize;
+    fn call<'v>(&self, args: &[eval::Value<'v>]) -> Result<eval::Value<'v>, Trap>;
+}
+
+macro_rules! host_fn {
+    ($n:expr; $($arg:ident $i:tt),*) => {
+        impl<F, R, $($arg),*> HostFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: HostResult,
+            $($arg: FromValue),*
+        {
+            fn ty() -> Rc<types::Type<'static>> {
+                let args: Vec<Rc<types::Type<'static>>> = vec![$($arg::ty()),*];
+                args.into_iter()
+                    .rev()
+                    .fold(R::ty(), |ty, arg| Rc::new(types::Type::Function(arg, ty)))
+            }
+
+            fn arity() -> usize {
+                $n
+            }
+
+            fn call<'v>(&self, args: &[eval::Value<'v>]) -> Result<eval::Value<'v>, Trap> {
+                self($($arg::from_value(&args[$i])?),*).into_result()
+            }
+        }
+    };
+}
+
+host_fn!(1; A 0);
+host_fn!(2; A 0, B 1);
+host_fn!(3; A 0, B 1, C 2);
+host_fn!(4; A 0, B 1, C 2, D 3);
+
+struct Binding {
+    name: &'static str,
+    ty: Rc<types::Type<'static>>,
+    val: eval::Value<'static>,
+}
+
+/// The bindings visible to programs, with their types and values, and the
+/// limits programs run under.
+pub struct Engine {
+    bindings: Vec<Binding>,
+    check_limits: typecheck::Limits,
+    eval_limits: eval::Limits,
+}
+
+impl Default for Engine {
+    fn default() -> Engine {
+        Engine::new()
+    }
+}
+
+impl Engine {
+    /// An engine with the prelude from `globals` and nothing else. Programs
+    /// are checked and run under `typecheck::Limits::standard()` and
+    /// `eval::Limits::standard()` until other limits are set.
+    pub fn new() -> Engine {
+        let bindings = globals::prelude()
+            .into_iter()
+            .map(|(name, ty, val)| Binding { name, ty, val })
+            .collect();
+        Engine {
+            bindings,
+            check_limits: typecheck::Limits::standard(),
+            eval_limits: eval::Limits::standard(),
+        }
+    }
+
+    /// Bound the work `check` and `eval` may do typechecking a program.
+    pub fn check_limits(&mut self, limits: typecheck::Limits) -> &mut Engine {
+        self.check_limits = limits;
+        self
+    }
+
+    /// Bound the resources `eval` may use running a program. Programs that
+    /// exceed them fail with an `OutOfFuel`, `RecursionLimit`, `HeapLimit`
+    /// or `Deadline` diagnostic.
+    pub fn eval_limits(&mut self, limits: eval::Limits) -> &mut Engine {
+        self.eval_limits = limits;
+        self
+    }
+
+    /// Make a Rust function callable as `name`. A binding with the same name,
+    /// including one from the prelude, is replaced.
+    pub fn register_fn<Args, F: HostFn<Args>>(&mut self, name: &'static str, func: F) -> &mut Engine {
+        let call: BuiltinFn = Rc::new(move |args: &[eval::Value], _: &mut dyn Apply| func.call(args));
+        self.bind(name, F::ty(), globals::builtin(name, F::arity(), call))
+    }
+
+    /// Bind `name` to a constant.
+    pub fn register_value<T: IntoValue>(&mut self, name: &'static str, val: T) -> &mut Engine {
+        self.bind(name, T::ty(), val.into_value())
+    }
+
+    fn bind(&mut self, name: &'static str, ty: Rc<types::Type<'static>>, val: eval::Value<'static>) -> &mut Engine {
+        self.bindings.retain(|bind| bind.name != name);
+        self.bindings.push(Binding { name, ty, val });
+        self
+    }
+
+    /// Parse and typecheck `src`, returning its type.
+    pub fn check(&self, src: &str) -> Result<Type, Diagnostics> {
+        let ast = parser::parse(INPUT, src).map_err(|e| Diagnostic::parse(src, &e))?;
+        typecheck::typecheck_with(&self.type_env(), &ast, &self.check_limits)
+            .map(|ty| Type::from(&*ty))
+            .map_err(|e| Diagnostic::typecheck(src, &e).into())
+    }
+
+    /// Parse, typecheck and evaluate `src`, returning its value.
+    pub fn eval(&self, src: &str) -> Result<Value, Diagnostics> {
+        let ast = parser::parse(INPUT, src).map_err(|e| Diagnostic::parse(src, &e))?;
+        let (_, elaborated) = typecheck::check_program(&self.type_env(), &ast, &self.check_limits)
+            .map_err(|e| Diagnostic::typecheck(src, &e))?;
+        eval::eval_traced(&self.values(), elaborated.as_ref().unwrap_or(&ast), &self.eval_limits)
+            .map(Value::from)
+            .map_err(|e| Diagnostic::failure(src, &e).into())
+    }
+
+    /// The types of every binding, for `typecheck`.
+    pub fn type_env(&self) -> Rc<TypeEnv<'static>> {
+        let mut binds = vec![
+            (names::typ("bool"), globals::bool()),
+            (names::typ("int"), globals::integer()),
+        ];
+        for bind in &self.bindings {
+            binds.push((names::ident(bind.name), Rc::clone(&bind.ty)));
+        }
+        TypeEnv::from_bindings(&binds)
+    }
+
+    /// The values of every binding, and `globals::instance_values`, for
+    /// `eval`.
+    pub fn values(&self) -> Rc<eval::Env<'static>> {
+        eval::Env::from_bindings(
+            self.bindings
+                .iter()
+                .map(|bind| (names::ident(bind.name), bind.val.clone()))
+                .chain(globals::instance_values())
+                .collect(),
+        )
+    }
+}
@@ -36,6 +36,11 @@ impl<'a> TypeEnv<'a> {
         }
     }
 
+    /// The bindings made directly in this frame, excluding its parents.
+    pub fn bindings(&self) -> Vec<(&Name<'a>, &Rc<Type<'a>>)> {
+        self.binds.iter().collect()
+    }
+
     pub fn with_bindings<'b>(
         parent: &Rc<TypeEnv<'a>>,
         binds: &'b [(Name<'a>, Rc<Type<'a>>)],
@@ -0,0 +1,636 @@
+//! A tree-walking evaluator.
+//!
+//! Evaluation does not rely on the program having typechecked: ill-typed
+//! programs fail with an `EvalError` when they go wrong, rather than
+//! panicking.
+
+use ast;
+use ast::AST;
+use names::Name;
+use parser;
+
+use num_bigint::BigInt;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// A failure inside a primitive operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trap {
+    /// A primitive was applied to a value of the wrong type.
+    BadArgument,
+    /// An integer did not fit the Rust type a host function takes.
+    OutOfRange,
+    /// A host function reported an error.
+    Host(String),
+    /// `head` or `tail` was applied to an empty list.
+    EmptyList,
+    /// `div` or `mod` was applied to a divisor of zero.
+    DivisionByZero,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Trap::BadArgument => f.write_str("primitive applied to an argument of the wrong type"),
+            Trap::OutOfRange => f.write_str("integer out of range for a host function"),
+            Trap::Host(ref msg) => write!(f, "host function failed: {}", msg),
+            Trap::EmptyList => f.write_str("took the head or tail of an empty list"),
+            Trap::DivisionByZero => f.write_str("divided by zero"),
+        }
+    }
+}
+
+/// Applies functions on behalf of a builtin, such as `map`, that takes
+/// them as arguments.
+pub trait Apply<'v> {
+    /// Apply `func` to `arg`. If the application fails, the evaluator
+    /// reports why once the builtin returns the error, so the builtin
+    /// should return it unchanged.
+    fn apply(&mut self, func: Value<'v>, arg: Value<'v>) -> Result<Value<'v>, Trap>;
+}
+
+/// The implementation of a `Builtin`, called once it has all its arguments.
+pub type BuiltinFn =
+    Rc<dyn for<'v> Fn(&[Value<'v>], &mut dyn Apply<'v>) -> Result<Value<'v>, Trap>>;
+
+/// A primitive function implemented in Rust.
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: usize,
+    pub func: BuiltinFn,
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Builtin({})", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Closure<'a> {
+    pub param: Name<'a>,
+    pub body: &'a AST<'a>,
+    pub env: Rc<Env<'a>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Value<'a> {
+    Integer(BigInt),
+    Boolean(bool),
+    Closure(Rc<Closure<'a>>),
+    /// A builtin and the arguments it has been applied to so far.
+    Builtin(Rc<Builtin>, Vec<Value<'a>>),
+    List(List<'a>),
+    Option(Option<Rc<Value<'a>>>),
+}
+
+/// An immutable singly linked list, which shares its tails.
+#[derive(Clone, Debug)]
+pub struct List<'a> {
+    cell: Option<Rc<(Value<'a>, List<'a>)>>,
+}
+
+impl<'a> List<'a> {
+    pub fn nil() -> List<'a> {
+        List { cell: None }
+    }
+
+    pub fn cons(head: Value<'a>, tail: List<'a>) -> List<'a> {
+        List {
+            cell: Some(Rc::new((head, tail))),
+        }
+    }
+
+    /// The head and tail, unless the list is empty.
+    pub fn uncons(&self) -> Option<(&Value<'a>, &List<'a>)> {
+        self.cell.as_ref().map(|cell| (&cell.0, &cell.1))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.cell.is_none()
+    }
+
+    pub fn iter<'l>(&'l self) -> ListIter<'l, 'a> {
+        ListIter { list: self }
+    }
+}
+
+pub struct ListIter<'l, 'a: 'l> {
+    list: &'l List<'a>,
+}
+
+impl<'l, 'a> Iterator for ListIter<'l, 'a> {
+    type Item = &'l Value<'a>;
+
+    fn next(&mut self) -> Option<&'l Value<'a>> {
+        let (head, tail) = self.list.uncons()?;
+        self.list = tail;
+        Some(head)
+    }
+}
+
+impl<'a> Drop for List<'a> {
+    /// Unlink the cells one at a time, so that dropping a long list does
+    /// not recurse once per element.
+    fn drop(&mut self) {
+        let mut next = self.cell.take();
+        while let Some(cell) = next {
+            next = match Rc::try_unwrap(cell) {
+                Ok((_, mut tail)) => tail.cell.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Value::Integer(ref val) => val.fmt(f),
+            Value::Boolean(val) => val.fmt(f),
+            Value::Closure(_) => f.write_str("<fn>"),
+            Value::Builtin(ref builtin, _) => write!(f, "<builtin {}>", builtin.name),
+            Value::List(ref list) => {
+                f.write_str("[")?;
+                for (i, val) in list.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    val.fmt(f)?;
+                }
+                f.write_str("]")
+            }
+            Value::Option(Some(ref val)) => write!(f, "some({})", val),
+            Value::Option(None) => f.write_str("none"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EvalError<'a> {
+    UnboundVariable(ast::Loc<'a>, Name<'a>),
+    NotAFunction(ast::Loc<'a>),
+    NotABoolean(ast::Loc<'a>),
+    NotAnExpression(ast::Loc<'a>),
+    Trap(ast::Loc<'a>, Trap),
+    /// Evaluation took more steps than `Limits::fuel` allows.
+    OutOfFuel(ast::Loc<'a>),
+    /// Evaluation nested deeper than `Limits::max_depth` allows.
+    RecursionLimit(ast::Loc<'a>),
+    /// Evaluation allocated more than `Limits::max_heap` allows.
+    HeapLimit(ast::Loc<'a>),
+    /// Evaluation ran for longer than `Limits::timeout` allows.
+    Deadline(ast::Loc<'a>),
+}
+
+impl<'a> EvalError<'a> {
+    /// The name of the error's variant, for tools that match on it.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            EvalError::UnboundVariable(..) => "UnboundVariable",
+            EvalError::NotAFunction(..) => "NotAFunction",
+            EvalError::NotABoolean(..) => "NotABoolean",
+            EvalError::NotAnExpression(..) => "NotAnExpression",
+            EvalError::Trap(_, Trap::BadArgument) => "BadArgument",
+            EvalError::Trap(_, Trap::OutOfRange) => "OutOfRange",
+            EvalError::Trap(_, Trap::Host(_)) => "HostError",
+            EvalError::Trap(_, Trap::EmptyList) => "EmptyList",
+            EvalError::Trap(_, Trap::DivisionByZero) => "DivisionByZero",
+            EvalError::OutOfFuel(..) => "OutOfFuel",
+            EvalError::RecursionLimit(..) => "RecursionLimit",
+            EvalError::HeapLimit(..) => "HeapLimit",
+            EvalError::Deadline(..) => "Deadline",
+        }
+    }
+
+    pub fn loc(&self) -> ast::Loc<'a> {
+        match *self {
+            EvalError::UnboundVariable(ref loc, _)
+            | EvalError::NotAFunction(ref loc)
+            | EvalError::NotABoolean(ref loc)
+            | EvalError::NotAnExpression(ref loc)
+            | EvalError::Trap(ref loc, _)
+            | EvalError::OutOfFuel(ref loc)
+            | EvalError::RecursionLimit(ref loc)
+            | EvalError::HeapLimit(ref loc)
+            | EvalError::Deadline(ref loc) => loc.clone(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for EvalError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            EvalError::UnboundVariable(_, ref name) => write!(f, "unbound variable `{}`", name),
+            EvalError::NotAFunction(_) => f.write_str("applied a value that is not a function"),
+            EvalError::NotABoolean(_) => f.write_str("condition is not a boolean"),
+            EvalError::NotAnExpression(_) => f.write_str("evaluated a type"),
+            EvalError::Trap(_, ref trap) => trap.fmt(f),
+            EvalError::OutOfFuel(_) => f.write_str("ran out of fuel"),
+            EvalError::RecursionLimit(_) => f.write_str("recursion limit exceeded"),
+            EvalError::HeapLimit(_) => f.write_str("heap limit exceeded"),
+            EvalError::Deadline(_) => f.write_str("deadline exceeded"),
+        }
+    }
+}
+
+pub type EvalResult<'a> = Result<Value<'a>, EvalError<'a>>;
+
+/// A call that was in progress when evaluation failed.
+#[derive(Clone, Debug)]
+pub struct Frame<'a> {
+    pub loc: ast::Loc<'a>,
+    /// The variable the called function was bound to, if the call applied
+    /// one directly.
+    pub name: Option<Name<'a>>,
+}
+
+/// An `EvalError`, and the calls in progress when it happened, innermost
+/// first.
+#[derive(Debug)]
+pub struct Failure<'a> {
+    pub error: EvalError<'a>,
+    pub trace: Vec<Frame<'a>>,
+}
+
+#[derive(Debug)]
+pub struct Env<'a> {
+    binds: HashMap<Name<'a>, Value<'a>>,
+    parent: Option<Rc<Env<'a>>>,
+}
+
+impl<'a> Drop for Env<'a> {
+    /// Free the frames and closures only this environment holds with a
+    /// worklist, so that dropping a long chain of closures, each capturing
+    /// the last, does not recurse once per link.
+    fn drop(&mut self) {
+        let mut vals: Vec<Value<'a>> = self.binds.drain().map(|(_, val)| val).collect();
+        let mut envs: Vec<Rc<Env<'a>>> = self.parent.take().into_iter().collect();
+        loop {
+            if let Some(val) = vals.pop() {
+                match val {
+                    Value::Closure(closure) => {
+                        if let Ok(closure) = Rc::try_unwrap(closure) {
+                            envs.push(closure.env);
+                        }
+                    }
+                    Value::Builtin(_, args) => vals.extend(args),
+                    Value::Option(Some(val)) => {
+                        if let Ok(val) = Rc::try_unwrap(val) {
+                            vals.push(val);
+                        }
+                    }
+                    _ => {}
+                }
+            } else if let Some(env) = envs.pop() {
+                if let Ok(mut env) = Rc::try_unwrap(env) {
+                    vals.extend(env.binds.drain().map(|(_, val)| val));
+                    envs.extend(env.parent.take());
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl<'a> Env<'a> {
+    pub fn from_bindings(binds: Vec<(Name<'a>, Value<'a>)>) -> Rc<Env<'a>> {
+        Rc::new(Env {
+            binds: binds.into_iter().collect(),
+            parent: None,
+        })
+    }
+
+    pub fn with_bindings(parent: &Rc<Env<'a>>, binds: Vec<(Name<'a>, Value<'a>)>) -> Rc<Env<'a>> {
+        Rc::new(Env {
+            binds: binds.into_iter().collect(),
+            parent: Some(Rc::clone(parent)),
+        })
+    }
+
+    pub fn lookup(&self, var: &Name) -> Option<Value<'a>> {
+        if let ok @ Some(_) = self.binds.get(var) {
+            ok.cloned()
+        } else if let Some(ref env) = self.parent {
+            env.lookup(var)
+        } else {
+            None
+        }
+    }
+
+    /// The bindings visible here other than the outermost frame's,
+    /// innermost first, leaving out any that are shadowed.
+    pub fn locals(&self) -> Vec<(&Name<'a>, &Value<'a>)> {
+        let mut locals: Vec<(&Name<'a>, &Value<'a>)> = Vec::new();
+        let mut env = self;
+        while let Some(ref parent) = env.parent {
+            let mut binds: Vec<_> = env.binds.iter().collect();
+            binds.sort_by_key(|&(name, _)| name.to_string());
+            for (name, val) in binds {
+                if !locals.iter().any(|&(seen, _)| seen == name) {
+                    locals.push((name, val));
+                }
+            }
+            env = parent;
+        }
+        locals
+    }
+}
+
+/// Bounds on the resources a single evaluation may use. Each limit left as
+/// `None` is not enforced; `Limits::default()` enforces none of them.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The most expressions that may be evaluated.
+    pub fuel: Option<u64>,
+    /// The deepest evaluation may nest. The stack grows onto the heap as
+    /// evaluation nests, so this also bounds how much memory that takes.
+    pub max_depth: Option<usize>,
+    /// The most bytes evaluation may allocate for closures, environments and
+    /// integers, counted over the whole run without crediting frees.
+    pub max_heap: Option<usize>,
+    /// How long evaluation may run.
+    pub timeout: Option<Duration>,
+}
+
+impl Limits {
+    /// Limits which stop runaway programs before they run forever or use
+    /// up memory. The depth is ten times the parser's `MAX_DEPTH`, so any
+    /// tree the parser accepts can be evaluated, with room left for a few
+    /// thousand recursive calls. `Engine`, the module loader and the
+    /// command line run programs under these.
+    pub fn standard() -> Limits {
+        Limits {
+            fuel: Some(10_000_000),
+            max_depth: Some(10 * parser::MAX_DEPTH),
+            ..Limits::default()
+        }
+    }
+}
+
+/// How many steps to take between checks of the clock.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// The resources an evaluation has used so far, against its `Limits`.
+/// The evaluator and the small-step evaluator both keep one, so that they
+/// stop in the same ways.
+pub struct Meter {
+    limits: Limits,
+    deadline: Option<Instant>,
+    steps: u64,
+    heap: usize,
+}
+
+impl Meter {
+    pub fn new(limits: &Limits) -> Meter {
+        Meter {
+            limits: limits.clone(),
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            steps: 0,
+            heap: 0,
+        }
+    }
+
+    /// Take one step of evaluation at `loc`, nested `depth` deep.
+    pub fn step<'a>(&mut self, loc: &ast::Loc<'a>, depth: usize) -> Result<(), EvalError<'a>> {
+        self.steps += 1;
+        if self.limits.fuel.is_some_and(|fuel| self.steps > fuel) {
+            return Err(EvalError::OutOfFuel(loc.clone()));
+        }
+        if depth > self.limits.max_depth.unwrap_or(usize::MAX) {
+            return Err(EvalError::RecursionLimit(loc.clone()));
+        }
+        if self.steps.is_multiple_of(CLOCK_INTERVAL) {
+            if let Some(deadline) = self.deadline {
+                if Instant::now() >= deadline {
+                    return Err(EvalError::Deadline(loc.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Account for `bytes` of allocation at `loc`.
+    pub fn alloc<'a>(&mut self, loc: &ast::Loc<'a>, bytes: usize) -> Result<(), EvalError<'a>> {
+        self.heap = self.heap.saturating_add(bytes);
+        if self.limits.max_heap.is_some_and(|max| self.heap > max) {
+            return Err(EvalError::HeapLimit(loc.clone()));
+        }
+        Ok(())
+    }
+
+    /// Account for the value a builtin returned.
+    fn alloc_result<'a>(&mut self, loc: &ast::Loc<'a>, val: &Value<'a>) -> Result<(), EvalError<'a>> {
+        match *val {
+            Value::Integer(ref i) => self.alloc(loc, int_size(i)),
+            Value::List(ref list) if !list.is_nil() => self.alloc(loc, mem::size_of::<(Value, List)>()),
+            Value::Option(Some(_)) => self.alloc(loc, mem::size_of::<Value>()),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Machine<'m, 'a> {
+    meter: &'m mut Meter,
+    depth: usize,
+    /// The calls in progress, outermost first.
+    calls: Vec<Frame<'a>>,
+    /// The calls in progress where evaluation first failed.
+    trace: Option<Vec<Frame<'a>>>,
+}
+
+/// The bytes an integer takes, as `Meter::alloc` counts them.
+pub fn int_size(val: &BigInt) -> usize {
+    mem::size_of::<BigInt>() + (val.bits() as usize).div_ceil(8)
+}
+
+/// The name a call's function was given, if it is a variable applied to
+/// all of the call's arguments.
+fn callee<'a>(mut func: &AST<'a>) -> Option<Name<'a>> {
+    loop {
+        match *func {
+            AST::Application(_, ref inner, _) | AST::Ascription(_, ref inner, _) => func = inner,
+            AST::Variable(_, ref name) => return Some(name.clone()),
+            _ => return None,
+        }
+    }
+}
+
+impl<'m, 'a> Machine<'m, 'a> {
+    fn new(meter: &'m mut Meter, depth: usize) -> Machine<'m, 'a> {
+        Machine {
+            meter,
+            depth,
+            calls: Vec::new(),
+            trace: None,
+        }
+    }
+
+    fn step(&mut self, loc: &ast::Loc<'a>) -> Result<(), EvalError<'a>> {
+        self.meter.step(loc, self.depth)
+    }
+
+    fn alloc(&mut self, loc: &ast::Loc<'a>, bytes: usize) -> Result<(), EvalError<'a>> {
+        self.meter.alloc(loc, bytes)
+    }
+
+    fn apply(&mut self, loc: &ast::Loc<'a>, func: Value<'a>, arg: Value<'a>) -> EvalResult<'a> {
+        match func {
+            Value::Closure(closure) => {
+                self.alloc(loc, mem::size_of::<Env>() + mem::size_of::<Value>())?;
+                let frame = Env::with_bindings(&closure.env, vec![(closure.param.clone(), arg)]);
+                self.eval(&frame, closure.body)
+            }
+            Value::Builtin(builtin, mut args) => {
+                args.push(arg);
+                if args.len() < builtin.arity {
+                    self.alloc(loc, mem::size_of::<Value>() * args.len())?;
+                    Ok(Value::Builtin(builtin, args))
+                } else {
+                    self.call(loc, &builtin, &args)
+                }
+            }
+            _ => Err(EvalError::NotAFunction(loc.clone())),
+        }
+    }
+
+    /// Run a builtin that has all its arguments.
+    fn call(&mut self, loc: &ast::Loc<'a>, builtin: &Builtin, args: &[Value<'a>]) -> EvalResult<'a> {
+        let mut callback = Callback {
+            machine: self,
+            loc,
+            error: None,
+        };
+        let val = (builtin.func)(args, &mut callback);
+        if let Some(err) = callback.error {
+            return Err(err);
+        }
+        let val = val.map_err(|trap| EvalError::Trap(loc.clone(), trap))?;
+        self.meter.alloc_result(loc, &val)?;
+        Ok(val)
+    }
+
+    fn eval(&mut self, env: &Rc<Env<'a>>, ast: &'a AST<'a>) -> EvalResult<'a> {
+        self.step(&ast.loc())?;
+        self.depth += 1;
+        let val = ast::grow(|| self.eval_inner(env, ast));
+        self.depth -= 1;
+        val
+    }
+
+    fn eval_inner(&mut self, env: &Rc<Env<'a>>, ast: &'a AST<'a>) -> EvalResult<'a> {
+        match *ast {
+            AST::Integer(ref loc, ref val) => {
+                self.alloc(loc, int_size(val))?;
+                Ok(Value::Integer(val.clone()))
+            }
+            AST::Boolean(_, val) => Ok(Value::Boolean(val)),
+            AST::Variable(ref loc, ref var) => env
+                .lookup(var)
+                .ok_or_else(|| EvalError::UnboundVariable(loc.clone(), var.clone())),
+            AST::Abstraction(ref loc, ref param, ref body) => {
+                let param = match **param {
+                    AST::Ascription(_, ref var, _) => var,
+                    _ => param,
+                };
+                match **param {
+                    AST::Variable(_, ref name) => {
+                        self.alloc(loc, mem::size_of::<Closure>())?;
+                        Ok(Value::Closure(Rc::new(Closure {
+                            param: name.clone(),
+                            body,
+                            env: Rc::clone(env),
+                        })))
+                    }
+                    _ => Err(EvalError::NotAnExpression(loc.clone())),
+                }
+            }
+            AST::Application(ref loc, ref func, ref arg) => {
+                let name = callee(func);
+                let func = self.eval(env, func)?;
+                let arg = self.eval(env, arg)?;
+                self.calls.push(Frame {
+                    loc: loc.clone(),
+                    name,
+                });
+                let val = self.apply(loc, func, arg);
+                if val.is_err() && self.trace.is_none() {
+                    self.trace = Some(self.calls.iter().rev().cloned().collect());
+                }
+                self.calls.pop();
+                val
+            }
+            AST::If(_, ref cond, ref cons, ref alt) => match self.eval(env, cond)? {
+                Value::Boolean(true) => self.eval(env, cons),
+                Value::Boolean(false) => self.eval(env, alt),
+                _ => Err(EvalError::NotABoolean(cond.loc())),
+            },
+            AST::Ascription(_, ref val, _) => self.eval(env, val),
+            AST::TyName(ref loc, _)
+            | AST::TyFn(ref loc, _, _)
+            | AST::TyApp(ref loc, _, _)
+            | AST::TyQual(ref loc, _, _) => {
+                Err(EvalError::NotAnExpression(loc.clone()))
+            }
+        }
+    }
+}
+
+/// Run a builtin that has all its arguments, nested `depth` deep, applying
+/// any functions it takes to completion. The work it does is charged to
+/// `meter`.
+pub fn call_builtin<'a>(
+    loc: &ast::Loc<'a>,
+    builtin: &Builtin,
+    args: &[Value<'a>],
+    meter: &mut Meter,
+    depth: usize,
+) -> EvalResult<'a> {
+    Machine::new(meter, depth).call(loc, builtin, args)
+}
+
+/// The `Apply` a `Machine` gives builtins. It keeps the first error an
+/// application fails with, to report in place of the builtin's trap.
+struct Callback<'c, 'm: 'c, 'a: 'c> {
+    machine: &'c mut Machine<'m, 'a>,
+    loc: &'c ast::Loc<'a>,
+    error: Option<EvalError<'a>>,
+}
+
+impl<'c, 'm, 'a> Apply<'a> for Callback<'c, 'm, 'a> {
+    fn apply(&mut self, func: Value<'a>, arg: Value<'a>) -> Result<Value<'a>, Trap> {
+        self.machine.step(self.loc).and_then(|()| self.machine.apply(self.loc, func, arg)).map_err(|err| {
+            self.error.get_or_insert(err);
+            Trap::BadArgument
+        })
+    }
+}
+
+pub fn eval<'a>(env: &Rc<Env<'a>>, ast: &'a AST<'a>) -> EvalResult<'a> {
+    eval_with(env, ast, &Limits::default())
+}
+
+/// Evaluate `ast`, stopping with an error if it exceeds any of `limits`.
+pub fn eval_with<'a>(env: &Rc<Env<'a>>, ast: &'a AST<'a>, limits: &Limits) -> EvalResult<'a> {
+    eval_traced(env, ast, limits).map_err(|failure| failure.error)
+}
+
+/// Like `eval_with`, but report the calls in progress when evaluation
+/// failed.
+pub fn eval_traced<'a>(
+    env: &Rc<Env<'a>>,
+    ast: &'a AST<'a>,
+    limits: &Limits,
+) -> Result<Value<'a>, Failure<'a>> {
+    let mut meter = Meter::new(limits);
+    let mut machine = Machine::new(&mut meter, 0);
+    machine.eval(env, ast).map_err(|error| Failure {
+        error,
+        trace: machine.trace.take().unwrap_or_default(),
+    })
+}
@@ -0,0 +1,270 @@
+//! A generator of random well-typed programs, for property testing.
+//!
+//! Programs are built top-down from the type they should have, using
+//! literals, the bindings in `globals::global_env`, parameters of enclosing
+//! `fn`s, and random nesting of abstractions, applications, conditionals
+//! and ascriptions. Every generated program is deterministic in its seed.
+//! `fix` is left out, so that every generated program terminates.
+
+use ast;
+use ast::AST;
+use globals;
+use names;
+use names::Name;
+use types::Type;
+
+use num_bigint::BigInt;
+
+use std::rc::Rc;
+
+/// Parameter names. None of them shadow a global.
+const VARS: &[&str] = &["a", "b", "c", "f", "g", "h", "x", "y", "z"];
+
+const LOC: ast::Loc<'static> = ast::Loc {
+    file: "<gen>",
+    begin: 0,
+    end: 0,
+};
+
+/// A xorshift PRNG; statistical quality is not a concern here.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+type Scope = Vec<(Name<'static>, Rc<Type<'static>>)>;
+
+pub struct Generator {
+    rng: Rng,
+    globals: Scope,
+    max_depth: usize,
+}
+
+/// Peel `ty` as a function of `args.len()` arguments, returning whether its
+/// final result is `want`.
+fn returns(
+    ty: &Rc<Type<'static>>,
+    want: &Type<'static>,
+    args: &mut Vec<Rc<Type<'static>>>,
+) -> bool {
+    if **ty == *want {
+        return true;
+    }
+    match **ty {
+        Type::Function(ref dom, ref range) => {
+            args.push(Rc::clone(dom));
+            if returns(range, want, args) {
+                return true;
+            }
+            args.pop();
+            false
+        }
+        _ => false,
+    }
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        let env = globals::global_env();
+        let prims = globals::primitives();
+        // The primitives that are class methods, like `add`, are used at the
+        // primitive's type.
+        let mut globals: Scope = env
+            .bindings()
+            .into_iter()
+            .filter(|&(name, _)| matches!(*name, Name::Ident(_)) && *name != names::ident("fix"))
+            .map(|(name, ty)| match prims.iter().find(|prim| *name == names::ident(prim.name)) {
+                Some(prim) => (name.clone(), Rc::clone(&prim.ty)),
+                None => (name.clone(), Rc::clone(ty)),
+            })
+            .collect();
+        globals.sort_by_key(|(name, _)| name.to_string());
+        Generator {
+            // xorshift must not be seeded with zero.
+            rng: Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1),
+            globals,
+            max_depth: 4,
+        }
+    }
+
+    /// Generate a program along with the type it was built to have.
+    pub fn program(&mut self) -> (Box<AST<'static>>, Rc<Type<'static>>) {
+        let ty = self.ty(2);
+        let ast = self.expr(&ty, &Vec::new(), self.max_depth);
+        (Box::new(ast), ty)
+    }
+
+    fn ty(&mut self, depth: usize) -> Rc<Type<'static>> {
+        match self.rng.below(if depth == 0 { 2 } else { 4 }) {
+            0 => globals::integer(),
+            1 => globals::bool(),
+            _ => Rc::new(Type::Function(self.ty(depth - 1), self.ty(depth - 1))),
+        }
+    }
+
+    fn literal(&mut self, ty: &Type<'static>) -> Option<AST<'static>> {
+        match *ty {
+            Type::Primitive(names::Name::Type("int")) => {
+                let val = match self.rng.below(10) {
+                    // Large enough that any fixed-width arithmetic would
+                    // overflow.
+                    0 => BigInt::from(i64::MAX) * BigInt::from(self.rng.next()),
+                    1 => BigInt::from(i64::MIN) * BigInt::from(self.rng.next()),
+                    _ => BigInt::from(self.rng.below(2001) as i64 - 1000),
+                };
+                Some(AST::Integer(LOC, val))
+            }
+            Type::Primitive(names::Name::Type("bool")) => {
+                Some(AST::Boolean(LOC, self.rng.chance(50)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Every variable in scope or global whose type, applied to some number
+    /// of arguments, produces `ty`.
+    fn callees(
+        &self,
+        ty: &Type<'static>,
+        scope: &Scope,
+    ) -> Vec<(Name<'static>, Vec<Rc<Type<'static>>>)> {
+        let mut found = Vec::new();
+        for (i, (name, var_ty)) in scope.iter().enumerate() {
+            // Skip bindings shadowed by a later one.
+            if scope[i + 1..].iter().any(|(later, _)| later == name) {
+                continue;
+            }
+            let mut args = Vec::new();
+            if returns(var_ty, ty, &mut args) {
+                found.push((name.clone(), args));
+            }
+        }
+        for (name, var_ty) in &self.globals {
+            let mut args = Vec::new();
+            if returns(var_ty, ty, &mut args) {
+                found.push((name.clone(), args));
+            }
+        }
+        found
+    }
+
+    fn lambda(
+        &mut self,
+        dom: &Rc<Type<'static>>,
+        range: &Rc<Type<'static>>,
+        scope: &Scope,
+        depth: usize,
+    ) -> AST<'static> {
+        let name = names::ident(VARS[self.rng.below(VARS.len())]);
+        let mut inner = scope.clone();
+        inner.push((name.clone(), Rc::clone(dom)));
+        let body = self.expr(range, &inner, depth.saturating_sub(1));
+        let param = AST::Ascription(
+            LOC,
+            Box::new(AST::Variable(LOC, name)),
+            Box::new(type_ast(dom)),
+        );
+        AST::Abstraction(LOC, Box::new(param), Box::new(body))
+    }
+
+    fn call(
+        &mut self,
+        callee: Name<'static>,
+        args: Vec<Rc<Type<'static>>>,
+        scope: &Scope,
+        depth: usize,
+    ) -> AST<'static> {
+        let mut ast = AST::Variable(LOC, callee);
+        for arg in args {
+            let arg = self.expr(&arg, scope, depth.saturating_sub(1));
+            ast = AST::Application(LOC, Box::new(ast), Box::new(arg));
+        }
+        ast
+    }
+
+    /// Generate an expression of type `ty`.
+    fn expr(&mut self, ty: &Rc<Type<'static>>, scope: &Scope, depth: usize) -> AST<'static> {
+        let mut callees = self.callees(ty, scope);
+        if depth == 0 {
+            // Prefer leaves: variables that need no arguments, and literals.
+            callees.retain(|(_, args)| args.is_empty());
+            if !callees.is_empty() && self.rng.chance(50) {
+                let (name, _) = callees.swap_remove(self.rng.below(callees.len()));
+                return AST::Variable(LOC, name);
+            }
+            if let Some(lit) = self.literal(ty) {
+                return lit;
+            }
+            if !callees.is_empty() {
+                let (name, _) = callees.swap_remove(self.rng.below(callees.len()));
+                return AST::Variable(LOC, name);
+            }
+        }
+
+        match self.rng.below(10) {
+            0 | 1 if depth > 0 => {
+                let cond = self.expr(&globals::bool(), scope, depth - 1);
+                let cons = self.expr(ty, scope, depth - 1);
+                let alt = self.expr(ty, scope, depth - 1);
+                AST::If(LOC, Box::new(cond), Box::new(cons), Box::new(alt))
+            }
+            2 if depth > 0 => {
+                let dom = self.ty(1);
+                let func = self.lambda(&dom, ty, scope, depth);
+                let arg = self.expr(&dom, scope, depth - 1);
+                AST::Application(LOC, Box::new(func), Box::new(arg))
+            }
+            3 => {
+                let inner = self.expr(ty, scope, depth.saturating_sub(1));
+                AST::Ascription(LOC, Box::new(inner), Box::new(type_ast(ty)))
+            }
+            4..=7 if !callees.is_empty() => {
+                let (name, args) = callees.swap_remove(self.rng.below(callees.len()));
+                self.call(name, args, scope, depth)
+            }
+            _ => match **ty {
+                Type::Function(ref dom, ref range) => self.lambda(dom, range, scope, depth),
+                _ => self.literal(ty).unwrap(),
+            },
+        }
+    }
+}
+
+/// Build the type syntax for `ty`. A scheme's quantifier is left implicit,
+/// as it is in a signature.
+pub fn type_ast(ty: &Type<'static>) -> AST<'static> {
+    match *ty {
+        Type::Primitive(ref name) | Type::Variable(ref name) => AST::TyName(LOC, name.clone()),
+        Type::Function(ref dom, ref range) => {
+            AST::TyFn(LOC, Box::new(type_ast(dom)), Box::new(type_ast(range)))
+        }
+        Type::App(ref con, ref args) => AST::TyApp(
+            LOC,
+            con.clone(),
+            args.iter().map(|arg| Box::new(type_ast(arg))).collect(),
+        ),
+        Type::ForAll(_, ref body) => type_ast(body),
+        Type::Qualified(ref constraints, ref body) => AST::TyQual(
+            LOC,
+            constraints
+                .iter()
+                .map(|(class, ty)| (class.clone(), Box::new(type_ast(ty))))
+                .collect(),
+            Box::new(type_ast(body)),
+        ),
+    }
+}
@@ -1,25 +1,451 @@
 use names;
-use types::Type;
+use names::Name;
+use types::{Kind, Type};
 use env::TypeEnv;
+use eval;
+use eval::{Apply, Builtin, BuiltinFn, List, Trap, Value};
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, Zero};
 
 use std::rc::Rc;
 
+/// A primitive function: its name, its type, and its implementation.
+///
+/// Both `global_env` and `global_values` are built from `primitives`, so a
+/// primitive's type and its runtime behavior are defined side by side.
+pub struct Primitive {
+    pub name: &'static str,
+    pub ty: Rc<Type<'static>>,
+    pub func: for<'v> fn(&[Value<'v>]) -> Result<Value<'v>, Trap>,
+}
+
+impl Primitive {
+    /// The number of arguments the primitive takes before it runs: the
+    /// number of arrows along the spine of its type.
+    pub fn arity(&self) -> usize {
+        arity(&self.ty)
+    }
+}
+
+/// A polymorphic primitive, on lists, options or functions. Those that take
+/// functions, like `map`, apply them through the `Apply` they are given.
+pub struct PolyPrimitive {
+    pub name: &'static str,
+    /// A type scheme over the types it works on.
+    pub ty: Rc<Type<'static>>,
+    pub func: for<'v> fn(&[Value<'v>], &mut dyn Apply<'v>) -> Result<Value<'v>, Trap>,
+}
+
+impl PolyPrimitive {
+    pub fn arity(&self) -> usize {
+        arity(&self.ty)
+    }
+}
+
+fn arity(mut ty: &Rc<Type<'static>>) -> usize {
+    if let Type::ForAll(_, ref body) = **ty {
+        ty = body;
+    }
+    let mut arity = 0;
+    while let Type::Function(_, ref range) = **ty {
+        arity += 1;
+        ty = range;
+    }
+    arity
+}
+
+fn func(args: &[Rc<Type<'static>>], ret: Rc<Type<'static>>) -> Rc<Type<'static>> {
+    args.iter()
+        .rev()
+        .fold(ret, |ty, arg| Rc::new(Type::Function(Rc::clone(arg), ty)))
+}
+
+fn prim(
+    name: &'static str,
+    ty: Rc<Type<'static>>,
+    func: for<'v> fn(&[Value<'v>]) -> Result<Value<'v>, Trap>,
+) -> Primitive {
+    Primitive { name, ty, func }
+}
+
+fn int<'v>(val: BigInt) -> Result<Value<'v>, Trap> {
+    Ok(Value::Integer(val))
+}
+
+fn boolean<'v>(val: bool) -> Result<Value<'v>, Trap> {
+    Ok(Value::Boolean(val))
+}
+
+/// Every primitive in the prelude.
+///
+/// Division rounds towards negative infinity, and `mod` takes the sign of
+/// the divisor, so that `add(mul(div(x, y), y), mod(x, y))` is always `x`.
+/// Dividing by zero fails with `Trap::DivisionByZero`.
+pub fn primitives() -> Vec<Primitive> {
+    let i = integer();
+    let b = bool();
+    let int_int = func(&[Rc::clone(&i)], Rc::clone(&i));
+    let int_int_int = func(&[Rc::clone(&i), Rc::clone(&i)], Rc::clone(&i));
+    let int_int_bool = func(&[Rc::clone(&i), Rc::clone(&i)], Rc::clone(&b));
+    let bool_bool_bool = func(&[Rc::clone(&b), Rc::clone(&b)], Rc::clone(&b));
+    vec![
+        prim("add", Rc::clone(&int_int_int), |args| int(int_arg(&args[0])? + int_arg(&args[1])?)),
+        prim("sub", Rc::clone(&int_int_int), |args| int(int_arg(&args[0])? - int_arg(&args[1])?)),
+        prim("mul", Rc::clone(&int_int_int), |args| int(int_arg(&args[0])? * int_arg(&args[1])?)),
+        prim("div", Rc::clone(&int_int_int), |args| {
+            int(int_arg(&args[0])?.div_floor(divisor(&args[1])?))
+        }),
+        prim("mod", Rc::clone(&int_int_int), |args| {
+            int(int_arg(&args[0])?.mod_floor(divisor(&args[1])?))
+        }),
+        prim("min", Rc::clone(&int_int_int), |args| {
+            int(int_arg(&args[0])?.min(int_arg(&args[1])?).clone())
+        }),
+        prim("max", Rc::clone(&int_int_int), |args| {
+            int(int_arg(&args[0])?.max(int_arg(&args[1])?).clone())
+        }),
+        prim("neg", Rc::clone(&int_int), |args| int(-int_arg(&args[0])?.clone())),
+        prim("abs", Rc::clone(&int_int), |args| int(int_arg(&args[0])?.abs())),
+        prim("inc", Rc::clone(&int_int), |args| int(int_arg(&args[0])? + 1)),
+        prim("dec", Rc::clone(&int_int), |args| int(int_arg(&args[0])? - 1)),
+        prim("iszero", func(&[Rc::clone(&i)], Rc::clone(&b)), |args| {
+            boolean(int_arg(&args[0])?.is_zero())
+        }),
+        prim("eq", Rc::clone(&int_int_bool), |args| boolean(int_arg(&args[0])? == int_arg(&args[1])?)),
+        prim("lt", Rc::clone(&int_int_bool), |args| boolean(int_arg(&args[0])? < int_arg(&args[1])?)),
+        prim("le", Rc::clone(&int_int_bool), |args| boolean(int_arg(&args[0])? <= int_arg(&args[1])?)),
+        prim("gt", Rc::clone(&int_int_bool), |args| boolean(int_arg(&args[0])? > int_arg(&args[1])?)),
+        prim("ge", Rc::clone(&int_int_bool), |args| boolean(int_arg(&args[0])? >= int_arg(&args[1])?)),
+        prim("not", func(&[Rc::clone(&b)], Rc::clone(&b)), |args| boolean(!bool_arg(&args[0])?)),
+        prim("and", Rc::clone(&bool_bool_bool), |args| boolean(bool_arg(&args[0])? && bool_arg(&args[1])?)),
+        prim("or", Rc::clone(&bool_bool_bool), |args| boolean(bool_arg(&args[0])? || bool_arg(&args[1])?)),
+        prim("xor", Rc::clone(&bool_bool_bool), |args| boolean(bool_arg(&args[0])? != bool_arg(&args[1])?)),
+    ]
+}
+
+/// The type constructors, and their kinds.
+pub fn type_constructors() -> Vec<(&'static str, Kind)> {
+    vec![
+        ("list", Kind::of_arity(1)),
+        ("option", Kind::of_arity(1)),
+        ("map", Kind::of_arity(2)),
+    ]
+}
+
+pub fn list(elem: Rc<Type<'static>>) -> Rc<Type<'static>> {
+    Rc::new(Type::App(names::typ("list"), vec![elem]))
+}
+
+pub fn option(elem: Rc<Type<'static>>) -> Rc<Type<'static>> {
+    Rc::new(Type::App(names::typ("option"), vec![elem]))
+}
+
+fn var(name: &'static str) -> Rc<Type<'static>> {
+    Rc::new(Type::Variable(names::typ(name)))
+}
+
+/// `ty` for any types in place of `vars`.
+fn scheme(vars: &[&'static str], ty: Rc<Type<'static>>) -> Rc<Type<'static>> {
+    Rc::new(Type::ForAll(vars.iter().map(|name| var(name)).collect(), ty))
+}
+
+/// The empty list, `nil : forall a. list<a>`, which `[]` stands for.
+pub fn nil() -> (Rc<Type<'static>>, Value<'static>) {
+    (scheme(&["a"], list(var("a"))), Value::List(List::nil()))
+}
+
+/// The missing value, `none : forall a. option<a>`.
+pub fn none() -> (Rc<Type<'static>>, Value<'static>) {
+    (scheme(&["a"], option(var("a"))), Value::Option(None))
+}
+
+fn list_arg<'b, 'v>(val: &'b Value<'v>) -> Result<&'b List<'v>, Trap> {
+    match *val {
+        Value::List(ref list) => Ok(list),
+        _ => Err(Trap::BadArgument),
+    }
+}
+
+/// Every primitive on lists. `fold` folds from the right:
+/// `fold(f, z, [x, y])` is `f(x, f(y, z))`.
+pub fn list_primitives() -> Vec<PolyPrimitive> {
+    let a = var("a");
+    let b = var("b");
+    let list_a = list(Rc::clone(&a));
+    let list_b = list(Rc::clone(&b));
+    let a_b = func(&[Rc::clone(&a)], Rc::clone(&b));
+    vec![
+        PolyPrimitive {
+            name: "cons",
+            ty: scheme(&["a"], func(&[Rc::clone(&a), Rc::clone(&list_a)], Rc::clone(&list_a))),
+            func: |args, _| Ok(Value::List(List::cons(args[0].clone(), list_arg(&args[1])?.clone()))),
+        },
+        PolyPrimitive {
+            name: "head",
+            ty: scheme(&["a"], func(&[Rc::clone(&list_a)], Rc::clone(&a))),
+            func: |args, _| match list_arg(&args[0])?.uncons() {
+                Some((head, _)) => Ok(head.clone()),
+                None => Err(Trap::EmptyList),
+            },
+        },
+        PolyPrimitive {
+            name: "tail",
+            ty: scheme(&["a"], func(&[Rc::clone(&list_a)], Rc::clone(&list_a))),
+            func: |args, _| match list_arg(&args[0])?.uncons() {
+                Some((_, tail)) => Ok(Value::List(tail.clone())),
+                None => Err(Trap::EmptyList),
+            },
+        },
+        PolyPrimitive {
+            name: "isnil",
+            ty: scheme(&["a"], func(&[Rc::clone(&list_a)], bool())),
+            func: |args, _| boolean(list_arg(&args[0])?.is_nil()),
+        },
+        PolyPrimitive {
+            name: "map",
+            ty: scheme(&["a", "b"], func(&[Rc::clone(&a_b), Rc::clone(&list_a)], Rc::clone(&list_b))),
+            func: |args, apply| {
+                let mut vals = Vec::new();
+                for val in list_arg(&args[1])?.iter() {
+                    vals.push(apply.apply(args[0].clone(), val.clone())?);
+                }
+                Ok(Value::List(vals.into_iter().rev().fold(List::nil(), |tail, head| List::cons(head, tail))))
+            },
+        },
+        PolyPrimitive {
+            name: "fold",
+            ty: scheme(
+                &["a", "b"],
+                func(
+                    &[func(&[Rc::clone(&a), Rc::clone(&b)], Rc::clone(&b)), Rc::clone(&b), Rc::clone(&list_a)],
+                    Rc::clone(&b),
+                ),
+            ),
+            func: |args, apply| {
+                let vals: Vec<&Value> = list_arg(&args[2])?.iter().collect();
+                let mut acc = args[1].clone();
+                for val in vals.into_iter().rev() {
+                    let partial = apply.apply(args[0].clone(), val.clone())?;
+                    acc = apply.apply(partial, acc)?;
+                }
+                Ok(acc)
+            },
+        },
+    ]
+}
+
+fn option_arg<'b, 'v>(val: &'b Value<'v>) -> Result<&'b Option<Rc<Value<'v>>>, Trap> {
+    match *val {
+        Value::Option(ref val) => Ok(val),
+        _ => Err(Trap::BadArgument),
+    }
+}
+
+/// Every primitive on options. `option(d, f, o)` is `f` applied to the
+/// value in `o`, or `d` if there is none.
+pub fn option_primitives() -> Vec<PolyPrimitive> {
+    let a = var("a");
+    let b = var("b");
+    let option_a = option(Rc::clone(&a));
+    let a_b = func(&[Rc::clone(&a)], Rc::clone(&b));
+    vec![
+        PolyPrimitive {
+            name: "some",
+            ty: scheme(&["a"], func(&[Rc::clone(&a)], Rc::clone(&option_a))),
+            func: |args, _| Ok(Value::Option(Some(Rc::new(args[0].clone())))),
+        },
+        PolyPrimitive {
+            name: "option",
+            ty: scheme(&["a", "b"], func(&[Rc::clone(&b), a_b, option_a], Rc::clone(&b))),
+            func: |args, apply| match *option_arg(&args[2])? {
+                Some(ref val) => apply.apply(args[1].clone(), (**val).clone()),
+                None => Ok(args[0].clone()),
+            },
+        },
+    ]
+}
+
+/// `fix(f, x)` is `f(fix(f), x)`. Definitions cannot refer to themselves,
+/// so this is how a program recurses.
+pub fn fix() -> PolyPrimitive {
+    fn call<'v>(args: &[Value<'v>], apply: &mut dyn Apply<'v>) -> Result<Value<'v>, Trap> {
+        let this = Rc::new(Builtin {
+            name: "fix",
+            arity: 2,
+            func: Rc::new(call),
+        });
+        let body = apply.apply(args[0].clone(), Value::Builtin(this, vec![args[0].clone()]))?;
+        apply.apply(body, args[1].clone())
+    }
+    let a_b = func(&[var("a")], var("b"));
+    PolyPrimitive {
+        name: "fix",
+        ty: scheme(&["a", "b"], func(&[func(&[Rc::clone(&a_b)], Rc::clone(&a_b)), var("a")], var("b"))),
+        func: call,
+    }
+}
+
+/// A class every program can use, with or without the standard library,
+/// and its instances for the primitive types.
+pub struct CoreClass {
+    pub name: &'static str,
+    pub var: &'static str,
+    /// Each method's name and type scheme, `forall a. Class a => ty`.
+    pub methods: Vec<(&'static str, Rc<Type<'static>>)>,
+    pub instances: Vec<CoreInstance>,
+}
+
+/// An instance of a `CoreClass`.
+pub struct CoreInstance {
+    pub ty: Rc<Type<'static>>,
+    /// The name its dictionary is bound to in `global_values`.
+    pub dict: &'static str,
+    /// The binding in `global_values` that defines each of the class's
+    /// methods, in order.
+    pub methods: Vec<Name<'static>>,
+}
+
+thread_local! {
+    static CLASSES: Rc<Vec<CoreClass>> = Rc::new(core_classes());
+}
+
+/// `Eq`, whose method is `eq`, with instances for `int` and `bool`, and
+/// `Num`, whose methods are `add`, `sub` and `mul`, with an instance for
+/// `int`. The methods of the `int` instances are the primitives of the
+/// same names.
+///
+/// Every call returns the same schemes, so a typechecker can tell a use of
+/// a method from a use of another binding with the same type.
+pub fn classes() -> Rc<Vec<CoreClass>> {
+    CLASSES.with(Rc::clone)
+}
+
+fn core_classes() -> Vec<CoreClass> {
+    let method = |class: &'static str, ty: Rc<Type<'static>>| {
+        let constraint = vec![(names::typ(class), var("a"))];
+        scheme(&["a"], Rc::new(Type::Qualified(constraint, ty)))
+    };
+    let a_a_bool = func(&[var("a"), var("a")], bool());
+    let a_a_a = func(&[var("a"), var("a")], var("a"));
+    vec![
+        CoreClass {
+            name: "Eq",
+            var: "a",
+            methods: vec![("eq", method("Eq", a_a_bool))],
+            instances: vec![
+                CoreInstance {
+                    ty: integer(),
+                    dict: "Eq<int>",
+                    methods: vec![names::ident("eq")],
+                },
+                CoreInstance {
+                    ty: bool(),
+                    dict: "Eq<bool>",
+                    methods: vec![names::method(names::ident("Eq<bool>"), 0)],
+                },
+            ],
+        },
+        CoreClass {
+            name: "Num",
+            var: "a",
+            methods: vec![
+                ("add", method("Num", Rc::clone(&a_a_a))),
+                ("sub", method("Num", Rc::clone(&a_a_a))),
+                ("mul", method("Num", a_a_a)),
+            ],
+            instances: vec![CoreInstance {
+                ty: integer(),
+                dict: "Num<int>",
+                methods: ["add", "sub", "mul"].iter().map(|name| names::ident(name)).collect(),
+            }],
+        },
+    ]
+}
+
+/// The values `global_values` binds besides the prelude's: the dictionary
+/// of each instance of a core class, and the definitions of its methods
+/// that are not primitives. No program can name them; `typecheck` makes a
+/// program use them in place of the methods it calls.
+pub fn instance_values() -> Vec<(Name<'static>, Value<'static>)> {
+    let bool_eq: BuiltinFn = Rc::new(|args: &[Value], _: &mut dyn Apply| {
+        boolean(bool_arg(&args[0])? == bool_arg(&args[1])?)
+    });
+    let mut defs = vec![(names::method(names::ident("Eq<bool>"), 0), builtin("eq", 2, bool_eq))];
+    let mut binds = defs.clone();
+    defs.extend(prelude().into_iter().map(|(name, _, val)| (names::ident(name), val)));
+    for class in classes().iter() {
+        for inst in &class.instances {
+            let methods: Vec<Rc<Builtin>> = inst
+                .methods
+                .iter()
+                .map(|method| match defs.iter().find(|(name, _)| name == method) {
+                    Some((_, Value::Builtin(ref func, _))) => Rc::clone(func),
+                    _ => unreachable!("core methods are builtins"),
+                })
+                .collect();
+            binds.push((names::ident(inst.dict), dictionary(inst.dict, methods)));
+        }
+    }
+    binds
+}
+
+/// The dictionary `name` holding `methods`, which passes them, in order, to
+/// its argument.
+fn dictionary(name: &'static str, methods: Vec<Rc<Builtin>>) -> Value<'static> {
+    let func: BuiltinFn = Rc::new(move |args: &[Value], apply: &mut dyn Apply| {
+        methods.iter().try_fold(args[0].clone(), |select, method| {
+            apply.apply(select, Value::Builtin(Rc::clone(method), vec![]))
+        })
+    });
+    builtin(name, 1, func)
+}
+
+/// Every binding in the prelude: the primitives, the primitives on lists
+/// and options, `fix`, `nil` and `none`, with their types and values. The
+/// primitives that are methods of a core class, like `add`, have the
+/// method's type scheme.
+pub fn prelude() -> Vec<(&'static str, Rc<Type<'static>>, Value<'static>)> {
+    let classes = classes();
+    let mut binds = Vec::new();
+    for prim in primitives() {
+        let arity = prim.arity();
+        let func = prim.func;
+        let func: BuiltinFn = Rc::new(move |args: &[Value], _: &mut dyn Apply| func(args));
+        let ty = classes
+            .iter()
+            .flat_map(|class| class.methods.iter())
+            .find(|(method, _)| *method == prim.name)
+            .map_or(prim.ty, |(_, scheme)| Rc::clone(scheme));
+        binds.push((prim.name, ty, builtin(prim.name, arity, func)));
+    }
+    let poly = list_primitives().into_iter().chain(option_primitives()).chain(Some(fix()));
+    for prim in poly {
+        let arity = prim.arity();
+        let func: BuiltinFn = Rc::new(prim.func);
+        binds.push((prim.name, prim.ty, builtin(prim.name, arity, func)));
+    }
+    let (ty, val) = nil();
+    binds.push(("nil", ty, val));
+    let (ty, val) = none();
+    binds.push(("none", ty, val));
+    binds
+}
+
+/// A builtin named `name` that runs `func` once applied to `arity`
+/// arguments.
+pub fn builtin(name: &'static str, arity: usize, func: BuiltinFn) -> Value<'static> {
+    Value::Builtin(Rc::new(Builtin { name, arity, func }), vec![])
+}
+
+/// The types of the bindings in `prelude`, and of the primitive types.
 pub fn global_env() -> Rc<TypeEnv<'static>> {
-    let unary_int = Rc::new(Type::Function(integer(), integer()));
-    let binary_int = Rc::new(Type::Function(integer(), Rc::clone(&unary_int)));
-    TypeEnv::from_bindings(&[
-        (names::typ("bool"), bool()),
-        (names::typ("int"), integer()),
-        (names::ident("add"), Rc::clone(&binary_int)),
-        (names::ident("sub"), Rc::clone(&binary_int)),
-        (names::ident("mul"), Rc::clone(&binary_int)),
-        (names::ident("dec"), Rc::clone(&unary_int)),
-        (
-            names::ident("iszero"),
-            Rc::new(Type::Function(integer(), bool())),
-        ),
-        (names::ident("not"), Rc::new(Type::Function(bool(), bool()))),
-    ])
+    let mut binds = vec![(names::typ("bool"), bool()), (names::typ("int"), integer())];
+    for (name, ty, _) in prelude() {
+        binds.push((names::ident(name), ty));
+    }
+    TypeEnv::from_bindings(&binds)
 }
 
 pub fn bool() -> Rc<Type<'static>> {
@@ -29,3 +455,37 @@ pub fn bool() -> Rc<Type<'static>> {
 pub fn integer() -> Rc<Type<'static>> {
     Rc::new(Type::Primitive(names::typ("int")))
 }
+
+fn int_arg<'b>(val: &'b Value) -> Result<&'b BigInt, Trap> {
+    match *val {
+        Value::Integer(ref i) => Ok(i),
+        _ => Err(Trap::BadArgument),
+    }
+}
+
+/// The divisor `div` or `mod` was given, unless it is zero.
+fn divisor<'b>(val: &'b Value) -> Result<&'b BigInt, Trap> {
+    match int_arg(val)? {
+        i if i.is_zero() => Err(Trap::DivisionByZero),
+        i => Ok(i),
+    }
+}
+
+fn bool_arg(val: &Value) -> Result<bool, Trap> {
+    match *val {
+        Value::Boolean(b) => Ok(b),
+        _ => Err(Trap::BadArgument),
+    }
+}
+
+/// The runtime values of the bindings in `global_env`, and the
+/// `instance_values`.
+pub fn global_values() -> Rc<eval::Env<'static>> {
+    eval::Env::from_bindings(
+        prelude()
+            .into_iter()
+            .map(|(name, _, val)| (names::ident(name), val))
+            .chain(instance_values())
+            .collect(),
+    )
+}
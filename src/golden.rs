@@ -0,0 +1,211 @@
+//! A golden-test runner for directories of `.gol` programs.
+//!
+//! Every `NAME.gol` under the directory is checked against the expectation
+//! files next to it:
+//!
+//! - `NAME.expect` holds the type the program must have, in type syntax.
+//! - `NAME.out` holds what a program that typechecks must evaluate to, as
+//!   the evaluator prints it: `42`, or `<fn>`. If evaluation fails, it holds
+//!   the error in the same form as `NAME.err`.
+//! - `NAME.err` holds the error the program must fail with, as the error's
+//!   kind and the line and column it points at: `Mismatch 2:5`. Parse errors
+//!   are reported the same way, with kinds like `Syntax`.
+//!
+//! A program with no expectation file fails, as does one that typechecks
+//! but has no `NAME.out`. In bless mode, the runner
+//! instead rewrites each program's expectations to match what it observed.
+
+use diagnostics::Diagnostic;
+use eval;
+use globals;
+use parser;
+use pretty;
+use typecheck;
+
+use glob::glob;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What a program did when checked.
+#[derive(Debug, PartialEq)]
+enum Observed {
+    /// The program's type, and what it evaluated to.
+    Type(String, String),
+    Error(String),
+}
+
+fn describe(diag: &Diagnostic) -> String {
+    format!("{} {}:{}", diag.kind, diag.start.line, diag.start.column)
+}
+
+impl Observed {
+    fn error(diag: &Diagnostic) -> Observed {
+        Observed::Error(describe(diag))
+    }
+}
+
+fn observe(path: &Path, src: &str) -> Observed {
+    let name = path.to_string_lossy();
+    let ast = match parser::parse(&name, src) {
+        Ok(ast) => ast,
+        Err(err) => return Observed::error(&Diagnostic::parse(src, &err)),
+    };
+    let limits = typecheck::Limits::default();
+    let (ty, elaborated) = match typecheck::check_program(&globals::global_env(), &ast, &limits) {
+        Ok(checked) => checked,
+        Err(err) => return Observed::error(&Diagnostic::typecheck(src, &err)),
+    };
+    let program = elaborated.as_ref().unwrap_or(&ast);
+    let out = match eval::eval_traced(&globals::global_values(), program, &eval::Limits::standard()) {
+        Ok(val) => val.to_string(),
+        Err(failure) => describe(&Diagnostic::failure(src, &failure)),
+    };
+    Observed::Type(ty.to_string(), out)
+}
+
+/// Put an expected type into the form `Type`'s `Display` produces. Types
+/// that mention inferred type variables cannot be parsed, and are compared
+/// verbatim.
+fn normalize_type(path: &Path, text: &str) -> String {
+    let name = path.to_string_lossy();
+    match parser::parse_type(&name, text.trim()) {
+        Ok(ty) => pretty::pretty(&ty),
+        Err(_) => text.trim().to_string(),
+    }
+}
+
+fn read_opt(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_opt(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+/// The result of running a single program.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    /// Bless mode rewrote the program's expectations.
+    Blessed,
+}
+
+pub struct Report {
+    pub results: Vec<(PathBuf, Outcome)>,
+}
+
+impl Report {
+    pub fn failures(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, outcome)| matches!(*outcome, Outcome::Fail(_)))
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (path, outcome) in &self.results {
+            match *outcome {
+                Outcome::Pass => writeln!(f, "PASS {}", path.display())?,
+                Outcome::Blessed => writeln!(f, "BLESS {}", path.display())?,
+                Outcome::Fail(ref why) => writeln!(f, "FAIL {}: {}", path.display(), why)?,
+            }
+        }
+        let failed = self.failures();
+        write!(f, "{} passed, {} failed", self.results.len() - failed, failed)
+    }
+}
+
+fn check(path: &Path, observed: &Observed) -> io::Result<Outcome> {
+    let expect = read_opt(&path.with_extension("expect"))?;
+    let err = read_opt(&path.with_extension("err"))?;
+    let outcome = match (observed, expect, err) {
+        (_, None, None) => Outcome::Fail("no .expect or .err file".to_string()),
+        (Observed::Type(got, out), Some(want), _) => {
+            let want = normalize_type(path, &want);
+            if *got != want {
+                Outcome::Fail(format!("expected type `{}`, got `{}`", want, got))
+            } else {
+                match read_opt(&path.with_extension("out"))? {
+                    None => Outcome::Fail("no .out file".to_string()),
+                    Some(ref want) if want.trim() == out => Outcome::Pass,
+                    Some(want) => Outcome::Fail(format!(
+                        "expected output `{}`, got `{}`",
+                        want.trim(),
+                        out
+                    )),
+                }
+            }
+        }
+        (Observed::Type(got, _), None, Some(want)) => Outcome::Fail(format!(
+            "expected error `{}`, but it typechecked as `{}`",
+            want.trim(),
+            got
+        )),
+        (Observed::Error(got), _, Some(want)) if got == want.trim() => Outcome::Pass,
+        (Observed::Error(got), _, Some(want)) => {
+            Outcome::Fail(format!("expected error `{}`, got `{}`", want.trim(), got))
+        }
+        (Observed::Error(got), Some(_), None) => {
+            Outcome::Fail(format!("expected a type, got error `{}`", got))
+        }
+    };
+    Ok(outcome)
+}
+
+fn bless(path: &Path, observed: &Observed) -> io::Result<Outcome> {
+    if check(path, observed)? == Outcome::Pass {
+        return Ok(Outcome::Pass);
+    }
+    match *observed {
+        Observed::Type(ref ty, ref out) => {
+            // Keep an expected type that matches, however it is written.
+            let expect = path.with_extension("expect");
+            if read_opt(&expect)?.map(|want| normalize_type(path, &want)).as_ref() != Some(ty) {
+                fs::write(expect, format!("{}\n", ty))?;
+            }
+            fs::write(path.with_extension("out"), format!("{}\n", out))?;
+            remove_opt(&path.with_extension("err"))?;
+        }
+        Observed::Error(ref err) => {
+            fs::write(path.with_extension("err"), format!("{}\n", err))?;
+            remove_opt(&path.with_extension("expect"))?;
+            remove_opt(&path.with_extension("out"))?;
+        }
+    }
+    Ok(Outcome::Blessed)
+}
+
+/// Run every `.gol` program under `dir`. If `bless_mode` is set, update the
+/// expectations of any that do not match instead of failing them.
+pub fn run(dir: &Path, bless_mode: bool) -> io::Result<Report> {
+    let pattern = dir.join("**").join("*.gol");
+    let paths = glob(&pattern.to_string_lossy())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+
+    let mut results = Vec::new();
+    for path in paths {
+        let path = path.map_err(|e| io::Error::other(e.to_string()))?;
+        let src = fs::read_to_string(&path)?;
+        let observed = observe(&path, &src);
+        let outcome = if bless_mode {
+            bless(&path, &observed)?
+        } else {
+            check(&path, &observed)?
+        };
+        results.push((path, outcome));
+    }
+    Ok(Report { results })
+}
@@ -3,6 +3,15 @@
 extern crate pest;
 #[macro_use]
 extern crate pest_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate glob;
+extern crate num_bigint;
+extern crate num_integer;
+extern crate num_traits;
+extern crate wat;
+extern crate typed_arena;
+extern crate stacker;
 
 pub mod names;
 pub mod parser;
@@ -11,5 +20,25 @@ pub mod types;
 pub mod env;
 pub mod globals;
 pub mod typecheck;
+pub mod module;
+pub mod stdlib;
+pub mod eval;
+pub mod step;
+pub mod debugger;
+pub mod engine;
+pub mod diagnostics;
+pub mod emit;
+pub mod dot;
+pub mod lift;
+pub mod anf;
+pub mod peval;
+pub mod nbe;
+pub mod cgen;
+pub mod wasm;
+pub mod pretty;
+pub mod query;
+pub mod lsp;
+pub mod golden;
+pub mod gen;
 
-const VERSION: &'static str = "0.0.1";
+const VERSION: &str = "0.0.1";
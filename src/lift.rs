@@ -0,0 +1,362 @@
+//! Closure conversion and lambda lifting.
+//!
+//! `lift` turns a typechecked program into a first-order IR: every `fn` in
+//! the source becomes a top-level `Function` that takes its captured
+//! variables as an explicit environment record alongside its parameter, and
+//! each place a `fn` appeared builds a closure from a function and the
+//! values it captures.
+//!
+//! Inside a function, a variable is one of
+//!
+//! - the function's own parameter (`Param`),
+//! - a slot of its environment record (`Env`), or
+//! - a binding from the global environment (`Global`).
+//!
+//! Printed, functions read
+//!
+//! ```text
+//! fn f0(env [y], x) : int -> int {
+//!   @add(x)(env.y)
+//! }
+//! ```
+//!
+//! where globals are marked with `@`, environment slots with `env.`, and
+//! `closure f0 [a, env.b]` builds a closure of `f0` capturing the values of
+//! `a` and `env.b`.
+
+use ast::AST;
+use env::TypeEnv;
+use names::Name;
+use query;
+use typecheck;
+use typecheck::{NodeTypes, TypeError};
+use types::Type;
+
+use num_bigint::BigInt;
+
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr<'a> {
+    Int(BigInt),
+    Bool(bool),
+    /// The parameter of the enclosing function.
+    Param(Name<'a>),
+    /// A slot of the enclosing function's environment record, and the name
+    /// of the variable it holds.
+    Env(usize, Name<'a>),
+    Global(Name<'a>),
+    /// A closure of `functions[index]`, capturing the given values in the
+    /// order of the function's `captures`.
+    Closure(usize, Vec<Expr<'a>>),
+    Apply(Box<Expr<'a>>, Box<Expr<'a>>),
+    If(Box<Expr<'a>>, Box<Expr<'a>>, Box<Expr<'a>>),
+}
+
+#[derive(Debug)]
+pub struct Function<'a> {
+    pub name: String,
+    /// The variables the function's environment record holds.
+    pub captures: Vec<Name<'a>>,
+    pub param: Name<'a>,
+    /// The type of the function, as a closure.
+    pub ty: Rc<Type<'a>>,
+    pub body: Expr<'a>,
+}
+
+#[derive(Debug)]
+pub struct Program<'a> {
+    /// Lifted functions, innermost first.
+    pub functions: Vec<Function<'a>>,
+    pub main: Expr<'a>,
+    pub ty: Rc<Type<'a>>,
+}
+
+/// The variables `ast` refers to without binding them, in the order they
+/// first appear.
+pub fn free_vars<'a>(ast: &AST<'a>) -> Vec<Name<'a>> {
+    fn walk<'a>(ast: &AST<'a>, bound: &mut Vec<Name<'a>>, free: &mut Vec<Name<'a>>) {
+        match *ast {
+            AST::Variable(_, ref name) => {
+                if !bound.contains(name) && !free.contains(name) {
+                    free.push(name.clone());
+                }
+            }
+            AST::Abstraction(_, ref param, ref body) => {
+                if let AST::Variable(_, ref name) = *query::binder(param) {
+                    bound.push(name.clone());
+                    walk(body, bound, free);
+                    bound.pop();
+                }
+            }
+            AST::Ascription(_, ref expr, _) => walk(expr, bound, free),
+            _ => {
+                for child in ast.children() {
+                    walk(child, bound, free);
+                }
+            }
+        }
+    }
+    let mut free = Vec::new();
+    walk(ast, &mut Vec::new(), &mut free);
+    free
+}
+
+/// The function being converted, and the names in scope around it.
+struct Frame<'a> {
+    param: Option<Name<'a>>,
+    captures: Vec<Name<'a>>,
+    /// Every variable bound by an enclosing `fn`, innermost last.
+    scope: Vec<Name<'a>>,
+}
+
+impl<'a> Frame<'a> {
+    fn resolve(&self, name: &Name<'a>) -> Expr<'a> {
+        if self.param.as_ref() == Some(name) {
+            Expr::Param(name.clone())
+        } else if let Some(i) = self.captures.iter().position(|cap| cap == name) {
+            Expr::Env(i, name.clone())
+        } else {
+            Expr::Global(name.clone())
+        }
+    }
+}
+
+struct Lifter<'a, 't> {
+    types: &'t NodeTypes<'a>,
+    functions: Vec<Function<'a>>,
+}
+
+impl<'a, 't> Lifter<'a, 't> {
+    fn convert(&mut self, frame: &Frame<'a>, ast: &AST<'a>) -> Result<Expr<'a>, TypeError<'a>> {
+        let expr = match *ast {
+            AST::Integer(_, ref val) => Expr::Int(val.clone()),
+            AST::Boolean(_, val) => Expr::Bool(val),
+            AST::Variable(_, ref name) => frame.resolve(name),
+            AST::Application(_, ref func, ref arg) => Expr::Apply(
+                Box::new(self.convert(frame, func)?),
+                Box::new(self.convert(frame, arg)?),
+            ),
+            AST::If(_, ref cond, ref cons, ref alt) => Expr::If(
+                Box::new(self.convert(frame, cond)?),
+                Box::new(self.convert(frame, cons)?),
+                Box::new(self.convert(frame, alt)?),
+            ),
+            AST::Ascription(_, ref expr, _) => return self.convert(frame, expr),
+            AST::Abstraction(_, ref param, ref body) => {
+                let param = match *query::binder(param) {
+                    AST::Variable(_, ref name) => name.clone(),
+                    _ => panic!("lift: malformed parameter"),
+                };
+                let captures: Vec<Name<'a>> = free_vars(ast)
+                    .into_iter()
+                    .filter(|name| frame.scope.contains(name))
+                    .collect();
+                let values = captures.iter().map(|name| frame.resolve(name)).collect();
+
+                let mut scope = frame.scope.clone();
+                scope.push(param.clone());
+                let inner = Frame {
+                    param: Some(param.clone()),
+                    captures: captures.clone(),
+                    scope,
+                };
+                let body = self.convert(&inner, body)?;
+                let index = self.functions.len();
+                self.functions.push(Function {
+                    name: format!("f{}", index),
+                    captures,
+                    param,
+                    ty: self.types.get(ast).expect("lift: untyped abstraction"),
+                    body,
+                });
+                Expr::Closure(index, values)
+            }
+            AST::TyName(ref loc, _)
+            | AST::TyFn(ref loc, _, _)
+            | AST::TyApp(ref loc, _, _)
+            | AST::TyQual(ref loc, _, _) => {
+                return Err(TypeError::Generic(loc.clone(), "a type is not an expression"))
+            }
+        };
+        Ok(expr)
+    }
+}
+
+/// Typecheck `ast` against `env`, then closure-convert and lambda-lift it.
+pub fn lift<'a>(env: &Rc<TypeEnv<'a>>, ast: &AST<'a>) -> Result<Program<'a>, TypeError<'a>> {
+    let (ty, types) = typecheck::typecheck_nodes(env, ast)?;
+    lift_typed(ast, ty, &types)
+}
+
+/// Lift `ast` for a backend that supports only the primitives, checking it
+/// with `typecheck::typecheck_primitive`.
+pub fn lift_primitive<'a>(ast: &AST<'a>) -> Result<Program<'a>, TypeError<'a>> {
+    let (ty, types) = typecheck::typecheck_primitive(ast)?;
+    lift_typed(ast, ty, &types)
+}
+
+fn lift_typed<'a>(
+    ast: &AST<'a>,
+    ty: Rc<Type<'a>>,
+    types: &NodeTypes<'a>,
+) -> Result<Program<'a>, TypeError<'a>> {
+    let mut lifter = Lifter {
+        types,
+        functions: Vec::new(),
+    };
+    let top = Frame {
+        param: None,
+        captures: Vec::new(),
+        scope: Vec::new(),
+    };
+    let main = lifter.convert(&top, ast)?;
+    Ok(Program {
+        functions: lifter.functions,
+        main,
+        ty,
+    })
+}
+
+struct ExprDisplay<'e, 'a: 'e> {
+    expr: &'e Expr<'a>,
+    functions: &'e [Function<'a>],
+}
+
+impl<'e, 'a> ExprDisplay<'e, 'a> {
+    fn sub(&self, expr: &'e Expr<'a>) -> ExprDisplay<'e, 'a> {
+        ExprDisplay {
+            expr,
+            functions: self.functions,
+        }
+    }
+}
+
+impl<'e, 'a> fmt::Display for ExprDisplay<'e, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self.expr {
+            Expr::Int(ref val) => val.fmt(f),
+            Expr::Bool(val) => val.fmt(f),
+            Expr::Param(ref name) => name.fmt(f),
+            Expr::Env(_, ref name) => write!(f, "env.{}", name),
+            Expr::Global(ref name) => write!(f, "@{}", name),
+            Expr::Closure(index, ref values) => {
+                write!(f, "closure {} [", self.functions[index].name)?;
+                for (i, val) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    self.sub(val).fmt(f)?;
+                }
+                f.write_str("]")
+            }
+            Expr::Apply(ref func, ref arg) => {
+                match **func {
+                    Expr::Closure(..) | Expr::If(..) => write!(f, "({})", self.sub(func))?,
+                    _ => self.sub(func).fmt(f)?,
+                }
+                write!(f, "({})", self.sub(arg))
+            }
+            Expr::If(ref cond, ref cons, ref alt) => write!(
+                f,
+                "if {} {{ {} }} else {{ {} }}",
+                self.sub(cond),
+                self.sub(cons),
+                self.sub(alt)
+            ),
+        }
+    }
+}
+
+impl<'a> Program<'a> {
+    /// Render `expr`, which must belong to this program.
+    pub fn display_expr<'e>(&'e self, expr: &'e Expr<'a>) -> impl fmt::Display + 'e {
+        ExprDisplay {
+            expr,
+            functions: &self.functions,
+        }
+    }
+}
+
+impl<'a> fmt::Display for Program<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for func in &self.functions {
+            let captures: Vec<String> = func.captures.iter().map(|name| name.to_string()).collect();
+            writeln!(
+                f,
+                "fn {}(env [{}], {}) : {} {{",
+                func.name,
+                captures.join(", "),
+                func.param,
+                func.ty
+            )?;
+            writeln!(f, "  {}", self.display_expr(&func.body))?;
+            writeln!(f, "}}")?;
+            writeln!(f)?;
+        }
+        writeln!(f, "main : {} {{", self.ty)?;
+        writeln!(f, "  {}", self.display_expr(&self.main))?;
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globals;
+    use names;
+    use parser;
+
+    fn lifted(src: &str) -> String {
+        let ast = parser::parse("test", src).unwrap();
+        lift(&globals::global_env(), &ast).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_free_vars() {
+        let ast = parser::parse("test", "fn(x) { add(x, fn(y) { mul(y, z) }(x)) }").unwrap();
+        assert_eq!(
+            free_vars(&ast),
+            vec![names::ident("add"), names::ident("mul"), names::ident("z")]
+        );
+    }
+
+    #[test]
+    fn test_lift() {
+        assert_eq!(
+            lifted("fn(x : int, y : int) { add(x, y) }"),
+            "fn f0(env [x], y) : int -> int {\n  @add(env.x)(y)\n}\n\n\
+             fn f1(env [], x) : int -> int -> int {\n  closure f0 [x]\n}\n\n\
+             main : int -> int -> int {\n  closure f1 []\n}\n"
+        );
+        assert_eq!(
+            lifted("fn(a : int) { fn(b : int) { fn(c : bool) { if c { a } else { b } } } }(1)(2)"),
+            "fn f0(env [a, b], c) : bool -> int {\n  if c { env.a } else { env.b }\n}\n\n\
+             fn f1(env [a], b) : int -> bool -> int {\n  closure f0 [env.a, b]\n}\n\n\
+             fn f2(env [], a) : int -> int -> bool -> int {\n  closure f1 [a]\n}\n\n\
+             main : bool -> int {\n  (closure f2 [])(1)(2)\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_shadowing() {
+        // The inner `x` is the inner parameter, so nothing is captured.
+        assert_eq!(
+            lifted("fn(x : int, add : int) { fn(x : bool) { x } }"),
+            "fn f0(env [], x) : bool -> bool {\n  x\n}\n\n\
+             fn f1(env [], add) : int -> bool -> bool {\n  closure f0 []\n}\n\n\
+             fn f2(env [], x) : int -> int -> bool -> bool {\n  closure f1 []\n}\n\n\
+             main : int -> int -> bool -> bool {\n  closure f2 []\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_shadow_global() {
+        assert_eq!(
+            lifted("fn(add : int) { add }"),
+            "fn f0(env [], add) : int -> int {\n  add\n}\n\n\
+             main : int -> int {\n  closure f0 []\n}\n"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! A Language Server Protocol server, spoken over stdio.
+//!
+//! Documents are synchronized in full on every change. The server publishes
+//! parse and type errors as diagnostics, and answers hover, go-to-definition
+//! and formatting requests.
+
+use ast;
+use ast::AST;
+use globals;
+use parser;
+use pretty;
+use query;
+use typecheck;
+
+use serde_json;
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Write};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_REQUEST: i64 = -32600;
+
+/// Read one framed message, returning `None` at end of input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            length = parts.next().and_then(|val| val.trim().parse::<usize>().ok());
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message<W: Write>(output: &mut W, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Convert a byte offset into an LSP position, whose `character` counts
+/// UTF-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = text[..line_start].matches('\n').count();
+    let character: usize = text[line_start..offset].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+fn offset(text: &str, pos: &Value) -> usize {
+    let line = pos["line"].as_u64().unwrap_or(0) as usize;
+    let character = pos["character"].as_u64().unwrap_or(0) as usize;
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, ch) in text[start..].char_indices() {
+        if units >= character || ch == '\n' {
+            return start + i;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, begin: usize, end: usize) -> Value {
+    json!({ "start": position(text, begin), "end": position(text, end) })
+}
+
+fn loc_range(text: &str, loc: &ast::Loc) -> Value {
+    range(text, loc.begin as usize, loc.end as usize)
+}
+
+fn diagnostics(uri: &str, text: &str) -> Vec<Value> {
+    let (range, message) = match parser::parse(uri, text) {
+        Err(err) => {
+            let (begin, end) = err.span();
+            (range(text, begin, end), err.to_string())
+        }
+        Ok(ast) => match typecheck::typecheck(&globals::global_env(), &ast) {
+            Err(err) => (loc_range(text, &err.loc()), err.to_string()),
+            Ok(_) => return vec![],
+        },
+    };
+    vec![json!({
+        "range": range,
+        "severity": 1,
+        "source": "iron-golem",
+        "message": message,
+    })]
+}
+
+fn covers(ast: &AST, offset: u32) -> bool {
+    let loc = ast.loc();
+    loc.begin <= offset && offset < loc.end
+}
+
+/// The binding site of the variable at `offset`, if it is bound by an
+/// enclosing `fn`.
+fn definition<'a, 'b>(
+    ast: &'b AST<'a>,
+    offset: u32,
+    scope: &mut Vec<&'b AST<'a>>,
+) -> Option<ast::Loc<'a>> {
+    if !covers(ast, offset) {
+        return None;
+    }
+    match *ast {
+        AST::Variable(_, ref name) => scope
+            .iter()
+            .rev()
+            .find(|var| match ***var {
+                AST::Variable(_, ref bound) => bound == name,
+                _ => false,
+            })
+            .map(|var| var.loc()),
+        AST::Abstraction(_, ref param, ref body) => {
+            let var = query::binder(param);
+            if covers(var, offset) {
+                return Some(var.loc());
+            }
+            scope.push(var);
+            let found = definition(body, offset, scope);
+            scope.pop();
+            found
+        }
+        _ => ast
+            .children()
+            .into_iter()
+            .filter_map(|child| definition(child, offset, scope))
+            .next(),
+    }
+}
+
+fn hover(text: &str, uri: &str, pos: &Value) -> Value {
+    let ast = match parser::parse(uri, text) {
+        Ok(ast) => ast,
+        Err(_) => return Value::Null,
+    };
+    let found = match query::type_at(&globals::global_env(), &ast, offset(text, pos) as u32) {
+        Ok(Some(found)) => found,
+        _ => return Value::Null,
+    };
+    let sig = match *found.node {
+        AST::Variable(_, ref name) => format!("{} : {}", name, found.ty),
+        _ => found.ty.to_string(),
+    };
+    json!({
+        "contents": { "kind": "markdown", "value": format!("```gollum\n{}\n```", sig) },
+        "range": loc_range(text, &found.node.loc()),
+    })
+}
+
+fn goto_definition(text: &str, uri: &str, pos: &Value) -> Value {
+    let ast = match parser::parse(uri, text) {
+        Ok(ast) => ast,
+        Err(_) => return Value::Null,
+    };
+    match definition(&ast, offset(text, pos) as u32, &mut Vec::new()) {
+        Some(loc) => json!({ "uri": uri, "range": loc_range(text, &loc) }),
+        None => Value::Null,
+    }
+}
+
+/// Reformat the whole document. Comments would be lost, so documents that
+/// contain any are left alone.
+fn format(text: &str, uri: &str) -> Value {
+    if text.contains('#') {
+        return Value::Null;
+    }
+    match parser::parse(uri, text) {
+        Ok(ast) => json!([{
+            "range": range(text, 0, text.len()),
+            "newText": format!("{}\n", pretty::pretty(&ast)),
+        }]),
+        Err(_) => Value::Null,
+    }
+}
+
+struct Server<W: Write> {
+    output: W,
+    docs: HashMap<String, String>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn publish(&mut self, uri: &str) -> io::Result<()> {
+        let diags = match self.docs.get(uri) {
+            Some(text) => diagnostics(uri, text),
+            None => vec![],
+        };
+        write_message(
+            &mut self.output,
+            &json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": uri, "diagnostics": diags },
+            }),
+        )
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.docs.insert(uri.clone(), text.to_string());
+                self.publish(&uri)
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|c| c.last()).and_then(|c| c["text"].as_str()) {
+                    self.docs.insert(uri.clone(), text.to_string());
+                }
+                self.publish(&uri)
+            }
+            "textDocument/didClose" => {
+                self.docs.remove(&uri);
+                self.publish(&uri)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let doc = self.docs.get(uri).map(|text| text.as_str());
+        match (method, doc) {
+            ("initialize", _) => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": { "name": "iron-golem" },
+            })),
+            ("shutdown", _) => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            ("textDocument/hover", Some(text)) => Ok(hover(text, uri, &params["position"])),
+            ("textDocument/definition", Some(text)) => {
+                Ok(goto_definition(text, uri, &params["position"]))
+            }
+            ("textDocument/formatting", Some(text)) => Ok(format(text, uri)),
+            ("textDocument/hover", None)
+            | ("textDocument/definition", None)
+            | ("textDocument/formatting", None) => {
+                Err((INVALID_REQUEST, format!("unknown document: {}", uri)))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+        }
+    }
+
+    fn handle(&mut self, msg: &Value) -> io::Result<bool> {
+        let method = msg["method"].as_str().unwrap_or("");
+        if method == "exit" {
+            return Ok(false);
+        }
+        let id = match msg.get("id") {
+            Some(id) => id.clone(),
+            None => {
+                self.notify(method, &msg["params"])?;
+                return Ok(true);
+            }
+        };
+        let reply = match self.request(method, &msg["params"]) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        write_message(&mut self.output, &reply)?;
+        Ok(true)
+    }
+}
+
+/// Serve requests from `input` until the client sends `exit` or closes the
+/// stream. Returns whether the client asked to shut down first.
+pub fn serve<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<bool> {
+    let mut server = Server {
+        output,
+        docs: HashMap::new(),
+        shutdown: false,
+    };
+    while let Some(msg) = read_message(&mut input)? {
+        if !server.handle(&msg)? {
+            break;
+        }
+    }
+    Ok(server.shutdown)
+}
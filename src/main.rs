@@ -1,19 +1,33 @@
 #![allow(dead_code)]
 
 extern crate clap;
+extern crate serde_json;
 
 extern crate iron_golem;
-use iron_golem::{parser,globals,typecheck};
+use iron_golem::{parser,globals,typecheck,module,eval,step,debugger,emit,dot,lift,anf,nbe,pretty,cgen,wasm,lsp,golden};
+use iron_golem::diagnostics::Diagnostic;
 
+use std::fs;
 use std::fs::File;
-use std::io::Read;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
 
-const VERSION: &'static str = "0.0.1";
+const VERSION: &str = "0.0.1";
 
 fn main() {
     let args = clap::App::new("Iron Gollum")
         .version(VERSION)
         .author("Nelson Elhage <nelhage@nelhage.com>")
+        // Without these, clap takes an input path that looks like a
+        // subcommand's name, such as `lsp.gol`, for a misspelling of it.
+        // Paths past the input end up as an external subcommand, which is
+        // rejected below.
+        .setting(clap::AppSettings::ArgsNegateSubcommands)
+        .setting(clap::AppSettings::AllowExternalSubcommands)
         .arg(
             clap::Arg::with_name("eval")
                 .short("e")
@@ -25,9 +39,154 @@ fn main() {
                 .long("print-ast")
                 .help("Print the parsed AST"),
         )
+        .arg(
+            clap::Arg::with_name("emit")
+                .long("emit")
+                .value_name("FORMAT")
+                .possible_values(&["ast-json", "ast-sexp", "typed-json", "dot", "constraints-dot", "lifted", "ir"])
+                .help("Print a machine-readable dump of the program and exit"),
+        )
+        .arg(
+            clap::Arg::with_name("step")
+                .long("step")
+                .help("Print each step of evaluation"),
+        )
+        .arg(
+            clap::Arg::with_name("debug")
+                .long("debug")
+                .conflicts_with("step")
+                .help("Evaluate the program under an interactive debugger"),
+        )
+        .arg(
+            clap::Arg::with_name("no-std")
+                .long("no-std")
+                .help("Do not load the standard library"),
+        )
+        .arg(
+            clap::Arg::with_name("fuel")
+                .long("fuel")
+                .value_name("STEPS")
+                .help("Stop evaluation after this many steps"),
+        )
+        .arg(
+            clap::Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("DEPTH")
+                .help("Stop evaluation when it nests deeper than this"),
+        )
+        .arg(
+            clap::Arg::with_name("max-heap")
+                .long("max-heap")
+                .value_name("BYTES")
+                .help("Stop evaluation when it allocates more than this"),
+        )
+        .arg(
+            clap::Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("SECONDS")
+                .help("Stop evaluation after this long"),
+        )
         .arg(clap::Arg::with_name("input").help("Source file").index(1))
+        .subcommand(
+            clap::SubCommand::with_name("lsp")
+                .about("Run a Language Server Protocol server over stdio"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("test")
+                .about("Check a directory of .gol programs against their expectations")
+                .arg(
+                    clap::Arg::with_name("bless")
+                        .long("bless")
+                        .help("Rewrite expectations to match the observed results"),
+                )
+                .arg(
+                    clap::Arg::with_name("dir")
+                        .help("Directory to search for .gol files")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("compile")
+                .about("Compile a program to another language")
+                .arg(
+                    clap::Arg::with_name("target")
+                        .long("target")
+                        .value_name("TARGET")
+                        .possible_values(&["c", "wat", "wasm"])
+                        .required(true)
+                        .help("Language to compile to"),
+                )
+                .arg(
+                    clap::Arg::with_name("output")
+                        .short("o")
+                        .value_name("FILE")
+                        .help("Write the output to FILE instead of stdout"),
+                )
+                .arg(
+                    clap::Arg::with_name("input")
+                        .help("Source file")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("equiv")
+                .about("Check whether two programs are beta-eta equivalent")
+                .arg(
+                    clap::Arg::with_name("fold")
+                        .long("fold")
+                        .help("Compute primitives applied to literals"),
+                )
+                .arg(clap::Arg::with_name("a").required(true).index(1))
+                .arg(clap::Arg::with_name("b").required(true).index(2)),
+        )
         .get_matches();
 
+    if args.subcommand_matches("lsp").is_some() {
+        let stdin = io::stdin();
+        match lsp::serve(stdin.lock(), io::stdout()) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                eprintln!("lsp: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(args) = args.subcommand_matches("test") {
+        let dir = Path::new(args.value_of("dir").unwrap());
+        match golden::run(dir, args.is_present("bless")) {
+            Ok(report) => {
+                println!("{}", report);
+                if report.failures() > 0 {
+                    process::exit(1);
+                }
+                return;
+            }
+            Err(e) => {
+                eprintln!("test: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(args) = args.subcommand_matches("compile") {
+        compile(args);
+        return;
+    }
+
+    if let Some(args) = args.subcommand_matches("equiv") {
+        equiv(args);
+        return;
+    }
+
+    if let (extra, Some(_)) = args.subcommand() {
+        eprintln!("error: unexpected argument '{}'", extra);
+        process::exit(1);
+    }
+
     let path: String;
     let mut src: String = "".to_string();
 
@@ -45,14 +204,254 @@ fn main() {
         }
     }
 
-    let ast = parser::parse(&path, &src).unwrap_or_else(|e| panic!("parse err: {}", e));
+    if args.value_of("emit").is_none() {
+        run(&args, &path, src);
+        return;
+    }
+
+    let ast = match parser::parse(&path, &src) {
+        Ok(ast) => ast,
+        Err(e) => {
+            eprintln!("{}", Diagnostic::parse(&src, &e));
+            process::exit(1);
+        }
+    };
 
     if args.is_present("print-ast") {
         println!("ast: {:?}", ast);
     }
 
+    match args.value_of("emit") {
+        Some("ast-json") => {
+            println!("{}", serde_json::to_string_pretty(&emit::ast_json(&src, &ast)).unwrap());
+            return;
+        }
+        Some("ast-sexp") => {
+            println!("{}", emit::ast_sexp(&src, &ast));
+            return;
+        }
+        Some("typed-json") => {
+            match typecheck::typecheck_nodes(&globals::global_env(), &ast) {
+                Ok((ty, types)) => {
+                    let doc = emit::typed_json(&src, &ast, &ty, &types);
+                    println!("{}", serde_json::to_string_pretty(&doc).unwrap());
+                }
+                Err(e) => {
+                    eprintln!("{}", Diagnostic::typecheck(&src, &e));
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("dot") => {
+            print!("{}", dot::ast_dot(&src, &ast));
+            return;
+        }
+        Some("constraints-dot") => {
+            let constraints = typecheck::constraints(&globals::global_env(), &ast);
+            print!("{}", dot::constraints_dot(&src, &constraints));
+            return;
+        }
+        Some("ir") => {
+            match anf::lower_primitive(&ast) {
+                Ok(ir) => {
+                    println!("# before optimization");
+                    println!("{}", ir);
+                    println!("# after optimization");
+                    print!("{}", anf::optimize(ir));
+                }
+                Err(e) => {
+                    eprintln!("{}", Diagnostic::typecheck(&src, &e));
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("lifted") => {
+            match lift::lift(&globals::global_env(), &ast) {
+                Ok(program) => print!("{}", program),
+                Err(e) => {
+                    eprintln!("{}", Diagnostic::typecheck(&src, &e));
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => {}
+    }
+
     match typecheck::typecheck(&globals::global_env(), &ast) {
         Ok(ty) => println!("type: {:?}", ty),
         Err(e) => println!("typecheck: err: {:?}", e),
     }
 }
+
+/// The value of the numeric option `name`, if it was given.
+fn number<T: FromStr>(args: &clap::ArgMatches, name: &str) -> Option<T> {
+    let arg = args.value_of(name)?;
+    match arg.parse() {
+        Ok(n) => Some(n),
+        Err(_) => {
+            eprintln!("--{}: not a number: {}", name, arg);
+            process::exit(1);
+        }
+    }
+}
+
+/// The standard evaluation limits, with any the command line overrides.
+fn limits(args: &clap::ArgMatches) -> eval::Limits {
+    let standard = eval::Limits::standard();
+    eval::Limits {
+        fuel: number(args, "fuel").or(standard.fuel),
+        max_depth: number(args, "max-depth").or(standard.max_depth),
+        max_heap: number(args, "max-heap").or(standard.max_heap),
+        timeout: number(args, "timeout")
+            .map(|secs| {
+                Duration::try_from_secs_f64(secs).unwrap_or_else(|e| {
+                    eprintln!("--timeout: {}", e);
+                    process::exit(1);
+                })
+            })
+            .or(standard.timeout),
+    }
+}
+
+/// Typecheck and evaluate a file and the modules it imports.
+fn run(args: &clap::ArgMatches, path: &str, src: String) {
+    let limits = limits(args);
+    let arena = module::Arena::new();
+    let mut loader = module::Loader::new(&arena);
+    loader.std(!args.is_present("no-std"));
+    loader.eval_limits(limits.clone());
+    let module = match loader.load_source(path, src) {
+        Ok(module) => module,
+        Err(e) => {
+            let src = loader.source(e.loc().file).unwrap_or("");
+            println!("{}", Diagnostic::module(src, &e));
+            return;
+        }
+    };
+    if args.is_present("print-ast") {
+        println!("ast: {:?}", module.ast);
+    }
+    let report = |e: eval::Failure| {
+        let src = loader.source(e.error.loc().file).unwrap_or(module.src);
+        Diagnostic::failure(src, &e)
+    };
+    let (ty, body) = match (&module.ty, module.body) {
+        (Some(ty), Some(body)) => (ty, body),
+        _ => {
+            for (name, ty) in &module.exports {
+                println!("{} : {}", name, ty);
+            }
+            return;
+        }
+    };
+    println!("type: {:?}", ty);
+    let env = match module.values() {
+        Ok(env) => env,
+        Err(e) => {
+            println!("{}", report(e));
+            return;
+        }
+    };
+
+    if args.is_present("debug") {
+        let stdin = io::stdin();
+        if let Err(e) = debugger::run(module.src, &env, body, &limits, stdin.lock(), io::stdout()) {
+            eprintln!("debug: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let result = if args.is_present("step") {
+        let stdout = io::stdout();
+        step::trace(module.src, &env, body, &limits, &mut stdout.lock())
+            .expect("write")
+            .map_err(|e| Diagnostic::eval(module.src, &e))
+    } else {
+        eval::eval_traced(&env, body, &limits).map_err(report)
+    };
+    match result {
+        Ok(val) => println!("value: {}", val),
+        Err(diag) => println!("{}", diag),
+    }
+}
+
+fn read_source(path: &str) -> String {
+    let mut src = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut src))
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+    src
+}
+
+fn parse_or_exit<'a>(path: &'a str, src: &'a str) -> Box<iron_golem::ast::AST<'a>> {
+    match parser::parse(path, src) {
+        Ok(ast) => ast,
+        Err(e) => {
+            eprintln!("{}", Diagnostic::parse(src, &e));
+            process::exit(1);
+        }
+    }
+}
+
+fn compile(args: &clap::ArgMatches) {
+    let path = args.value_of("input").unwrap();
+    let src = read_source(path);
+    let ast = parse_or_exit(path, &src);
+    let out = match args.value_of("target") {
+        Some("c") => cgen::compile(&ast).map(String::into_bytes),
+        Some("wat") => wasm::compile(&ast).map(String::into_bytes),
+        Some("wasm") => wasm::assemble(&ast),
+        target => panic!("unknown target {:?}", target),
+    };
+    let out = match out {
+        Ok(out) => out,
+        Err(e) => {
+            eprintln!("{}", Diagnostic::typecheck(&src, &e));
+            process::exit(1);
+        }
+    };
+    let written = match args.value_of("output") {
+        Some(file) => fs::write(file, out),
+        None => io::stdout().write_all(&out),
+    };
+    if let Err(e) = written {
+        eprintln!("compile: {}", e);
+        process::exit(1);
+    }
+}
+
+fn equiv(args: &clap::ArgMatches) {
+    let options = nbe::Options {
+        fold_primitives: args.is_present("fold"),
+        ..nbe::Options::default()
+    };
+    let paths = [args.value_of("a").unwrap(), args.value_of("b").unwrap()];
+    let srcs: Vec<String> = paths.iter().map(|path| read_source(path)).collect();
+    let mut normal = Vec::new();
+    for (path, src) in paths.iter().zip(&srcs) {
+        let ast = parse_or_exit(path, src);
+        match nbe::normalize(&ast, &options) {
+            Ok(ast) => normal.push(ast),
+            Err(e) => {
+                eprintln!("{}", Diagnostic::normalize(src, &e));
+                process::exit(1);
+            }
+        }
+    }
+    if nbe::alpha_equivalent(&normal[0], &normal[1]) {
+        println!("equivalent");
+        return;
+    }
+    println!("not equivalent");
+    for (path, ast) in paths.iter().zip(&normal) {
+        println!("{}: {}", path, pretty::pretty(ast));
+    }
+    process::exit(1);
+}
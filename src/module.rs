@@ -0,0 +1,646 @@
+//! Modules: files that define names and import one another.
+//!
+//! A file is a sequence of declarations, optionally followed by the
+//! expression the program evaluates to:
+//!
+//! ```text
+//! import "lib/math.gol" as math
+//! use math::{square}
+//! let twice = fn(f, x) { f(f(x)) };
+//! twice(square, math::cube(2))
+//! ```
+//!
+//! `import` loads a file, relative to the directory of the importing one,
+//! and binds each of its definitions as `alias::name`. `use` binds some of
+//! an imported module's definitions under their own names. `let` defines a
+//! name for the rest of the file and for importers. A definition's type is
+//! generalized, so each use of `let id = fn(x) { x };` may be at a
+//! different type.
+//!
+//! A `Loader` parses and typechecks each file once, however many times it
+//! is imported, and rejects files that import themselves. Evaluating a
+//! module's definitions is separate, and also happens at most once.
+//!
+//! A file may also declare type classes, and instances of them:
+//!
+//! ```text
+//! class Size a { size : a -> int }
+//! instance Size bool { size = fn(x) { 1 } }
+//! let sizes : Size a => list<a> -> int = fn(xs) { fold(fn(x, n) { add(size(x), n) }, 0, xs) };
+//! ```
+//!
+//! Each method is a definition, which importers see like any other.
+//! Classes and instances are in scope, under their own names, in every
+//! file that imports their module, even indirectly, and the classes of
+//! `globals::classes`, like `Eq`, are in scope in every file. Definitions that use methods are checked with
+//! `typecheck::check_definition`, which makes the instances they use
+//! explicit arguments, so evaluation knows nothing of classes.
+//!
+//! Unless told not to, a `Loader` binds the definitions of the standard
+//! library, in `stdlib`, in every file before its own declarations.
+
+use ast;
+use ast::{Item, Loc, AST};
+use env::TypeEnv;
+use eval;
+use eval::{Failure, Value};
+use globals;
+use names::Name;
+use parser;
+use parser::ParseError;
+use pretty;
+use stdlib;
+use typecheck;
+use typecheck::{Classes, TypeError};
+use types::Type;
+
+use typed_arena;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Storage for the files a `Loader` reads. Modules borrow from it, so it
+/// must outlive them.
+pub struct Arena<'a> {
+    strings: typed_arena::Arena<String>,
+    modules: typed_arena::Arena<ast::Module<'a>>,
+    /// Definitions as they are evaluated, with dictionaries passed
+    /// explicitly, and the values of class methods and instances.
+    asts: typed_arena::Arena<AST<'a>>,
+}
+
+impl<'a> Arena<'a> {
+    pub fn new() -> Arena<'a> {
+        Arena {
+            strings: typed_arena::Arena::new(),
+            modules: typed_arena::Arena::new(),
+            asts: typed_arena::Arena::new(),
+        }
+    }
+}
+
+impl<'a> Default for Arena<'a> {
+    fn default() -> Arena<'a> {
+        Arena::new()
+    }
+}
+
+#[derive(Debug)]
+pub enum ModuleError<'a> {
+    /// An imported file could not be read.
+    Read(Loc<'a>, &'a str, String),
+    Parse(ParseError<'a>),
+    Type(TypeError<'a>),
+    /// A file imports itself, through the files listed.
+    Cycle(Loc<'a>, Vec<&'a str>),
+    /// `use` names a module that was not imported.
+    UnknownModule(Loc<'a>, Name<'a>),
+    /// `use` names something the module does not define.
+    UnknownName(Loc<'a>, Name<'a>, Name<'a>),
+}
+
+impl<'a> ModuleError<'a> {
+    /// The name of the error's variant, for tools that match on it.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            ModuleError::Read(..) => "Read",
+            ModuleError::Parse(ref err) => err.kind(),
+            ModuleError::Type(ref err) => err.kind(),
+            ModuleError::Cycle(..) => "Cycle",
+            ModuleError::UnknownModule(..) => "UnknownModule",
+            ModuleError::UnknownName(..) => "UnknownName",
+        }
+    }
+
+    pub fn loc(&self) -> Loc<'a> {
+        match *self {
+            ModuleError::Parse(ref err) => err.loc(),
+            ModuleError::Type(ref err) => err.loc(),
+            ModuleError::Read(ref loc, ..)
+            | ModuleError::Cycle(ref loc, _)
+            | ModuleError::UnknownModule(ref loc, _)
+            | ModuleError::UnknownName(ref loc, ..) => loc.clone(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for ModuleError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ModuleError::Read(_, path, ref err) => write!(f, "cannot read `{}`: {}", path, err),
+            ModuleError::Parse(ref err) => err.fmt(f),
+            ModuleError::Type(ref err) => err.fmt(f),
+            ModuleError::Cycle(_, ref files) => write!(f, "import cycle: {}", files.join(" -> ")),
+            ModuleError::UnknownModule(_, ref module) => {
+                write!(f, "no module is imported as `{}`", module)
+            }
+            ModuleError::UnknownName(_, ref module, ref name) => {
+                write!(f, "module `{}` does not define `{}`", module, name)
+            }
+        }
+    }
+}
+
+/// Where the value of a name in a module's scope comes from.
+#[derive(Clone)]
+enum Source<'a> {
+    /// The name at this index in another module's scope.
+    Export(Rc<Module<'a>>, usize),
+    /// A definition in this module.
+    Def(&'a AST<'a>),
+}
+
+/// A parsed and typechecked file.
+pub struct Module<'a> {
+    /// The name locations in the file use.
+    pub path: &'a str,
+    pub src: &'a str,
+    pub ast: &'a ast::Module<'a>,
+    /// The modules the file imports, by alias.
+    pub imports: Vec<(Name<'a>, Rc<Module<'a>>)>,
+    /// Each definition's name and type, in order. Class methods are
+    /// definitions.
+    pub exports: Vec<(Name<'a>, Rc<Type<'a>>)>,
+    /// The environment the body is checked in.
+    pub type_env: Rc<TypeEnv<'a>>,
+    /// The classes and instances in scope at the body.
+    pub classes: Classes<'a>,
+    /// The type of the body, if the file has one.
+    pub ty: Option<Rc<Type<'a>>>,
+    /// The body as it is evaluated, with dictionaries passed explicitly.
+    pub body: Option<&'a AST<'a>>,
+    /// Each name in scope at the body, in the order it was bound.
+    scope: Vec<(Name<'a>, Source<'a>)>,
+    /// The index in `scope` of each export.
+    export_slots: Vec<usize>,
+    /// The index in `scope` of each binding of an instance in scope: its
+    /// definitions of its class's methods, and its dictionary.
+    instance_slots: Vec<usize>,
+    /// The limits each definition and the body are evaluated under.
+    limits: eval::Limits,
+    /// The environment the body runs in, and the value of each name in
+    /// `scope`, once they have been evaluated.
+    values: RefCell<Option<(Rc<eval::Env<'a>>, Vec<Value<'a>>)>>,
+}
+
+impl<'a> Module<'a> {
+    /// Evaluate the definitions of this module and the modules it imports,
+    /// each at most once, and return the environment the body runs in.
+    pub fn values(&self) -> Result<Rc<eval::Env<'a>>, Failure<'a>> {
+        if let Some((ref env, _)) = *self.values.borrow() {
+            return Ok(Rc::clone(env));
+        }
+        let mut env = globals::global_values();
+        let mut vals = Vec::new();
+        for (name, source) in &self.scope {
+            let val = match *source {
+                Source::Export(ref module, slot) => module.value(slot)?,
+                Source::Def(ast) => eval::eval_traced(&env, ast, &self.limits)?,
+            };
+            vals.push(val.clone());
+            env = eval::Env::with_bindings(&env, vec![(name.clone(), val)]);
+        }
+        *self.values.borrow_mut() = Some((Rc::clone(&env), vals));
+        Ok(env)
+    }
+
+    /// The value of the name at index `slot` of the scope.
+    fn value(&self, slot: usize) -> Result<Value<'a>, Failure<'a>> {
+        self.values()?;
+        Ok(self.values.borrow().as_ref().unwrap().1[slot].clone())
+    }
+
+    /// Evaluate the body, if the file has one.
+    pub fn eval(&self) -> Result<Option<Value<'a>>, Failure<'a>> {
+        let env = self.values()?;
+        match self.body {
+            Some(body) => eval::eval_traced(&env, body, &self.limits).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Loads files and the files they import.
+pub struct Loader<'a> {
+    arena: &'a Arena<'a>,
+    /// Every file loaded so far, by canonical path.
+    cache: HashMap<PathBuf, Rc<Module<'a>>>,
+    /// The files being loaded, each importing the next.
+    loading: Vec<(PathBuf, &'a str)>,
+    sources: HashMap<&'a str, &'a str>,
+    /// Whether to bind the standard library in each file.
+    use_std: bool,
+    /// The standard library's files, once they have been loaded.
+    std: Option<Vec<Rc<Module<'a>>>>,
+    check_limits: typecheck::Limits,
+    eval_limits: eval::Limits,
+}
+
+impl<'a> Loader<'a> {
+    pub fn new(arena: &'a Arena<'a>) -> Loader<'a> {
+        Loader {
+            arena,
+            cache: HashMap::new(),
+            loading: Vec::new(),
+            sources: HashMap::new(),
+            use_std: true,
+            std: None,
+            check_limits: typecheck::Limits::standard(),
+            eval_limits: eval::Limits::standard(),
+        }
+    }
+
+    /// Bound the work typechecking each file may do. Files are checked
+    /// under `typecheck::Limits::standard()` by default.
+    pub fn check_limits(&mut self, limits: typecheck::Limits) -> &mut Loader<'a> {
+        self.check_limits = limits;
+        self
+    }
+
+    /// Bound the resources evaluating each definition and body of the
+    /// files loaded may use. They run under `eval::Limits::standard()` by
+    /// default.
+    pub fn eval_limits(&mut self, limits: eval::Limits) -> &mut Loader<'a> {
+        self.eval_limits = limits;
+        self
+    }
+
+    /// Whether to bind the definitions of the standard library, in
+    /// `stdlib::FILES`, in each file loaded. They are bound by default.
+    pub fn std(&mut self, enabled: bool) -> &mut Loader<'a> {
+        self.use_std = enabled;
+        self
+    }
+
+    /// The standard library's files, loading them the first time.
+    fn load_std(&mut self) -> Result<Vec<Rc<Module<'a>>>, ModuleError<'a>> {
+        if !self.use_std {
+            return Ok(Vec::new());
+        }
+        if let Some(ref modules) = self.std {
+            return Ok(modules.clone());
+        }
+        self.std = Some(Vec::new());
+        for &(path, src) in stdlib::FILES {
+            let module = self.check(PathBuf::from(path), path, src)?;
+            self.std.as_mut().unwrap().push(module);
+        }
+        Ok(self.std.clone().unwrap())
+    }
+
+    /// The text of a file that has been loaded, by the name its locations
+    /// use.
+    pub fn source(&self, file: &str) -> Option<&'a str> {
+        self.sources.get(file).cloned()
+    }
+
+    fn alloc(&self, s: String) -> &'a str {
+        self.arena.strings.alloc(s)
+    }
+
+    /// Load `src` as the contents of the file `path`. Its imports are
+    /// relative to `path`'s directory.
+    pub fn load_source(
+        &mut self,
+        path: &str,
+        src: String,
+    ) -> Result<Rc<Module<'a>>, ModuleError<'a>> {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        let path = self.alloc(path.to_string());
+        let src = self.alloc(src);
+        self.check(key, path, src)
+    }
+
+    fn import(
+        &mut self,
+        importer: &'a str,
+        loc: &Loc<'a>,
+        file: &str,
+    ) -> Result<Rc<Module<'a>>, ModuleError<'a>> {
+        let dir = Path::new(importer)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let path = self.alloc(dir.join(file).to_string_lossy().into_owned());
+        let read = |e: ::std::io::Error| ModuleError::Read(loc.clone(), path, e.to_string());
+        let key = fs::canonicalize(path).map_err(read)?;
+        if let Some(i) = self.loading.iter().position(|(open, _)| *open == key) {
+            let mut files: Vec<&'a str> = self.loading[i..].iter().map(|&(_, file)| file).collect();
+            files.push(path);
+            return Err(ModuleError::Cycle(loc.clone(), files));
+        }
+        if let Some(module) = self.cache.get(&key) {
+            return Ok(Rc::clone(module));
+        }
+        let src = fs::read_to_string(path).map_err(read)?;
+        let src = self.alloc(src);
+        self.check(key, path, src)
+    }
+
+    fn check(
+        &mut self,
+        key: PathBuf,
+        path: &'a str,
+        src: &'a str,
+    ) -> Result<Rc<Module<'a>>, ModuleError<'a>> {
+        self.sources.insert(path, src);
+        let ast = parser::parse_module(path, src).map_err(ModuleError::Parse)?;
+        let ast: &'a ast::Module<'a> = self.arena.modules.alloc(ast);
+        self.loading.push((key.clone(), path));
+        let module = self.check_items(path, src, ast);
+        self.loading.pop();
+        let module = Rc::new(module?);
+        self.cache.insert(key, Rc::clone(&module));
+        Ok(module)
+    }
+
+    fn check_items(
+        &mut self,
+        path: &'a str,
+        src: &'a str,
+        ast: &'a ast::Module<'a>,
+    ) -> Result<Module<'a>, ModuleError<'a>> {
+        let mut env = globals::global_env();
+        let mut classes = Classes::core();
+        let mut imports: Vec<(Name<'a>, Rc<Module<'a>>)> = Vec::new();
+        let mut exports = Vec::new();
+        let mut export_slots = Vec::new();
+        let mut scope: Vec<(Name<'a>, Source<'a>)> = Vec::new();
+        let mut instance_slots = Vec::new();
+        let start = Loc {
+            file: path,
+            begin: 0,
+            end: 0,
+        };
+        for module in self.load_std()? {
+            for (i, (name, ty)) in module.exports.iter().enumerate() {
+                env = TypeEnv::with_bindings(&env, &[(name.clone(), Rc::clone(ty))]);
+                let source = Source::Export(Rc::clone(&module), module.export_slots[i]);
+                scope.push((name.clone(), source));
+            }
+            import_instances(&start, &module, &mut classes, &mut scope, &mut instance_slots)?;
+        }
+        for item in &ast.items {
+            match *item {
+                Item::Import(ref loc, file, ref alias) => {
+                    let module = self.import(path, loc, file)?;
+                    for (i, (name, ty)) in module.exports.iter().enumerate() {
+                        let name = Name::Ident(self.alloc(format!("{}::{}", alias, name)));
+                        env = TypeEnv::with_bindings(&env, &[(name.clone(), Rc::clone(ty))]);
+                        let source = Source::Export(Rc::clone(&module), module.export_slots[i]);
+                        scope.push((name, source));
+                    }
+                    import_instances(loc, &module, &mut classes, &mut scope, &mut instance_slots)?;
+                    imports.push((alias.clone(), module));
+                }
+                Item::Use(ref loc, ref alias, ref names) => {
+                    if !imports.iter().any(|(imported, _)| imported == alias) {
+                        return Err(ModuleError::UnknownModule(loc.clone(), alias.clone()));
+                    }
+                    for (loc, name) in names {
+                        let qualified = Name::Ident(self.alloc(format!("{}::{}", alias, name)));
+                        let source = match scope.iter().rev().find(|(bound, _)| *bound == qualified)
+                        {
+                            Some((_, source)) => source.clone(),
+                            None => {
+                                return Err(ModuleError::UnknownName(
+                                    loc.clone(),
+                                    alias.clone(),
+                                    name.clone(),
+                                ))
+                            }
+                        };
+                        let ty = env.lookup(&qualified).unwrap();
+                        env = TypeEnv::with_bindings(&env, &[(name.clone(), ty)]);
+                        scope.push((name.clone(), source));
+                    }
+                }
+                Item::Let(_, ref name, ref value) => {
+                    let (ty, elaborated) =
+                        typecheck::check_definition(&env, &classes, value, &self.check_limits)
+                            .map_err(ModuleError::Type)?;
+                    let value: &'a AST<'a> = match elaborated {
+                        Some(value) => self.arena.asts.alloc(value),
+                        None => value,
+                    };
+                    env = TypeEnv::with_bindings(&env, &[(name.clone(), Rc::clone(&ty))]);
+                    exports.push((name.clone(), ty));
+                    export_slots.push(scope.len());
+                    scope.push((name.clone(), Source::Def(value)));
+                }
+                Item::Class(ref loc, ref name, ref var, ref methods) => {
+                    let class = typecheck::check_class(&env, &classes, loc, name, var, methods)
+                        .map_err(ModuleError::Type)?;
+                    let class = Rc::new(class);
+                    for (i, (method, _)) in class.methods.iter().enumerate() {
+                        let ty = class.scheme(i);
+                        env = TypeEnv::with_bindings(&env, &[(method.clone(), Rc::clone(&ty))]);
+                        exports.push((method.clone(), ty));
+                        export_slots.push(scope.len());
+                        let selector: &'a AST<'a> = self.arena.asts.alloc(class.selector(i, loc));
+                        scope.push((method.clone(), Source::Def(selector)));
+                    }
+                    classes.add_class(class);
+                }
+                Item::Instance(ref loc, ref class, ref ty, ref methods) => {
+                    // Not a name a program can write, so it shadows nothing.
+                    let dict = format!("{}<{}>", class, pretty::pretty(ty));
+                    let dict = Name::Ident(self.alloc(dict));
+                    let (instance, bindings) = typecheck::check_instance(
+                        &env,
+                        &classes,
+                        loc,
+                        class,
+                        ty,
+                        methods,
+                        dict.clone(),
+                        &self.check_limits,
+                    )
+                    .map_err(ModuleError::Type)?;
+                    for (name, value) in bindings {
+                        instance_slots.push(scope.len());
+                        scope.push((name, Source::Def(self.arena.asts.alloc(value))));
+                    }
+                    classes.add_instance(Rc::new(instance));
+                }
+            }
+        }
+        let (ty, body) = match ast.body {
+            Some(ref body) => {
+                let (ty, elaborated) =
+                    typecheck::check_body(&env, &classes, body, &self.check_limits)
+                        .map_err(ModuleError::Type)?;
+                let body: &'a AST<'a> = match elaborated {
+                    Some(body) => self.arena.asts.alloc(body),
+                    None => body,
+                };
+                (Some(ty), Some(body))
+            }
+            None => (None, None),
+        };
+        Ok(Module {
+            path,
+            src,
+            ast,
+            imports,
+            exports,
+            type_env: env,
+            classes,
+            ty,
+            body,
+            scope,
+            export_slots,
+            instance_slots,
+            limits: self.eval_limits.clone(),
+            values: RefCell::new(None),
+        })
+    }
+}
+
+/// Bring the classes and instances in scope in `module` into scope, and
+/// bind the dictionary and methods of each instance that is new.
+fn import_instances<'a>(
+    loc: &Loc<'a>,
+    module: &Rc<Module<'a>>,
+    classes: &mut Classes<'a>,
+    scope: &mut Vec<(Name<'a>, Source<'a>)>,
+    instance_slots: &mut Vec<usize>,
+) -> Result<(), ModuleError<'a>> {
+    classes
+        .import(loc, &module.classes)
+        .map_err(ModuleError::Type)?;
+    for &slot in &module.instance_slots {
+        let name = &module.scope[slot].0;
+        if !instance_slots.iter().any(|&bound| scope[bound].0 == *name) {
+            instance_slots.push(scope.len());
+            scope.push((name.clone(), Source::Export(Rc::clone(module), slot)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(src: &str) -> Result<Vec<String>, String> {
+        let arena = Arena::new();
+        let mut loader = Loader::new(&arena);
+        let module = loader
+            .load_source("test", src.to_string())
+            .map_err(|e| e.kind().to_string())?;
+        let mut types: Vec<String> = module
+            .exports
+            .iter()
+            .map(|(name, ty)| format!("{} : {}", name, ty))
+            .collect();
+        if let Some(ref ty) = module.ty {
+            types.push(ty.to_string());
+        }
+        Ok(types)
+    }
+
+    #[test]
+    fn test_definitions() {
+        let tests = vec![
+            ("1", vec!["int"]),
+            ("let x = 1;", vec!["x : int"]),
+            (
+                "let x = 1; let y = add(x, x); y",
+                vec!["x : int", "y : int", "int"],
+            ),
+            (
+                "let inc : int -> int = fn(x) { add(x, 1) };",
+                vec!["inc : int -> int"],
+            ),
+            (
+                "let id = fn(x) { x }; if id(true) { id(1) } else { 2 }",
+                vec!["id : forall a. a -> a", "int"],
+            ),
+            (
+                "let k = fn(x, y) { x }; k",
+                vec!["k : forall a b. a -> b -> a", "t$1 -> t$2 -> t$1"],
+            ),
+            (
+                "# a comment\nlet x = 1;\n# another\nx",
+                vec!["x : int", "int"],
+            ),
+            (
+                "let same = fn(x, y) { eq(x, y) }; same(true, false)",
+                vec!["same : forall a. Eq a => a -> a -> bool", "bool"],
+            ),
+            (
+                "let double = fn(x) { add(x, x) }; double",
+                vec!["double : forall a. Num a => a -> a", "int -> int"],
+            ),
+            (
+                "class Size a { size : a -> int }\n\
+                 instance Size bool { size = fn(x) { 1 } }\n\
+                 instance Size list<a> { size = length }\n\
+                 let sizes : Size a, Size b => a -> b -> int = fn(x, y) { add(size(x), size(y)) };",
+                vec![
+                    "size : forall a. Size a => a -> int",
+                    "sizes : forall a b. Size a, Size b => a -> b -> int",
+                ],
+            ),
+        ];
+        for (src, expected) in tests {
+            assert_eq!(
+                check(src),
+                Ok(expected.iter().map(|s| s.to_string()).collect()),
+                "check({:?})",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = vec![
+            ("let x = y;", "UnboundVariable"),
+            ("let x = 1; add(x, true)", "Mismatch"),
+            ("let x = 1", "Syntax"),
+            ("use m::{f} 1", "UnknownModule"),
+            ("import \"does-not-exist.gol\" as m 1", "Read"),
+            ("eq(fn(x) { x }, fn(x) { x })", "NoInstance"),
+            ("class Def a { def : a } isnil([def])", "Ambiguous"),
+            ("class Eq a { eq : a -> a -> bool } 1", "DuplicateClass"),
+            ("instance Eq int { eq = fn(x, y) { true } } 1", "DuplicateInstance"),
+            ("instance Eq list<a> {} 1", "MissingMethod"),
+            ("instance Eq list<a> { eq = fn(x, y) { true }; ne = 1 } 1", "NotAMethod"),
+            ("instance Ord int {} 1", "UnboundClass"),
+            ("let f : Eq a => a -> a = fn(x) { add(x, x) };", "NoInstance"),
+            ("instance Eq list<int> { eq = fn(x, y) { true } } 1", "BadType"),
+            ("instance Eq list<a> { eq = fn(x, y) { eq(head(x), head(y)) } } 1", "NoInstance"),
+        ];
+        for (src, expected) in tests {
+            assert_eq!(check(src), Err(expected.to_string()), "check({:?})", src);
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        let arena = Arena::new();
+        let mut loader = Loader::new(&arena);
+        let src = "let twice = fn(f, x) { f(f(x)) }; let inc = fn(x) { add(x, 1) }; twice(inc, 40)";
+        let module = loader.load_source("test", src.to_string()).expect("load");
+        assert_eq!(module.eval().expect("eval").unwrap().to_string(), "42");
+        assert_eq!(module.eval().expect("eval").unwrap().to_string(), "42");
+    }
+
+    #[test]
+    fn test_defaulting() {
+        let arena = Arena::new();
+        let mut loader = Loader::new(&arena);
+        let src = "class Def a { def : a }\n\
+                   instance Def int { def = 7 }\n\
+                   instance Def bool { def = true }\n\
+                   [head([def]), add(def, 1), if def { 1 } else { 0 }]";
+        let module = loader.load_source("test", src.to_string()).expect("load");
+        assert_eq!(module.ty.as_ref().unwrap().to_string(), "list<int>");
+        assert_eq!(module.eval().expect("eval").unwrap().to_string(), "[7, 8, 1]");
+    }
+}
@@ -7,14 +7,20 @@ pub enum Name<'a> {
     Unique(Box<Name<'a>>, i32),
 }
 
-pub fn ident(s: &str) -> Name {
+pub fn ident(s: &str) -> Name<'_> {
     Name::Ident(s)
 }
 
-pub fn typ(s: &str) -> Name {
+pub fn typ(s: &str) -> Name<'_> {
     Name::Type(s)
 }
 
+/// The name an instance's definition of its `i`th method is bound to,
+/// given the name of the instance's dictionary. No program can write it.
+pub fn method(dict: Name, i: usize) -> Name {
+    Name::Unique(Box::new(dict), i as i32)
+}
+
 impl<'a> fmt::Display for Name<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
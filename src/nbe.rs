@@ -0,0 +1,536 @@
+//! Normalization by evaluation.
+//!
+//! `normalize` puts a term in beta-eta normal form. It evaluates the term
+//! into a domain where functions are closures and anything that cannot be
+//! computed yet (a variable, or a call of one) is a neutral value, then
+//! reads the result back as syntax. Readback goes under a binder by applying
+//! the closure to a fresh variable, and eta-contracts `fn(x) { f(x) }` to
+//! `f` when `x` does not appear in `f`.
+//!
+//! Two terms are beta-eta equivalent exactly when their normal forms are
+//! equal up to the names of bound variables, which is what `equivalent`
+//! checks. Type annotations are erased, and an `if` on a neutral condition
+//! normalizes both of its branches.
+//!
+//! Primitives are opaque by default: `add(1, 2)` is already normal. With
+//! `Options::fold_primitives`, a saturated call whose arguments are all
+//! literals is computed instead. Normalization need not terminate on
+//! ill-typed terms, so it is bounded by `Options::fuel` and
+//! `Options::max_depth`.
+
+use ast::{Loc, AST};
+use eval;
+use globals;
+use lift;
+use names::Name;
+use query;
+
+use num_bigint::BigInt;
+
+use std::fmt;
+use std::rc::Rc;
+
+/// How to normalize a term.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Compute saturated calls of primitives on literals.
+    pub fold_primitives: bool,
+    /// The most evaluation steps normalization may take.
+    pub fuel: u64,
+    /// The deepest evaluation may nest.
+    pub max_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            fold_primitives: false,
+            fuel: 1_000_000,
+            max_depth: 1000,
+        }
+    }
+}
+
+/// Why a term could not be normalized, and the subterm normalization had
+/// reached.
+#[derive(Clone, Debug)]
+pub enum Error<'a> {
+    /// Normalization took more steps than `Options::fuel` allows.
+    OutOfFuel(Loc<'a>),
+    /// Evaluation nested deeper than `Options::max_depth` allows.
+    RecursionLimit(Loc<'a>),
+}
+
+impl<'a> Error<'a> {
+    /// The name of the error's variant, for tools that match on it.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Error::OutOfFuel(_) => "OutOfFuel",
+            Error::RecursionLimit(_) => "RecursionLimit",
+        }
+    }
+
+    pub fn loc(&self) -> Loc<'a> {
+        match *self {
+            Error::OutOfFuel(ref loc) | Error::RecursionLimit(ref loc) => loc.clone(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Error<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::OutOfFuel(_) => f.write_str("ran out of fuel"),
+            Error::RecursionLimit(_) => f.write_str("recursion limit exceeded"),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Value<'p, 'a: 'p> {
+    Int(BigInt),
+    Bool(bool),
+    Closure(Rc<Closure<'p, 'a>>),
+    /// A primitive and the arguments it has been applied to so far.
+    Prim(&'static str, Vec<Value<'p, 'a>>),
+    Neutral(Rc<Neutral<'p, 'a>>),
+}
+
+struct Closure<'p, 'a: 'p> {
+    param: Name<'a>,
+    body: &'p AST<'a>,
+    env: Env<'p, 'a>,
+}
+
+enum Neutral<'p, 'a: 'p> {
+    /// The variable bound by the readback binder at this depth.
+    Var(usize),
+    /// A variable the term does not bind.
+    Free(Name<'a>),
+    /// A call whose function is neutral, or is not a function at all.
+    App(Value<'p, 'a>, Value<'p, 'a>),
+    If(Value<'p, 'a>, Value<'p, 'a>, Value<'p, 'a>),
+}
+
+type Env<'p, 'a> = Option<Rc<Frame<'p, 'a>>>;
+
+struct Frame<'p, 'a: 'p> {
+    name: Name<'a>,
+    val: Value<'p, 'a>,
+    next: Env<'p, 'a>,
+}
+
+fn bind<'p, 'a>(env: &Env<'p, 'a>, name: Name<'a>, val: Value<'p, 'a>) -> Env<'p, 'a> {
+    Some(Rc::new(Frame {
+        name,
+        val,
+        next: env.clone(),
+    }))
+}
+
+fn lookup<'p, 'a>(mut env: &Env<'p, 'a>, name: &Name<'a>) -> Option<Value<'p, 'a>> {
+    while let Some(ref frame) = *env {
+        if frame.name == *name {
+            return Some(frame.val.clone());
+        }
+        env = &frame.next;
+    }
+    None
+}
+
+fn neutral<'p, 'a>(neutral: Neutral<'p, 'a>) -> Value<'p, 'a> {
+    Value::Neutral(Rc::new(neutral))
+}
+
+fn occurs<'a>(name: &Name<'a>, ast: &AST<'a>) -> bool {
+    match *ast {
+        AST::Variable(_, ref var) => var == name,
+        _ => ast.children().iter().any(|child| occurs(name, child)),
+    }
+}
+
+struct Normalizer<'a> {
+    prims: Vec<globals::Primitive>,
+    options: Options,
+    fuel: u64,
+    depth: usize,
+    loc: Loc<'a>,
+    /// Names the term refers to without binding; binders avoid them.
+    free: Vec<Name<'a>>,
+    /// The names of the binders readback is under, outermost first.
+    binders: Vec<Name<'a>>,
+}
+
+impl<'a> Normalizer<'a> {
+    fn tick(&mut self, ast: &AST<'a>) -> Result<(), Error<'a>> {
+        if self.fuel == 0 {
+            return Err(Error::OutOfFuel(ast.loc()));
+        }
+        self.fuel -= 1;
+        Ok(())
+    }
+
+    fn eval<'p>(&mut self, ast: &'p AST<'a>, env: &Env<'p, 'a>) -> Result<Value<'p, 'a>, Error<'a>> {
+        self.tick(ast)?;
+        if self.depth == self.options.max_depth {
+            return Err(Error::RecursionLimit(ast.loc()));
+        }
+        self.depth += 1;
+        let val = self.eval_inner(ast, env);
+        self.depth -= 1;
+        val
+    }
+
+    fn eval_inner<'p>(
+        &mut self,
+        ast: &'p AST<'a>,
+        env: &Env<'p, 'a>,
+    ) -> Result<Value<'p, 'a>, Error<'a>> {
+        Ok(match *ast {
+            AST::Integer(_, ref val) => Value::Int(val.clone()),
+            AST::Boolean(_, val) => Value::Bool(val),
+            AST::Variable(_, ref name) => match lookup(env, name) {
+                Some(val) => val,
+                None => match self.prims.iter().find(|p| Name::Ident(p.name) == *name) {
+                    Some(prim) => Value::Prim(prim.name, Vec::new()),
+                    None => neutral(Neutral::Free(name.clone())),
+                },
+            },
+            AST::Abstraction(_, ref param, ref body) => {
+                let param = match *query::binder(param) {
+                    AST::Variable(_, ref name) => name.clone(),
+                    _ => panic!("nbe: malformed parameter"),
+                };
+                Value::Closure(Rc::new(Closure {
+                    param,
+                    body,
+                    env: env.clone(),
+                }))
+            }
+            AST::Application(_, ref func, ref arg) => {
+                let func = self.eval(func, env)?;
+                let arg = self.eval(arg, env)?;
+                self.apply(func, arg)?
+            }
+            AST::If(_, ref cond, ref cons, ref alt) => match self.eval(cond, env)? {
+                Value::Bool(cond) => self.eval(if cond { cons } else { alt }, env)?,
+                cond => {
+                    let cons = self.eval(cons, env)?;
+                    let alt = self.eval(alt, env)?;
+                    neutral(Neutral::If(cond, cons, alt))
+                }
+            },
+            AST::Ascription(_, ref expr, _) => self.eval(expr, env)?,
+            AST::TyName(..) | AST::TyFn(..) | AST::TyApp(..) | AST::TyQual(..) => {
+                panic!("nbe: type in expression position")
+            }
+        })
+    }
+
+    fn apply<'p>(
+        &mut self,
+        func: Value<'p, 'a>,
+        arg: Value<'p, 'a>,
+    ) -> Result<Value<'p, 'a>, Error<'a>> {
+        match func {
+            Value::Closure(closure) => {
+                self.tick(closure.body)?;
+                let env = bind(&closure.env, closure.param.clone(), arg);
+                self.eval(closure.body, &env)
+            }
+            Value::Prim(name, mut args) => {
+                args.push(arg);
+                let prim = self.prims.iter().find(|p| p.name == name).unwrap();
+                if args.len() < prim.arity() {
+                    return Ok(Value::Prim(name, args));
+                }
+                if self.options.fold_primitives {
+                    let vals: Vec<eval::Value> = args
+                        .iter()
+                        .filter_map(|arg| match *arg {
+                            Value::Int(ref val) => Some(eval::Value::Integer(val.clone())),
+                            Value::Bool(val) => Some(eval::Value::Boolean(val)),
+                            _ => None,
+                        })
+                        .collect();
+                    if vals.len() == args.len() {
+                        match (prim.func)(&vals) {
+                            Ok(eval::Value::Integer(val)) => return Ok(Value::Int(val)),
+                            Ok(eval::Value::Boolean(val)) => return Ok(Value::Bool(val)),
+                            _ => {}
+                        }
+                    }
+                }
+                Ok(args
+                    .into_iter()
+                    .fold(neutral(Neutral::Free(Name::Ident(name))), |func, arg| {
+                        neutral(Neutral::App(func, arg))
+                    }))
+            }
+            func => Ok(neutral(Neutral::App(func, arg))),
+        }
+    }
+
+    /// A name for a new binder, based on the source's name for it, that
+    /// captures neither an enclosing binder nor a free variable.
+    fn fresh(&self, name: &Name<'a>) -> Name<'a> {
+        let taken = |name: &Name<'a>| self.binders.contains(name) || self.free.contains(name);
+        if !taken(name) {
+            return name.clone();
+        }
+        let mut n = self.binders.len() as i32;
+        loop {
+            let name = Name::Unique(Box::new(name.clone()), n);
+            if !taken(&name) {
+                return name;
+            }
+            n += 1;
+        }
+    }
+
+    fn read_back<'p>(&mut self, val: Value<'p, 'a>) -> Result<AST<'a>, Error<'a>> {
+        let loc = self.loc.clone();
+        Ok(match val {
+            Value::Int(val) => AST::Integer(loc, val),
+            Value::Bool(val) => AST::Boolean(loc, val),
+            Value::Prim(name, args) => {
+                let mut ast = AST::Variable(loc.clone(), Name::Ident(name));
+                for arg in args {
+                    let arg = self.read_back(arg)?;
+                    ast = AST::Application(loc.clone(), Box::new(ast), Box::new(arg));
+                }
+                ast
+            }
+            Value::Neutral(neutral) => match *neutral {
+                Neutral::Var(level) => AST::Variable(loc, self.binders[level].clone()),
+                Neutral::Free(ref name) => AST::Variable(loc, name.clone()),
+                Neutral::App(ref func, ref arg) => {
+                    let func = self.read_back(func.clone())?;
+                    let arg = self.read_back(arg.clone())?;
+                    AST::Application(loc, Box::new(func), Box::new(arg))
+                }
+                Neutral::If(ref cond, ref cons, ref alt) => {
+                    let cond = self.read_back(cond.clone())?;
+                    let cons = self.read_back(cons.clone())?;
+                    let alt = self.read_back(alt.clone())?;
+                    AST::If(loc, Box::new(cond), Box::new(cons), Box::new(alt))
+                }
+            },
+            closure @ Value::Closure(_) => {
+                let name = match closure {
+                    Value::Closure(ref closure) => self.fresh(&closure.param),
+                    _ => unreachable!(),
+                };
+                let var = neutral(Neutral::Var(self.binders.len()));
+                let body = self.apply(closure, var)?;
+                self.binders.push(name.clone());
+                let body = self.read_back(body);
+                self.binders.pop();
+                match body? {
+                    AST::Application(_, func, ref arg)
+                        if matches!(**arg, AST::Variable(_, ref var) if *var == name)
+                            && !occurs(&name, &func) =>
+                    {
+                        *func
+                    }
+                    body => AST::Abstraction(
+                        loc.clone(),
+                        Box::new(AST::Variable(loc, name)),
+                        Box::new(body),
+                    ),
+                }
+            }
+        })
+    }
+}
+
+/// The beta-eta normal form of `ast`.
+pub fn normalize<'a>(ast: &AST<'a>, options: &Options) -> Result<AST<'a>, Error<'a>> {
+    let mut normalizer = Normalizer {
+        prims: globals::primitives(),
+        options: options.clone(),
+        fuel: options.fuel,
+        depth: 0,
+        loc: ast.loc(),
+        free: lift::free_vars(ast),
+        binders: Vec::new(),
+    };
+    let val = normalizer.eval(ast, &None)?;
+    normalizer.read_back(val)
+}
+
+/// Whether `a` and `b` are the same term up to the names of bound
+/// variables. Type annotations are ignored.
+pub fn alpha_equivalent<'a>(a: &AST<'a>, b: &AST<'a>) -> bool {
+    fn name<'a, 'b>(param: &'b AST<'a>) -> &'b Name<'a> {
+        match *query::binder(param) {
+            AST::Variable(_, ref name) => name,
+            _ => panic!("nbe: malformed parameter"),
+        }
+    }
+
+    fn walk<'a, 'b>(
+        a: &'b AST<'a>,
+        b: &'b AST<'a>,
+        bound: &mut Vec<(&'b Name<'a>, &'b Name<'a>)>,
+    ) -> bool {
+        match (a, b) {
+            (AST::Ascription(_, a, _), b) => walk(a, b, bound),
+            (a, AST::Ascription(_, b, _)) => walk(a, b, bound),
+            (AST::Variable(_, x), AST::Variable(_, y)) => {
+                match bound.iter().rev().find(|&&(bx, by)| bx == x || by == y) {
+                    Some(&(bx, by)) => bx == x && by == y,
+                    None => x == y,
+                }
+            }
+            (AST::Integer(_, x), AST::Integer(_, y)) => x == y,
+            (AST::Boolean(_, x), AST::Boolean(_, y)) => x == y,
+            (AST::Application(_, f, x), AST::Application(_, g, y)) => {
+                walk(f, g, bound) && walk(x, y, bound)
+            }
+            (AST::Abstraction(_, x, body), AST::Abstraction(_, y, other)) => {
+                bound.push((name(x), name(y)));
+                let same = walk(body, other, bound);
+                bound.pop();
+                same
+            }
+            (AST::If(_, c, t, e), AST::If(_, d, u, f)) => {
+                walk(c, d, bound) && walk(t, u, bound) && walk(e, f, bound)
+            }
+            _ => false,
+        }
+    }
+
+    walk(a, b, &mut Vec::new())
+}
+
+/// Whether `a` and `b` are beta-eta equivalent.
+pub fn equivalent<'a>(a: &AST<'a>, b: &AST<'a>, options: &Options) -> Result<bool, Error<'a>> {
+    Ok(alpha_equivalent(
+        &normalize(a, options)?,
+        &normalize(b, options)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser;
+    use pretty;
+
+    fn normal(src: &str, options: &Options) -> String {
+        let ast = parser::parse("test", src).unwrap();
+        pretty::pretty(&normalize(&ast, options).unwrap())
+    }
+
+    fn equiv(a: &str, b: &str) -> bool {
+        let a = parser::parse("a", a).unwrap();
+        let b = parser::parse("b", b).unwrap();
+        equivalent(&a, &b, &Options::default()).unwrap()
+    }
+
+    #[test]
+    fn test_normalize() {
+        let opaque = Options::default();
+        let fold = Options {
+            fold_primitives: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            normal("(fn(x : int) { add(x, 1) })(2)", &opaque),
+            "add(2, 1)"
+        );
+        assert_eq!(normal("(fn(x : int) { add(x, 1) })(2)", &fold), "3");
+        assert_eq!(normal("fn(x : int) { add(1, x) }", &opaque), "add(1)");
+        assert_eq!(
+            normal("fn(f : int -> int, x : int) { f(x) }", &opaque),
+            "fn(f) { f }"
+        );
+        assert_eq!(
+            normal(
+                "fn(b : bool, x : int) { if b { x } else { (fn(y : int) { y })(1) } }",
+                &opaque
+            ),
+            "fn(b, x) { if b { x } else { 1 } }"
+        );
+        assert_eq!(normal("if iszero(0) { 1 } else { 2 }", &fold), "1");
+    }
+
+    #[test]
+    fn test_capture() {
+        // Substituting `x` under `fn(x)` must rename the binder.
+        assert_eq!(
+            normal(
+                "fn(x : int) { (fn(y : int, x : int) { add(x, y) })(x) }",
+                &Options::default()
+            ),
+            "fn(x, x$1) { add(x$1, x) }"
+        );
+        // Nor may a binder capture a free variable.
+        assert_eq!(
+            normal(
+                "(fn(y : int, x : int) { add(x, y) })(x)",
+                &Options::default()
+            ),
+            "fn(x$0) { add(x$0, x) }"
+        );
+    }
+
+    #[test]
+    fn test_equivalent() {
+        assert!(equiv("fn(x : int) { x }", "fn(y : int) { y }"));
+        assert!(equiv(
+            "fn(f : int -> int) { fn(x : int) { f(x) } }",
+            "fn(g : int -> int) { g }"
+        ));
+        assert!(equiv(
+            "(fn(x : int) { fn(y : int) { x } })(3)",
+            "fn(z : int) { 3 }"
+        ));
+        assert!(!equiv(
+            "fn(x : int, y : int) { x }",
+            "fn(x : int, y : int) { y }"
+        ));
+        assert!(!equiv("add(1, 2)", "3"));
+    }
+
+    #[test]
+    fn test_alpha_symmetry() {
+        let pairs = [
+            ("fn(x) { y }", "fn(y) { x : int }"),
+            ("fn(x, y) { x }", "fn(a, b) { a : int }"),
+            ("fn(x, y) { x }", "fn(a, b) { b : int }"),
+        ];
+        for &(a, b) in &pairs {
+            let a = parser::parse("a", a).unwrap();
+            let b = parser::parse("b", b).unwrap();
+            assert_eq!(alpha_equivalent(&a, &b), alpha_equivalent(&b, &a));
+        }
+        let a = parser::parse("a", "fn(x) { y }").unwrap();
+        let b = parser::parse("b", "fn(y) { x : int }").unwrap();
+        assert!(!alpha_equivalent(&a, &b));
+        let b = parser::parse("b", "fn(z) { y : int }").unwrap();
+        assert!(alpha_equivalent(&a, &b));
+    }
+
+    #[test]
+    fn test_limits() {
+        let omega = parser::parse("test", "(fn(x) { x(x) })(fn(x) { x(x) })").unwrap();
+        let options = Options {
+            max_depth: 100,
+            ..Options::default()
+        };
+        assert_eq!(
+            normalize(&omega, &options).map_err(|e| e.kind()).err(),
+            Some("RecursionLimit")
+        );
+        let options = Options {
+            fuel: 100,
+            ..Options::default()
+        };
+        assert_eq!(
+            normalize(&omega, &options).map_err(|e| e.kind()).err(),
+            Some("OutOfFuel")
+        );
+    }
+}
@@ -4,23 +4,194 @@ use names;
 use pest;
 use pest::Parser;
 
+use num_bigint::BigInt;
+
+use std::fmt;
+
 #[cfg(debug_assertions)]
-const _GRAMMAR: &'static str = include_str!("gollum.pest");
+const _GRAMMAR: &str = include_str!("gollum.pest");
 
 #[derive(Parser)]
 #[grammar = "gollum.pest"]
 struct Gollum;
 
-fn parse_bool(val: &str) -> bool {
+/// An error produced while parsing a program.
+#[derive(Debug)]
+pub enum ParseError<'a> {
+    /// The input does not match the grammar. The message says what was
+    /// expected instead.
+    Syntax(ast::Loc<'a>, String),
+    /// A literal matched the grammar but could not be interpreted.
+    MalformedLiteral(ast::Loc<'a>, &'a str),
+    /// The input nests deeper than `MAX_NESTING` or `MAX_DEPTH` allow.
+    TooDeep(ast::Loc<'a>),
+}
+
+impl<'a> ParseError<'a> {
+    fn from_pest(path: &'a str, err: pest::Error<'a, Rule>) -> ParseError<'a> {
+        let (begin, end, message) = match err {
+            pest::Error::ParsingError { ref positives, ref pos, .. } if !positives.is_empty() => {
+                let expected: Vec<String> = positives.iter().map(|r| format!("{:?}", r)).collect();
+                let message = format!("syntax error: expected {}", expected.join(" or "));
+                (pos.pos(), pos.pos(), message)
+            }
+            pest::Error::ParsingError { ref pos, .. } => {
+                (pos.pos(), pos.pos(), "syntax error".to_string())
+            }
+            pest::Error::CustomErrorPos { ref pos, ref message } => {
+                (pos.pos(), pos.pos(), message.clone())
+            }
+            pest::Error::CustomErrorSpan { ref span, ref message } => {
+                (span.start(), span.end(), message.clone())
+            }
+        };
+        let loc = ast::Loc {
+            file: path,
+            begin: begin as u32,
+            end: end as u32,
+        };
+        ParseError::Syntax(loc, message)
+    }
+
+    /// The name of the error's variant, for tools that match on it.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            ParseError::Syntax(..) => "Syntax",
+            ParseError::MalformedLiteral(..) => "MalformedLiteral",
+            ParseError::TooDeep(..) => "TooDeep",
+        }
+    }
+
+    pub fn loc(&self) -> ast::Loc<'a> {
+        match *self {
+            ParseError::Syntax(ref loc, _)
+            | ParseError::MalformedLiteral(ref loc, _)
+            | ParseError::TooDeep(ref loc) => loc.clone(),
+        }
+    }
+
+    /// The byte range of the input that the error refers to.
+    pub fn span(&self) -> (usize, usize) {
+        let loc = self.loc();
+        (loc.begin as usize, loc.end as usize)
+    }
+}
+
+impl<'a> fmt::Display for ParseError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ParseError::Syntax(_, ref message) => f.write_str(message),
+            ParseError::MalformedLiteral(_, text) => write!(f, "malformed literal `{}`", text),
+            ParseError::TooDeep(_) => f.write_str("program is nested too deeply"),
+        }
+    }
+}
+
+/// How deeply brackets and `if`s may nest. The grammar is matched by
+/// recursive descent, so deeper input would exhaust the stack before it
+/// could be rejected.
+pub const MAX_NESTING: usize = 100;
+
+/// The deepest a parsed syntax tree may be. Every later pass walks the tree
+/// recursively, as does dropping it.
+pub const MAX_DEPTH: usize = 1000;
+
+/// What an open bracket or `if` in `check_nesting`'s stack is waiting for.
+enum Open {
+    Bracket,
+    If,
+    Else,
+}
+
+/// Reject input that nests deeper than `MAX_NESTING`, before handing it to
+/// the grammar. Only counts nesting: mismatched brackets are left for the
+/// grammar to report.
+fn check_nesting<'a>(path: &'a str, input: &'a str) -> Result<(), ParseError<'a>> {
+    let bytes = input.as_bytes();
+    let word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let mut stack = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'(' | b'[' | b'{' | b'<' => stack.push(Open::Bracket),
+            // `->` and `=>` do not close a type's arguments.
+            b'>' if i > 0 && (bytes[i - 1] == b'-' || bytes[i - 1] == b'=') => {}
+            c @ b')' | c @ b']' | c @ b'}' | c @ b'>' => {
+                while let Some(open) = stack.pop() {
+                    if let Open::Bracket = open {
+                        break;
+                    }
+                }
+                // The `else` block closes the whole `if`.
+                if c == b'}' {
+                    if let Some(&Open::Else) = stack.last() {
+                        stack.pop();
+                    }
+                }
+            }
+            c if word(c) => {
+                while i + 1 < bytes.len() && word(bytes[i + 1]) {
+                    i += 1;
+                }
+                match &input[start..i + 1] {
+                    "if" => stack.push(Open::If),
+                    "else" => {
+                        if let Some(&Open::If) = stack.last() {
+                            stack.pop();
+                            stack.push(Open::Else);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+        if stack.len() > MAX_NESTING {
+            return Err(ParseError::TooDeep(ast::Loc {
+                file: path,
+                begin: start as u32,
+                end: i as u32,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Reject a tree deeper than `MAX_DEPTH`, freeing it without recursing.
+fn check_depth<'a>(ast: Box<ast::AST<'a>>) -> Result<Box<ast::AST<'a>>, ParseError<'a>> {
+    match ast::too_deep(&ast, MAX_DEPTH).map(|node| node.loc()) {
+        Some(loc) => {
+            ast::dispose(ast);
+            Err(ParseError::TooDeep(loc))
+        }
+        None => Ok(ast),
+    }
+}
+
+fn parse_bool<'a>(loc: ast::Loc<'a>, val: &'a str) -> Result<bool, ParseError<'a>> {
     match val {
-        "true" => true,
-        "false" => true,
-        _ => panic!("bad bool"),
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(ParseError::MalformedLiteral(loc, val)),
     }
 }
 
-fn parse_int(val: &str) -> i64 {
-    val.parse::<i64>().unwrap()
+fn parse_int<'a>(loc: ast::Loc<'a>, val: &'a str) -> Result<BigInt, ParseError<'a>> {
+    val.parse::<BigInt>()
+        .map_err(|_| ParseError::MalformedLiteral(loc, val))
 }
 
 fn pair_loc<'a>(path: &'a str, pair: &pest::iterators::Pair<'a, Rule>) -> ast::Loc<'a> {
@@ -32,7 +203,10 @@ fn pair_loc<'a>(path: &'a str, pair: &pest::iterators::Pair<'a, Rule>) -> ast::L
     }
 }
 
-fn build_vec<'a>(path: &'a str, pair: pest::iterators::Pair<'a, Rule>) -> Vec<Box<ast::AST<'a>>> {
+fn build_vec<'a>(
+    path: &'a str,
+    pair: pest::iterators::Pair<'a, Rule>,
+) -> Result<Vec<Box<ast::AST<'a>>>, ParseError<'a>> {
     let pairs = pair.into_inner();
     pairs.map(|pair| build(path, pair)).collect()
 }
@@ -41,13 +215,13 @@ fn build_type<'a>(path: &'a str, pair: pest::iterators::Pair<'a, Rule>) -> Box<a
     let loc = pair_loc(path, &pair);
     let ast = match pair.as_rule() {
         Rule::typ => {
-            let mut inner = pair.into_inner();
+            let inner = pair.into_inner();
             let mut stk: Vec<Box<ast::AST>> = inner.map(|pair| build_type(path, pair)).collect();
             let ty = stk.pop().unwrap();
             stk.reverse();
             stk.into_iter().fold(ty, |rv, arg| {
                 let floc = ast::Loc {
-                    file: &path,
+                    file: path,
                     begin: arg.loc().begin,
                     end: loc.end,
                 };
@@ -55,91 +229,243 @@ fn build_type<'a>(path: &'a str, pair: pest::iterators::Pair<'a, Rule>) -> Box<a
             })
         }
         Rule::typ_variable => Box::new(ast::AST::TyName(loc, names::typ(pair.as_str()))),
+        Rule::typ_app => {
+            let mut inner = pair.into_inner();
+            let con = names::typ(inner.next().unwrap().as_str());
+            let args = inner.map(|pair| build_type(path, pair)).collect();
+            Box::new(ast::AST::TyApp(loc, con, args))
+        }
+        Rule::qualified => {
+            let mut constraints = Vec::new();
+            let mut ty = None;
+            for pair in pair.into_inner() {
+                match pair.as_rule() {
+                    Rule::constraint => {
+                        let mut inner = pair.into_inner();
+                        let class = names::typ(inner.next().unwrap().as_str());
+                        constraints.push((class, build_type(path, inner.next().unwrap())));
+                    }
+                    _ => ty = Some(build_type(path, pair)),
+                }
+            }
+            Box::new(ast::AST::TyQual(loc, constraints, ty.unwrap()))
+        }
         _ => panic!("should not have generated a token: {:?}", pair.as_rule()),
     };
     ast
 }
 
-fn build<'a>(path: &'a str, pair: pest::iterators::Pair<'a, Rule>) -> Box<ast::AST<'a>> {
+fn build<'a>(
+    path: &'a str,
+    pair: pest::iterators::Pair<'a, Rule>,
+) -> Result<Box<ast::AST<'a>>, ParseError<'a>> {
     let loc = pair_loc(path, &pair);
     let ast = match pair.as_rule() {
         Rule::condition => {
             let mut inner = pair.into_inner();
-            let cond = build(path, inner.next().unwrap());
-            let cons = build(path, inner.next().unwrap());
-            let alt = build(path, inner.next().unwrap());
+            let cond = build(path, inner.next().unwrap())?;
+            let cons = build(path, inner.next().unwrap())?;
+            let alt = build(path, inner.next().unwrap())?;
             ast::AST::If(loc, cond, cons, alt)
         }
-        Rule::typ => *build_type(path, pair),
+        Rule::typ | Rule::qualified => *build_type(path, pair),
         Rule::expression => {
             let mut inner = pair.into_inner();
-            let mut expr = *build(path, inner.next().unwrap());
-            inner.fold(expr, |expr, pair| match pair.as_rule() {
-                Rule::func_args => build_vec(path, pair.into_inner().next().unwrap())
-                    .into_iter()
-                    .fold(expr, |ast, arg| {
-                        ast::AST::Application(loc.clone(), Box::new(ast), arg)
-                    }),
-                Rule::ascription => ast::AST::Ascription(
-                    loc.clone(),
-                    Box::new(expr),
-                    build(path, pair.into_inner().next().unwrap()),
-                ),
-                _ => panic!("unexpected: {:?}", pair.as_rule()),
-            })
+            let mut expr = *build(path, inner.next().unwrap())?;
+            for pair in inner {
+                expr = match pair.as_rule() {
+                    Rule::func_args => {
+                        // `f(x, y)` is `f(x)(y)`; each application spans up
+                        // to its own argument, and the last up to the
+                        // closing paren.
+                        let close = pair_loc(path, &pair).end;
+                        let args = build_vec(path, pair.into_inner().next().unwrap())?;
+                        let n = args.len();
+                        args.into_iter().enumerate().fold(expr, |ast, (i, arg)| {
+                            let end = if i + 1 == n { close } else { arg.loc().end };
+                            let aloc = ast::Loc {
+                                file: path,
+                                begin: loc.begin,
+                                end,
+                            };
+                            ast::AST::Application(aloc, Box::new(ast), arg)
+                        })
+                    }
+                    Rule::ascription => ast::AST::Ascription(
+                        loc.clone(),
+                        Box::new(expr),
+                        build(path, pair.into_inner().next().unwrap())?,
+                    ),
+                    _ => panic!("unexpected: {:?}", pair.as_rule()),
+                }
+            }
+            expr
         }
         Rule::typed_var => {
             let mut inner = pair.into_inner();
-            let var = build(path, inner.next().unwrap());
+            let var = build(path, inner.next().unwrap())?;
             match inner.next() {
                 Some(pair) => {
                     if pair.as_rule() != Rule::ascription {
                         panic!("expected ascription")
                     }
-                    ast::AST::Ascription(loc, var, build(path, pair.into_inner().next().unwrap()))
+                    ast::AST::Ascription(loc, var, build(path, pair.into_inner().next().unwrap())?)
                 }
                 None => *var,
             }
         }
         Rule::abstraction => {
             let mut inner = pair.into_inner();
-            let mut vars = build_vec(path, inner.next().unwrap());
-            let body = build(path, inner.next().unwrap());
+            let mut vars = build_vec(path, inner.next().unwrap())?;
+            let body = build(path, inner.next().unwrap())?;
             vars.reverse();
-            vars.into_iter().fold(*body, |ast, arg| {
-                ast::AST::Abstraction(loc.clone(), arg, Box::new(ast))
+            // `fn(x, y) { b }` is `fn(x) { fn(y) { b } }`; the inner
+            // abstractions start at their own parameter.
+            let n = vars.len();
+            vars.into_iter().enumerate().fold(*body, |ast, (i, arg)| {
+                let begin = if i + 1 == n { loc.begin } else { arg.loc().begin };
+                let floc = ast::Loc {
+                    file: path,
+                    begin,
+                    end: loc.end,
+                };
+                ast::AST::Abstraction(floc, arg, Box::new(ast))
+            })
+        }
+        Rule::list => {
+            // `[x, y]` is `cons(x, cons(y, nil))`; each `cons` spans from
+            // its element to the closing bracket.
+            let elems = build_vec(path, pair.into_inner().next().unwrap())?;
+            let nil = ast::AST::Variable(loc.clone(), names::ident("nil"));
+            elems.into_iter().enumerate().rev().fold(nil, |rest, (i, elem)| {
+                let begin = if i == 0 { loc.begin } else { elem.loc().begin };
+                let cloc = ast::Loc {
+                    file: path,
+                    begin,
+                    end: loc.end,
+                };
+                let cons = ast::AST::Variable(loc.clone(), names::ident("cons"));
+                let partial = ast::AST::Application(cloc.clone(), Box::new(cons), elem);
+                ast::AST::Application(cloc, Box::new(partial), Box::new(rest))
             })
         }
-        Rule::boolean => ast::AST::Boolean(loc, parse_bool(pair.as_str())),
-        Rule::variable => ast::AST::Variable(loc, names::ident(pair.as_str())),
-        Rule::int => ast::AST::Integer(loc, parse_int(pair.as_str())),
+        Rule::boolean => ast::AST::Boolean(loc.clone(), parse_bool(loc, pair.as_str())?),
+        Rule::variable | Rule::binder => ast::AST::Variable(loc, names::ident(pair.as_str())),
+        Rule::int => ast::AST::Integer(loc.clone(), parse_int(loc, pair.as_str())?),
         _ => panic!("should not have generated a token: {:?}", pair.as_rule()),
     };
 
-    Box::new(ast)
+    Ok(Box::new(ast))
 }
 
-pub fn parse<'a>(
-    path: &'a str,
-    input: &'a str,
-) -> Result<Box<ast::AST<'a>>, pest::Error<'a, Rule>> {
-    let mut pairs = Gollum::parse(Rule::program, input)?;
+pub fn parse<'a>(path: &'a str, input: &'a str) -> Result<Box<ast::AST<'a>>, ParseError<'a>> {
+    check_nesting(path, input)?;
+    let mut pairs =
+        Gollum::parse(Rule::program, input).map_err(|e| ParseError::from_pest(path, e))?;
 
-    Ok(build(path, pairs.next().unwrap()))
+    check_depth(build(path, pairs.next().unwrap())?)
 }
 
-pub fn parse_type<'a>(
+fn build_item<'a>(
     path: &'a str,
-    input: &'a str,
-) -> Result<Box<ast::AST<'a>>, pest::Error<'a, Rule>> {
-    let mut pairs = Gollum::parse(Rule::typeexpr, input)?;
+    pair: pest::iterators::Pair<'a, Rule>,
+) -> Result<ast::Item<'a>, ParseError<'a>> {
+    let loc = pair_loc(path, &pair);
+    let rule = pair.as_rule();
+    let mut inner = pair.into_inner();
+    match rule {
+        Rule::import => {
+            let file = inner.next().unwrap().as_str();
+            let alias = names::ident(inner.next().unwrap().as_str());
+            Ok(ast::Item::Import(loc, &file[1..file.len() - 1], alias))
+        }
+        Rule::use_names => {
+            let module = names::ident(inner.next().unwrap().as_str());
+            let names = inner
+                .map(|pair| (pair_loc(path, &pair), names::ident(pair.as_str())))
+                .collect();
+            Ok(ast::Item::Use(loc, module, names))
+        }
+        Rule::definition => {
+            let var = build(path, inner.next().unwrap())?;
+            let mut value = check_depth(build(path, inner.next().unwrap())?)?;
+            let name = match *var {
+                ast::AST::Ascription(_, var, ty) => {
+                    let vloc = value.loc();
+                    value = Box::new(ast::AST::Ascription(vloc, value, ty));
+                    match *var {
+                        ast::AST::Variable(_, name) => name,
+                        _ => unreachable!(),
+                    }
+                }
+                ast::AST::Variable(_, name) => name,
+                _ => unreachable!(),
+            };
+            Ok(ast::Item::Let(loc, name, value))
+        }
+        Rule::class => {
+            let name = names::typ(inner.next().unwrap().as_str());
+            let var = names::typ(inner.next().unwrap().as_str());
+            let methods = inner.map(|pair| build_method(path, pair)).collect::<Result<_, _>>()?;
+            Ok(ast::Item::Class(loc, name, var, methods))
+        }
+        Rule::instance => {
+            let class = names::typ(inner.next().unwrap().as_str());
+            let ty = check_depth(build_type(path, inner.next().unwrap()))?;
+            let methods = inner.map(|pair| build_method(path, pair)).collect::<Result<_, _>>()?;
+            Ok(ast::Item::Instance(loc, class, ty, methods))
+        }
+        _ => panic!("should not have generated a token: {:?}", rule),
+    }
+}
 
-    Ok(build(path, pairs.next().unwrap()))
+/// A method's signature in a class, or its definition in an instance.
+fn build_method<'a>(
+    path: &'a str,
+    pair: pest::iterators::Pair<'a, Rule>,
+) -> Result<ast::Method<'a>, ParseError<'a>> {
+    let rule = pair.as_rule();
+    let mut inner = pair.into_inner();
+    let name_pair = inner.next().unwrap();
+    let loc = pair_loc(path, &name_pair);
+    let name = names::ident(name_pair.as_str());
+    let value = inner.next().unwrap();
+    match rule {
+        Rule::method_sig => Ok((loc, name, check_depth(build_type(path, value))?)),
+        Rule::method_def => Ok((loc, name, check_depth(build(path, value)?)?)),
+        _ => panic!("should not have generated a token: {:?}", rule),
+    }
+}
+
+/// Parse a whole file: its declarations, then its expression, if any.
+pub fn parse_module<'a>(path: &'a str, input: &'a str) -> Result<ast::Module<'a>, ParseError<'a>> {
+    check_nesting(path, input)?;
+    let pairs = Gollum::parse(Rule::module, input).map_err(|e| ParseError::from_pest(path, e))?;
+    let mut module = ast::Module {
+        items: Vec::new(),
+        body: None,
+    };
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::expression => module.body = Some(check_depth(build(path, pair)?)?),
+            _ => module.items.push(build_item(path, pair)?),
+        }
+    }
+    Ok(module)
+}
+
+pub fn parse_type<'a>(path: &'a str, input: &'a str) -> Result<Box<ast::AST<'a>>, ParseError<'a>> {
+    check_nesting(path, input)?;
+    let mut pairs =
+        Gollum::parse(Rule::typeexpr, input).map_err(|e| ParseError::from_pest(path, e))?;
+
+    check_depth(build(path, pairs.next().unwrap())?)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pretty;
 
     #[test]
     fn test_parser() {
@@ -167,6 +493,12 @@ mod tests {
             "f()",
             "fn(){0}",
             "fn(x : int) { y }",
+            "[]",
+            "[1, 2, 3,]",
+            "[[1], []]",
+            "f([x])",
+            "x : list<int>",
+            "x : list<list<int>> -> list<int>",
         ];
         for test in tests {
             let path = &format!("test: {}", test);
@@ -185,12 +517,134 @@ mod tests {
             "(x",
             "fn(x) y",
             "if x 1 else 2",
+            "[1, 2",
+            "[,]",
+            "x : list<>",
         ];
         for test in tests {
             let path = &format!("test: {}", test);
             let res = parse(path, test);
-            assert!(!res.is_ok(), "parse({}): {:?}", test, res)
+            assert!(res.is_err(), "parse({}): {:?}", test, res)
+        }
+    }
+
+    #[test]
+    fn test_literals() {
+        match *parse("test", "false").unwrap() {
+            ast::AST::Boolean(_, val) => assert!(!val),
+            ref other => panic!("parse(false) = {:?}", other),
+        }
+        for src in &["0", "-9223372036854775809", "99999999999999999999"] {
+            match *parse("test", src).unwrap() {
+                ast::AST::Integer(_, ref val) => assert_eq!(val.to_string(), *src),
+                ref other => panic!("parse({}) = {:?}", src, other),
+            }
+        }
+        assert_eq!(
+            parse_int(ast::Loc { file: "test", begin: 0, end: 2 }, "1x").unwrap_err().kind(),
+            "MalformedLiteral"
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        // `[1, 2]` is `cons(1, cons(2, nil))`.
+        let ast = parse("test", "[1, 2]").unwrap();
+        assert_eq!(pretty::pretty(&ast), "cons(1, cons(2, nil))");
+        let loc = ast.loc();
+        assert_eq!((loc.begin, loc.end), (0, 6));
+        match *parse("test", "x : list<int>").unwrap() {
+            ast::AST::Ascription(_, _, ref ty) => assert_eq!(pretty::pretty(ty), "list<int>"),
+            ref ast => panic!("not an ascription: {:?}", ast),
+        }
+    }
+
+    #[test]
+    fn test_module() {
+        let src = "import \"lib/a.gol\" as a\nuse a::{f, g,}\nlet x : int = a::f(1);\nx";
+        let module = parse_module("test", src).unwrap();
+        assert_eq!(module.items.len(), 3);
+        match module.items[0] {
+            ast::Item::Import(_, path, ref alias) => assert_eq!((path, alias.to_string()), ("lib/a.gol", "a".to_string())),
+            ref item => panic!("not an import: {:?}", item),
+        }
+        match module.items[1] {
+            ast::Item::Use(_, _, ref names) => assert_eq!(names.len(), 2),
+            ref item => panic!("not a use: {:?}", item),
+        }
+        match module.items[2] {
+            ast::Item::Let(_, _, ref value) => match **value {
+                ast::AST::Ascription(..) => {}
+                ref value => panic!("not an ascription: {:?}", value),
+            },
+            ref item => panic!("not a let: {:?}", item),
+        }
+        assert!(module.body.is_some());
+        assert!(parse_module("test", "let x = 1;").unwrap().body.is_none());
+        for bad in &["let a::x = 1; x", "import lib as l 1", "let x = 1"] {
+            assert!(parse_module("test", bad).is_err(), "parse_module({})", bad);
         }
     }
 
+    #[test]
+    fn test_keywords() {
+        // A keyword followed by more of a name is just a name.
+        for src in &["iffy", "fnord(1)", "truex", "elsewhere", "as_list"] {
+            match *parse("test", src).unwrap() {
+                ast::AST::Variable(..) | ast::AST::Application(..) => {}
+                ref other => panic!("parse({}) = {:?}", src, other),
+            }
+        }
+        let module = parse_module("test", "let letx = 1; classy(usex)").unwrap();
+        match module.items[0] {
+            ast::Item::Let(_, ref name, _) => assert_eq!(name.to_string(), "letx"),
+            ref item => panic!("not a let: {:?}", item),
+        }
+        assert_eq!(pretty::pretty(module.body.as_ref().unwrap()), "classy(usex)");
+        for bad in &["ifx { 1 } else { 2 }", "if x { 1 } elsey { 2 }", "fnx() { 1 }"] {
+            assert!(parse("test", bad).is_err(), "parse({})", bad);
+        }
+        for bad in &["letx = 1; x", "instanceEq int {}", "import \"a.gol\" asa a"] {
+            assert!(parse_module("test", bad).is_err(), "parse_module({})", bad);
+        }
+        // Binders cannot be qualified.
+        assert!(parse("test", "fn(a::x) { 1 }").is_err());
+    }
+
+    #[test]
+    fn test_classes() {
+        let src = "class Eq a { eq : a -> a -> bool; ne : a -> a -> bool }\n\
+                   instance Eq list<b> { eq = fn(x, y) { true }; ne = fn(x, y) { false }; }\n\
+                   let f : Eq a, Show b => a -> b -> int = g;";
+        let module = parse_module("test", src).unwrap();
+        match module.items[0] {
+            ast::Item::Class(_, ref name, ref var, ref methods) => {
+                assert_eq!(format!("{} {}", name, var), "Eq a");
+                let methods: Vec<String> = methods
+                    .iter()
+                    .map(|(_, name, ty)| format!("{} : {}", name, pretty::pretty(ty)))
+                    .collect();
+                assert_eq!(methods, vec!["eq : a -> a -> bool", "ne : a -> a -> bool"]);
+            }
+            ref item => panic!("not a class: {:?}", item),
+        }
+        match module.items[1] {
+            ast::Item::Instance(_, ref class, ref ty, ref methods) => {
+                assert_eq!(format!("{} {}", class, pretty::pretty(ty)), "Eq list<b>");
+                assert_eq!(methods.len(), 2);
+            }
+            ref item => panic!("not an instance: {:?}", item),
+        }
+        match module.items[2] {
+            ast::Item::Let(_, _, ref value) => {
+                assert_eq!(pretty::pretty(value), "g : Eq a, Show b => a -> b -> int")
+            }
+            ref item => panic!("not a let: {:?}", item),
+        }
+        assert!(parse_module("test", "class Empty a {} instance Empty int {}").is_ok());
+        let bad = ["class Eq { eq : int }", "class Eq a { eq = 1 }", "instance Eq int { eq : int }"];
+        for bad in &bad {
+            assert!(parse_module("test", bad).is_err(), "parse_module({})", bad);
+        }
+    }
 }
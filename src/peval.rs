@@ -0,0 +1,431 @@
+//! Partial evaluation.
+//!
+//! `specialize` runs a program as far as it can with some of its arguments
+//! known, and returns a residual program over the arguments that are not.
+//! Applying `fn(debug : bool, x : int) { if debug { .. } else { add(x, 1) } }`
+//! to `false` and an unknown `x` leaves `fn(x : int) { add(x, 1) }`.
+//!
+//! The evaluator knows literals, functions and partially applied primitives.
+//! Anything else is dynamic: it is represented by the residual code that
+//! computes it at run time. A call of a known function is unfolded, by
+//! evaluating its body with the parameter bound to the argument, and each
+//! unfolding spends one unit of a budget. Once the budget is spent, calls
+//! are left in the residual program, so specialization terminates even for
+//! programs that would not.
+//!
+//! Residual binders keep their source names unless that would capture a
+//! name, in which case they get a `Name::Unique`.
+
+use ast::{Loc, AST};
+use eval;
+use globals;
+use names;
+use names::Name;
+use query;
+
+use num_bigint::BigInt;
+
+use std::rc::Rc;
+
+/// A budget of unfoldings that suffices for most programs.
+pub const DEFAULT_BUDGET: usize = 1000;
+
+#[derive(Clone)]
+enum Value<'p, 'a: 'p> {
+    Int(BigInt),
+    Bool(bool),
+    Closure(Rc<Closure<'p, 'a>>),
+    /// A primitive and the arguments it has been applied to so far.
+    Prim(&'static str, Vec<Value<'p, 'a>>),
+    Dynamic(AST<'a>),
+}
+
+struct Closure<'p, 'a: 'p> {
+    loc: Loc<'a>,
+    param: &'p AST<'a>,
+    body: &'p AST<'a>,
+    env: Env<'p, 'a>,
+}
+
+type Env<'p, 'a> = Option<Rc<Frame<'p, 'a>>>;
+
+struct Frame<'p, 'a: 'p> {
+    name: Name<'a>,
+    val: Value<'p, 'a>,
+    next: Env<'p, 'a>,
+}
+
+fn bind<'p, 'a>(env: &Env<'p, 'a>, name: Name<'a>, val: Value<'p, 'a>) -> Env<'p, 'a> {
+    Some(Rc::new(Frame {
+        name,
+        val,
+        next: env.clone(),
+    }))
+}
+
+fn lookup<'p, 'a>(mut env: &Env<'p, 'a>, name: &Name<'a>) -> Option<Value<'p, 'a>> {
+    while let Some(ref frame) = *env {
+        if frame.name == *name {
+            return Some(frame.val.clone());
+        }
+        env = &frame.next;
+    }
+    None
+}
+
+fn param_name<'a>(param: &AST<'a>) -> Name<'a> {
+    match *query::binder(param) {
+        AST::Variable(_, ref name) => name.clone(),
+        _ => panic!("peval: malformed parameter"),
+    }
+}
+
+/// `param`, binding `name` instead.
+fn rename_param<'a>(param: &AST<'a>, name: Name<'a>) -> AST<'a> {
+    match *param {
+        AST::Ascription(ref loc, ref var, ref ty) => AST::Ascription(
+            loc.clone(),
+            Box::new(AST::Variable(var.loc(), name)),
+            ty.clone(),
+        ),
+        _ => AST::Variable(param.loc(), name),
+    }
+}
+
+struct Specializer<'a> {
+    prims: Vec<globals::Primitive>,
+    budget: usize,
+    /// Where code that does not come from a single node of the source is.
+    loc: Loc<'a>,
+    /// The names bound around the residual code being built.
+    bound: Vec<Name<'a>>,
+    next: i32,
+}
+
+impl<'a> Specializer<'a> {
+    fn is_prim(&self, name: &Name<'a>) -> bool {
+        match *name {
+            Name::Ident(ident) => self.prims.iter().any(|p| p.name == ident),
+            _ => false,
+        }
+    }
+
+    /// A name for a residual binder of `name`.
+    fn binder(&mut self, name: &Name<'a>) -> Name<'a> {
+        if self.bound.contains(name) || self.is_prim(name) {
+            self.next += 1;
+            Name::Unique(Box::new(name.clone()), self.next)
+        } else {
+            name.clone()
+        }
+    }
+
+    fn eval<'p>(&mut self, ast: &'p AST<'a>, env: &Env<'p, 'a>) -> Value<'p, 'a> {
+        match *ast {
+            AST::Integer(_, ref val) => Value::Int(val.clone()),
+            AST::Boolean(_, val) => Value::Bool(val),
+            AST::Variable(_, ref name) => match lookup(env, name) {
+                Some(val) => val,
+                None => match *name {
+                    Name::Ident(ident) if self.is_prim(name) => {
+                        let prim = self.prims.iter().find(|p| p.name == ident).unwrap();
+                        Value::Prim(prim.name, Vec::new())
+                    }
+                    _ => Value::Dynamic(ast.clone()),
+                },
+            },
+            AST::Abstraction(ref loc, ref param, ref body) => Value::Closure(Rc::new(Closure {
+                loc: loc.clone(),
+                param,
+                body,
+                env: env.clone(),
+            })),
+            AST::Application(ref loc, ref func, ref arg) => {
+                let func = self.eval(func, env);
+                let arg = self.eval(arg, env);
+                self.apply(loc, func, arg)
+            }
+            AST::If(ref loc, ref cond, ref cons, ref alt) => match self.eval(cond, env) {
+                Value::Bool(cond) => self.eval(if cond { cons } else { alt }, env),
+                cond => {
+                    let cond = self.reify(cond);
+                    let cons = self.eval(cons, env);
+                    let cons = self.reify(cons);
+                    let alt = self.eval(alt, env);
+                    let alt = self.reify(alt);
+                    Value::Dynamic(AST::If(
+                        loc.clone(),
+                        Box::new(cond),
+                        Box::new(cons),
+                        Box::new(alt),
+                    ))
+                }
+            },
+            AST::Ascription(ref loc, ref expr, ref ty) => match self.eval(expr, env) {
+                Value::Dynamic(expr) => {
+                    Value::Dynamic(AST::Ascription(loc.clone(), Box::new(expr), ty.clone()))
+                }
+                val => val,
+            },
+            AST::TyName(..) | AST::TyFn(..) | AST::TyApp(..) | AST::TyQual(..) => {
+                panic!("peval: type in expression position")
+            }
+        }
+    }
+
+    fn apply<'p>(
+        &mut self,
+        loc: &Loc<'a>,
+        func: Value<'p, 'a>,
+        arg: Value<'p, 'a>,
+    ) -> Value<'p, 'a> {
+        match func {
+            Value::Closure(ref closure) if self.budget > 0 => {
+                self.budget -= 1;
+                self.unfold(closure, arg)
+            }
+            Value::Prim(name, mut args) => {
+                args.push(arg);
+                let prim = self.prims.iter().find(|p| p.name == name).unwrap();
+                if args.len() < prim.arity() {
+                    return Value::Prim(name, args);
+                }
+                let mut vals = Vec::new();
+                for arg in &args {
+                    match *arg {
+                        Value::Int(ref val) => vals.push(eval::Value::Integer(val.clone())),
+                        Value::Bool(val) => vals.push(eval::Value::Boolean(val)),
+                        _ => break,
+                    }
+                }
+                let folded = if vals.len() == args.len() {
+                    (prim.func)(&vals).ok()
+                } else {
+                    None
+                };
+                match folded {
+                    Some(eval::Value::Integer(val)) => Value::Int(val),
+                    Some(eval::Value::Boolean(val)) => Value::Bool(val),
+                    _ => Value::Dynamic(self.reify(Value::Prim(name, args))),
+                }
+            }
+            func => {
+                let func = self.reify(func);
+                let arg = self.reify(arg);
+                Value::Dynamic(AST::Application(loc.clone(), Box::new(func), Box::new(arg)))
+            }
+        }
+    }
+
+    /// Evaluate the body of `closure` with its parameter bound to `arg`.
+    /// A dynamic argument that is not a variable or literal is bound in the
+    /// residual program, rather than copied to each use.
+    fn unfold<'p>(&mut self, closure: &Closure<'p, 'a>, arg: Value<'p, 'a>) -> Value<'p, 'a> {
+        let name = param_name(closure.param);
+        let arg = match arg {
+            Value::Dynamic(AST::Variable(..))
+            | Value::Dynamic(AST::Integer(..))
+            | Value::Dynamic(AST::Boolean(..)) => arg,
+            Value::Dynamic(arg) => {
+                let func = self.residual_fn(closure);
+                return Value::Dynamic(AST::Application(
+                    closure.loc.clone(),
+                    Box::new(func),
+                    Box::new(arg),
+                ));
+            }
+            arg => arg,
+        };
+        let env = bind(&closure.env, name, arg);
+        self.eval(closure.body, &env)
+    }
+
+    /// `closure` as residual code.
+    fn residual_fn<'p>(&mut self, closure: &Closure<'p, 'a>) -> AST<'a> {
+        let name = param_name(closure.param);
+        let fresh = self.binder(&name);
+        let var = Value::Dynamic(AST::Variable(closure.param.loc(), fresh.clone()));
+        self.bound.push(fresh.clone());
+        let body = self.eval(closure.body, &bind(&closure.env, name, var));
+        let body = self.reify(body);
+        self.bound.pop();
+        AST::Abstraction(
+            closure.loc.clone(),
+            Box::new(rename_param(closure.param, fresh)),
+            Box::new(body),
+        )
+    }
+
+    /// Code that computes `val`.
+    fn reify(&mut self, val: Value<'_, 'a>) -> AST<'a> {
+        match val {
+            Value::Int(val) => AST::Integer(self.loc.clone(), val),
+            Value::Bool(val) => AST::Boolean(self.loc.clone(), val),
+            Value::Closure(closure) => self.residual_fn(&closure),
+            Value::Prim(name, args) => {
+                let head = AST::Variable(self.loc.clone(), Name::Ident(name));
+                args.into_iter().fold(head, |func, arg| {
+                    let arg = self.reify(arg);
+                    AST::Application(self.loc.clone(), Box::new(func), Box::new(arg))
+                })
+            }
+            Value::Dynamic(ast) => ast,
+        }
+    }
+
+    /// The residual program for `func` applied to `args`.
+    fn residual<'p>(&mut self, func: Value<'p, 'a>, args: &[Option<&'p AST<'a>>]) -> AST<'a> {
+        match args.split_first() {
+            None => self.reify(func),
+            Some((&Some(arg), rest)) => {
+                let loc = arg.loc();
+                let arg = self.eval(arg, &None);
+                let val = self.apply(&loc, func, arg);
+                self.residual(val, rest)
+            }
+            Some((&None, rest)) => {
+                // Abstract over the argument, named after the parameter
+                // it is passed for when that is known.
+                let (name, param) = match func {
+                    Value::Closure(ref closure) => (param_name(closure.param), Some(closure.param)),
+                    _ => (names::ident("arg"), None),
+                };
+                let fresh = self.binder(&name);
+                let var = AST::Variable(self.loc.clone(), fresh.clone());
+                self.bound.push(fresh.clone());
+                let val = match func {
+                    Value::Closure(ref closure) => self.unfold(closure, Value::Dynamic(var)),
+                    func => {
+                        let loc = self.loc.clone();
+                        self.apply(&loc, func, Value::Dynamic(var))
+                    }
+                };
+                let body = self.residual(val, rest);
+                self.bound.pop();
+                let param = match param {
+                    Some(param) => rename_param(param, fresh),
+                    None => AST::Variable(self.loc.clone(), fresh),
+                };
+                AST::Abstraction(self.loc.clone(), Box::new(param), Box::new(body))
+            }
+        }
+    }
+}
+
+/// Specialize `ast` to `args`: the residual program takes the arguments
+/// that are `None`, in order, and behaves like `ast` applied to all of
+/// them. Known arguments must be closed. At most `budget` calls of known
+/// functions are unfolded.
+pub fn specialize<'p, 'a>(
+    ast: &'p AST<'a>,
+    args: &[Option<&'p AST<'a>>],
+    budget: usize,
+) -> AST<'a> {
+    let mut spec = Specializer {
+        prims: globals::primitives(),
+        budget,
+        loc: ast.loc(),
+        bound: Vec::new(),
+        next: 0,
+    };
+    let func = spec.eval(ast, &None);
+    spec.residual(func, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser;
+    use pretty;
+
+    fn specialized(src: &str, args: &[Option<&str>], budget: usize) -> String {
+        let ast = parser::parse("test", src).unwrap();
+        let args: Vec<Option<Box<AST>>> = args
+            .iter()
+            .map(|arg| arg.map(|arg| parser::parse("arg", arg).unwrap()))
+            .collect();
+        let args: Vec<Option<&AST>> = args
+            .iter()
+            .map(|arg| arg.as_ref().map(|arg| &**arg))
+            .collect();
+        pretty::pretty(&specialize(&ast, &args, budget))
+    }
+
+    #[test]
+    fn test_specialize() {
+        let config =
+            "fn(debug : bool, x : int) { if debug { mul(x, 100) } else { add(x, mul(2, 3)) } }";
+        assert_eq!(
+            specialized(config, &[Some("false"), None], DEFAULT_BUDGET),
+            "fn(x : int) { add(x, 6) }"
+        );
+        assert_eq!(
+            specialized(config, &[None, Some("4")], DEFAULT_BUDGET),
+            "fn(debug : bool) { if debug { 400 } else { 10 } }"
+        );
+        assert_eq!(
+            specialized(config, &[Some("true"), Some("4")], DEFAULT_BUDGET),
+            "400"
+        );
+        // Unknown arguments of known functions are bound, not copied.
+        assert_eq!(
+            specialized(
+                "fn(f : int -> int, x : int) { f(add(x, 1)) }",
+                &[Some("fn(y : int) { mul(y, y) }"), None],
+                DEFAULT_BUDGET
+            ),
+            "fn(x : int) { (fn(y : int) { mul(y, y) })(add(x, 1)) }"
+        );
+    }
+
+    #[test]
+    fn test_capture() {
+        // The residual `fn(x)` must not capture the outer `x`.
+        assert_eq!(
+            specialized(
+                "fn(x : int, k : int -> int -> int) { k(x) }",
+                &[None, Some("fn(y : int) { fn(x : int) { add(x, y) } }")],
+                DEFAULT_BUDGET
+            ),
+            "fn(x : int, x$1 : int) { add(x$1, x) }"
+        );
+    }
+
+    /// `x` to the power `n`, with a fixed point combinator passed in.
+    const POWER: &str = "fn(fix : ((int -> int) -> int -> int) -> int -> int, x : int, n : int) {
+        fix(fn(pow : int -> int, k : int) { if iszero(k) { 1 } else { mul(x, pow(dec(k))) } })(n)
+    }";
+
+    const Z: &str = "fn(f) { (fn(x) { f(fn(v) { x(x)(v) }) })(fn(x) { f(fn(v) { x(x)(v) }) }) }";
+
+    #[test]
+    fn test_unfold_recursion() {
+        assert_eq!(
+            specialized(POWER, &[Some(Z), None, Some("3")], DEFAULT_BUDGET),
+            "fn(x : int) { mul(x, mul(x, mul(x, 1))) }"
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        // With `n` unknown, the recursion never bottoms out; the budget
+        // stops it, and the residual program still computes the power.
+        let ast = parser::parse("test", POWER).unwrap();
+        let z = parser::parse("z", Z).unwrap();
+        let loc = ast.loc();
+        for &budget in &[0, 1, 10, 100] {
+            let residual = specialize(&ast, &[Some(&*z)], budget);
+            let call = AST::Application(
+                loc.clone(),
+                Box::new(AST::Application(
+                    loc.clone(),
+                    Box::new(residual),
+                    Box::new(AST::Integer(loc.clone(), BigInt::from(2))),
+                )),
+                Box::new(AST::Integer(loc.clone(), BigInt::from(10))),
+            );
+            let val = eval::eval(&globals::global_values(), &call).unwrap();
+            assert_eq!(val.to_string(), "1024", "budget {}", budget);
+        }
+    }
+}
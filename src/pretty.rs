@@ -0,0 +1,183 @@
+//! A pretty-printer that renders an AST back into canonical Gollum source.
+//!
+//! Comments are not part of the AST, so they do not survive a round trip.
+
+use ast::AST;
+
+const WIDTH: usize = 80;
+const INDENT: &str = "  ";
+
+/// Split a chain of applications `f(a)(b)` into `f` and `[a, b]`.
+fn spine<'a, 'b>(ast: &'b AST<'a>) -> (&'b AST<'a>, Vec<&'b AST<'a>>) {
+    let mut args = Vec::new();
+    let mut head = ast;
+    while let AST::Application(_, ref func, ref arg) = *head {
+        args.push(&**arg);
+        head = func;
+    }
+    args.reverse();
+    (head, args)
+}
+
+/// Split nested abstractions `fn(x) { fn(y) { b } }` into `[x, y]` and `b`.
+fn params<'a, 'b>(ast: &'b AST<'a>) -> (Vec<&'b AST<'a>>, &'b AST<'a>) {
+    let mut params = Vec::new();
+    let mut body = ast;
+    while let AST::Abstraction(_, ref param, ref inner) = *body {
+        params.push(&**param);
+        body = inner;
+    }
+    (params, body)
+}
+
+fn flat_head(ast: &AST) -> String {
+    match *ast {
+        AST::Abstraction(..) | AST::If(..) | AST::Ascription(..) => format!("({})", flat(ast)),
+        _ => flat(ast),
+    }
+}
+
+fn flat_list(items: &[&AST]) -> String {
+    items.iter().map(|item| flat(item)).collect::<Vec<_>>().join(", ")
+}
+
+/// Render `ast` on a single line.
+fn flat(ast: &AST) -> String {
+    match *ast {
+        AST::Variable(_, ref name) | AST::TyName(_, ref name) => name.to_string(),
+        AST::Integer(_, ref val) => val.to_string(),
+        AST::Boolean(_, val) => val.to_string(),
+        AST::Application(..) => {
+            let (head, args) = spine(ast);
+            format!("{}({})", flat_head(head), flat_list(&args))
+        }
+        AST::Abstraction(..) => {
+            let (params, body) = params(ast);
+            format!("fn({}) {{ {} }}", flat_list(&params), flat(body))
+        }
+        AST::Ascription(_, ref expr, ref ty) => match **expr {
+            AST::Ascription(..) => format!("({}) : {}", flat(expr), flat(ty)),
+            _ => format!("{} : {}", flat(expr), flat(ty)),
+        },
+        AST::If(_, ref cond, ref cons, ref alt) => format!(
+            "if {} {{ {} }} else {{ {} }}",
+            flat(cond),
+            flat(cons),
+            flat(alt)
+        ),
+        AST::TyFn(_, ref dom, ref range) => match **dom {
+            AST::TyFn(..) => format!("({}) -> {}", flat(dom), flat(range)),
+            _ => format!("{} -> {}", flat(dom), flat(range)),
+        },
+        AST::TyApp(_, ref con, ref args) => {
+            let args: Vec<&AST> = args.iter().map(|arg| &**arg).collect();
+            format!("{}<{}>", con, flat_list(&args))
+        }
+        AST::TyQual(_, ref constraints, ref ty) => {
+            let constraints: Vec<String> = constraints
+                .iter()
+                .map(|(class, var)| format!("{} {}", class, flat(var)))
+                .collect();
+            format!("{} => {}", constraints.join(", "), flat(ty))
+        }
+    }
+}
+
+fn indent(depth: usize) -> String {
+    INDENT.repeat(depth)
+}
+
+/// Render `ast`, which starts at column `col` of a line indented to `depth`,
+/// breaking it across lines if it does not fit.
+fn render(ast: &AST, depth: usize, col: usize) -> String {
+    let line = flat(ast);
+    if col + line.len() <= WIDTH {
+        return line;
+    }
+    match *ast {
+        AST::Abstraction(..) => {
+            let (params, body) = params(ast);
+            format!(
+                "fn({}) {{\n{}{}\n{}}}",
+                flat_list(&params),
+                indent(depth + 1),
+                render(body, depth + 1, (depth + 1) * INDENT.len()),
+                indent(depth)
+            )
+        }
+        AST::If(_, ref cond, ref cons, ref alt) => format!(
+            "if {} {{\n{}{}\n{}}} else {{\n{}{}\n{}}}",
+            flat(cond),
+            indent(depth + 1),
+            render(cons, depth + 1, (depth + 1) * INDENT.len()),
+            indent(depth),
+            indent(depth + 1),
+            render(alt, depth + 1, (depth + 1) * INDENT.len()),
+            indent(depth)
+        ),
+        AST::Application(..) => {
+            let (head, args) = spine(ast);
+            let head = match *head {
+                AST::Abstraction(..) | AST::If(..) | AST::Ascription(..) => {
+                    format!("({})", render(head, depth, col + 1))
+                }
+                _ => render(head, depth, col),
+            };
+            let args: Vec<String> = args
+                .into_iter()
+                .map(|arg| {
+                    format!(
+                        "{}{}",
+                        indent(depth + 1),
+                        render(arg, depth + 1, (depth + 1) * INDENT.len())
+                    )
+                })
+                .collect();
+            format!("{}(\n{}\n{})", head, args.join(",\n"), indent(depth))
+        }
+        AST::Ascription(_, ref expr, ref ty) => match **expr {
+            AST::Ascription(..) => format!("({}) : {}", render(expr, depth, col + 1), flat(ty)),
+            _ => format!("{} : {}", render(expr, depth, col), flat(ty)),
+        },
+        _ => line,
+    }
+}
+
+/// Render a whole program.
+pub fn pretty(ast: &AST) -> String {
+    render(ast, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser;
+
+    #[test]
+    fn test_pretty() {
+        let tests = vec![
+            ("f(x,y)", "f(x, y)"),
+            ("f(x)(y)", "f(x, y)"),
+            ("(fn(x){x})(1)", "(fn(x) { x })(1)"),
+            ("fn(x : (int -> int) -> int) { x }", "fn(x : (int -> int) -> int) { x }"),
+            ("if x {1} else {0} : int", "if x { 1 } else { 0 } : int"),
+            ("(f : int -> int)(1)", "(f : int -> int)(1)"),
+        ];
+        for (src, want) in tests {
+            let ast = parser::parse("test", src).unwrap();
+            assert_eq!(pretty(&ast), want);
+        }
+    }
+
+    #[test]
+    fn test_pretty_breaks() {
+        let src = "fn(fact: int -> int, x: int) { if iszero(x) { 1 } else { mul(x, fact(dec(x))) } }";
+        let ast = parser::parse("test", src).unwrap();
+        assert_eq!(
+            pretty(&ast),
+            "fn(fact : int -> int, x : int) {\n  \
+             if iszero(x) { 1 } else { mul(x, fact(dec(x))) }\n\
+             }"
+        );
+    }
+}
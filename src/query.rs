@@ -0,0 +1,145 @@
+//! Queries about a program's source positions, for editors and tools.
+
+use ast::AST;
+use env::TypeEnv;
+use names::Name;
+use typecheck;
+use typecheck::{NodeTypes, TypeError};
+use types::Type;
+
+use std::rc::Rc;
+
+/// The result of a `type_at` query.
+#[derive(Debug)]
+pub struct TypeAt<'a, 'b> {
+    /// The innermost expression covering the queried offset.
+    pub node: &'b AST<'a>,
+    /// The node's type, with every solved type variable substituted.
+    pub ty: Rc<Type<'a>>,
+    /// The variables bound by the `fn`s enclosing `node`, outermost first.
+    /// Shadowed bindings are omitted.
+    pub scope: Vec<(Name<'a>, Rc<Type<'a>>)>,
+}
+
+fn covers(ast: &AST, offset: u32) -> bool {
+    let loc = ast.loc();
+    loc.begin <= offset && offset < loc.end
+}
+
+/// The variable a lambda parameter binds.
+pub fn binder<'a, 'b>(param: &'b AST<'a>) -> &'b AST<'a> {
+    match *param {
+        AST::Ascription(_, ref var, _) => var,
+        _ => param,
+    }
+}
+
+fn find<'a, 'b>(
+    ast: &'b AST<'a>,
+    offset: u32,
+    types: &NodeTypes<'a>,
+    scope: &mut Vec<&'b AST<'a>>,
+) -> Option<(&'b AST<'a>, Vec<&'b AST<'a>>)> {
+    if !covers(ast, offset) {
+        return None;
+    }
+    if let AST::Abstraction(_, ref param, ref body) = *ast {
+        if let Some(found) = find(param, offset, types, scope) {
+            return Some(found);
+        }
+        scope.push(binder(param));
+        let found = find(body, offset, types, scope);
+        scope.pop();
+        if found.is_some() {
+            return found;
+        }
+    } else {
+        for child in ast.children() {
+            if let Some(found) = find(child, offset, types, scope) {
+                return Some(found);
+            }
+        }
+    }
+    types.get(ast).map(|_| (ast, scope.clone()))
+}
+
+/// Find the innermost expression in `ast` that covers the byte `offset`,
+/// and report its type along with the local bindings visible there.
+///
+/// Returns `Ok(None)` if no expression covers `offset`, and an error if the
+/// program does not typecheck.
+pub fn type_at<'a, 'b>(
+    env: &Rc<TypeEnv<'a>>,
+    ast: &'b AST<'a>,
+    offset: u32,
+) -> Result<Option<TypeAt<'a, 'b>>, TypeError<'a>> {
+    let (_, types) = typecheck::typecheck_nodes(env, ast)?;
+    let (node, binders) = match find(ast, offset, &types, &mut Vec::new()) {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    let mut scope: Vec<(Name<'a>, Rc<Type<'a>>)> = Vec::new();
+    for var in binders {
+        if let AST::Variable(_, ref name) = *var {
+            scope.retain(|(bound, _)| bound != name);
+            scope.push((name.clone(), types.get(var).unwrap()));
+        }
+    }
+
+    Ok(Some(TypeAt {
+        node,
+        ty: types.get(node).unwrap(),
+        scope,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globals;
+    use names;
+    use parser;
+
+    fn query(src: &str, offset: u32) -> Option<(String, Vec<String>)> {
+        let ast = parser::parse("test", src).unwrap();
+        let found = type_at(&globals::global_env(), &ast, offset).unwrap();
+        found.map(|found| {
+            let scope = found
+                .scope
+                .iter()
+                .map(|(name, ty)| format!("{} : {}", name, ty))
+                .collect();
+            (found.ty.to_string(), scope)
+        })
+    }
+
+    #[test]
+    fn test_type_at() {
+        let src = "fn(f, x : int) { if iszero(x) { f(x) } else { dec(x) } }";
+        assert_eq!(
+            query(src, src.find("f(x)").unwrap() as u32),
+            Some((
+                "int -> int".to_string(),
+                vec!["f : int -> int".to_string(), "x : int".to_string()]
+            ))
+        );
+        assert_eq!(
+            query(src, src.find("iszero").unwrap() as u32 + 7),
+            Some(("int".to_string(), vec!["f : int -> int".to_string(), "x : int".to_string()]))
+        );
+        assert_eq!(
+            query(src, 0),
+            Some(("(int -> int) -> int -> int".to_string(), vec![]))
+        );
+        assert_eq!(query(src, 1000), None);
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let src = "fn(x : bool) { fn(x : int) { x } }";
+        let ast = parser::parse("test", src).unwrap();
+        let found = type_at(&globals::global_env(), &ast, 29).unwrap().unwrap();
+        assert_eq!(found.scope, vec![(names::ident("x"), globals::integer())]);
+    }
+}
@@ -0,0 +1,349 @@
+/* The runtime for Gollum programs compiled to C.
+ *
+ * Every value is a pointer to a tagged object. Integers are immutable
+ * sign-magnitude bignums in 32-bit limbs, booleans are two static objects,
+ * and closures pair a code pointer with an array of captured values.
+ * Nothing is ever freed.
+ *
+ * Code for a closure takes the closure itself, its argument, and a `tail`
+ * record. To make a call in tail position it fills in the record and
+ * returns NULL instead of calling, and `rt_apply` makes the call in a loop,
+ * so tail calls do not grow the C stack.
+ */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+enum { T_INT, T_BOOL, T_CLOSURE };
+
+typedef struct obj *V;
+struct obj {
+    int tag;
+};
+
+struct gint {
+    int tag;
+    int neg;
+    size_t len; /* no high zero limbs; zero has len 0 and is never negative */
+    uint32_t d[];
+};
+
+struct gbool {
+    int tag;
+    int val;
+};
+
+struct closure;
+struct tail {
+    struct closure *fn;
+    V arg;
+};
+typedef V (*code_t)(struct closure *self, V arg, struct tail *tc);
+
+struct closure {
+    int tag;
+    const char *name; /* for primitives, NULL otherwise */
+    code_t code;
+    V env[];
+};
+
+static void *rt_alloc(size_t n) {
+    void *p = malloc(n ? n : 1);
+    if (!p) {
+        fputs("out of memory\n", stderr);
+        exit(2);
+    }
+    return p;
+}
+
+/* Stop the program where the interpreter would fail with a trap. */
+static void rt_trap(const char *msg) {
+    fprintf(stderr, "runtime error: %s\n", msg);
+    exit(1);
+}
+
+/* Booleans */
+
+static struct gbool rt_true_ = {T_BOOL, 1};
+static struct gbool rt_false_ = {T_BOOL, 0};
+#define RT_TRUE ((V)&rt_true_)
+#define RT_FALSE ((V)&rt_false_)
+
+static V rt_bool(int b) { return b ? RT_TRUE : RT_FALSE; }
+static int rt_truthy(V v) { return ((struct gbool *)v)->val; }
+
+/* Closures */
+
+static V rt_make(code_t code, const char *name, size_t n, const V *env) {
+    struct closure *c = rt_alloc(sizeof *c + n * sizeof(V));
+    c->tag = T_CLOSURE;
+    c->name = name;
+    c->code = code;
+    if (n)
+        memcpy(c->env, env, n * sizeof(V));
+    return (V)c;
+}
+
+static V rt_apply(V f, V x) {
+    struct tail tc;
+    struct closure *c = (struct closure *)f;
+    for (;;) {
+        V r = c->code(c, x, &tc);
+        if (r)
+            return r;
+        c = tc.fn;
+        x = tc.arg;
+    }
+}
+
+/* Integers */
+
+#define INT(v) ((struct gint *)(v))
+
+static struct gint *int_new(size_t len) {
+    struct gint *r = rt_alloc(sizeof *r + len * sizeof(uint32_t));
+    r->tag = T_INT;
+    r->neg = 0;
+    r->len = len;
+    memset(r->d, 0, len * sizeof(uint32_t));
+    return r;
+}
+
+static V int_norm(struct gint *r) {
+    while (r->len && !r->d[r->len - 1])
+        r->len--;
+    if (!r->len)
+        r->neg = 0;
+    return (V)r;
+}
+
+static V rt_int(int64_t v) {
+    uint64_t m = v < 0 ? -(uint64_t)v : (uint64_t)v;
+    struct gint *r = int_new(2);
+    r->neg = v < 0;
+    r->d[0] = (uint32_t)m;
+    r->d[1] = (uint32_t)(m >> 32);
+    return int_norm(r);
+}
+
+static V rt_int_limbs(int neg, size_t len, const uint32_t *d) {
+    struct gint *r = int_new(len);
+    r->neg = neg;
+    memcpy(r->d, d, len * sizeof(uint32_t));
+    return int_norm(r);
+}
+
+static int mag_cmp(const struct gint *a, const struct gint *b) {
+    if (a->len != b->len)
+        return a->len < b->len ? -1 : 1;
+    for (size_t i = a->len; i-- > 0;)
+        if (a->d[i] != b->d[i])
+            return a->d[i] < b->d[i] ? -1 : 1;
+    return 0;
+}
+
+static struct gint *mag_add(const struct gint *a, const struct gint *b) {
+    if (a->len < b->len) {
+        const struct gint *t = a;
+        a = b;
+        b = t;
+    }
+    struct gint *r = int_new(a->len + 1);
+    uint64_t c = 0;
+    for (size_t i = 0; i < a->len; i++) {
+        c += (uint64_t)a->d[i] + (i < b->len ? b->d[i] : 0);
+        r->d[i] = (uint32_t)c;
+        c >>= 32;
+    }
+    r->d[a->len] = (uint32_t)c;
+    return r;
+}
+
+/* |a| - |b|, where |a| >= |b|. */
+static struct gint *mag_sub(const struct gint *a, const struct gint *b) {
+    struct gint *r = int_new(a->len);
+    int64_t borrow = 0;
+    for (size_t i = 0; i < a->len; i++) {
+        int64_t t = (int64_t)a->d[i] - (i < b->len ? b->d[i] : 0) - borrow;
+        borrow = t < 0;
+        r->d[i] = (uint32_t)(t + (borrow ? (int64_t)1 << 32 : 0));
+    }
+    return r;
+}
+
+static V int_addsub(V x, V y, int negate_y) {
+    struct gint *a = INT(x), *b = INT(y), *r;
+    int bneg = b->len ? b->neg ^ negate_y : 0;
+    if (a->neg == bneg) {
+        r = mag_add(a, b);
+        r->neg = a->neg;
+    } else if (mag_cmp(a, b) >= 0) {
+        r = mag_sub(a, b);
+        r->neg = a->neg;
+    } else {
+        r = mag_sub(b, a);
+        r->neg = bneg;
+    }
+    return int_norm(r);
+}
+
+static int int_cmp(V x, V y) {
+    struct gint *a = INT(x), *b = INT(y);
+    if (a->neg != b->neg)
+        return a->neg ? -1 : 1;
+    int c = mag_cmp(a, b);
+    return a->neg ? -c : c;
+}
+
+/* Floor division: the quotient rounds towards negative infinity and the
+ * remainder takes the sign of the divisor, which must not be zero. */
+static void int_divmod(V x, V y, V *qp, V *rp) {
+    struct gint *a = INT(x), *b = INT(y);
+    struct gint *q = int_new(a->len);
+    struct gint *r = int_new(b->len + 1);
+    r->len = 0;
+    for (size_t i = a->len * 32; i-- > 0;) {
+        uint32_t carry = 0;
+        for (size_t k = 0; k < r->len; k++) {
+            uint32_t top = r->d[k] >> 31;
+            r->d[k] = (r->d[k] << 1) | carry;
+            carry = top;
+        }
+        if (carry)
+            r->d[r->len++] = carry;
+        if ((a->d[i / 32] >> (i % 32)) & 1) {
+            if (!r->len)
+                r->d[r->len++] = 0;
+            r->d[0] |= 1;
+        }
+        if (mag_cmp(r, b) >= 0) {
+            int64_t borrow = 0;
+            for (size_t k = 0; k < r->len; k++) {
+                int64_t t = (int64_t)r->d[k] - (k < b->len ? b->d[k] : 0) - borrow;
+                borrow = t < 0;
+                r->d[k] = (uint32_t)(t + (borrow ? (int64_t)1 << 32 : 0));
+            }
+            while (r->len && !r->d[r->len - 1])
+                r->len--;
+            q->d[i / 32] |= (uint32_t)1 << (i % 32);
+        }
+    }
+    q->neg = a->neg ^ b->neg;
+    r->neg = a->neg;
+    V qv = int_norm(q), rv = int_norm(r);
+    if (INT(rv)->len && a->neg != b->neg) {
+        qv = int_addsub(qv, rt_int(1), 1);
+        rv = int_addsub(rv, y, 0);
+    }
+    *qp = qv;
+    *rp = rv;
+}
+
+static void rt_print_int(V x) {
+    struct gint *a = INT(x);
+    if (!a->len) {
+        fputs("0", stdout);
+        return;
+    }
+    size_t n = a->len, nc = 0;
+    uint32_t *t = rt_alloc(n * sizeof(uint32_t));
+    uint32_t *chunks = rt_alloc((2 * n + 1) * sizeof(uint32_t));
+    for (size_t i = 0; i < n; i++)
+        t[i] = a->d[i];
+    while (n) {
+        uint64_t rem = 0;
+        for (size_t i = n; i-- > 0;) {
+            uint64_t cur = (rem << 32) | t[i];
+            t[i] = (uint32_t)(cur / 1000000000u);
+            rem = cur % 1000000000u;
+        }
+        chunks[nc++] = (uint32_t)rem;
+        while (n && !t[n - 1])
+            n--;
+    }
+    if (a->neg)
+        putchar('-');
+    printf("%u", (unsigned)chunks[nc - 1]);
+    for (size_t i = nc - 1; i-- > 0;)
+        printf("%09u", (unsigned)chunks[i]);
+}
+
+/* Primitives. Each `globals` primitive NAME is `rt_NAME` here. */
+
+static V rt_add(V x, V y) { return int_addsub(x, y, 0); }
+static V rt_sub(V x, V y) { return int_addsub(x, y, 1); }
+
+static V rt_mul(V x, V y) {
+    struct gint *a = INT(x), *b = INT(y);
+    struct gint *r = int_new(a->len + b->len);
+    for (size_t i = 0; i < a->len; i++) {
+        uint64_t c = 0;
+        for (size_t j = 0; j < b->len; j++) {
+            c += (uint64_t)a->d[i] * b->d[j] + r->d[i + j];
+            r->d[i + j] = (uint32_t)c;
+            c >>= 32;
+        }
+        r->d[i + b->len] = (uint32_t)c;
+    }
+    r->neg = a->neg ^ b->neg;
+    return int_norm(r);
+}
+
+static V rt_div(V x, V y) {
+    V q, r;
+    if (!INT(y)->len)
+        rt_trap("divided by zero");
+    int_divmod(x, y, &q, &r);
+    return q;
+}
+
+static V rt_mod(V x, V y) {
+    V q, r;
+    if (!INT(y)->len)
+        rt_trap("divided by zero");
+    int_divmod(x, y, &q, &r);
+    return r;
+}
+
+static V rt_min(V x, V y) { return int_cmp(x, y) <= 0 ? x : y; }
+static V rt_max(V x, V y) { return int_cmp(x, y) >= 0 ? x : y; }
+
+static V rt_neg(V x) {
+    struct gint *a = INT(x);
+    V r = rt_int_limbs(!a->neg, a->len, a->d);
+    return r;
+}
+
+static V rt_abs(V x) { return INT(x)->neg ? rt_neg(x) : x; }
+static V rt_inc(V x) { return int_addsub(x, rt_int(1), 0); }
+static V rt_dec(V x) { return int_addsub(x, rt_int(1), 1); }
+static V rt_iszero(V x) { return rt_bool(!INT(x)->len); }
+static V rt_eq(V x, V y) { return rt_bool(int_cmp(x, y) == 0); }
+static V rt_lt(V x, V y) { return rt_bool(int_cmp(x, y) < 0); }
+static V rt_le(V x, V y) { return rt_bool(int_cmp(x, y) <= 0); }
+static V rt_gt(V x, V y) { return rt_bool(int_cmp(x, y) > 0); }
+static V rt_ge(V x, V y) { return rt_bool(int_cmp(x, y) >= 0); }
+static V rt_not(V x) { return rt_bool(!rt_truthy(x)); }
+static V rt_and(V x, V y) { return rt_bool(rt_truthy(x) && rt_truthy(y)); }
+static V rt_or(V x, V y) { return rt_bool(rt_truthy(x) || rt_truthy(y)); }
+static V rt_xor(V x, V y) { return rt_bool(rt_truthy(x) != rt_truthy(y)); }
+
+/* Print a value the way the interpreter does. */
+static void rt_print(V v) {
+    switch (v->tag) {
+    case T_INT:
+        rt_print_int(v);
+        break;
+    case T_BOOL:
+        fputs(rt_truthy(v) ? "true" : "false", stdout);
+        break;
+    case T_CLOSURE:
+        if (((struct closure *)v)->name)
+            printf("<builtin %s>", ((struct closure *)v)->name);
+        else
+            fputs("<fn>", stdout);
+        break;
+    }
+}
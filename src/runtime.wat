@@ -0,0 +1,522 @@
+;; The runtime for Gollum programs compiled to WebAssembly.
+;;
+;; These are module fields that `wasm` splices into every module it
+;; generates, next to the table of closure code, the global `$hp`, and the
+;; program's static data. It mirrors `runtime.c`.
+;;
+;; Every value is the address of a tagged object in linear memory. Integers
+;; are immutable sign-magnitude bignums in 32-bit limbs,
+;;
+;;   [0] tag = 0   [4] neg   [8] len   [12] limbs, least significant first
+;;
+;; with no high zero limbs, so zero has len 0 and is never negative.
+;; Booleans are two static objects, `[0] tag = 1  [4] val`, and closures are
+;;
+;;   [0] tag = 2   [4] name   [8] name length   [12] table index   [16] env
+;;
+;; where the name is a primitive's, and 0 for other closures. Memory is
+;; allocated by bumping `$hp` and is never freed.
+;;
+;; Code for a closure takes the closure itself and its argument. To make a
+;; call in tail position it stores the callee and argument in `$tc_fn` and
+;; `$tc_arg` and returns 0 instead of calling, and `$rt_apply` makes the
+;; call in a loop, so tail calls do not grow the wasm stack.
+
+(type $code (func (param i32 i32) (result i32)))
+
+(global $tc_fn (mut i32) (i32.const 0))
+(global $tc_arg (mut i32) (i32.const 0))
+(global $rem (mut i32) (i32.const 0))
+(global $out_len (mut i32) (i32.const 0))
+
+;; false, true, and the text `rt_show` uses.
+(data (i32.const 8) "\01\00\00\00\00\00\00\00\01\00\00\00\01\00\00\00")
+(data (i32.const 24) "truefalse<fn><builtin >")
+
+(func $rt_alloc (param $n i32) (result i32)
+  (local $p i32)
+  (local $need i32)
+  (local.set $p (global.get $hp))
+  (global.set $hp (i32.and (i32.add (i32.add (local.get $p) (local.get $n)) (i32.const 3))
+                           (i32.const -4)))
+  (local.set $need (i32.sub (global.get $hp) (i32.shl (memory.size) (i32.const 16))))
+  (if (i32.gt_s (local.get $need) (i32.const 0))
+    (then
+      (if (i32.eq (memory.grow (i32.shr_u (i32.add (local.get $need) (i32.const 65535))
+                                          (i32.const 16)))
+                  (i32.const -1))
+        (then (unreachable)))))
+  (local.get $p))
+
+;; Booleans
+
+(func $rt_bool (param $b i32) (result i32)
+  (select (i32.const 16) (i32.const 8) (local.get $b)))
+
+(func $rt_truthy (param $v i32) (result i32)
+  (i32.load offset=4 (local.get $v)))
+
+;; Closures
+
+(func $rt_make (param $index i32) (param $name i32) (param $name_len i32) (param $n i32)
+               (result i32)
+  (local $c i32)
+  (local.set $c (call $rt_alloc (i32.add (i32.const 16) (i32.shl (local.get $n) (i32.const 2)))))
+  (i32.store (local.get $c) (i32.const 2))
+  (i32.store offset=4 (local.get $c) (local.get $name))
+  (i32.store offset=8 (local.get $c) (local.get $name_len))
+  (i32.store offset=12 (local.get $c) (local.get $index))
+  (local.get $c))
+
+(func $rt_apply (param $f i32) (param $x i32) (result i32)
+  (local $r i32)
+  (loop $call
+    (local.set $r (call_indirect (type $code)
+                    (local.get $f) (local.get $x) (i32.load offset=12 (local.get $f))))
+    (if (i32.eqz (local.get $r))
+      (then
+        (local.set $f (global.get $tc_fn))
+        (local.set $x (global.get $tc_arg))
+        (br $call))))
+  (local.get $r))
+
+(func $rt_tail (param $f i32) (param $x i32) (result i32)
+  (global.set $tc_fn (local.get $f))
+  (global.set $tc_arg (local.get $x))
+  (i32.const 0))
+
+;; Integers
+
+(func $len (param $a i32) (result i32)
+  (i32.load offset=8 (local.get $a)))
+
+(func $neg (param $a i32) (result i32)
+  (i32.load offset=4 (local.get $a)))
+
+;; Limb `i` of `a`, or 0 past its end.
+(func $d (param $a i32) (param $i i32) (result i32)
+  (if (result i32) (i32.lt_u (local.get $i) (call $len (local.get $a)))
+    (then (i32.load offset=12 (i32.add (local.get $a) (i32.shl (local.get $i) (i32.const 2)))))
+    (else (i32.const 0))))
+
+(func $set_d (param $a i32) (param $i i32) (param $v i32)
+  (i32.store offset=12 (i32.add (local.get $a) (i32.shl (local.get $i) (i32.const 2)))
+             (local.get $v)))
+
+(func $int_new (param $len i32) (result i32)
+  (local $r i32)
+  (local.set $r (call $rt_alloc (i32.add (i32.const 12) (i32.shl (local.get $len) (i32.const 2)))))
+  (i32.store (local.get $r) (i32.const 0))
+  (i32.store offset=4 (local.get $r) (i32.const 0))
+  (i32.store offset=8 (local.get $r) (local.get $len))
+  (local.get $r))
+
+(func $int_norm (param $r i32) (result i32)
+  (block $done
+    (loop $trim
+      (br_if $done (i32.eqz (call $len (local.get $r))))
+      (br_if $done (call $d (local.get $r) (i32.sub (call $len (local.get $r)) (i32.const 1))))
+      (i32.store offset=8 (local.get $r) (i32.sub (call $len (local.get $r)) (i32.const 1)))
+      (br $trim)))
+  (if (i32.eqz (call $len (local.get $r)))
+    (then (i32.store offset=4 (local.get $r) (i32.const 0))))
+  (local.get $r))
+
+(func $rt_int (param $v i64) (result i32)
+  (local $m i64)
+  (local $r i32)
+  (local.set $m (select (i64.sub (i64.const 0) (local.get $v)) (local.get $v)
+                        (i64.lt_s (local.get $v) (i64.const 0))))
+  (local.set $r (call $int_new (i32.const 2)))
+  (i32.store offset=4 (local.get $r) (i64.lt_s (local.get $v) (i64.const 0)))
+  (call $set_d (local.get $r) (i32.const 0) (i32.wrap_i64 (local.get $m)))
+  (call $set_d (local.get $r) (i32.const 1) (i32.wrap_i64 (i64.shr_u (local.get $m) (i64.const 32))))
+  (call $int_norm (local.get $r)))
+
+(func $mag_cmp (param $a i32) (param $b i32) (result i32)
+  (local $i i32)
+  (local $x i32)
+  (local $y i32)
+  (if (i32.ne (call $len (local.get $a)) (call $len (local.get $b)))
+    (then
+      (return (select (i32.const -1) (i32.const 1)
+                      (i32.lt_u (call $len (local.get $a)) (call $len (local.get $b)))))))
+  (local.set $i (call $len (local.get $a)))
+  (block $done
+    (loop $next
+      (br_if $done (i32.eqz (local.get $i)))
+      (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+      (local.set $x (call $d (local.get $a) (local.get $i)))
+      (local.set $y (call $d (local.get $b) (local.get $i)))
+      (if (i32.ne (local.get $x) (local.get $y))
+        (then
+          (return (select (i32.const -1) (i32.const 1) (i32.lt_u (local.get $x) (local.get $y))))))
+      (br $next)))
+  (i32.const 0))
+
+(func $mag_add (param $a i32) (param $b i32) (result i32)
+  (local $t i32)
+  (local $r i32)
+  (local $i i32)
+  (local $c i64)
+  (if (i32.lt_u (call $len (local.get $a)) (call $len (local.get $b)))
+    (then
+      (local.set $t (local.get $a))
+      (local.set $a (local.get $b))
+      (local.set $b (local.get $t))))
+  (local.set $r (call $int_new (i32.add (call $len (local.get $a)) (i32.const 1))))
+  (block $done
+    (loop $next
+      (br_if $done (i32.ge_u (local.get $i) (call $len (local.get $a))))
+      (local.set $c (i64.add (local.get $c)
+                             (i64.add (i64.extend_i32_u (call $d (local.get $a) (local.get $i)))
+                                      (i64.extend_i32_u (call $d (local.get $b) (local.get $i))))))
+      (call $set_d (local.get $r) (local.get $i) (i32.wrap_i64 (local.get $c)))
+      (local.set $c (i64.shr_u (local.get $c) (i64.const 32)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $next)))
+  (call $set_d (local.get $r) (local.get $i) (i32.wrap_i64 (local.get $c)))
+  (local.get $r))
+
+;; Subtract |b| from |a| in place, where |a| >= |b|.
+(func $mag_sub_from (param $a i32) (param $b i32)
+  (local $i i32)
+  (local $t i64)
+  (local $borrow i64)
+  (block $done
+    (loop $next
+      (br_if $done (i32.ge_u (local.get $i) (call $len (local.get $a))))
+      (local.set $t (i64.sub (i64.sub (i64.extend_i32_u (call $d (local.get $a) (local.get $i)))
+                                      (i64.extend_i32_u (call $d (local.get $b) (local.get $i))))
+                             (local.get $borrow)))
+      (local.set $borrow (i64.extend_i32_u (i64.lt_s (local.get $t) (i64.const 0))))
+      (call $set_d (local.get $a) (local.get $i) (i32.wrap_i64 (local.get $t)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $next))))
+
+(func $int_copy (param $a i32) (result i32)
+  (local $r i32)
+  (local.set $r (call $int_new (call $len (local.get $a))))
+  (i32.store offset=4 (local.get $r) (call $neg (local.get $a)))
+  (memory.copy (i32.add (local.get $r) (i32.const 12))
+               (i32.add (local.get $a) (i32.const 12))
+               (i32.shl (call $len (local.get $a)) (i32.const 2)))
+  (local.get $r))
+
+;; |a| - |b|, where |a| >= |b|.
+(func $mag_sub (param $a i32) (param $b i32) (result i32)
+  (local $r i32)
+  (local.set $r (call $int_copy (local.get $a)))
+  (call $mag_sub_from (local.get $r) (local.get $b))
+  (local.get $r))
+
+(func $int_addsub (param $a i32) (param $b i32) (param $negate_b i32) (result i32)
+  (local $bneg i32)
+  (local $r i32)
+  (local.set $bneg (select (i32.xor (call $neg (local.get $b)) (local.get $negate_b))
+                           (i32.const 0)
+                           (call $len (local.get $b))))
+  (if (i32.eq (call $neg (local.get $a)) (local.get $bneg))
+    (then
+      (local.set $r (call $mag_add (local.get $a) (local.get $b)))
+      (i32.store offset=4 (local.get $r) (call $neg (local.get $a))))
+    (else
+      (if (i32.ge_s (call $mag_cmp (local.get $a) (local.get $b)) (i32.const 0))
+        (then
+          (local.set $r (call $mag_sub (local.get $a) (local.get $b)))
+          (i32.store offset=4 (local.get $r) (call $neg (local.get $a))))
+        (else
+          (local.set $r (call $mag_sub (local.get $b) (local.get $a)))
+          (i32.store offset=4 (local.get $r) (local.get $bneg))))))
+  (call $int_norm (local.get $r)))
+
+(func $int_cmp (param $a i32) (param $b i32) (result i32)
+  (local $c i32)
+  (if (i32.ne (call $neg (local.get $a)) (call $neg (local.get $b)))
+    (then (return (select (i32.const -1) (i32.const 1) (call $neg (local.get $a))))))
+  (local.set $c (call $mag_cmp (local.get $a) (local.get $b)))
+  (select (i32.sub (i32.const 0) (local.get $c)) (local.get $c) (call $neg (local.get $a))))
+
+;; Floor division: the quotient rounds towards negative infinity and the
+;; remainder, left in `$rem`, takes the sign of the divisor, which must not
+;; be zero.
+(func $int_divmod (param $a i32) (param $b i32) (result i32)
+  (local $q i32)
+  (local $r i32)
+  (local $i i32)
+  (local $k i32)
+  (local $top i32)
+  (local $carry i32)
+  (local $limb i32)
+  (local.set $q (call $int_new (call $len (local.get $a))))
+  (local.set $r (call $int_new (i32.add (call $len (local.get $b)) (i32.const 1))))
+  (i32.store offset=8 (local.get $r) (i32.const 0))
+  (local.set $i (i32.shl (call $len (local.get $a)) (i32.const 5)))
+  (block $done
+    (loop $bit
+      (br_if $done (i32.eqz (local.get $i)))
+      (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+      ;; r = r * 2 + bit i of a
+      (local.set $carry (i32.const 0))
+      (local.set $k (i32.const 0))
+      (block $shifted
+        (loop $shift
+          (br_if $shifted (i32.ge_u (local.get $k) (call $len (local.get $r))))
+          (local.set $limb (call $d (local.get $r) (local.get $k)))
+          (local.set $top (i32.shr_u (local.get $limb) (i32.const 31)))
+          (call $set_d (local.get $r) (local.get $k)
+                       (i32.or (i32.shl (local.get $limb) (i32.const 1)) (local.get $carry)))
+          (local.set $carry (local.get $top))
+          (local.set $k (i32.add (local.get $k) (i32.const 1)))
+          (br $shift)))
+      (if (local.get $carry)
+        (then
+          (call $set_d (local.get $r) (call $len (local.get $r)) (local.get $carry))
+          (i32.store offset=8 (local.get $r) (i32.add (call $len (local.get $r)) (i32.const 1)))))
+      (if (i32.and (i32.shr_u (call $d (local.get $a) (i32.shr_u (local.get $i) (i32.const 5)))
+                              (i32.and (local.get $i) (i32.const 31)))
+                   (i32.const 1))
+        (then
+          (if (i32.eqz (call $len (local.get $r)))
+            (then (i32.store offset=8 (local.get $r) (i32.const 1))))
+          (call $set_d (local.get $r) (i32.const 0)
+                       (i32.or (call $d (local.get $r) (i32.const 0)) (i32.const 1)))))
+      ;; if r >= b, subtract b and set bit i of q
+      (if (i32.ge_s (call $mag_cmp (local.get $r) (local.get $b)) (i32.const 0))
+        (then
+          (call $mag_sub_from (local.get $r) (local.get $b))
+          (drop (call $int_norm (local.get $r)))
+          (call $set_d (local.get $q) (i32.shr_u (local.get $i) (i32.const 5))
+                       (i32.or (call $d (local.get $q) (i32.shr_u (local.get $i) (i32.const 5)))
+                               (i32.shl (i32.const 1) (i32.and (local.get $i) (i32.const 31)))))))
+      (br $bit)))
+  (i32.store offset=4 (local.get $q) (i32.xor (call $neg (local.get $a)) (call $neg (local.get $b))))
+  (i32.store offset=4 (local.get $r) (call $neg (local.get $a)))
+  (local.set $q (call $int_norm (local.get $q)))
+  (local.set $r (call $int_norm (local.get $r)))
+  (if (i32.and (i32.ne (call $len (local.get $r)) (i32.const 0))
+               (i32.ne (call $neg (local.get $a)) (call $neg (local.get $b))))
+    (then
+      (local.set $q (call $int_addsub (local.get $q) (call $rt_int (i64.const 1)) (i32.const 1)))
+      (local.set $r (call $int_addsub (local.get $r) (local.get $b) (i32.const 0)))))
+  (global.set $rem (local.get $r))
+  (local.get $q))
+
+;; Primitives. Each `globals` primitive NAME is `$rt_NAME` here.
+
+(func $rt_add (param $x i32) (param $y i32) (result i32)
+  (call $int_addsub (local.get $x) (local.get $y) (i32.const 0)))
+
+(func $rt_sub (param $x i32) (param $y i32) (result i32)
+  (call $int_addsub (local.get $x) (local.get $y) (i32.const 1)))
+
+(func $rt_mul (param $a i32) (param $b i32) (result i32)
+  (local $r i32)
+  (local $i i32)
+  (local $j i32)
+  (local $c i64)
+  (local.set $r (call $int_new (i32.add (call $len (local.get $a)) (call $len (local.get $b)))))
+  (block $done
+    (loop $outer
+      (br_if $done (i32.ge_u (local.get $i) (call $len (local.get $a))))
+      (local.set $c (i64.const 0))
+      (local.set $j (i32.const 0))
+      (block $row
+        (loop $inner
+          (br_if $row (i32.ge_u (local.get $j) (call $len (local.get $b))))
+          (local.set $c
+            (i64.add (local.get $c)
+                     (i64.add (i64.mul (i64.extend_i32_u (call $d (local.get $a) (local.get $i)))
+                                       (i64.extend_i32_u (call $d (local.get $b) (local.get $j))))
+                              (i64.extend_i32_u
+                                (call $d (local.get $r) (i32.add (local.get $i) (local.get $j)))))))
+          (call $set_d (local.get $r) (i32.add (local.get $i) (local.get $j))
+                       (i32.wrap_i64 (local.get $c)))
+          (local.set $c (i64.shr_u (local.get $c) (i64.const 32)))
+          (local.set $j (i32.add (local.get $j) (i32.const 1)))
+          (br $inner)))
+      (call $set_d (local.get $r) (i32.add (local.get $i) (local.get $j)) (i32.wrap_i64 (local.get $c)))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $outer)))
+  (i32.store offset=4 (local.get $r) (i32.xor (call $neg (local.get $a)) (call $neg (local.get $b))))
+  (call $int_norm (local.get $r)))
+
+;; Dividing by zero traps, where the interpreter fails with a trap.
+(func $rt_div (param $x i32) (param $y i32) (result i32)
+  (if (i32.eqz (call $len (local.get $y)))
+    (then (unreachable)))
+  (call $int_divmod (local.get $x) (local.get $y)))
+
+(func $rt_mod (param $x i32) (param $y i32) (result i32)
+  (if (i32.eqz (call $len (local.get $y)))
+    (then (unreachable)))
+  (drop (call $int_divmod (local.get $x) (local.get $y)))
+  (global.get $rem))
+
+(func $rt_min (param $x i32) (param $y i32) (result i32)
+  (select (local.get $x) (local.get $y)
+          (i32.le_s (call $int_cmp (local.get $x) (local.get $y)) (i32.const 0))))
+
+(func $rt_max (param $x i32) (param $y i32) (result i32)
+  (select (local.get $x) (local.get $y)
+          (i32.ge_s (call $int_cmp (local.get $x) (local.get $y)) (i32.const 0))))
+
+(func $rt_neg (param $x i32) (result i32)
+  (local $r i32)
+  (local.set $r (call $int_copy (local.get $x)))
+  (i32.store offset=4 (local.get $r) (i32.eqz (call $neg (local.get $x))))
+  (call $int_norm (local.get $r)))
+
+(func $rt_abs (param $x i32) (result i32)
+  (if (result i32) (call $neg (local.get $x))
+    (then (call $rt_neg (local.get $x)))
+    (else (local.get $x))))
+
+(func $rt_inc (param $x i32) (result i32)
+  (call $int_addsub (local.get $x) (call $rt_int (i64.const 1)) (i32.const 0)))
+
+(func $rt_dec (param $x i32) (result i32)
+  (call $int_addsub (local.get $x) (call $rt_int (i64.const 1)) (i32.const 1)))
+
+(func $rt_iszero (param $x i32) (result i32)
+  (call $rt_bool (i32.eqz (call $len (local.get $x)))))
+
+(func $rt_eq (param $x i32) (param $y i32) (result i32)
+  (call $rt_bool (i32.eqz (call $int_cmp (local.get $x) (local.get $y)))))
+
+(func $rt_lt (param $x i32) (param $y i32) (result i32)
+  (call $rt_bool (i32.lt_s (call $int_cmp (local.get $x) (local.get $y)) (i32.const 0))))
+
+(func $rt_le (param $x i32) (param $y i32) (result i32)
+  (call $rt_bool (i32.le_s (call $int_cmp (local.get $x) (local.get $y)) (i32.const 0))))
+
+(func $rt_gt (param $x i32) (param $y i32) (result i32)
+  (call $rt_bool (i32.gt_s (call $int_cmp (local.get $x) (local.get $y)) (i32.const 0))))
+
+(func $rt_ge (param $x i32) (param $y i32) (result i32)
+  (call $rt_bool (i32.ge_s (call $int_cmp (local.get $x) (local.get $y)) (i32.const 0))))
+
+(func $rt_not (param $x i32) (result i32)
+  (call $rt_bool (i32.eqz (call $rt_truthy (local.get $x)))))
+
+(func $rt_and (param $x i32) (param $y i32) (result i32)
+  (call $rt_bool (i32.and (call $rt_truthy (local.get $x)) (call $rt_truthy (local.get $y)))))
+
+(func $rt_or (param $x i32) (param $y i32) (result i32)
+  (call $rt_bool (i32.or (call $rt_truthy (local.get $x)) (call $rt_truthy (local.get $y)))))
+
+(func $rt_xor (param $x i32) (param $y i32) (result i32)
+  (call $rt_bool (i32.xor (call $rt_truthy (local.get $x)) (call $rt_truthy (local.get $y)))))
+
+;; Printing
+
+(func $put (param $p i32) (param $src i32) (param $n i32) (result i32)
+  (memory.copy (local.get $p) (local.get $src) (local.get $n))
+  (i32.add (local.get $p) (local.get $n)))
+
+;; Write the digits of `v`, padded with zeros to at least `width`.
+(func $put_digits (param $p i32) (param $v i32) (param $width i32) (result i32)
+  (local $n i32)
+  (local $t i32)
+  (local.set $t (local.get $v))
+  (loop $count
+    (local.set $n (i32.add (local.get $n) (i32.const 1)))
+    (local.set $t (i32.div_u (local.get $t) (i32.const 10)))
+    (br_if $count (local.get $t)))
+  (if (i32.lt_u (local.get $n) (local.get $width))
+    (then (local.set $n (local.get $width))))
+  (local.set $t (i32.add (local.get $p) (local.get $n)))
+  (loop $digit
+    (local.set $t (i32.sub (local.get $t) (i32.const 1)))
+    (i32.store8 (local.get $t) (i32.add (i32.const 48) (i32.rem_u (local.get $v) (i32.const 10))))
+    (local.set $v (i32.div_u (local.get $v) (i32.const 10)))
+    (br_if $digit (i32.gt_u (local.get $t) (local.get $p))))
+  (i32.add (local.get $p) (local.get $n)))
+
+(func $put_int (param $p i32) (param $a i32) (result i32)
+  (local $t i32)
+  (local $chunks i32)
+  (local $nc i32)
+  (local $i i32)
+  (local $rem i64)
+  (local $cur i64)
+  (if (i32.eqz (call $len (local.get $a)))
+    (then (return (call $put_digits (local.get $p) (i32.const 0) (i32.const 0)))))
+  ;; Peel off base 10^9 chunks, least significant first.
+  (local.set $t (call $int_copy (local.get $a)))
+  (local.set $chunks (call $rt_alloc (i32.shl (i32.add (i32.shl (call $len (local.get $a))
+                                                               (i32.const 1))
+                                                      (i32.const 1))
+                                             (i32.const 2))))
+  (loop $chunk
+    (local.set $rem (i64.const 0))
+    (local.set $i (call $len (local.get $t)))
+    (block $divided
+      (loop $limb
+        (br_if $divided (i32.eqz (local.get $i)))
+        (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+        (local.set $cur (i64.or (i64.shl (local.get $rem) (i64.const 32))
+                                (i64.extend_i32_u (call $d (local.get $t) (local.get $i)))))
+        (call $set_d (local.get $t) (local.get $i)
+                     (i32.wrap_i64 (i64.div_u (local.get $cur) (i64.const 1000000000))))
+        (local.set $rem (i64.rem_u (local.get $cur) (i64.const 1000000000)))
+        (br $limb)))
+    (i32.store (i32.add (local.get $chunks) (i32.shl (local.get $nc) (i32.const 2)))
+               (i32.wrap_i64 (local.get $rem)))
+    (local.set $nc (i32.add (local.get $nc) (i32.const 1)))
+    (br_if $chunk (call $len (call $int_norm (local.get $t)))))
+  (if (call $neg (local.get $a))
+    (then
+      (i32.store8 (local.get $p) (i32.const 45))
+      (local.set $p (i32.add (local.get $p) (i32.const 1)))))
+  (local.set $nc (i32.sub (local.get $nc) (i32.const 1)))
+  (local.set $p (call $put_digits (local.get $p)
+                  (i32.load (i32.add (local.get $chunks) (i32.shl (local.get $nc) (i32.const 2))))
+                  (i32.const 0)))
+  (block $done
+    (loop $next
+      (br_if $done (i32.eqz (local.get $nc)))
+      (local.set $nc (i32.sub (local.get $nc) (i32.const 1)))
+      (local.set $p (call $put_digits (local.get $p)
+                      (i32.load (i32.add (local.get $chunks) (i32.shl (local.get $nc) (i32.const 2))))
+                      (i32.const 9)))
+      (br $next)))
+  (local.get $p))
+
+;; Render a value the way the interpreter prints it. Returns the address of
+;; the text, and leaves its length in `$out_len`.
+(func $rt_show (param $v i32) (result i32)
+  (local $out i32)
+  (local $p i32)
+  (local $tag i32)
+  (local.set $tag (i32.load (local.get $v)))
+  (local.set $out
+    (call $rt_alloc
+      (if (result i32) (i32.eqz (local.get $tag))
+        (then (i32.add (i32.mul (call $len (local.get $v)) (i32.const 10)) (i32.const 2)))
+        (else
+          (if (result i32) (i32.eq (local.get $tag) (i32.const 1))
+            (then (i32.const 5))
+            (else (i32.add (i32.load offset=8 (local.get $v)) (i32.const 10))))))))
+  (local.set $p (local.get $out))
+  (block $shown
+    (if (i32.eqz (local.get $tag))
+      (then
+        (local.set $p (call $put_int (local.get $p) (local.get $v)))
+        (br $shown)))
+    (if (i32.eq (local.get $tag) (i32.const 1))
+      (then
+        (local.set $p (if (result i32) (call $rt_truthy (local.get $v))
+                        (then (call $put (local.get $p) (i32.const 24) (i32.const 4)))
+                        (else (call $put (local.get $p) (i32.const 28) (i32.const 5)))))
+        (br $shown)))
+    (if (i32.load offset=4 (local.get $v))
+      (then
+        (local.set $p (call $put (local.get $p) (i32.const 37) (i32.const 9)))
+        (local.set $p (call $put (local.get $p) (i32.load offset=4 (local.get $v))
+                                 (i32.load offset=8 (local.get $v))))
+        (local.set $p (call $put (local.get $p) (i32.const 46) (i32.const 1))))
+      (else
+        (local.set $p (call $put (local.get $p) (i32.const 33) (i32.const 4))))))
+  (global.set $out_len (i32.sub (local.get $p) (local.get $out)))
+  (local.get $out))
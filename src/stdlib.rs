@@ -0,0 +1,14 @@
+//! The standard library: definitions written in Gollum, built into the
+//! binary.
+//!
+//! A `module::Loader` loads these files, in order, before any other, and
+//! binds their definitions in every file it loads. Each file sees the
+//! definitions of the ones before it.
+
+/// The path and contents of each file.
+pub const FILES: &[(&str, &str)] = &[
+    ("std/function.gol", include_str!("std/function.gol")),
+    ("std/num.gol", include_str!("std/num.gol")),
+    ("std/list.gol", include_str!("std/list.gol")),
+    ("std/option.gol", include_str!("std/option.gol")),
+];
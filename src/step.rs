@@ -0,0 +1,452 @@
+//! A small-step evaluator.
+//!
+//! `Stepper` evaluates a program one transition at a time, on an explicit
+//! stack rather than the native one, so that evaluation can be paused and
+//! inspected between any two steps. Most transitions are bookkeeping:
+//! evaluating a literal or a `fn`, or moving from a call's function to its
+//! argument. The ones that reduce the program, and the returns from calls,
+//! are reported as `Step`s.
+//!
+//! Evaluation agrees with `eval::eval`, including its errors, and stops
+//! when it exceeds its `Limits` in the same ways.
+
+use ast::{Loc, AST};
+use eval;
+use eval::{Env, EvalError, Limits, Meter, Value};
+use names::Name;
+
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::rc::Rc;
+
+/// Something that happened to the program, at the call depth `depth`.
+#[derive(Clone, Debug)]
+pub struct Step<'a> {
+    /// The expression that was reduced, or the call that returned.
+    pub loc: Loc<'a>,
+    pub depth: usize,
+    pub kind: Kind<'a>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Kind<'a> {
+    /// A variable was replaced by its value. Looking up a primitive is not
+    /// reported.
+    Lookup(Name<'a>, Value<'a>),
+    /// A function was applied, binding its parameter; evaluation continues
+    /// in its body, one call deeper.
+    Apply(Name<'a>, Value<'a>),
+    /// A primitive received its last argument and computed its result.
+    Builtin(&'static str, Vec<Value<'a>>, Value<'a>),
+    /// An `if` chose a branch.
+    Branch(bool),
+    /// A call's body finished with a value.
+    Return(Value<'a>),
+}
+
+impl<'a> fmt::Display for Kind<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Kind::Lookup(ref name, ref val) => write!(f, "{} = {}", name, val),
+            Kind::Apply(ref param, ref arg) => write!(f, "apply: {} = {}", param, arg),
+            Kind::Builtin(name, ref args, ref result) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ") = {}", result)
+            }
+            Kind::Branch(cond) => write!(f, "if {}", cond),
+            Kind::Return(ref val) => write!(f, "return {}", val),
+        }
+    }
+}
+
+enum State<'a> {
+    Eval(&'a AST<'a>, Rc<Env<'a>>),
+    Return(Value<'a>),
+}
+
+/// What to do with the value of the expression being evaluated.
+enum Frame<'a> {
+    /// It is a call's function; evaluate the argument next.
+    Arg(Loc<'a>, &'a AST<'a>, Rc<Env<'a>>),
+    /// It is a call's argument; apply the function to it.
+    Call(Loc<'a>, Value<'a>),
+    /// It is an `if`'s condition.
+    Branch(Loc<'a>, Loc<'a>, &'a AST<'a>, &'a AST<'a>, Rc<Env<'a>>),
+    /// It is the result of the call at this location, made from the given
+    /// environment.
+    Body(Loc<'a>, Rc<Env<'a>>),
+}
+
+pub struct Stepper<'a> {
+    state: Option<State<'a>>,
+    stack: Vec<Frame<'a>>,
+    /// The environment of the innermost call.
+    env: Rc<Env<'a>>,
+    depth: usize,
+    value: Option<Value<'a>>,
+    meter: Meter,
+}
+
+impl<'a> Stepper<'a> {
+    /// Evaluate `ast` under `limits`.
+    pub fn new(env: &Rc<Env<'a>>, ast: &'a AST<'a>, limits: &Limits) -> Stepper<'a> {
+        Stepper {
+            state: Some(State::Eval(ast, Rc::clone(env))),
+            stack: Vec::new(),
+            env: Rc::clone(env),
+            depth: 0,
+            value: None,
+            meter: Meter::new(limits),
+        }
+    }
+
+    /// The expression about to be evaluated, if the next transition starts
+    /// evaluating one.
+    pub fn focus(&self) -> Option<&'a AST<'a>> {
+        match self.state {
+            Some(State::Eval(ast, _)) => Some(ast),
+            _ => None,
+        }
+    }
+
+    /// The environment of the expression in focus, or else of the
+    /// innermost call.
+    pub fn env(&self) -> &Rc<Env<'a>> {
+        match self.state {
+            Some(State::Eval(_, ref env)) => env,
+            _ => &self.env,
+        }
+    }
+
+    /// How many calls are in progress.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The locations of the calls in progress, innermost first.
+    pub fn calls(&self) -> Vec<Loc<'a>> {
+        self.stack
+            .iter()
+            .rev()
+            .filter_map(|frame| match *frame {
+                Frame::Body(ref loc, _) => Some(loc.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The program's value, once it has finished.
+    pub fn value(&self) -> Option<&Value<'a>> {
+        self.value.as_ref()
+    }
+
+    /// Take one transition, returning the step it made, if any. Returns
+    /// `Ok(None)` forever once evaluation has finished or failed.
+    pub fn advance(&mut self) -> Result<Option<Step<'a>>, EvalError<'a>> {
+        match self.state.take() {
+            None => Ok(None),
+            Some(State::Eval(ast, env)) => {
+                self.env = Rc::clone(&env);
+                self.eval(ast, env)
+            }
+            Some(State::Return(val)) => self.ret(val),
+        }
+    }
+
+    /// Run to the next step, or return `Ok(None)` if there are none left.
+    pub fn step(&mut self) -> Result<Option<Step<'a>>, EvalError<'a>> {
+        while self.state.is_some() {
+            if let Some(step) = self.advance()? {
+                return Ok(Some(step));
+            }
+        }
+        Ok(None)
+    }
+
+    fn event(&self, loc: &Loc<'a>, kind: Kind<'a>) -> Option<Step<'a>> {
+        Some(Step {
+            loc: loc.clone(),
+            depth: self.depth,
+            kind,
+        })
+    }
+
+    fn eval(
+        &mut self,
+        ast: &'a AST<'a>,
+        env: Rc<Env<'a>>,
+    ) -> Result<Option<Step<'a>>, EvalError<'a>> {
+        self.meter.step(&ast.loc(), self.stack.len())?;
+        match *ast {
+            AST::Integer(ref loc, ref val) => {
+                self.meter.alloc(loc, eval::int_size(val))?;
+                self.state = Some(State::Return(Value::Integer(val.clone())))
+            }
+            AST::Boolean(_, val) => self.state = Some(State::Return(Value::Boolean(val))),
+            AST::Variable(ref loc, ref var) => {
+                let val = env
+                    .lookup(var)
+                    .ok_or_else(|| EvalError::UnboundVariable(loc.clone(), var.clone()))?;
+                self.state = Some(State::Return(val.clone()));
+                if let Value::Builtin(..) = val {
+                    return Ok(None);
+                }
+                return Ok(self.event(loc, Kind::Lookup(var.clone(), val)));
+            }
+            AST::Abstraction(ref loc, ref param, ref body) => {
+                let param = match **param {
+                    AST::Ascription(_, ref var, _) => var,
+                    _ => param,
+                };
+                match **param {
+                    AST::Variable(_, ref name) => {
+                        self.meter.alloc(loc, mem::size_of::<eval::Closure>())?;
+                        self.state = Some(State::Return(Value::Closure(Rc::new(eval::Closure {
+                            param: name.clone(),
+                            body,
+                            env,
+                        }))))
+                    }
+                    _ => return Err(EvalError::NotAnExpression(loc.clone())),
+                }
+            }
+            AST::Application(ref loc, ref func, ref arg) => {
+                self.stack
+                    .push(Frame::Arg(loc.clone(), arg, Rc::clone(&env)));
+                self.state = Some(State::Eval(func, env));
+            }
+            AST::If(ref loc, ref cond, ref cons, ref alt) => {
+                self.stack.push(Frame::Branch(
+                    loc.clone(),
+                    cond.loc(),
+                    cons,
+                    alt,
+                    Rc::clone(&env),
+                ));
+                self.state = Some(State::Eval(cond, env));
+            }
+            AST::Ascription(_, ref val, _) => self.state = Some(State::Eval(val, env)),
+            AST::TyName(ref loc, _)
+            | AST::TyFn(ref loc, _, _)
+            | AST::TyApp(ref loc, _, _)
+            | AST::TyQual(ref loc, _, _) => {
+                return Err(EvalError::NotAnExpression(loc.clone()))
+            }
+        }
+        Ok(None)
+    }
+
+    fn ret(&mut self, val: Value<'a>) -> Result<Option<Step<'a>>, EvalError<'a>> {
+        match self.stack.pop() {
+            None => {
+                self.value = Some(val);
+                Ok(None)
+            }
+            Some(Frame::Arg(loc, arg, env)) => {
+                self.stack.push(Frame::Call(loc, val));
+                self.state = Some(State::Eval(arg, env));
+                Ok(None)
+            }
+            Some(Frame::Call(loc, func)) => self.apply(loc, func, val),
+            Some(Frame::Branch(loc, cond_loc, cons, alt, env)) => match val {
+                Value::Boolean(cond) => {
+                    self.state = Some(State::Eval(if cond { cons } else { alt }, env));
+                    Ok(self.event(&loc, Kind::Branch(cond)))
+                }
+                _ => Err(EvalError::NotABoolean(cond_loc)),
+            },
+            Some(Frame::Body(loc, env)) => {
+                self.env = env;
+                self.depth -= 1;
+                self.state = Some(State::Return(val.clone()));
+                Ok(self.event(&loc, Kind::Return(val)))
+            }
+        }
+    }
+
+    fn apply(
+        &mut self,
+        loc: Loc<'a>,
+        func: Value<'a>,
+        arg: Value<'a>,
+    ) -> Result<Option<Step<'a>>, EvalError<'a>> {
+        match func {
+            Value::Closure(closure) => {
+                self.meter.alloc(&loc, mem::size_of::<Env>() + mem::size_of::<Value>())?;
+                let step = self.event(&loc, Kind::Apply(closure.param.clone(), arg.clone()));
+                let frame = Env::with_bindings(&closure.env, vec![(closure.param.clone(), arg)]);
+                self.stack.push(Frame::Body(loc, Rc::clone(&self.env)));
+                self.depth += 1;
+                self.state = Some(State::Eval(closure.body, frame));
+                Ok(step)
+            }
+            Value::Builtin(builtin, mut args) => {
+                args.push(arg);
+                if args.len() < builtin.arity {
+                    self.meter.alloc(&loc, mem::size_of::<Value>() * args.len())?;
+                    self.state = Some(State::Return(Value::Builtin(builtin, args)));
+                    return Ok(None);
+                }
+                let depth = self.stack.len();
+                let val = eval::call_builtin(&loc, &builtin, &args, &mut self.meter, depth)?;
+                self.state = Some(State::Return(val.clone()));
+                Ok(self.event(&loc, Kind::Builtin(builtin.name, args, val)))
+            }
+            _ => Err(EvalError::NotAFunction(loc)),
+        }
+    }
+}
+
+/// The line of `src` that `loc` starts on, with the part of it `loc` covers
+/// underlined.
+pub fn highlight(src: &str, loc: &Loc) -> String {
+    let begin = (loc.begin as usize).min(src.len());
+    let end = (loc.end as usize).clamp(begin, src.len());
+    let start = src[..begin].rfind('\n').map_or(0, |i| i + 1);
+    let stop = src[begin..].find('\n').map_or(src.len(), |i| begin + i);
+    let mut out = String::from("    ");
+    out.push_str(&src[start..stop]);
+    out.push_str("\n    ");
+    for ch in src[start..begin].chars() {
+        out.push(if ch == '\t' { '\t' } else { ' ' });
+    }
+    let width = src[begin..end.min(stop)].chars().count();
+    for _ in 0..width.max(1) {
+        out.push('^');
+    }
+    out
+}
+
+/// Evaluate `ast` under `limits`, writing each step to `out` along with the
+/// source it happened at.
+pub fn trace<'a, W: Write>(
+    src: &str,
+    env: &Rc<Env<'a>>,
+    ast: &'a AST<'a>,
+    limits: &Limits,
+    out: &mut W,
+) -> io::Result<Result<Value<'a>, EvalError<'a>>> {
+    let mut stepper = Stepper::new(env, ast, limits);
+    loop {
+        match stepper.step() {
+            Ok(Some(step)) => {
+                let pos = step.loc.start(src);
+                writeln!(
+                    out,
+                    "{}:{}:{}: {}",
+                    step.loc.file, pos.line, pos.column, step.kind
+                )?;
+                writeln!(out, "{}", highlight(src, &step.loc))?;
+            }
+            Ok(None) => return Ok(Ok(stepper.value().unwrap().clone())),
+            Err(e) => return Ok(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globals;
+    use parser;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_highlight() {
+        let src = "let\n  add(x, 1)\n";
+        let loc = Loc {
+            file: "test",
+            begin: 6,
+            end: 15,
+        };
+        assert_eq!(highlight(src, &loc), "      add(x, 1)\n      ^^^^^^^^^");
+    }
+
+    #[test]
+    fn test_steps() {
+        let src = "(fn(x : int) { if iszero(x) { 1 } else { add(x, 1) } })(2)";
+        let ast = parser::parse("test", src).unwrap();
+        let mut stepper = Stepper::new(&globals::global_values(), &ast, &Limits::default());
+        let mut steps = Vec::new();
+        while let Some(step) = stepper.step().unwrap() {
+            steps.push(format!("{} {} {}", step.loc.begin, step.depth, step.kind));
+        }
+        assert_eq!(
+            steps,
+            vec![
+                "0 0 apply: x = 2",
+                "25 1 x = 2",
+                "18 1 iszero(2) = false",
+                "15 1 if false",
+                "45 1 x = 2",
+                "41 1 add(2, 1) = 3",
+                "0 0 return 3",
+            ]
+        );
+        assert_eq!(stepper.value().unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn test_errors() {
+        let ast = parser::parse("test", "add(1, 2)(3)").unwrap();
+        let mut stepper = Stepper::new(&globals::global_values(), &ast, &Limits::default());
+        let err = loop {
+            match stepper.step() {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("expected an error"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(err.kind(), "NotAFunction");
+        assert!(stepper.step().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_limits() {
+        let run = |src: &str, limits: Limits| {
+            let ast = parser::parse("test", src).unwrap();
+            let mut out = Vec::new();
+            trace(src, &globals::global_values(), &ast, &limits, &mut out)
+                .unwrap()
+                .map(|val| val.to_string())
+                .map_err(|e| e.kind())
+        };
+        let omega = "fn(x) { x(x) }(fn(x) { x(x) })";
+        let fuel = Limits {
+            fuel: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(run(omega, fuel.clone()), Err("OutOfFuel"));
+        assert_eq!(run("add(1, 2)", fuel.clone()), Ok("3".to_string()));
+        // Builtins that apply functions draw on the same fuel.
+        assert_eq!(run("fix(fn(f, x) { f(x) }, 1)", fuel), Err("OutOfFuel"));
+
+        let depth = Limits {
+            max_depth: Some(100),
+            ..Limits::default()
+        };
+        assert_eq!(run(omega, depth), Err("RecursionLimit"));
+
+        let heap = Limits {
+            max_heap: Some(4096),
+            ..Limits::default()
+        };
+        let squares = format!("fn(sq) {{ {}2{} }}(fn(x) {{ mul(x, x) }})", "sq(".repeat(16), ")".repeat(16));
+        assert_eq!(run(&squares, heap), Err("HeapLimit"));
+
+        let deadline = Limits {
+            timeout: Some(Duration::from_secs(0)),
+            ..Limits::default()
+        };
+        assert_eq!(run(omega, deadline), Err("Deadline"));
+    }
+}
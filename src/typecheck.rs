@@ -1,3 +1,6 @@
+use std::fmt;
+use std::mem;
+use std::ptr;
 use std::rc::Rc;
 use std::collections::HashMap;
 
@@ -6,11 +9,12 @@ use names;
 use names::Name;
 
 use types;
-use types::Type;
+use types::{Kind, Type};
 
 use env::TypeEnv;
 
 use globals;
+use parser;
 
 #[derive(Debug)]
 pub enum TypeError<'a> {
@@ -21,21 +25,239 @@ pub enum TypeError<'a> {
     Mismatch(ast::Loc<'a>, Rc<Type<'a>>, Rc<Type<'a>>),
     BadDecl(ast::Loc<'a>),
     BadType(ast::Loc<'a>),
+    /// A type constructor of the given kind applied to the wrong number of
+    /// arguments.
+    KindMismatch(ast::Loc<'a>, Name<'a>, Kind, usize),
     Occur(ast::Loc<'a>, Rc<Type<'a>>, Rc<Type<'a>>),
+    UnboundClass(ast::Loc<'a>, Name<'a>),
+    /// An overloaded name is used at a type with no instance of its class.
+    NoInstance(ast::Loc<'a>, Name<'a>, Rc<Type<'a>>),
+    /// An overloaded name is used at a type nothing determines, and that
+    /// the default, `int`, cannot stand for.
+    Ambiguous(ast::Loc<'a>, Name<'a>, Rc<Type<'a>>),
+    DuplicateClass(ast::Loc<'a>, Name<'a>),
+    DuplicateInstance(ast::Loc<'a>, Name<'a>, Rc<Type<'a>>),
+    /// An instance does not define one of its class's methods.
+    MissingMethod(ast::Loc<'a>, Name<'a>, Name<'a>),
+    /// An instance defines something its class does not declare.
+    NotAMethod(ast::Loc<'a>, Name<'a>, Name<'a>),
+    /// A type grew beyond `Limits::max_type_size`.
+    TypeTooLarge(ast::Loc<'a>),
+    /// The program nests deeper than `Limits::max_depth`.
+    TooDeep(ast::Loc<'a>),
+    /// A binding or type that typechecks, but that the backend the program
+    /// is for cannot compile.
+    Unsupported(ast::Loc<'a>, String),
+}
+
+impl<'a> TypeError<'a> {
+    /// The name of the error's variant, for tools that match on it.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            TypeError::Generic(..) => "Generic",
+            TypeError::UnboundVariable(..) => "UnboundVariable",
+            TypeError::UnboundType(..) => "UnboundType",
+            TypeError::BadFunction(..) => "BadFunction",
+            TypeError::Mismatch(..) => "Mismatch",
+            TypeError::BadDecl(..) => "BadDecl",
+            TypeError::BadType(..) => "BadType",
+            TypeError::KindMismatch(..) => "KindMismatch",
+            TypeError::Occur(..) => "Occur",
+            TypeError::UnboundClass(..) => "UnboundClass",
+            TypeError::NoInstance(..) => "NoInstance",
+            TypeError::Ambiguous(..) => "Ambiguous",
+            TypeError::DuplicateClass(..) => "DuplicateClass",
+            TypeError::DuplicateInstance(..) => "DuplicateInstance",
+            TypeError::MissingMethod(..) => "MissingMethod",
+            TypeError::NotAMethod(..) => "NotAMethod",
+            TypeError::TypeTooLarge(..) => "TypeTooLarge",
+            TypeError::TooDeep(..) => "TooDeep",
+            TypeError::Unsupported(..) => "Unsupported",
+        }
+    }
+
+    pub fn loc(&self) -> ast::Loc<'a> {
+        match *self {
+            TypeError::Generic(ref loc, _)
+            | TypeError::UnboundVariable(ref loc, _)
+            | TypeError::UnboundType(ref loc, _)
+            | TypeError::BadFunction(ref loc, _)
+            | TypeError::Mismatch(ref loc, _, _)
+            | TypeError::BadDecl(ref loc)
+            | TypeError::BadType(ref loc)
+            | TypeError::KindMismatch(ref loc, _, _, _)
+            | TypeError::Occur(ref loc, _, _)
+            | TypeError::UnboundClass(ref loc, _)
+            | TypeError::NoInstance(ref loc, _, _)
+            | TypeError::Ambiguous(ref loc, _, _)
+            | TypeError::DuplicateClass(ref loc, _)
+            | TypeError::DuplicateInstance(ref loc, _, _)
+            | TypeError::MissingMethod(ref loc, _, _)
+            | TypeError::NotAMethod(ref loc, _, _)
+            | TypeError::TypeTooLarge(ref loc)
+            | TypeError::TooDeep(ref loc)
+            | TypeError::Unsupported(ref loc, _) => loc.clone(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for TypeError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            TypeError::Generic(_, msg) => f.write_str(msg),
+            TypeError::UnboundVariable(_, ref name) => write!(f, "unbound variable `{}`", name),
+            TypeError::UnboundType(_, ref name) => write!(f, "unknown type `{}`", name),
+            TypeError::BadFunction(_, ref ty) => write!(f, "`{}` is not a function", ty),
+            TypeError::Mismatch(_, ref left, ref right) => {
+                write!(f, "type mismatch: `{}` is not `{}`", left, right)
+            }
+            TypeError::BadDecl(_) => f.write_str("malformed declaration"),
+            TypeError::BadType(_) => f.write_str("malformed type"),
+            TypeError::KindMismatch(_, ref name, ref kind, args) => write!(
+                f,
+                "`{}` has kind `{}` but is applied to {} type argument{}",
+                name,
+                kind,
+                args,
+                if args == 1 { "" } else { "s" }
+            ),
+            TypeError::Occur(_, ref var, ref ty) => {
+                write!(f, "infinite type: `{}` occurs in `{}`", var, ty)
+            }
+            TypeError::UnboundClass(_, ref name) => write!(f, "unknown class `{}`", name),
+            TypeError::NoInstance(_, ref class, ref ty) => {
+                write!(f, "no instance of `{}` for `{}`", class, ty)
+            }
+            TypeError::Ambiguous(_, ref class, ref ty) => {
+                write!(f, "ambiguous type: cannot choose an instance of `{}` for `{}`", class, ty)
+            }
+            TypeError::DuplicateClass(_, ref name) => {
+                write!(f, "class `{}` is already declared", name)
+            }
+            TypeError::DuplicateInstance(_, ref class, ref ty) => {
+                write!(f, "instance `{} {}` is already declared", class, ty)
+            }
+            TypeError::MissingMethod(_, ref class, ref method) => {
+                write!(f, "instance of `{}` does not define `{}`", class, method)
+            }
+            TypeError::NotAMethod(_, ref class, ref method) => {
+                write!(f, "`{}` is not a method of `{}`", method, class)
+            }
+            TypeError::TypeTooLarge(_) => f.write_str("type is too large"),
+            TypeError::TooDeep(_) => f.write_str("expression is nested too deeply"),
+            TypeError::Unsupported(_, ref what) => {
+                write!(f, "`{}` is not supported by this backend", what)
+            }
+        }
+    }
 }
 
 pub type TCResult<'a> = Result<Rc<types::Type<'a>>, TypeError<'a>>;
 
+/// The inferred type of every expression node in a program. Nodes are
+/// identified by address, so lookups must use the same AST that was
+/// checked.
+#[derive(Debug)]
+pub struct NodeTypes<'a> {
+    types: HashMap<*const ast::AST<'a>, Rc<Type<'a>>>,
+}
+
+impl<'a> NodeTypes<'a> {
+    pub fn get(&self, node: &ast::AST<'a>) -> Option<Rc<Type<'a>>> {
+        self.types.get(&(node as *const ast::AST<'a>)).cloned()
+    }
+}
+
+/// The solutions `unify` discovered while checking a program, in the order
+/// it found them, and the error that stopped it, if any.
+#[derive(Debug)]
+pub struct Constraints<'a> {
+    pub solutions: Vec<(Name<'a>, Rc<Type<'a>>)>,
+    pub error: Option<TypeError<'a>>,
+}
+
+/// Bounds on the work checking a program may do. Each limit left as `None`
+/// is not enforced; `Limits::default()` enforces none of them.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The most nodes any type built during checking may have.
+    pub max_type_size: Option<usize>,
+    /// The deepest the program's syntax tree may be.
+    pub max_depth: Option<usize>,
+}
+
+impl Limits {
+    /// Limits generous enough for any reasonable program, which stop
+    /// checking before it exhausts the stack or memory. The depth is the
+    /// parser's `MAX_DEPTH`, so no tree the parser accepts is too deep to
+    /// check. `Engine` and the module loader check programs under these.
+    pub fn standard() -> Limits {
+        Limits {
+            max_type_size: Some(100_000),
+            max_depth: Some(parser::MAX_DEPTH),
+        }
+    }
+}
+
+/// Whether `ty` has more than `max` nodes. Stops counting once it does, so
+/// that types which share structure do not have to be expanded in full.
+fn larger_than(ty: &Type, max: usize) -> bool {
+    fn count(ty: &Type, budget: &mut usize) -> bool {
+        if *budget == 0 {
+            return true;
+        }
+        *budget -= 1;
+        match *ty {
+            Type::Function(ref dom, ref range) => count(dom, budget) || count(range, budget),
+            Type::App(_, ref args) => args.iter().any(|arg| count(arg, budget)),
+            _ => false,
+        }
+    }
+    let mut budget = max;
+    count(ty, &mut budget)
+}
+
+/// A use of an overloaded name, which needs the dictionary of an instance
+/// of `class` for `ty`.
+struct Wanted<'a> {
+    /// The variable the dictionary is passed to, or null for a constraint
+    /// a signature declares.
+    node: *const ast::AST<'a>,
+    loc: ast::Loc<'a>,
+    class: Name<'a>,
+    ty: Rc<Type<'a>>,
+    /// The scheme of the variable, by which a use of a method is told
+    /// apart from other overloaded names.
+    scheme: Option<Rc<Type<'a>>>,
+}
+
 struct Typecheck<'a> {
+    limits: Limits,
     uniq: i32,
     soln: HashMap<Name<'a>, Rc<Type<'a>>>,
+    solved: Vec<(Name<'a>, Rc<Type<'a>>)>,
+    nodes: HashMap<*const ast::AST<'a>, Rc<Type<'a>>>,
+    wanted: Vec<Wanted<'a>>,
 }
 
 impl<'a, 'b> Typecheck<'a> {
-    fn new() -> Typecheck<'a> {
+    fn new(limits: &Limits) -> Typecheck<'a> {
         Typecheck {
+            limits: limits.clone(),
             uniq: 0,
             soln: HashMap::new(),
+            solved: Vec::new(),
+            nodes: HashMap::new(),
+            wanted: Vec::new(),
+        }
+    }
+
+    /// Reject `ast` if it is deeper than `Limits::max_depth`, before
+    /// checking it recursively.
+    fn check_depth(&self, ast: &ast::AST<'a>) -> Result<(), TypeError<'a>> {
+        match self.limits.max_depth.and_then(|max| ast::too_deep(ast, max)) {
+            Some(node) => Err(TypeError::TooDeep(node.loc())),
+            None => Ok(()),
         }
     }
 
@@ -50,13 +272,10 @@ impl<'a, 'b> Typecheck<'a> {
 
     pub fn subst_type(&'b mut self, ty: &Rc<types::Type<'a>>) -> Rc<types::Type<'a>> {
         types::map_vars(ty, &mut |var| {
-            println!("map?({:?})", var);
-
             match *var {
                 types::Type::Variable(ref name) => match self.soln.get(name).cloned() {
                     Some(mapped) => {
                         let inner = self.subst_type(&mapped);
-                        println!(" = {:?} = {:?}", mapped, &inner);
                         self.soln.insert(name.clone(), Rc::clone(&inner));
                         inner
                     }
@@ -67,13 +286,77 @@ impl<'a, 'b> Typecheck<'a> {
         })
     }
 
-    fn occur(&'b self, _var: &Name<'a>, _ty: &Rc<types::Type<'a>>) -> bool {
-        return false;
+    /// Whether `var` appears in `ty`, which must already be substituted.
+    fn occur(&'b self, var: &Name<'a>, ty: &Rc<types::Type<'a>>) -> bool {
+        match **ty {
+            types::Type::Variable(ref name) => name == var,
+            types::Type::Function(ref dom, ref range) => {
+                self.occur(var, dom) || self.occur(var, range)
+            }
+            types::Type::App(_, ref args) => args.iter().any(|arg| self.occur(var, arg)),
+            types::Type::Qualified(ref constraints, ref body) => {
+                constraints.iter().any(|(_, ty)| self.occur(var, ty)) || self.occur(var, body)
+            }
+            types::Type::Primitive(_) => false,
+            types::Type::ForAll(ref vars, ref body) => {
+                !vars.iter().any(|bound| match **bound {
+                    types::Type::Variable(ref name) => name == var,
+                    _ => false,
+                }) && self.occur(var, body)
+            }
+        }
+    }
+
+    /// The type of the variable `node`, whose scheme is `ty`. Any
+    /// constraints on the scheme are added to those `node` needs
+    /// dictionaries for.
+    fn instantiate(
+        &'b mut self,
+        node: &ast::AST<'a>,
+        ty: Rc<types::Type<'a>>,
+    ) -> Rc<types::Type<'a>> {
+        let scheme = Rc::clone(&ty);
+        let ty = self.fresh(ty);
+        match *ty {
+            types::Type::Qualified(ref constraints, ref body) => {
+                for (class, arg) in constraints {
+                    self.wanted.push(Wanted {
+                        node,
+                        loc: node.loc(),
+                        class: class.clone(),
+                        ty: Rc::clone(arg),
+                        scheme: Some(Rc::clone(&scheme)),
+                    });
+                }
+                Rc::clone(body)
+            }
+            _ => Rc::clone(&ty),
+        }
+    }
+
+    /// A copy of the scheme `ty` with fresh variables for its quantified
+    /// ones. Other types are returned as they are.
+    fn fresh(&'b mut self, ty: Rc<types::Type<'a>>) -> Rc<types::Type<'a>> {
+        match *ty {
+            types::Type::ForAll(ref vars, ref body) => {
+                let fresh: Vec<(Rc<Type<'a>>, Rc<Type<'a>>)> = vars
+                    .iter()
+                    .map(|var| (Rc::clone(var), self.genvar(names::typ("t"))))
+                    .collect();
+                types::map_vars(body, &mut |var| {
+                    match fresh.iter().find(|(bound, _)| *bound == var) {
+                        Some((_, to)) => Rc::clone(to),
+                        None => var,
+                    }
+                })
+            }
+            _ => ty,
+        }
     }
 
     fn add_soln(&'b mut self, var: &Name<'a>, ty: &Rc<types::Type<'a>>) {
-        println!("solve: {:?} = {:?}", var, ty);
         self.soln.insert(var.clone(), Rc::clone(ty));
+        self.solved.push((var.clone(), Rc::clone(ty)));
     }
 
     fn unify(
@@ -82,19 +365,15 @@ impl<'a, 'b> Typecheck<'a> {
         left: &Rc<types::Type<'a>>,
         right: &Rc<types::Type<'a>>,
     ) -> TCResult<'a> {
-        println!("unify: {:?} =?= {:?}", left, right);
-
         let left = self.subst_type(left);
         let right = self.subst_type(right);
 
-        println!("substituted: {:?} =?= {:?}", left, right);
-
         if Rc::ptr_eq(&left, &right) {
             return Ok(left);
         }
 
         match (&*left, &*right) {
-            (&types::Type::Variable(ref lv), _) => {
+            (types::Type::Variable(lv), _) => {
                 if self.occur(lv, &right) {
                     return Err(TypeError::Occur(
                         node.loc(),
@@ -104,7 +383,7 @@ impl<'a, 'b> Typecheck<'a> {
                 }
                 self.add_soln(lv, &right);
             }
-            (_, &types::Type::Variable(ref rv)) => {
+            (_, types::Type::Variable(rv)) => {
                 if self.occur(rv, &left) {
                     return Err(TypeError::Occur(
                         node.loc(),
@@ -115,13 +394,20 @@ impl<'a, 'b> Typecheck<'a> {
                 self.add_soln(rv, &left);
             }
             (
-                &types::Type::Function(ref ldom, ref lrange),
-                &types::Type::Function(ref rdom, ref rrange),
+                types::Type::Function(ldom, lrange),
+                types::Type::Function(rdom, rrange),
             ) => {
                 self.unify(node, ldom, rdom)?;
                 self.unify(node, lrange, rrange)?;
             }
-            (&types::Type::Primitive(ref lt), &types::Type::Primitive(ref rt)) if lt == rt => {}
+            (types::Type::Primitive(lt), types::Type::Primitive(rt)) if lt == rt => {}
+            (types::Type::App(lcon, largs), types::Type::App(rcon, rargs))
+                if lcon == rcon && largs.len() == rargs.len() =>
+            {
+                for (larg, rarg) in largs.iter().zip(rargs) {
+                    self.unify(node, larg, rarg)?;
+                }
+            }
             (_, _) => {
                 return Err(TypeError::Mismatch(
                     node.loc(),
@@ -131,41 +417,76 @@ impl<'a, 'b> Typecheck<'a> {
             }
         }
 
-        Ok(self.subst_type(&left))
+        let ty = self.subst_type(&left);
+        if let Some(max) = self.limits.max_type_size {
+            if larger_than(&ty, max) {
+                return Err(TypeError::TypeTooLarge(node.loc()));
+            }
+        }
+        Ok(ty)
     }
 
     fn ast_to_type(&mut self, env: &Rc<TypeEnv<'a>>, ast: &ast::AST<'a>) -> TCResult<'a> {
         match *ast {
             ast::AST::TyName(_, ref tyvar) => {
-                if let Some(ty) = env.lookup(tyvar) {
+                if let Some(kind) = constructor_kind(tyvar) {
+                    Err(TypeError::KindMismatch(ast.loc(), tyvar.clone(), kind, 0))
+                } else if let Some(ty) = env.lookup(tyvar) {
                     Ok(ty)
                 } else {
                     Err(TypeError::UnboundType(ast.loc(), tyvar.clone()))
                 }
             }
             ast::AST::TyFn(_, ref dom, ref range) => {
-                let dom_ty = self.ast_to_type(&env, dom)?;
-                let range_ty = self.ast_to_type(&env, range)?;
+                let dom_ty = self.ast_to_type(env, dom)?;
+                let range_ty = self.ast_to_type(env, range)?;
                 Ok(Rc::new(Type::Function(dom_ty, range_ty)))
             }
+            ast::AST::TyApp(_, ref con, ref args) => {
+                // Named types in scope, like `int` or a type variable, take
+                // no arguments.
+                let kind = match constructor_kind(con) {
+                    Some(kind) => kind,
+                    None if env.lookup(con).is_some() => Kind::Star,
+                    None => return Err(TypeError::UnboundType(ast.loc(), con.clone())),
+                };
+                if args.len() != kind.arity() {
+                    return Err(TypeError::KindMismatch(ast.loc(), con.clone(), kind, args.len()));
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| self.ast_to_type(env, arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Rc::new(Type::App(con.clone(), args)))
+            }
             _ => Err(TypeError::BadType(ast.loc())),
         }
     }
 
     fn typecheck(&mut self, env: &Rc<TypeEnv<'a>>, ast: &ast::AST<'a>) -> TCResult<'a> {
+        let ty = ast::grow(|| self.infer(env, ast))?;
+        self.record(ast, &ty);
+        Ok(ty)
+    }
+
+    fn record(&mut self, node: &ast::AST<'a>, ty: &Rc<Type<'a>>) {
+        self.nodes.insert(node as *const ast::AST<'a>, Rc::clone(ty));
+    }
+
+    fn infer(&mut self, env: &Rc<TypeEnv<'a>>, ast: &ast::AST<'a>) -> TCResult<'a> {
         match *ast {
             ast::AST::Integer(..) => Ok(globals::integer()),
             ast::AST::Boolean(..) => Ok(globals::bool()),
             ast::AST::Variable(_, ref var) => {
                 if let Some(ty) = env.lookup(var) {
-                    Ok(ty)
+                    Ok(self.instantiate(ast, ty))
                 } else {
                     Err(TypeError::UnboundVariable(ast.loc(), var.clone()))
                 }
             }
             ast::AST::Application(_, ref func, ref arg) => {
-                let func_ty = self.typecheck(&env, func)?;
-                let arg_ty = self.typecheck(&env, arg)?;
+                let func_ty = self.typecheck(env, func)?;
+                let arg_ty = self.typecheck(env, arg)?;
                 let rng = self.genvar(names::typ("rv"));
                 self.unify(
                     ast,
@@ -177,7 +498,7 @@ impl<'a, 'b> Typecheck<'a> {
             ast::AST::Abstraction(_, ref arg, ref body) => {
                 let (ty, var) = match **arg {
                     ast::AST::Ascription(_, ref vbox, ref ty) => {
-                        let ty = self.ast_to_type(&env, ty)?;
+                        let ty = self.ast_to_type(env, ty)?;
                         if let ast::AST::Variable(_, ref var) = **vbox {
                             (ty, var)
                         } else {
@@ -189,14 +510,18 @@ impl<'a, 'b> Typecheck<'a> {
                     },
                     _ => panic!("unexpected ast")
                 };
-                let frame = TypeEnv::with_bindings(&env, &[(var.clone(), Rc::clone(&ty))]);
+                self.record(arg, &ty);
+                if let ast::AST::Ascription(_, ref vbox, _) = **arg {
+                    self.record(vbox, &ty);
+                }
+                let frame = TypeEnv::with_bindings(env, &[(var.clone(), Rc::clone(&ty))]);
                 let result_ty = self.typecheck(&frame, body)?;
                 Ok(Rc::new(types::Type::Function(Rc::clone(&ty), result_ty)))
             }
             ast::AST::If(_, ref cond, ref cons, ref alt) => {
-                let cond_ty = self.typecheck(&env, cond)?;
-                let cons_ty = self.typecheck(&env, cons)?;
-                let alt_ty = self.typecheck(&env, alt)?;
+                let cond_ty = self.typecheck(env, cond)?;
+                let cons_ty = self.typecheck(env, cons)?;
+                let alt_ty = self.typecheck(env, alt)?;
 
                 self.unify(cond, &cond_ty, &globals::bool())?;
                 self.unify(ast, &cons_ty, &alt_ty)?;
@@ -204,8 +529,8 @@ impl<'a, 'b> Typecheck<'a> {
                 Ok(cons_ty)
             }
             ast::AST::Ascription(_, ref val, ref ty) => {
-                let got_ty = self.typecheck(&env, val)?;
-                let exp_ty = self.ast_to_type(&env, ty)?;
+                let got_ty = self.typecheck(env, val)?;
+                let exp_ty = self.ast_to_type(env, ty)?;
                 self.unify(ast, &got_ty, &exp_ty)?;
                 Ok(got_ty)
             }
@@ -215,11 +540,713 @@ impl<'a, 'b> Typecheck<'a> {
 }
 
 pub fn typecheck<'a>(env: &Rc<TypeEnv<'a>>, ast: &ast::AST<'a>) -> TCResult<'a> {
-    let mut tc = Typecheck::new();
+    typecheck_with(env, ast, &Limits::default())
+}
+
+/// Typecheck `ast`, stopping with an error if it exceeds any of `limits`.
+pub fn typecheck_with<'a>(env: &Rc<TypeEnv<'a>>, ast: &ast::AST<'a>, limits: &Limits) -> TCResult<'a> {
+    check_program(env, ast, limits).map(|(ty, _)| ty)
+}
+
+/// Typecheck the program `ast` like `check_body`, with the classes in
+/// `Classes::core()`. Returns its type and, if it uses methods like `eq`,
+/// the copy of it to evaluate, which uses the instances' definitions of
+/// them instead.
+pub fn check_program<'a>(
+    env: &Rc<TypeEnv<'a>>,
+    ast: &ast::AST<'a>,
+    limits: &Limits,
+) -> Result<(Rc<Type<'a>>, Option<ast::AST<'a>>), TypeError<'a>> {
+    check_body(env, &Classes::core(), ast, limits)
+}
+
+/// Typecheck `ast` like `check_program`, additionally returning the
+/// fully-substituted type of each of its expression nodes.
+pub fn typecheck_nodes<'a>(
+    env: &Rc<TypeEnv<'a>>,
+    ast: &ast::AST<'a>,
+) -> Result<(Rc<Type<'a>>, NodeTypes<'a>), TypeError<'a>> {
+    let mut tc = Typecheck::new(&Limits::default());
     let ty = tc.typecheck(env, ast)?;
-    Ok(tc.subst_type(&ty))
+    let (ty, _) = tc.solve(&Classes::core(), ast, &ty, false)?;
+    let nodes = tc.nodes.clone();
+    let types = nodes
+        .into_iter()
+        .map(|(node, ty)| (node, tc.subst_type(&ty)))
+        .collect();
+    Ok((tc.subst_type(&ty), NodeTypes { types }))
+}
+
+/// Typecheck `ast` like `typecheck_nodes`, against `globals::global_env`,
+/// for a backend that supports only `globals::primitives`. A program that
+/// uses another binding, like `cons`, a method at another type than its
+/// primitive's, like `eq` on `bool`, or a value of a type built with a
+/// constructor, like `list<int>`, fails with `TypeError::Unsupported`.
+pub fn typecheck_primitive<'a>(
+    ast: &ast::AST<'a>,
+) -> Result<(Rc<Type<'a>>, NodeTypes<'a>), TypeError<'a>> {
+    fn constructed(ty: &Type) -> bool {
+        match *ty {
+            Type::Primitive(_) | Type::Variable(_) => false,
+            Type::App(..) => true,
+            Type::Function(ref dom, ref range) => constructed(dom) || constructed(range),
+            Type::ForAll(_, ref body) | Type::Qualified(_, ref body) => constructed(body),
+        }
+    }
+    fn check<'a>(
+        ast: &ast::AST<'a>,
+        types: &NodeTypes<'a>,
+        prims: &[(Name<'static>, Rc<Type<'static>>)],
+        bound: &mut Vec<Name<'a>>,
+    ) -> Result<(), TypeError<'a>> {
+        match *ast {
+            ast::AST::Variable(ref loc, ref name) if !bound.contains(name) => {
+                match (prims.iter().find(|(prim, _)| prim == name), types.get(ast)) {
+                    (Some((_, ty)), Some(ref used)) if *used != *ty => {
+                        let what = format!("{} : {}", name, used);
+                        return Err(TypeError::Unsupported(loc.clone(), what));
+                    }
+                    (Some(_), _) => {}
+                    (None, _) => return Err(TypeError::Unsupported(loc.clone(), name.to_string())),
+                }
+            }
+            ast::AST::Abstraction(_, ref param, ref body) => {
+                let param = match **param {
+                    ast::AST::Ascription(_, ref var, _) => var,
+                    _ => param,
+                };
+                if let ast::AST::Variable(_, ref name) = **param {
+                    bound.push(name.clone());
+                    check(body, types, prims, bound)?;
+                    bound.pop();
+                }
+            }
+            ast::AST::Ascription(_, ref expr, _) => check(expr, types, prims, bound)?,
+            _ => {
+                for child in ast.children() {
+                    check(child, types, prims, bound)?;
+                }
+            }
+        }
+        // Report the names a program uses before the types they give it.
+        match types.get(ast) {
+            Some(ref ty) if constructed(ty) => {
+                Err(TypeError::Unsupported(ast.loc(), ty.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+    let (ty, types) = typecheck_nodes(&globals::global_env(), ast)?;
+    let prims: Vec<(Name<'static>, Rc<Type<'static>>)> = globals::primitives()
+        .into_iter()
+        .map(|prim| (names::ident(prim.name), prim.ty))
+        .collect();
+    check(ast, &types, &prims, &mut Vec::new())?;
+    Ok((ty, types))
+}
+
+/// Typecheck `ast`, returning the constraint graph built along the way
+/// rather than the program's type.
+pub fn constraints<'a>(env: &Rc<TypeEnv<'a>>, ast: &ast::AST<'a>) -> Constraints<'a> {
+    let mut tc = Typecheck::new(&Limits::default());
+    let error = tc
+        .typecheck(env, ast)
+        .and_then(|ty| tc.solve(&Classes::core(), ast, &ty, false))
+        .err();
+    Constraints {
+        solutions: tc.solved,
+        error,
+    }
+}
+
+/// Names for the variables of generalized types.
+const SCHEME_VARS: [&str; 8] = ["a", "b", "c", "d", "e", "f", "g", "h"];
+
+/// Quantify `ty` over every type variable in it, renaming them `a`, `b`,
+/// and so on in the order they appear. A type with no variables is
+/// returned as it is.
+pub fn generalize<'a>(ty: &Rc<Type<'a>>) -> Rc<Type<'a>> {
+    let mut vars: Vec<(Rc<Type<'a>>, Rc<Type<'a>>)> = Vec::new();
+    let body = types::map_vars(ty, &mut |var| {
+        if let Some((_, to)) = vars.iter().find(|(from, _)| *from == var) {
+            return Rc::clone(to);
+        }
+        let i = vars.len();
+        let name = match SCHEME_VARS.get(i) {
+            Some(name) => names::typ(name),
+            None => Name::Unique(Box::new(names::typ("t")), i as i32),
+        };
+        let to = Rc::new(Type::Variable(name));
+        vars.push((var, Rc::clone(&to)));
+        to
+    });
+    if vars.is_empty() {
+        return body;
+    }
+    Rc::new(Type::ForAll(vars.into_iter().map(|(_, to)| to).collect(), body))
+}
+
+/// A type class: methods whose types mention `var`, for which each
+/// instance substitutes its own type.
+#[derive(Debug)]
+pub struct Class<'a> {
+    pub name: Name<'a>,
+    pub var: Name<'a>,
+    pub methods: Vec<(Name<'a>, Rc<Type<'a>>)>,
+    /// The type of each method, which every binding of it shares.
+    schemes: Vec<Rc<Type<'a>>>,
+}
+
+impl<'a> Class<'a> {
+    pub fn new(name: Name<'a>, var: Name<'a>, methods: Vec<(Name<'a>, Rc<Type<'a>>)>) -> Class<'a> {
+        let tyvar = Rc::new(Type::Variable(var.clone()));
+        let schemes = methods
+            .iter()
+            .map(|(_, ty)| {
+                let body = Type::Qualified(vec![(name.clone(), Rc::clone(&tyvar))], Rc::clone(ty));
+                Rc::new(Type::ForAll(vec![Rc::clone(&tyvar)], Rc::new(body)))
+            })
+            .collect();
+        Class {
+            name,
+            var,
+            methods,
+            schemes,
+        }
+    }
+
+    /// The type of the `i`th method: `forall a. Class a => ty`. A variable
+    /// is a use of the method only if it has this very scheme.
+    pub fn scheme(&self, i: usize) -> Rc<Type<'a>> {
+        Rc::clone(&self.schemes[i])
+    }
+
+    /// The value of the `i`th method: a function from an instance's
+    /// dictionary to the instance's definition of the method.
+    pub fn selector(&self, i: usize, loc: &ast::Loc<'a>) -> ast::AST<'a> {
+        let var = |name| ast::AST::Variable(loc.clone(), name);
+        let dict = names::ident("dict");
+        let call = ast::AST::Application(
+            loc.clone(),
+            Box::new(var(dict.clone())),
+            Box::new(self.select(i, loc)),
+        );
+        ast::AST::Abstraction(loc.clone(), Box::new(var(dict)), Box::new(call))
+    }
+
+    /// The function a dictionary passes its methods to that returns the
+    /// `i`th.
+    fn select(&self, i: usize, loc: &ast::Loc<'a>) -> ast::AST<'a> {
+        let var = |name| ast::AST::Variable(loc.clone(), name);
+        (0..self.methods.len()).rev().fold(var(method_param(i)), |body, j| {
+            ast::AST::Abstraction(loc.clone(), Box::new(var(method_param(j))), Box::new(body))
+        })
+    }
+}
+
+/// The parameter a dictionary binds its `i`th method to.
+fn method_param<'a>(i: usize) -> Name<'a> {
+    Name::Unique(Box::new(names::ident("method")), i as i32)
+}
+
+/// A dictionary holding `methods`: a function that passes them, in order,
+/// to its argument.
+fn dictionary<'a>(loc: &ast::Loc<'a>, methods: Vec<ast::AST<'a>>) -> ast::AST<'a> {
+    let var = |name| ast::AST::Variable(loc.clone(), name);
+    let select = names::ident("select");
+    let call = (0..methods.len()).fold(var(select.clone()), |func, i| {
+        ast::AST::Application(loc.clone(), Box::new(func), Box::new(var(method_param(i))))
+    });
+    let dict = ast::AST::Abstraction(loc.clone(), Box::new(var(select)), Box::new(call));
+    let make = (0..methods.len()).rev().fold(dict, |body, i| {
+        ast::AST::Abstraction(loc.clone(), Box::new(var(method_param(i))), Box::new(body))
+    });
+    methods.into_iter().fold(make, |func, method| {
+        ast::AST::Application(loc.clone(), Box::new(func), Box::new(method))
+    })
+}
+
+/// An instance of a class for the types one constructor builds, and the
+/// name its dictionary is bound to.
+#[derive(Debug)]
+pub struct Instance<'a> {
+    pub class: Name<'a>,
+    /// The type the instance is for. Its variables are primitive types, so
+    /// that the instance's methods must work whatever they stand for.
+    pub ty: Rc<Type<'a>>,
+    pub dict: Name<'a>,
+    /// The binding that defines each of the class's methods, in order.
+    pub methods: Vec<Name<'a>>,
+}
+
+/// The constructor of `ty`, by which instances are chosen.
+fn head<'a, 'b>(ty: &'b Type<'a>) -> Option<&'b Name<'a>> {
+    match *ty {
+        Type::Primitive(ref name) | Type::App(ref name, _) => Some(name),
+        _ => None,
+    }
+}
+
+/// The classes and instances in scope.
+#[derive(Clone, Debug, Default)]
+pub struct Classes<'a> {
+    classes: Vec<Rc<Class<'a>>>,
+    instances: Vec<Rc<Instance<'a>>>,
+}
+
+thread_local! {
+    static CORE: Classes<'static> = core_classes();
+}
+
+fn core_classes() -> Classes<'static> {
+    let mut classes = Classes::new();
+    for core in globals::classes().iter() {
+        let name = names::typ(core.name);
+        let mut class = Class::new(name.clone(), names::typ(core.var), Vec::new());
+        for (method, scheme) in &core.methods {
+            let ty = match **scheme {
+                Type::ForAll(_, ref body) => match **body {
+                    Type::Qualified(_, ref ty) => Rc::clone(ty),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+            class.methods.push((names::ident(method), ty));
+            class.schemes.push(Rc::clone(scheme));
+        }
+        classes.add_class(Rc::new(class));
+        for inst in &core.instances {
+            classes.add_instance(Rc::new(Instance {
+                class: name.clone(),
+                ty: Rc::clone(&inst.ty),
+                dict: names::ident(inst.dict),
+                methods: inst.methods.clone(),
+            }));
+        }
+    }
+    classes
+}
+
+impl<'a> Classes<'a> {
+    pub fn new() -> Classes<'a> {
+        Classes::default()
+    }
+
+    /// The classes in `globals::classes`, which every program can use. They
+    /// are the same classes, and instances, each time.
+    pub fn core() -> Classes<'a> {
+        CORE.with(Classes::clone)
+    }
+
+    pub fn class(&self, name: &Name<'a>) -> Option<&Rc<Class<'a>>> {
+        self.classes.iter().find(|class| class.name == *name)
+    }
+
+    /// The instance of `class` for the types `con` builds.
+    fn instance(&self, class: &Name<'a>, con: &Name<'a>) -> Option<&Rc<Instance<'a>>> {
+        self.instances
+            .iter()
+            .find(|inst| inst.class == *class && head(&inst.ty) == Some(con))
+    }
+
+    pub fn add_class(&mut self, class: Rc<Class<'a>>) {
+        self.classes.push(class);
+    }
+
+    pub fn add_instance(&mut self, instance: Rc<Instance<'a>>) {
+        self.instances.push(instance);
+    }
+
+    /// Bring the classes and instances of an imported module into scope.
+    /// One that is not already in scope, but has the name of a class or
+    /// the type of an instance that is, is an error at `loc`.
+    pub fn import(&mut self, loc: &ast::Loc<'a>, other: &Classes<'a>) -> Result<(), TypeError<'a>> {
+        for class in &other.classes {
+            match self.class(&class.name) {
+                Some(known) if Rc::ptr_eq(known, class) => {}
+                Some(_) => return Err(TypeError::DuplicateClass(loc.clone(), class.name.clone())),
+                None => self.classes.push(Rc::clone(class)),
+            }
+        }
+        for inst in &other.instances {
+            match self.instance(&inst.class, head(&inst.ty).unwrap()) {
+                Some(known) if Rc::ptr_eq(known, inst) => {}
+                Some(_) => {
+                    return Err(TypeError::DuplicateInstance(
+                        loc.clone(),
+                        inst.class.clone(),
+                        Rc::clone(&inst.ty),
+                    ))
+                }
+                None => self.instances.push(Rc::clone(inst)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> Typecheck<'a> {
+    /// The type of the signature `constraints => ty`, with fresh variables.
+    /// The constraints are declared: they are the only ones a value with
+    /// the signature may need.
+    fn qualified(
+        &'b mut self,
+        env: &Rc<TypeEnv<'a>>,
+        classes: &Classes<'a>,
+        constraints: &[(Name<'a>, Box<ast::AST<'a>>)],
+        ty: &ast::AST<'a>,
+    ) -> TCResult<'a> {
+        let mut binds: Vec<(Name<'a>, Rc<Type<'a>>)> = Vec::new();
+        for (class, var) in constraints {
+            if classes.class(class).is_none() {
+                return Err(TypeError::UnboundClass(var.loc(), class.clone()));
+            }
+            let name = match **var {
+                ast::AST::TyName(_, ref name) => name,
+                _ => return Err(TypeError::BadType(var.loc())),
+            };
+            let tyvar = match binds.iter().find(|(bound, _)| bound == name) {
+                Some((_, tyvar)) => Rc::clone(tyvar),
+                None => {
+                    let tyvar = self.genvar(name.clone());
+                    binds.push((name.clone(), Rc::clone(&tyvar)));
+                    tyvar
+                }
+            };
+            self.wanted.push(Wanted {
+                node: ptr::null(),
+                loc: var.loc(),
+                class: class.clone(),
+                ty: tyvar,
+                scheme: None,
+            });
+        }
+        self.ast_to_type(&TypeEnv::with_bindings(env, &binds), ty)
+    }
+
+    /// Resolve the constraints collected while checking `value`, of type
+    /// `ty`, to dictionaries. Returns the type, and, if `value` needs any
+    /// dictionaries, a copy of it that passes them explicitly.
+    ///
+    /// If `quantify` is set, a constraint on a variable of `ty` becomes a
+    /// parameter of the copy, and a constraint of the type returned, which
+    /// is generalized. Any other constraint on a variable is ambiguous, and
+    /// the variable defaults to `int` if `int` has an instance of every
+    /// class it needs.
+    fn solve(
+        &'b mut self,
+        classes: &Classes<'a>,
+        value: &ast::AST<'a>,
+        ty: &Rc<Type<'a>>,
+        quantify: bool,
+    ) -> Result<(Rc<Type<'a>>, Option<ast::AST<'a>>), TypeError<'a>> {
+        let ty = self.subst_type(ty);
+        let mut free = Vec::new();
+        if quantify {
+            types::map_vars(&ty, &mut |var| {
+                free.push(Rc::clone(&var));
+                var
+            });
+        }
+        let wanted = mem::take(&mut self.wanted);
+        let int = globals::integer();
+        for want in &wanted {
+            let var = self.subst_type(&want.ty);
+            if !matches!(*var, Type::Variable(_)) || free.contains(&var) {
+                continue;
+            }
+            for other in &wanted {
+                if self.subst_type(&other.ty) == var
+                    && classes.instance(&other.class, head(&int).unwrap()).is_none()
+                {
+                    return Err(TypeError::Ambiguous(other.loc.clone(), other.class.clone(), var));
+                }
+            }
+            self.unify(value, &var, &int)?;
+        }
+
+        let declared = wanted.iter().any(|want| want.node.is_null());
+        let mut params: Vec<(Name<'a>, Rc<Type<'a>>, Name<'a>)> = Vec::new();
+        let mut dicts: HashMap<*const ast::AST<'a>, Vec<ast::AST<'a>>> = HashMap::new();
+        let mut methods: HashMap<*const ast::AST<'a>, ast::AST<'a>> = HashMap::new();
+        for want in &wanted {
+            // A use of a method becomes the instance's definition of it, or
+            // takes it from the dictionary it is passed.
+            let method = classes.class(&want.class).and_then(|class| {
+                let scheme = want.scheme.as_ref()?;
+                let i = class.schemes.iter().position(|known| Rc::ptr_eq(known, scheme))?;
+                Some((class, i))
+            });
+            let arg = self.subst_type(&want.ty);
+            let no_instance =
+                || TypeError::NoInstance(want.loc.clone(), want.class.clone(), Rc::clone(&arg));
+            let dict = match *arg {
+                Type::Variable(_) => {
+                    let param = params
+                        .iter()
+                        .find(|(class, var, _)| *class == want.class && *var == arg);
+                    match param {
+                        Some((_, _, param)) => param.clone(),
+                        None if declared && !want.node.is_null() => return Err(no_instance()),
+                        None => {
+                            let param = self.gensym(names::ident("dict"));
+                            params.push((want.class.clone(), Rc::clone(&arg), param.clone()));
+                            param
+                        }
+                    }
+                }
+                _ => match head(&arg).and_then(|con| classes.instance(&want.class, con)) {
+                    Some(inst) => match method {
+                        Some((_, i)) => {
+                            let def = ast::AST::Variable(want.loc.clone(), inst.methods[i].clone());
+                            methods.insert(want.node, def);
+                            continue;
+                        }
+                        None => inst.dict.clone(),
+                    },
+                    None => return Err(no_instance()),
+                },
+            };
+            if let Some((class, i)) = method {
+                let dict = Box::new(ast::AST::Variable(want.loc.clone(), dict));
+                let select = Box::new(class.select(i, &want.loc));
+                methods.insert(want.node, ast::AST::Application(want.loc.clone(), dict, select));
+            } else if !want.node.is_null() {
+                dicts
+                    .entry(want.node)
+                    .or_default()
+                    .push(ast::AST::Variable(want.loc.clone(), dict));
+            }
+        }
+
+        let mut ty = self.subst_type(&ty);
+        if !params.is_empty() {
+            let constraints = params.iter().map(|(class, var, _)| (class.clone(), Rc::clone(var)));
+            ty = Rc::new(Type::Qualified(constraints.collect(), ty));
+        }
+        if quantify {
+            ty = generalize(&ty);
+        }
+        if dicts.is_empty() && methods.is_empty() && params.is_empty() {
+            return Ok((ty, None));
+        }
+        let loc = value.loc();
+        let value = elaborate(value, &methods, &dicts);
+        let value = params.iter().rev().fold(value, |body, (_, _, param)| {
+            let param = ast::AST::Variable(loc.clone(), param.clone());
+            ast::AST::Abstraction(loc.clone(), Box::new(param), Box::new(body))
+        });
+        Ok((ty, Some(value)))
+    }
+}
+
+/// A copy of `ast` in which each use of a method in `methods` is replaced,
+/// and each variable in `dicts` is applied to its dictionaries.
+fn elaborate<'a>(
+    ast: &ast::AST<'a>,
+    methods: &HashMap<*const ast::AST<'a>, ast::AST<'a>>,
+    dicts: &HashMap<*const ast::AST<'a>, Vec<ast::AST<'a>>>,
+) -> ast::AST<'a> {
+    if let Some(method) = methods.get(&(ast as *const ast::AST<'a>)) {
+        return method.clone();
+    }
+    let each = |child: &ast::AST<'a>| Box::new(ast::grow(|| elaborate(child, methods, dicts)));
+    let copy = match *ast {
+        ast::AST::Application(ref loc, ref func, ref arg) => {
+            ast::AST::Application(loc.clone(), each(func), each(arg))
+        }
+        ast::AST::Abstraction(ref loc, ref param, ref body) => {
+            ast::AST::Abstraction(loc.clone(), param.clone(), each(body))
+        }
+        ast::AST::Ascription(ref loc, ref val, ref ty) => {
+            ast::AST::Ascription(loc.clone(), each(val), ty.clone())
+        }
+        ast::AST::If(ref loc, ref cond, ref cons, ref alt) => {
+            ast::AST::If(loc.clone(), each(cond), each(cons), each(alt))
+        }
+        _ => ast.clone(),
+    };
+    match dicts.get(&(ast as *const ast::AST<'a>)) {
+        Some(args) => args.iter().fold(copy, |func, dict| {
+            ast::AST::Application(ast.loc(), Box::new(func), Box::new(dict.clone()))
+        }),
+        None => copy,
+    }
+}
+
+/// Check the declaration `class name var { methods }`.
+pub fn check_class<'a>(
+    env: &Rc<TypeEnv<'a>>,
+    classes: &Classes<'a>,
+    loc: &ast::Loc<'a>,
+    name: &Name<'a>,
+    var: &Name<'a>,
+    methods: &[ast::Method<'a>],
+) -> Result<Class<'a>, TypeError<'a>> {
+    if classes.class(name).is_some() {
+        return Err(TypeError::DuplicateClass(loc.clone(), name.clone()));
+    }
+    let tyvar = Rc::new(Type::Variable(var.clone()));
+    let env = TypeEnv::with_bindings(env, &[(var.clone(), Rc::clone(&tyvar))]);
+    let mut tc = Typecheck::new(&Limits::default());
+    let mut checked: Vec<(Name<'a>, Rc<Type<'a>>)> = Vec::new();
+    for (mloc, method, ty) in methods {
+        if checked.iter().any(|(seen, _)| seen == method) {
+            return Err(TypeError::BadDecl(mloc.clone()));
+        }
+        let ty = tc.ast_to_type(&env, ty)?;
+        // No use of a method whose type does not mention the class's
+        // variable could choose an instance.
+        if !tc.occur(var, &ty) {
+            return Err(TypeError::Ambiguous(mloc.clone(), name.clone(), tyvar));
+        }
+        checked.push((method.clone(), ty));
+    }
+    Ok(Class::new(name.clone(), var.clone(), checked))
+}
+
+/// The type an instance is for: a type constructor applied to distinct
+/// variables, which become primitive types.
+fn instance_type<'a>(env: &Rc<TypeEnv<'a>>, ty: &ast::AST<'a>) -> TCResult<'a> {
+    let mut tc = Typecheck::new(&Limits::default());
+    match *ty {
+        ast::AST::TyName(..) => tc.ast_to_type(env, ty),
+        ast::AST::TyApp(_, _, ref args) => {
+            let mut binds: Vec<(Name<'a>, Rc<Type<'a>>)> = Vec::new();
+            for arg in args {
+                match **arg {
+                    ast::AST::TyName(_, ref var)
+                        if env.lookup(var).is_none()
+                            && constructor_kind(var).is_none()
+                            && !binds.iter().any(|(bound, _)| bound == var) =>
+                    {
+                        binds.push((var.clone(), Rc::new(Type::Primitive(var.clone()))))
+                    }
+                    _ => return Err(TypeError::BadType(arg.loc())),
+                }
+            }
+            tc.ast_to_type(&TypeEnv::with_bindings(env, &binds), ty)
+        }
+        _ => Err(TypeError::BadType(ty.loc())),
+    }
+}
+
+/// Check the declaration `instance class ty { methods }`, whose dictionary
+/// will be bound to `dict`. Returns the instance and the definitions to
+/// bind, in order: each method's, under the name `names::method` gives it,
+/// then the dictionary's. An instance cannot use itself: its methods are
+/// checked before it is in scope.
+#[allow(clippy::too_many_arguments)]
+pub fn check_instance<'a>(
+    env: &Rc<TypeEnv<'a>>,
+    classes: &Classes<'a>,
+    loc: &ast::Loc<'a>,
+    class: &Name<'a>,
+    ty: &ast::AST<'a>,
+    methods: &[ast::Method<'a>],
+    dict: Name<'a>,
+    limits: &Limits,
+) -> Result<(Instance<'a>, Vec<(Name<'a>, ast::AST<'a>)>), TypeError<'a>> {
+    let class = match classes.class(class) {
+        Some(class) => Rc::clone(class),
+        None => return Err(TypeError::UnboundClass(loc.clone(), class.clone())),
+    };
+    let inst_ty = instance_type(env, ty)?;
+    if classes.instance(&class.name, head(&inst_ty).unwrap()).is_some() {
+        return Err(TypeError::DuplicateInstance(loc.clone(), class.name.clone(), inst_ty));
+    }
+    for (mloc, method, _) in methods {
+        if !class.methods.iter().any(|(name, _)| name == method) {
+            return Err(TypeError::NotAMethod(mloc.clone(), class.name.clone(), method.clone()));
+        }
+    }
+    let mut bindings = Vec::new();
+    for (i, (method, method_ty)) in class.methods.iter().enumerate() {
+        let mut defs = methods.iter().filter(|(_, name, _)| name == method);
+        let value = match defs.next() {
+            Some((_, _, value)) => value,
+            None => {
+                let class = class.name.clone();
+                return Err(TypeError::MissingMethod(loc.clone(), class, method.clone()));
+            }
+        };
+        if let Some((mloc, _, _)) = defs.next() {
+            return Err(TypeError::BadDecl(mloc.clone()));
+        }
+        let expected = types::map_vars(method_ty, &mut |var| {
+            if *var == Type::Variable(class.var.clone()) {
+                Rc::clone(&inst_ty)
+            } else {
+                var
+            }
+        });
+        let mut tc = Typecheck::new(limits);
+        tc.check_depth(value)?;
+        let got = tc.typecheck(env, value)?;
+        tc.unify(value, &got, &expected)?;
+        let (_, elaborated) = tc.solve(classes, value, &got, false)?;
+        let name = names::method(dict.clone(), i);
+        bindings.push((name, elaborated.unwrap_or_else(|| (**value).clone())));
+    }
+    let methods: Vec<Name<'a>> = bindings.iter().map(|(name, _)| name.clone()).collect();
+    let vars = methods.iter().map(|name| ast::AST::Variable(loc.clone(), name.clone()));
+    bindings.push((dict.clone(), dictionary(loc, vars.collect())));
+    let instance = Instance {
+        class: class.name.clone(),
+        ty: inst_ty,
+        dict,
+        methods,
+    };
+    Ok((instance, bindings))
+}
+
+/// Typecheck the value of a definition. Returns its generalized type and,
+/// if it uses overloaded names, a copy of the value that passes their
+/// dictionaries explicitly. A signature with constraints on the value, like
+/// `Eq a => a -> a -> bool`, must list every constraint the value needs.
+pub fn check_definition<'a>(
+    env: &Rc<TypeEnv<'a>>,
+    classes: &Classes<'a>,
+    value: &ast::AST<'a>,
+    limits: &Limits,
+) -> Result<(Rc<Type<'a>>, Option<ast::AST<'a>>), TypeError<'a>> {
+    let mut tc = Typecheck::new(limits);
+    tc.check_depth(value)?;
+    if let ast::AST::Ascription(_, ref inner, ref sig) = *value {
+        if let ast::AST::TyQual(_, ref constraints, ref sig_ty) = **sig {
+            let sig_ty = tc.qualified(env, classes, constraints, sig_ty)?;
+            let ty = tc.typecheck(env, inner)?;
+            tc.unify(value, &ty, &sig_ty)?;
+            return tc.solve(classes, inner, &ty, true);
+        }
+    }
+    let ty = tc.typecheck(env, value)?;
+    tc.solve(classes, value, &ty, true)
+}
+
+/// Typecheck the body of a file, like `check_definition`. The type of the
+/// body is not generalized, so constraints on its variables take the
+/// default.
+pub fn check_body<'a>(
+    env: &Rc<TypeEnv<'a>>,
+    classes: &Classes<'a>,
+    body: &ast::AST<'a>,
+    limits: &Limits,
+) -> Result<(Rc<Type<'a>>, Option<ast::AST<'a>>), TypeError<'a>> {
+    let mut tc = Typecheck::new(limits);
+    tc.check_depth(body)?;
+    let ty = tc.typecheck(env, body)?;
+    tc.solve(classes, body, &ty, false)
+}
+
+/// The kind of `name`, if it is a built-in type constructor.
+fn constructor_kind(name: &Name) -> Option<Kind> {
+    globals::type_constructors()
+        .into_iter()
+        .find(|&(con, _)| *name == names::typ(con))
+        .map(|(_, kind)| kind)
 }
 
 pub fn ast_to_type<'a>(env: &Rc<TypeEnv<'a>>, ast: &ast::AST<'a>) -> TCResult<'a> {
-    Typecheck::new().ast_to_type(env, ast)
+    Typecheck::new(&Limits::default()).ast_to_type(env, ast)
 }
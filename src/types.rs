@@ -1,24 +1,139 @@
 use names::Name;
 
+use std::fmt;
 use std::rc::Rc;
 
 #[derive(Debug, PartialEq)]
 pub enum Type<'a> {
     Primitive(Name<'a>),
     Variable(Name<'a>),
-    // ForAll(Vec<Rc<Type<'a>>>, Rc<Type<'a>>),
+    /// A type scheme: its body, for any types in place of the listed
+    /// `Variable`s. Only definitions have schemes; each use of one gets
+    /// fresh variables.
+    ForAll(Vec<Rc<Type<'a>>>, Rc<Type<'a>>),
     Function(Rc<Type<'a>>, Rc<Type<'a>>),
+    /// A type constructor applied to arguments, like `list<int>`.
+    App(Name<'a>, Vec<Rc<Type<'a>>>),
+    /// A type whose variables must have instances of some classes, like
+    /// `Eq a => a -> a -> bool`. Only the bodies of schemes are qualified.
+    Qualified(Vec<(Name<'a>, Rc<Type<'a>>)>, Rc<Type<'a>>),
+}
+
+/// The kind of a type constructor. Constructors take only types as
+/// arguments, so every kind is `*` or `* -> ... -> *`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    /// The kind of types that have values, like `int` or `list<bool>`.
+    Star,
+    Arrow(Rc<Kind>, Rc<Kind>),
+}
+
+impl Kind {
+    /// The kind of a constructor that takes `arity` types.
+    pub fn of_arity(arity: usize) -> Kind {
+        (0..arity).fold(Kind::Star, |kind, _| Kind::Arrow(Rc::new(Kind::Star), Rc::new(kind)))
+    }
+
+    /// How many types a constructor of this kind takes.
+    pub fn arity(&self) -> usize {
+        match *self {
+            Kind::Star => 0,
+            Kind::Arrow(_, ref range) => 1 + range.arity(),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Kind::Star => f.write_str("*"),
+            Kind::Arrow(ref dom, ref range) => match **dom {
+                Kind::Arrow(..) => write!(f, "({}) -> {}", dom, range),
+                Kind::Star => write!(f, "{} -> {}", dom, range),
+            },
+        }
+    }
 }
 
 pub fn map_vars<'a, F>(ty: &Rc<Type<'a>>, map: &mut F) -> Rc<Type<'a>>
 where
     F: FnMut(Rc<Type<'a>>) -> Rc<Type<'a>>,
 {
+    map_vars_dyn(ty, map)
+}
+
+fn map_vars_dyn<'a>(
+    ty: &Rc<Type<'a>>,
+    map: &mut dyn FnMut(Rc<Type<'a>>) -> Rc<Type<'a>>,
+) -> Rc<Type<'a>> {
     match &**ty {
-        &Type::Primitive(_) => Rc::clone(ty),
-        &Type::Variable(_) => map(Rc::clone(ty)),
-        &Type::Function(ref dom, ref range) => {
-            Rc::new(Type::Function(map_vars(&dom, map), map_vars(&range, map)))
+        Type::Primitive(_) => Rc::clone(ty),
+        Type::Variable(_) => map(Rc::clone(ty)),
+        Type::Function(dom, range) => {
+            Rc::new(Type::Function(map_vars_dyn(dom, map), map_vars_dyn(range, map)))
+        }
+        Type::App(con, args) => Rc::new(Type::App(
+            con.clone(),
+            args.iter().map(|arg| map_vars_dyn(arg, map)).collect(),
+        )),
+        Type::Qualified(constraints, body) => {
+            let body = map_vars_dyn(body, map);
+            let constraints = constraints
+                .iter()
+                .map(|(class, ty)| (class.clone(), map_vars_dyn(ty, map)))
+                .collect();
+            Rc::new(Type::Qualified(constraints, body))
+        }
+        Type::ForAll(vars, body) => {
+            let body = map_vars_dyn(body, &mut |var| {
+                if vars.contains(&var) {
+                    var
+                } else {
+                    map(var)
+                }
+            });
+            Rc::new(Type::ForAll(vars.clone(), body))
+        }
+    }
+}
+
+impl<'a> fmt::Display for Type<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Type::Primitive(ref name) => name.fmt(f),
+            Type::Variable(ref name) => name.fmt(f),
+            Type::Function(ref dom, ref range) => match **dom {
+                Type::Function(..) | Type::ForAll(..) | Type::Qualified(..) => {
+                    write!(f, "({}) -> {}", dom, range)
+                }
+                _ => write!(f, "{} -> {}", dom, range),
+            },
+            Type::App(ref con, ref args) => {
+                write!(f, "{}<", con)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    arg.fmt(f)?;
+                }
+                f.write_str(">")
+            }
+            Type::Qualified(ref constraints, ref body) => {
+                for (i, (class, ty)) in constraints.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{} {}", class, ty)?;
+                }
+                write!(f, " => {}", body)
+            }
+            Type::ForAll(ref vars, ref body) => {
+                f.write_str("forall")?;
+                for var in vars {
+                    write!(f, " {}", var)?;
+                }
+                write!(f, ". {}", body)
+            }
         }
     }
 }
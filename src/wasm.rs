@@ -0,0 +1,517 @@
+//! Compiling Gollum to WebAssembly.
+//!
+//! Like `cgen`, this works from the lambda-lifted `lift::Program`: each
+//! lifted function becomes a wasm function over the closure it belongs to,
+//! and the runtime in `runtime.wat`, which implements values and the
+//! `globals` primitives, is copied into every module. `compile` produces
+//! the text format and `assemble` the binary format.
+//!
+//! Closures hold the index of their code in the module's function table and
+//! are called with `call_indirect`. Primitives are chains of closures, one
+//! per argument, and a call with all of a primitive's arguments calls its
+//! `$rt_` implementation directly. Calls in tail position return to
+//! `$rt_apply`'s loop rather than nesting.
+//!
+//! A module imports nothing and exports its `memory` and a function `run`,
+//! which evaluates the program and returns the address and length of its
+//! value printed the way the interpreter prints it:
+//!
+//! ```text
+//! const { instance } = await WebAssembly.instantiate(bytes);
+//! const [ptr, len] = instance.exports.run();
+//! const mem = new Uint8Array(instance.exports.memory.buffer, ptr, len);
+//! console.log(new TextDecoder().decode(mem));
+//! ```
+
+use ast::AST;
+use globals;
+use lift;
+use lift::{Expr, Program};
+use names::Name;
+use typecheck::TypeError;
+
+use num_bigint::{BigInt, Sign};
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const RUNTIME: &str = include_str!("runtime.wat");
+
+/// Where the program's static data starts; the runtime's is below it.
+const DATA_BASE: usize = 64;
+
+const PAGE: usize = 65536;
+
+/// Width to print s-expressions on one line within.
+const WIDTH: usize = 100;
+
+/// An s-expression of the text format, kept as a tree so it can be laid
+/// out readably.
+enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+fn atom<S: Into<String>>(text: S) -> Sexp {
+    Sexp::Atom(text.into())
+}
+
+fn list(head: &str, mut rest: Vec<Sexp>) -> Sexp {
+    rest.insert(0, atom(head));
+    Sexp::List(rest)
+}
+
+fn i32_const(val: usize) -> Sexp {
+    list("i32.const", vec![atom(val.to_string())])
+}
+
+fn call(func: &str, args: Vec<Sexp>) -> Sexp {
+    let mut items = vec![atom("call"), atom(format!("${}", func))];
+    items.extend(args);
+    Sexp::List(items)
+}
+
+impl Sexp {
+    fn flat(&self, out: &mut String) {
+        match *self {
+            Sexp::Atom(ref text) => out.push_str(text),
+            Sexp::List(ref items) => {
+                out.push('(');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    item.flat(out);
+                }
+                out.push(')');
+            }
+        }
+    }
+
+    /// Write `self` at `indent`, breaking lists that do not fit on a line
+    /// so each argument is on its own line.
+    fn layout(&self, indent: usize, out: &mut String) {
+        let mut flat = String::new();
+        self.flat(&mut flat);
+        let items = match *self {
+            Sexp::List(ref items) if indent + flat.len() > WIDTH => items,
+            _ => {
+                out.push_str(&flat);
+                return;
+            }
+        };
+        // Keep leading atoms, like `call $f` or `i32.store offset=16`, and
+        // signatures with the head.
+        let head = items
+            .iter()
+            .position(|item| match *item {
+                Sexp::List(ref inner) => match inner.first() {
+                    Some(Sexp::Atom(head)) => !["type", "param", "result"].contains(&head.as_str()),
+                    _ => true,
+                },
+                Sexp::Atom(_) => false,
+            })
+            .unwrap_or(items.len());
+        out.push('(');
+        for (i, item) in items[..head].iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            item.flat(out);
+        }
+        for item in &items[head..] {
+            out.push('\n');
+            for _ in 0..indent + 2 {
+                out.push(' ');
+            }
+            item.layout(indent + 2, out);
+        }
+        out.push(')');
+    }
+}
+
+/// Static data, laid out from `DATA_BASE`.
+struct Data {
+    bytes: Vec<u8>,
+}
+
+impl Data {
+    fn addr(&self) -> usize {
+        DATA_BASE + self.bytes.len()
+    }
+
+    fn align(&mut self) {
+        while !self.bytes.len().is_multiple_of(4) {
+            self.bytes.push(0);
+        }
+    }
+
+    fn word(&mut self, val: u32) {
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// Lay out an integer object, and return its address.
+    fn int(&mut self, val: &BigInt) -> usize {
+        self.align();
+        let addr = self.addr();
+        let (sign, limbs) = val.to_u32_digits();
+        self.word(0);
+        self.word((sign == Sign::Minus) as u32);
+        self.word(limbs.len() as u32);
+        for limb in limbs {
+            self.word(limb);
+        }
+        addr
+    }
+
+    fn segment(&self) -> String {
+        let mut text = String::new();
+        for byte in &self.bytes {
+            write!(text, "\\{:02x}", byte).unwrap();
+        }
+        format!("(data (i32.const {}) \"{}\")", DATA_BASE, text)
+    }
+}
+
+/// The head of an application spine and its arguments, in order.
+fn spine<'e, 'a>(mut expr: &'e Expr<'a>) -> (&'e Expr<'a>, Vec<&'e Expr<'a>>) {
+    let mut args = Vec::new();
+    while let Expr::Apply(ref func, ref arg) = *expr {
+        args.push(&**arg);
+        expr = func;
+    }
+    args.reverse();
+    (expr, args)
+}
+
+/// Build a closure of the function at table `index` from a fresh local
+/// `temp`, storing `values` in its environment.
+fn make_closure(temp: &str, index: usize, name: (usize, usize), values: Vec<Sexp>) -> Sexp {
+    let local = || list("local.get", vec![atom(temp)]);
+    let mut body = vec![
+        list("result", vec![atom("i32")]),
+        list(
+            "local.set",
+            vec![
+                atom(temp),
+                call(
+                    "rt_make",
+                    vec![
+                        i32_const(index),
+                        i32_const(name.0),
+                        i32_const(name.1),
+                        i32_const(values.len()),
+                    ],
+                ),
+            ],
+        ),
+    ];
+    for (i, val) in values.into_iter().enumerate() {
+        body.push(list(
+            "i32.store",
+            vec![atom(format!("offset={}", 16 + 4 * i)), local(), val],
+        ));
+    }
+    body.push(local());
+    list("block", body)
+}
+
+fn env_slot(i: usize) -> Sexp {
+    list(
+        "i32.load",
+        vec![
+            atom(format!("offset={}", 16 + 4 * i)),
+            list("local.get", vec![atom("$self")]),
+        ],
+    )
+}
+
+struct Gen<'p, 'a: 'p> {
+    program: &'p Program<'a>,
+    prims: Vec<globals::Primitive>,
+    data: Data,
+    /// The address of each primitive's closure and of its name.
+    prim_closures: HashMap<&'static str, usize>,
+    prim_names: HashMap<&'static str, (usize, usize)>,
+    /// Table indices of the primitives' stages and the lifted functions.
+    table: Vec<String>,
+    temps: usize,
+}
+
+impl<'p, 'a> Gen<'p, 'a> {
+    fn index(&self, func: &str) -> usize {
+        self.table
+            .iter()
+            .position(|name| name == func)
+            .expect("wasm: function missing from the table")
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("$t{}", self.temps)
+    }
+
+    fn arity(&self, name: &Name<'a>) -> Option<usize> {
+        match *name {
+            Name::Ident(ident) => self
+                .prims
+                .iter()
+                .find(|p| p.name == ident)
+                .map(|p| p.arity()),
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr<'a>) -> Sexp {
+        match *expr {
+            Expr::Int(ref val) => i32_const(self.data.int(val)),
+            Expr::Bool(val) => i32_const(if val { 16 } else { 8 }),
+            Expr::Param(_) => list("local.get", vec![atom("$arg")]),
+            Expr::Env(i, _) => env_slot(i),
+            Expr::Global(ref name) => match *name {
+                Name::Ident(ident) if self.prim_closures.contains_key(ident) => {
+                    i32_const(self.prim_closures[ident])
+                }
+                _ => panic!("wasm: unknown global {}", name),
+            },
+            Expr::Closure(index, ref values) => {
+                let values = values.iter().map(|val| self.expr(val)).collect();
+                let temp = self.temp();
+                let index = self.index(&self.program.functions[index].name);
+                make_closure(&temp, index, (0, 0), values)
+            }
+            Expr::Apply(ref func, ref arg) => {
+                if let Some(call) = self.saturated(expr) {
+                    return call;
+                }
+                let func = self.expr(func);
+                let arg = self.expr(arg);
+                call("rt_apply", vec![func, arg])
+            }
+            Expr::If(ref cond, ref cons, ref alt) => {
+                let cond = self.expr(cond);
+                let cons = self.expr(cons);
+                let alt = self.expr(alt);
+                self.branch(cond, cons, alt)
+            }
+        }
+    }
+
+    fn branch(&self, cond: Sexp, cons: Sexp, alt: Sexp) -> Sexp {
+        list(
+            "if",
+            vec![
+                list("result", vec![atom("i32")]),
+                call("rt_truthy", vec![cond]),
+                list("then", vec![cons]),
+                list("else", vec![alt]),
+            ],
+        )
+    }
+
+    /// If `expr` applies a primitive to all its arguments, a direct call to
+    /// the primitive.
+    fn saturated(&mut self, expr: &Expr<'a>) -> Option<Sexp> {
+        let (head, args) = spine(expr);
+        let name = match *head {
+            Expr::Global(ref name) if self.arity(name) == Some(args.len()) => name.to_string(),
+            _ => return None,
+        };
+        let args = args.into_iter().map(|arg| self.expr(arg)).collect();
+        Some(call(&format!("rt_{}", name), args))
+    }
+
+    /// `expr` as the result of a function, making calls in tail position
+    /// through `$rt_tail`.
+    fn tail(&mut self, expr: &Expr<'a>) -> Sexp {
+        match *expr {
+            Expr::Apply(ref func, ref arg) => {
+                if let Some(call) = self.saturated(expr) {
+                    return call;
+                }
+                let func = self.expr(func);
+                let arg = self.expr(arg);
+                call("rt_tail", vec![func, arg])
+            }
+            Expr::If(ref cond, ref cons, ref alt) => {
+                let cond = self.expr(cond);
+                let cons = self.tail(cons);
+                let alt = self.tail(alt);
+                self.branch(cond, cons, alt)
+            }
+            _ => self.expr(expr),
+        }
+    }
+
+    /// A function of type `$code` named `name` returning `body`, whose
+    /// temporaries are numbered up to `self.temps`.
+    fn function(&mut self, name: &str, comment: &str, body: Sexp, out: &mut String) {
+        writeln!(out, ";; {}", comment).unwrap();
+        let mut items = vec![
+            atom(format!("${}", name)),
+            list("type", vec![atom("$code")]),
+            list("param", vec![atom("$self"), atom("i32")]),
+            list("param", vec![atom("$arg"), atom("i32")]),
+            list("result", vec![atom("i32")]),
+        ];
+        for t in 1..=self.temps {
+            items.push(list("local", vec![atom(format!("$t{}", t)), atom("i32")]));
+        }
+        items.push(body);
+        list("func", items).layout(0, out);
+        out.push_str("\n\n");
+        self.temps = 0;
+    }
+
+    /// Lay out the primitives' names and closures, and fill in the table
+    /// with their stages: `$p_NAME_K` takes the K-th argument.
+    fn primitive_data(&mut self) {
+        let prims: Vec<(&'static str, usize)> =
+            self.prims.iter().map(|p| (p.name, p.arity())).collect();
+        for (name, arity) in prims {
+            let addr = self.data.addr();
+            self.data.bytes.extend_from_slice(name.as_bytes());
+            self.prim_names.insert(name, (addr, name.len()));
+            self.data.align();
+            self.prim_closures.insert(name, self.data.addr());
+            self.data.word(2);
+            self.data.word(addr as u32);
+            self.data.word(name.len() as u32);
+            self.data.word(self.table.len() as u32);
+            for k in 1..=arity {
+                self.table.push(format!("p_{}_{}", name, k));
+            }
+        }
+    }
+
+    fn primitives(&mut self, out: &mut String) {
+        let prims: Vec<(&'static str, usize)> =
+            self.prims.iter().map(|p| (p.name, p.arity())).collect();
+        for (name, arity) in prims {
+            for k in 1..=arity {
+                let mut args: Vec<Sexp> = (0..k - 1).map(env_slot).collect();
+                args.push(list("local.get", vec![atom("$arg")]));
+                let body = if k == arity {
+                    call(&format!("rt_{}", name), args)
+                } else {
+                    let temp = self.temp();
+                    let index = self.index(&format!("p_{}_{}", name, k + 1));
+                    make_closure(&temp, index, self.prim_names[name], args)
+                };
+                let stage = format!("p_{}_{}", name, k);
+                self.function(&stage, &format!("{}, argument {}", name, k), body, out);
+            }
+        }
+    }
+
+    fn module(&mut self) -> String {
+        self.primitive_data();
+        let program = self.program;
+        for func in &program.functions {
+            self.table.push(func.name.clone());
+        }
+
+        let mut funcs = String::new();
+        self.primitives(&mut funcs);
+        for func in &program.functions {
+            let body = self.tail(&func.body);
+            let comment = format!("{} : {}", func.param, func.ty);
+            self.function(&func.name, &comment, body, &mut funcs);
+        }
+
+        let main = self.expr(&program.main);
+        let mut items = vec![atom("$main"), list("result", vec![atom("i32")])];
+        for t in 1..=self.temps {
+            items.push(list("local", vec![atom(format!("$t{}", t)), atom("i32")]));
+        }
+        items.push(main);
+        writeln!(funcs, ";; main : {}", program.ty).unwrap();
+        list("func", items).layout(0, &mut funcs);
+        funcs.push('\n');
+
+        let mut out = String::new();
+        writeln!(out, ";; Generated by iron-golem.").unwrap();
+        writeln!(out, "(module").unwrap();
+        out.push_str(RUNTIME);
+        out.push('\n');
+        let heap = (self.data.addr() + 7) & !7;
+        writeln!(out, "(memory (export \"memory\") {})", heap / PAGE + 1).unwrap();
+        writeln!(out, "(global $hp (mut i32) (i32.const {}))", heap).unwrap();
+        writeln!(out, "{}", self.data.segment()).unwrap();
+        writeln!(out, "(table {} funcref)", self.table.len()).unwrap();
+        let mut elem = vec![list("i32.const", vec![atom("0")]), atom("func")];
+        elem.extend(self.table.iter().map(|name| atom(format!("${}", name))));
+        list("elem", elem).flat(&mut out);
+        out.push_str("\n\n");
+        out.push_str(&funcs);
+        out.push('\n');
+        writeln!(
+            out,
+            "(func (export \"run\") (result i32 i32)\n  \
+             (call $rt_show (call $main))\n  \
+             (global.get $out_len))"
+        )
+        .unwrap();
+        writeln!(out, ")").unwrap();
+        out
+    }
+}
+
+/// Typecheck `ast`, which may use only the primitives, and compile it to
+/// a module in the WebAssembly text format.
+pub fn compile<'a>(ast: &AST<'a>) -> Result<String, TypeError<'a>> {
+    let program = lift::lift_primitive(ast)?;
+    let mut gen = Gen {
+        program: &program,
+        prims: globals::primitives(),
+        data: Data { bytes: Vec::new() },
+        prim_closures: HashMap::new(),
+        prim_names: HashMap::new(),
+        table: Vec::new(),
+        temps: 0,
+    };
+    Ok(gen.module())
+}
+
+/// Like `compile`, but produce the binary format.
+pub fn assemble<'a>(ast: &AST<'a>) -> Result<Vec<u8>, TypeError<'a>> {
+    let text = compile(ast)?;
+    Ok(wat::parse_str(&text).expect("wasm: generated an invalid module"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser;
+
+    fn compiled(src: &str) -> String {
+        compile(&parser::parse("test", src).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let wat = compiled("fn(f : int -> int, x : int) { f(x) }");
+        assert!(wat.contains(
+            "  (call $rt_tail (i32.load offset=16 (local.get $self)) (local.get $arg)))"
+        ));
+        // A call to a primitive with all its arguments is made directly.
+        let wat = compiled("fn(x : int) { add(x, 1) }");
+        assert!(wat.contains("  (call $rt_add (local.get $arg) (i32.const "));
+    }
+
+    #[test]
+    fn test_layout() {
+        let short = call("f", vec![i32_const(1)]);
+        let mut out = String::new();
+        short.layout(0, &mut out);
+        assert_eq!(out, "(call $f (i32.const 1))");
+
+        let long = call("f", (0..10).map(|_| i32_const(1_000_000)).collect());
+        let mut out = String::new();
+        long.layout(0, &mut out);
+        assert_eq!(
+            out,
+            format!("(call $f{})", "\n  (i32.const 1000000)".repeat(10))
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Sums the integers up to 5000 with 5000 nested recursive calls, more than
+/// the standard limits allow.
+const DEEP: &str = "fix(fn(self, n) { if iszero(n) { 0 } else { add(n, self(dec(n))) } }, 5000)";
+
+fn iron_golem(args: &[&str]) -> Output {
+    iron_golem_in(Path::new("."), args)
+}
+
+fn iron_golem_in(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_iron-golem"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("run iron-golem")
+}
+
+fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+#[test]
+fn test_limit_flags() {
+    let out = iron_golem(&["-e", DEEP]);
+    assert!(stdout(&out).contains("runtime error: recursion limit exceeded"));
+
+    let out = iron_golem(&["--max-depth", "100000", "-e", DEEP]);
+    assert_eq!(stdout(&out), "type: Primitive(Type(\"int\"))\nvalue: 12502500\n");
+
+    let out = iron_golem(&["--fuel", "10", "-e", DEEP]);
+    assert!(stdout(&out).contains("runtime error: ran out of fuel"));
+
+    let out = iron_golem(&["--max-heap", "lots", "-e", DEEP]);
+    assert!(!out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stderr), "--max-heap: not a number: lots\n");
+}
+
+#[test]
+fn test_step_limits() {
+    let diverge = "fix(fn(f, x) { f(x) }, 1)";
+    let out = iron_golem(&["--step", "--fuel", "100", "-e", diverge]);
+    assert!(stdout(&out).ends_with("runtime error: ran out of fuel\n"), "{}", stdout(&out));
+}
+
+/// Input paths are not taken for misspelled subcommands.
+#[test]
+fn test_subcommand_like_paths() {
+    let dir = env::temp_dir().join(format!("iron-golem-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lsp.gol"), "add(1, 2)").unwrap();
+    fs::write(dir.join("test.gol"), "add(1, 2)").unwrap();
+    for args in &[
+        &["lsp.gol"][..],
+        &["--", "lsp.gol"],
+        &["--no-std", "lsp.gol"],
+        &["test.gol"],
+        &["--", "test.gol"],
+    ] {
+        let out = iron_golem_in(&dir, args);
+        assert_eq!(stdout(&out), "type: Primitive(Type(\"int\"))\nvalue: 3\n", "{:?}", args);
+    }
+
+    let out = iron_golem_in(&dir, &["lsp.gol", "lsp.gol"]);
+    assert!(!out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stderr), "error: unexpected argument 'lsp.gol'\n");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The golden corpus can be run a file at a time, as well as with `test`.
+#[test]
+fn test_corpus_paths() {
+    let out = iron_golem(&["tests/testdata/typecheck/good/05-fact.gol"]);
+    assert!(out.status.success());
+    assert!(stdout(&out).starts_with("type: "), "{}", stdout(&out));
+
+    let out = iron_golem(&["test", "tests/testdata/eval"]);
+    assert!(out.status.success(), "{}", stdout(&out));
+    assert!(stdout(&out).ends_with(" passed, 0 failed\n"), "{}", stdout(&out));
+}
+
+/// Every `--emit` mode reports errors as `FILE:LINE:COL` diagnostics.
+#[test]
+fn test_emit_errors() {
+    for emit in &["ir", "lifted", "typed-json"] {
+        let out = iron_golem(&["--emit", emit, "-e", "add(1, true)"]);
+        assert!(!out.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&out.stderr),
+            "-e:1:1: type error: type mismatch: `bool` is not `int`\n",
+            "{}",
+            emit
+        );
+    }
+}
+
+#[test]
+fn test_equiv_errors() {
+    let dir = env::temp_dir().join(format!("iron-golem-equiv-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.gol"), "add(1, 2)").unwrap();
+    fs::write(dir.join("omega.gol"), "(fn(x) { x(x) })(fn(x) { x(x) })").unwrap();
+    let out = iron_golem_in(&dir, &["equiv", "a.gol", "omega.gol"]);
+    assert!(!out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        "omega.gol:1:26: runtime error: recursion limit exceeded\n"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,173 @@
+extern crate iron_golem;
+
+use iron_golem::cgen;
+use iron_golem::eval;
+use iron_golem::gen::Generator;
+use iron_golem::globals;
+use iron_golem::parser;
+use iron_golem::pretty;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+fn scratch() -> PathBuf {
+    let dir = env::temp_dir().join(format!(
+        "iron-golem-cgen-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Compile `src` to C, build it with the system compiler, and return what
+/// it prints, or what it reports if it fails.
+fn run_compiled(src: &str) -> Result<String, String> {
+    let ast = parser::parse("test.gol", src).unwrap();
+    let c = cgen::compile(&ast).unwrap_or_else(|e| panic!("compile({}): {}", src, e));
+    let dir = scratch();
+    let (c_path, exe) = (dir.join("prog.c"), dir.join("prog"));
+    fs::write(&c_path, c).unwrap();
+    let cc = Command::new("cc")
+        .arg("-O1")
+        .arg("-o")
+        .arg(&exe)
+        .arg(&c_path)
+        .output()
+        .expect("run cc");
+    assert!(
+        cc.status.success(),
+        "cc failed on {}:\n{}",
+        src,
+        String::from_utf8_lossy(&cc.stderr)
+    );
+    let out = Command::new(&exe).output().expect("run compiled program");
+    fs::remove_dir_all(&dir).unwrap();
+    let text = |bytes| String::from_utf8(bytes).unwrap().trim_end().to_string();
+    if out.status.success() {
+        Ok(text(out.stdout))
+    } else {
+        Err(text(out.stderr))
+    }
+}
+
+fn run_interpreted(src: &str) -> Result<String, String> {
+    let ast = parser::parse("test.gol", src).unwrap();
+    eval::eval(&globals::global_values(), &ast)
+        .map(|val| val.to_string())
+        .map_err(|e| format!("runtime error: {}", e))
+}
+
+fn agree(src: &str) {
+    assert_eq!(run_compiled(src), run_interpreted(src), "program: {}", src);
+}
+
+#[test]
+fn test_corpus() {
+    let mut paths: Vec<PathBuf> = fs::read_dir("tests/testdata/typecheck/good")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "gol"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    for path in paths {
+        agree(&fs::read_to_string(&path).unwrap());
+    }
+}
+
+#[test]
+fn test_programs() {
+    let tests = vec![
+        "add(1, 2)",
+        "if lt(1, 2) { 10 } else { 20 }",
+        "add(1)",
+        "fn(x : int) { x }",
+        "fn(x : int, y : int) { sub(x, y) }(10)(3)",
+        "fn(f : int -> int, x : int) { f(f(x)) }(fn(y : int) { mul(y, y) }, 3)",
+        "fn(a : int) { fn(b : int) { fn(c : bool) { if c { a } else { b } } } }(1)(2)(false)",
+        "mul(4294967296, mul(4294967296, neg(4294967296)))",
+        "sub(0, 99999999999999999999)",
+        "-9223372036854775808",
+        "mul(mul(mul(123456789, 987654321), 1000000007), -998244353)",
+        "div(mul(99999999999999999999, 7), 99999999999999999999)",
+        "mod(neg(mul(99999999999999999999, 7)), 12345678901)",
+        "fn(n : int -> int) { n(n(n(n(n(n(n(n(n(n(2)))))))))) }(fn(x : int) { mul(x, x) })",
+    ];
+    for src in tests {
+        agree(src);
+    }
+}
+
+/// Every primitive behaves the same compiled as interpreted.
+#[test]
+fn test_primitives() {
+    let mut calls = Vec::new();
+    for prim in globals::primitives() {
+        let ty = prim.ty.to_string();
+        let samples: &[&[&str]] = if ty.starts_with("bool") {
+            &[
+                &["true", "true"],
+                &["true", "false"],
+                &["false", "true"],
+                &["false", "false"],
+            ]
+        } else {
+            &[
+                &["-7", "2"],
+                &["7", "-2"],
+                &["-7", "-2"],
+                &["6", "3"],
+                &["5", "0"],
+                &["0", "5"],
+                &["-99999999999999999999", "4294967296"],
+            ]
+        };
+        for args in samples {
+            calls.push(format!(
+                "{}({})",
+                prim.name,
+                args[..prim.arity()].join(", ")
+            ));
+        }
+    }
+    for call in calls {
+        agree(&call);
+    }
+}
+
+#[test]
+fn test_division_by_zero() {
+    for src in &["div(5, 0)", "mod(5, 0)", "fn(x : int) { 1 }(div(1, sub(2, 2)))"] {
+        assert_eq!(run_compiled(src), Err("runtime error: divided by zero".to_string()), "{}", src);
+        agree(src);
+    }
+}
+
+#[test]
+fn test_generated() {
+    for seed in 0..40 {
+        let (ast, _) = Generator::new(seed).program();
+        agree(&pretty::pretty(&ast));
+    }
+}
+
+#[test]
+fn test_unsupported() {
+    let tests = vec![
+        ("[1, 2]", "`cons` is not supported by this backend"),
+        ("fn(x : list<int>) { 1 }", "`list<int> -> int` is not supported by this backend"),
+        ("option(0, inc, none)", "`option` is not supported by this backend"),
+        ("eq(true, false)", "`eq : bool -> bool -> bool` is not supported by this backend"),
+    ];
+    for (src, expected) in tests {
+        let ast = parser::parse("test", src).unwrap();
+        let err = cgen::compile(&ast).unwrap_err();
+        assert_eq!((err.kind(), err.to_string()), ("Unsupported", expected.to_string()), "{}", src);
+    }
+}
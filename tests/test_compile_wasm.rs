@@ -0,0 +1,176 @@
+extern crate iron_golem;
+extern crate wasmi;
+
+use iron_golem::eval;
+use iron_golem::gen::Generator;
+use iron_golem::globals;
+use iron_golem::parser;
+use iron_golem::pretty;
+use iron_golem::wasm;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Compile `src` to a wasm binary, instantiate it, and return what its `run`
+/// export prints and how many pages of memory it finished with, or the trap
+/// it stopped with.
+fn run_wasm(src: &str) -> Result<(String, u32), wasmi::Error> {
+    let ast = parser::parse("test.gol", src).unwrap();
+    let bytes = wasm::assemble(&ast).unwrap_or_else(|e| panic!("compile({}): {}", src, e));
+    let engine = wasmi::Engine::default();
+    wasmi::Module::validate(&engine, &bytes).unwrap_or_else(|e| panic!("validate({}): {}", src, e));
+    let module = wasmi::Module::new(&engine, &bytes[..]).unwrap();
+    let mut store = wasmi::Store::new(&engine, ());
+    let instance = wasmi::Linker::<()>::new(&engine)
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+    let run = instance
+        .get_typed_func::<(), (i32, i32)>(&store, "run")
+        .unwrap();
+    let (ptr, len) = run.call(&mut store, ())?;
+    let memory = instance.get_memory(&store, "memory").unwrap();
+    let text = &memory.data(&store)[ptr as usize..(ptr + len) as usize];
+    let text = String::from_utf8(text.to_vec()).unwrap();
+    Ok((text, memory.size(&store) as u32))
+}
+
+/// What the compiled program prints, or `Err` if it traps.
+fn run_compiled(src: &str) -> Result<String, ()> {
+    run_wasm(src).map(|(text, _)| text).map_err(|_| ())
+}
+
+fn run_interpreted(src: &str) -> Result<String, ()> {
+    let ast = parser::parse("test.gol", src).unwrap();
+    eval::eval(&globals::global_values(), &ast)
+        .map(|val| val.to_string())
+        .map_err(|_| ())
+}
+
+fn agree(src: &str) {
+    assert_eq!(run_compiled(src), run_interpreted(src), "program: {}", src);
+}
+
+#[test]
+fn test_text_format() {
+    let ast = parser::parse("test.gol", "add(1, 2)").unwrap();
+    let text = wasm::compile(&ast).unwrap();
+    assert!(text.starts_with(";; Generated by iron-golem.\n(module\n"));
+    assert!(text.contains("(func (export \"run\") (result i32 i32)"));
+    assert_eq!(&wasm::assemble(&ast).unwrap()[..4], b"\0asm");
+}
+
+#[test]
+fn test_corpus() {
+    let mut paths: Vec<PathBuf> = fs::read_dir("tests/testdata/typecheck/good")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "gol"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    for path in paths {
+        agree(&fs::read_to_string(&path).unwrap());
+    }
+}
+
+#[test]
+fn test_programs() {
+    let tests = vec![
+        "add(1, 2)",
+        "true",
+        "if lt(1, 2) { 10 } else { 20 }",
+        "add(1)",
+        "fn(x : int) { x }",
+        "fn(x : int, y : int) { sub(x, y) }(10)(3)",
+        "fn(f : int -> int, x : int) { f(f(x)) }(fn(y : int) { mul(y, y) }, 3)",
+        "fn(a : int) { fn(b : int) { fn(c : bool) { if c { a } else { b } } } }(1)(2)(false)",
+        "mul(4294967296, mul(4294967296, neg(4294967296)))",
+        "sub(0, 99999999999999999999)",
+        "-9223372036854775808",
+        "mul(mul(mul(123456789, 987654321), 1000000007), -998244353)",
+        "div(mul(99999999999999999999, 7), 99999999999999999999)",
+        "mod(neg(mul(99999999999999999999, 7)), 12345678901)",
+        "fn(n : int -> int) { n(n(n(n(n(n(n(n(n(n(2)))))))))) }(fn(x : int) { mul(x, x) })",
+    ];
+    for src in tests {
+        agree(src);
+    }
+}
+
+/// Programs get as much memory as their static data and values need.
+#[test]
+fn test_memory_grows() {
+    // About 40KiB as a literal, and as much again for the sum.
+    let big = format!("1{}", "0".repeat(100_000));
+    let src = format!("iszero(add({}, {}))", big, big);
+    let (val, pages) = run_wasm(&src).unwrap();
+    assert_eq!(val, "false");
+    assert!(pages > 1);
+}
+
+/// Every primitive behaves the same compiled as interpreted.
+#[test]
+fn test_primitives() {
+    for prim in globals::primitives() {
+        let ty = prim.ty.to_string();
+        let samples: &[&[&str]] = if ty.starts_with("bool") {
+            &[
+                &["true", "true"],
+                &["true", "false"],
+                &["false", "true"],
+                &["false", "false"],
+            ]
+        } else {
+            &[
+                &["-7", "2"],
+                &["7", "-2"],
+                &["-7", "-2"],
+                &["6", "3"],
+                &["5", "0"],
+                &["0", "5"],
+                &["-99999999999999999999", "4294967296"],
+            ]
+        };
+        for args in samples {
+            agree(&format!(
+                "{}({})",
+                prim.name,
+                args[..prim.arity()].join(", ")
+            ));
+        }
+    }
+}
+
+#[test]
+fn test_division_by_zero() {
+    for src in &["div(5, 0)", "mod(5, 0)", "fn(x : int) { 1 }(div(1, sub(2, 2)))"] {
+        let trap = run_wasm(src).unwrap_err();
+        assert_eq!(trap.as_trap_code(), Some(wasmi::core::TrapCode::UnreachableCodeReached), "{}", src);
+        agree(src);
+    }
+}
+
+#[test]
+fn test_generated() {
+    for seed in 0..100 {
+        let (ast, _) = Generator::new(seed).program();
+        agree(&pretty::pretty(&ast));
+    }
+}
+
+#[test]
+fn test_unsupported() {
+    let tests = vec![
+        ("[1, 2]", "`cons` is not supported by this backend"),
+        ("fn(x : list<int>) { 1 }", "`list<int> -> int` is not supported by this backend"),
+        ("option(0, inc, none)", "`option` is not supported by this backend"),
+        ("eq(true, false)", "`eq : bool -> bool -> bool` is not supported by this backend"),
+    ];
+    for (src, expected) in tests {
+        let ast = parser::parse("test", src).unwrap();
+        let err = wasm::compile(&ast).unwrap_err();
+        assert_eq!((err.kind(), err.to_string()), ("Unsupported", expected.to_string()), "{}", src);
+    }
+}
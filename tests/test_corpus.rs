@@ -1,10 +1,13 @@
 extern crate iron_golem;
 extern crate glob;
 
+use std::env;
+use std::fs;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
+use iron_golem::golden;
 use iron_golem::globals;
 use iron_golem::typecheck;
 use iron_golem::parser;
@@ -22,7 +25,7 @@ fn read_file(path: &Path) -> String {
 fn test_typecheck() {
     let mut i = 0;
     for entry in glob("tests/testdata/typecheck/good/*.gol").expect("glob failed") {
-        i = i + 1;
+        i += 1;
         let path = entry.expect("failed to glob path");
         println!("checking: {}...", path.display());
         let src = read_file(&path);
@@ -35,15 +38,15 @@ fn test_typecheck() {
                 println!("test: {}", src);
                 let got = typecheck::typecheck(&globals::global_env(), &ast);
                 let ty = typecheck::ast_to_type(&globals::global_env(), &ty_ast);
-                assert!(got.is_ok(), format!("typecheck({}): {:?}", src, got));
-                assert!(ty.is_ok(), format!("expect: {:?}", ty));
+                assert!(got.is_ok(), "typecheck({}): {:?}", src, got);
+                assert!(ty.is_ok(), "expect: {:?}", ty);
                 assert!(
                     got.as_ref().unwrap() == ty.as_ref().unwrap(),
-                    format!("tc({}) = {:?} != {:?}", src, got, ty)
+                    "tc({}) = {:?} != {:?}", src, got, ty
                 );
             }
-            (Err(err), _) => assert!(false, format!("parse({}): {:?}", src, err)),
-            (_, Err(err)) => assert!(false, format!("parse_type({}): {:?}", expect_src, err)),
+            (Err(err), _) => panic!("parse({}): {:?}", src, err),
+            (_, Err(err)) => panic!("parse_type({}): {:?}", expect_src, err),
         }
     }
     assert!(i > 0, "found no examples!");
@@ -53,7 +56,7 @@ fn test_typecheck() {
 fn test_bad() {
     let mut i = 0;
     for entry in glob("tests/testdata/typecheck/bad/*.gol").expect("glob failed") {
-         i= i +1;
+         i += 1;
         let path = entry.expect("failed to glob path");
         println!("checking: {}...", path.display());
         let src = read_file(&path);
@@ -63,9 +66,69 @@ fn test_bad() {
                 // OK
             },
             Ok(ty) => {
-                assert!(false, format!("typecheck({}) = {:?}", path.display(), ty))
+                panic!("typecheck({}) = {:?}", path.display(), ty)
             }
         }
     }
     assert!(i > 0, "found no examples!");
 }
+
+#[test]
+fn test_kinds() {
+    let env = globals::global_env();
+    for src in &["list<int>", "option<bool>", "map<int, list<bool>>", "option<int -> int>"] {
+        let ty_ast = parser::parse_type("<test>", src).expect("parse ok");
+        match typecheck::ast_to_type(&env, &ty_ast) {
+            Ok(ty) => assert_eq!(ty.to_string(), *src),
+            Err(err) => panic!("ast_to_type({}): {}", src, err),
+        }
+    }
+    for &(src, msg) in &[
+        ("list<int, bool>", "`list` has kind `* -> *` but is applied to 2 type arguments"),
+        ("int<bool>", "`int` has kind `*` but is applied to 1 type argument"),
+        ("map", "`map` has kind `* -> * -> *` but is applied to 0 type arguments"),
+        ("int -> option", "`option` has kind `* -> *` but is applied to 0 type arguments"),
+    ] {
+        let ty_ast = parser::parse_type("<test>", src).expect("parse ok");
+        match typecheck::ast_to_type(&env, &ty_ast) {
+            Err(err) => assert_eq!(err.to_string(), msg, "{}", src),
+            Ok(ty) => panic!("ast_to_type({}) = {}", src, ty),
+        }
+    }
+}
+
+#[test]
+fn test_golden() {
+    let report = golden::run(Path::new("tests/testdata"), false).expect("run to succeed");
+    assert!(!report.results.is_empty(), "found no examples!");
+    assert_eq!(report.failures(), 0, "{}", report);
+}
+
+#[test]
+fn test_bless() {
+    let dir = env::temp_dir().join(format!("iron-golem-bless-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("ok.gol"), "fn(x : int) { iszero(x) }").unwrap();
+    fs::write(dir.join("bad.gol"), "not(\n  1)").unwrap();
+    fs::write(dir.join("bad.expect"), "bool").unwrap();
+    fs::write(dir.join("two.gol"), "add(1, 1)").unwrap();
+    fs::write(dir.join("two.expect"), "int").unwrap();
+    fs::write(dir.join("two.out"), "3").unwrap();
+    fs::write(dir.join("empty.gol"), "head(tail([1]))").unwrap();
+
+    let report = golden::run(&dir, false).unwrap();
+    assert_eq!(report.failures(), 4, "{}", report);
+
+    let report = golden::run(&dir, true).unwrap();
+    assert_eq!(report.failures(), 0, "{}", report);
+    assert_eq!(fs::read_to_string(dir.join("ok.expect")).unwrap(), "int -> bool\n");
+    assert_eq!(fs::read_to_string(dir.join("ok.out")).unwrap(), "<fn>\n");
+    assert_eq!(fs::read_to_string(dir.join("two.out")).unwrap(), "2\n");
+    assert_eq!(fs::read_to_string(dir.join("empty.out")).unwrap(), "EmptyList 1:1\n");
+    assert_eq!(fs::read_to_string(dir.join("bad.err")).unwrap(), "Mismatch 1:1\n");
+    assert!(!dir.join("bad.expect").exists());
+
+    let report = golden::run(&dir, false).unwrap();
+    assert_eq!(report.failures(), 0, "{}", report);
+    fs::remove_dir_all(&dir).unwrap();
+}
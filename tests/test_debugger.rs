@@ -0,0 +1,112 @@
+extern crate iron_golem;
+
+use std::io::Cursor;
+
+use iron_golem::debugger;
+use iron_golem::eval::Limits;
+use iron_golem::globals;
+use iron_golem::parser;
+
+const TWICE: &str = "(fn(twice : (int -> int) -> int -> int) {
+  twice(fn(x : int) { mul(x, 2) })(3)
+})(fn(f : int -> int, x : int) { f(f(x)) })
+";
+
+fn session(src: &str, commands: &str) -> String {
+    session_with(src, commands, &Limits::default())
+}
+
+fn session_with(src: &str, commands: &str, limits: &Limits) -> String {
+    let ast = parser::parse("twice.gol", src).unwrap();
+    let mut out = Vec::new();
+    debugger::run(
+        src,
+        &globals::global_values(),
+        &ast,
+        limits,
+        Cursor::new(commands),
+        &mut out,
+    )
+    .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_breakpoint() {
+    let out = session(TWICE, "break 2:23\ncontinue\nenv\nwhere\ncontinue\nenv\n");
+    assert_eq!(
+        out,
+        "stopped at twice.gol:1:1
+breakpoint 1 at 2:23
+breakpoint 1 at twice.gol:2:23
+      twice(fn(x : int) { mul(x, 2) })(3)
+                          ^^^^^^^^^
+x = 3
+twice = <fn>
+at twice.gol:2:23
+#0 called from twice.gol:3:36
+#1 called from twice.gol:2:3
+#2 called from twice.gol:1:1
+breakpoint 1 at twice.gol:2:23
+      twice(fn(x : int) { mul(x, 2) })(3)
+                          ^^^^^^^^^
+x = 6
+twice = <fn>
+"
+    );
+}
+
+#[test]
+fn test_step_over_and_out() {
+    // Stepping into the outer call, over `twice`'s application to the
+    // doubling function, and then out of the whole program.
+    let out = session(TWICE, "step\nstep\nnext\nfinish\n");
+    let steps: Vec<&str> = out
+        .lines()
+        .filter(|line| line.starts_with("twice.gol"))
+        .collect();
+    assert_eq!(
+        steps,
+        vec![
+            "twice.gol:1:1: apply: twice = <fn>",
+            "twice.gol:2:3: twice = <fn>",
+            "twice.gol:2:3: return <fn>",
+            "twice.gol:1:1: return 12",
+        ]
+    );
+}
+
+#[test]
+fn test_end_of_program() {
+    let out = session("add(1, 2)", "delete 1\nstep\nstep\n");
+    assert_eq!(
+        out,
+        "stopped at twice.gol:1:1
+usage: delete N
+twice.gol:1:1: add(1, 2) = 3
+    add(1, 2)
+    ^^^^^^^^^
+value: 3
+"
+    );
+}
+
+#[test]
+fn test_runtime_error() {
+    let out = session("add(1, 2)(3)", "continue\n");
+    assert!(
+        out.ends_with("twice.gol:1:1: runtime error: applied a value that is not a function\n"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn test_limits() {
+    let limits = Limits {
+        fuel: Some(1000),
+        ..Limits::default()
+    };
+    let out = session_with("fn(x) { x(x) }(fn(x) { x(x) })", "continue\n", &limits);
+    assert!(out.ends_with("twice.gol:1:24: runtime error: ran out of fuel\n"), "{}", out);
+}
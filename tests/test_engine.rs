@@ -0,0 +1,234 @@
+extern crate iron_golem;
+extern crate num_bigint;
+
+use iron_golem::diagnostics::Phase;
+use iron_golem::engine::{Engine, FromValue, Type, Value};
+use iron_golem::eval;
+use iron_golem::parser;
+use iron_golem::typecheck;
+
+use num_bigint::BigInt;
+
+use std::error::Error;
+
+fn run(engine: &Engine, src: &str) -> Result<(String, String), String> {
+    let ast = parser::parse("test", src).map_err(|e| e.to_string())?;
+    let ty = typecheck::typecheck(&engine.type_env(), &ast).map_err(|e| e.kind().to_string())?;
+    let val = eval::eval(&engine.values(), &ast).map_err(|e| e.kind().to_string())?;
+    Ok((ty.to_string(), val.to_string()))
+}
+
+fn ok(ty: &str, val: &str) -> Result<(String, String), String> {
+    Ok((ty.to_string(), val.to_string()))
+}
+
+#[test]
+fn test_register_fn() {
+    let mut engine = Engine::new();
+    let scale = 3;
+    engine
+        .register_fn("scale", move |x: i64| x * scale)
+        .register_fn("clamp", |x: i64, lo: i64, hi: i64| x.max(lo).min(hi))
+        .register_fn("between", |x: i64, lo: i64, hi: i64| lo <= x && x <= hi)
+        .register_fn("square", |x: BigInt| &x * &x)
+        .register_value("limit", 10i64)
+        .register_value("debug", true);
+
+    assert_eq!(run(&engine, "scale"), ok("int -> int", "<builtin scale>"));
+    assert_eq!(run(&engine, "scale(add(limit, 4))"), ok("int", "42"));
+    assert_eq!(run(&engine, "clamp(50, 0, limit)"), ok("int", "10"));
+    assert_eq!(run(&engine, "clamp(50, 0)"), ok("int -> int", "<builtin clamp>"));
+    assert_eq!(run(&engine, "between(5, 0, limit)"), ok("bool", "true"));
+    assert_eq!(
+        run(&engine, "square(100000000000000000000)"),
+        ok("int", "10000000000000000000000000000000000000000")
+    );
+    assert_eq!(run(&engine, "if debug { 1 } else { 2 }"), ok("int", "1"));
+
+    // The registered types are enforced by the typechecker.
+    assert_eq!(run(&engine, "scale(true)"), Err("Mismatch".to_string()));
+    assert_eq!(run(&engine, "not(limit)"), Err("Mismatch".to_string()));
+}
+
+#[test]
+fn test_register_errors() {
+    let mut engine = Engine::new();
+    engine.register_fn("checked_div", |x: i64, y: i64| {
+        x.checked_div(y).ok_or_else(|| format!("{} / {}", x, y))
+    });
+
+    assert_eq!(run(&engine, "checked_div(7, 2)"), ok("int", "3"));
+    assert_eq!(run(&engine, "checked_div(7, 0)"), Err("HostError".to_string()));
+    assert_eq!(
+        run(&engine, "checked_div(99999999999999999999, 1)"),
+        Err("OutOfRange".to_string())
+    );
+}
+
+#[test]
+fn test_register_lists() {
+    let mut engine = Engine::new();
+    engine
+        .register_fn("range", |n: i64| (0..n).collect::<Vec<i64>>())
+        .register_fn("total", |xs: Vec<i64>| xs.iter().sum::<i64>());
+
+    assert_eq!(
+        run(&engine, "range"),
+        ok("int -> list<int>", "<builtin range>")
+    );
+    assert_eq!(run(&engine, "range(3)"), ok("list<int>", "[0, 1, 2]"));
+    assert_eq!(run(&engine, "total(map(inc, range(4)))"), ok("int", "10"));
+    assert_eq!(run(&engine, "total([true])"), Err("Mismatch".to_string()));
+    assert_eq!(
+        engine.eval("[[1], []]").map_err(|e| e.to_string()),
+        Ok(Value::List(vec![
+            Value::List(vec![Value::Integer(BigInt::from(1))]),
+            Value::List(vec![]),
+        ]))
+    );
+}
+
+#[test]
+fn test_register_options() {
+    let mut engine = Engine::new();
+    engine
+        .register_fn("half", |n: i64| if n % 2 == 0 { Some(n / 2) } else { None })
+        .register_fn("or_zero", |n: Option<i64>| n.unwrap_or(0));
+
+    assert_eq!(run(&engine, "half"), ok("int -> option<int>", "<builtin half>"));
+    assert_eq!(run(&engine, "half(6)"), ok("option<int>", "some(3)"));
+    assert_eq!(run(&engine, "or_zero(half(3))"), ok("int", "0"));
+    assert_eq!(run(&engine, "option(0, inc, half(8))"), ok("int", "5"));
+    assert_eq!(
+        engine.eval("[some(true), none]").map_err(|e| e.to_string()),
+        Ok(Value::List(vec![
+            Value::Option(Some(Box::new(Value::Boolean(true)))),
+            Value::Option(None),
+        ]))
+    );
+}
+
+#[test]
+fn test_replace_prelude() {
+    let mut engine = Engine::new();
+    engine.register_fn("add", |x: i64, y: i64| x + y + 1);
+    assert_eq!(run(&engine, "add(1, 1)"), ok("int", "3"));
+    assert_eq!(run(&engine, "sub(1, 1)"), ok("int", "0"));
+}
+
+#[test]
+fn test_from_value() {
+    let engine = Engine::new();
+    let src = "mul(6, 7)";
+    let ast = parser::parse("test", src).unwrap();
+    let val = eval::eval(&engine.values(), &ast).unwrap();
+    assert_eq!(i64::from_value(&val), Ok(42));
+    assert_eq!(BigInt::from_value(&val), Ok(BigInt::from(42)));
+    assert_eq!(bool::from_value(&val), Err(eval::Trap::BadArgument));
+}
+
+/// Results and errors outlive the source they came from, and errors work
+/// with `?` in functions returning `Box<dyn Error>`.
+fn eval_owned(engine: &Engine, src: String) -> Result<Value, Box<dyn Error>> {
+    Ok(engine.eval(&src)?)
+}
+
+#[test]
+fn test_check() {
+    let engine = Engine::new();
+    assert_eq!(engine.check("add(1)").unwrap().to_string(), "int -> int");
+    assert_eq!(
+        engine.check("fn(f : int -> bool) { f }").unwrap(),
+        Type::Function(
+            Box::new(Type::Function(
+                Box::new(Type::Primitive("int".to_string())),
+                Box::new(Type::Primitive("bool".to_string()))
+            )),
+            Box::new(Type::Function(
+                Box::new(Type::Primitive("int".to_string())),
+                Box::new(Type::Primitive("bool".to_string()))
+            ))
+        )
+    );
+
+    let err = engine.check("if 1 { 2 } else { 3 }").unwrap_err();
+    assert_eq!(err.diagnostics.len(), 1);
+    let diag = &err.diagnostics[0];
+    assert_eq!((diag.phase, diag.kind), (Phase::Type, "Mismatch"));
+    assert_eq!(diag.file, "<input>");
+
+    let err = engine.check("add(1,").unwrap_err();
+    let diag = &err.diagnostics[0];
+    assert_eq!((diag.phase, diag.kind), (Phase::Parse, "Syntax"));
+    assert_eq!((diag.start.line, diag.start.column), (1, 7));
+    assert_eq!(err.to_string(), "<input>:1:7: parse error: syntax error: expected expression");
+}
+
+#[test]
+fn test_eval() {
+    let mut engine = Engine::new();
+    engine.register_fn("checked_div", |x: i64, y: i64| {
+        x.checked_div(y).ok_or("division by zero")
+    });
+
+    let val = eval_owned(&engine, "mul(6, 7)".to_string()).unwrap();
+    assert_eq!(val, Value::Integer(BigInt::from(42)));
+    assert_eq!(val.as_i64(), Some(42));
+    assert_eq!(engine.eval("lt(1, 2)").unwrap().as_bool(), Some(true));
+    assert_eq!(engine.eval("add").unwrap().to_string(), "<builtin add>");
+
+    let err = eval_owned(&engine, "add(1, checked_div(1, 0))".to_string()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "<input>:1:8: runtime error: host function failed: division by zero\n    in checked_div at <input>:1:8"
+    );
+}
+
+#[test]
+fn test_classes() {
+    let engine = Engine::new();
+    assert_eq!(engine.check("eq(true, true)").unwrap().to_string(), "bool");
+    assert_eq!(engine.check("fn(x) { eq(x, x) }").unwrap().to_string(), "int -> bool");
+    assert_eq!(engine.eval("eq(true, false)").unwrap(), Value::Boolean(false));
+    assert_eq!(engine.eval("eq(eq(1, 2), eq(true, false))").unwrap(), Value::Boolean(true));
+
+    let err = engine.eval("add(true, false)").unwrap_err();
+    let diag = &err.diagnostics[0];
+    assert_eq!((diag.phase, diag.kind), (Phase::Type, "NoInstance"));
+}
+
+#[test]
+fn test_stack_trace() {
+    let mut engine = Engine::new();
+    engine.register_fn("checked_div", |x: i64, y: i64| {
+        x.checked_div(y).ok_or("division by zero")
+    });
+    let src = "(fn(ratio : int -> int -> int) {
+  (fn(x : int) { ratio(x, 0) })(1)
+})(fn(x : int, y : int) { checked_div(x, y) })";
+    let err = engine.eval(src).unwrap_err();
+    let diag = &err.diagnostics[0];
+    assert_eq!(diag.phase, Phase::Runtime);
+    let trace: Vec<(Option<&str>, u32, u32)> = diag
+        .trace
+        .iter()
+        .map(|frame| (frame.name.as_ref().map(|s| &s[..]), frame.start.line, frame.start.column))
+        .collect();
+    assert_eq!(
+        trace,
+        vec![
+            (Some("checked_div"), 3, 27),
+            (Some("ratio"), 2, 18),
+            (None, 2, 3),
+            (None, 1, 1),
+        ]
+    );
+    assert_eq!(
+        err.to_string(),
+        "<input>:3:27: runtime error: host function failed: division by zero
+    in checked_div at <input>:3:27
+    in ratio at <input>:2:18
+    in <input>:2:3
+    in <input>:1:1"
+    );
+}
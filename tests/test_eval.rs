@@ -0,0 +1,210 @@
+extern crate iron_golem;
+
+use iron_golem::eval;
+use iron_golem::globals;
+use iron_golem::parser;
+
+fn run(src: &str) -> Result<String, String> {
+    let ast = parser::parse("test", src).map_err(|e| e.to_string())?;
+    eval::eval(&globals::global_values(), &ast)
+        .map(|val| val.to_string())
+        .map_err(|e| format!("{} {}", e.kind(), e.loc().start(src).column))
+}
+
+#[test]
+fn test_literal_roundtrip() {
+    let literals = vec![
+        "0",
+        "1",
+        "-1",
+        "42",
+        "9223372036854775807",
+        "-9223372036854775808",
+        "123456789012345678901234567890",
+        "-123456789012345678901234567890",
+        "true",
+        "false",
+    ];
+    for lit in literals {
+        assert_eq!(run(lit), Ok(lit.to_string()), "eval({})", lit);
+    }
+}
+
+#[test]
+fn test_eval() {
+    let tests = vec![
+        ("add(1, 2)", "3"),
+        ("if not(false) { 1 } else { 2 }", "1"),
+        ("if iszero(1) { true } else { false }", "false"),
+        ("fn(x : int) { x }(7)", "7"),
+        ("fn(x, y) { sub(x, y) }(10)(3)", "7"),
+        ("add(9223372036854775807, 1)", "9223372036854775808"),
+        ("dec(-9223372036854775808)", "-9223372036854775809"),
+        (
+            "mul(4294967296, mul(4294967296, 4294967296))",
+            "79228162514264337593543950336",
+        ),
+        ("sub(0, 99999999999999999999)", "-99999999999999999999"),
+        ("add(1)", "<builtin add>"),
+        ("fn(x) { x }", "<fn>"),
+        (
+            "fn(f : (int -> int) -> int -> int) {
+               fn(x : int) { f(fn(v : int) { x(x)(v) }) }(fn(x) { f(fn(v : int) { x(x)(v) }) })
+             }(fn(rec : int -> int, n : int) {
+               if iszero(n) { 1 } else { mul(n, rec(dec(n))) }
+             })(10)",
+            "3628800",
+        ),
+    ];
+    for (src, want) in tests {
+        assert_eq!(run(src), Ok(want.to_string()), "eval({})", src);
+    }
+}
+
+#[test]
+fn test_errors() {
+    let tests = vec![
+        ("1(2)", "NotAFunction 1"),
+        ("if 1 { 2 } else { 3 }", "NotABoolean 4"),
+        ("add(true, 1)", "BadArgument 1"),
+        ("undefined", "UnboundVariable 1"),
+        ("head([])", "EmptyList 1"),
+        ("tail(tail([1]))", "EmptyList 1"),
+        ("map(fn(x) { x(1) }, [1])", "NotAFunction 13"),
+    ];
+    for (src, want) in tests {
+        assert_eq!(run(src), Err(want.to_string()), "eval({})", src);
+    }
+}
+
+#[test]
+fn test_lists() {
+    let tests = vec![
+        ("[]", "[]"),
+        ("[1, 2, 3]", "[1, 2, 3]"),
+        ("[[1], [], [true]]", "[[1], [], [true]]"),
+        ("cons(0, [1])", "[0, 1]"),
+        ("head([1, 2])", "1"),
+        ("tail([1, 2])", "[2]"),
+        ("isnil([])", "true"),
+        ("isnil([[]])", "false"),
+        ("map(inc, [1, 2, 3])", "[2, 3, 4]"),
+        ("map(fn(x) { [x] }, [])", "[]"),
+        ("fold(add, 0, [1, 2, 3])", "6"),
+        ("fold(sub, 0, [1, 2, 3])", "2"),
+        ("fold(cons, [4], [1, 2, 3])", "[1, 2, 3, 4]"),
+        (
+            "fn(xs : list<int>) { map(iszero, xs) }([0, 1])",
+            "[true, false]",
+        ),
+    ];
+    for (src, want) in tests {
+        assert_eq!(run(src), Ok(want.to_string()), "eval({})", src);
+    }
+}
+
+#[test]
+fn test_long_list() {
+    // Dropping a list does not recurse once per cell.
+    let list = (0..1_000_000).fold(eval::List::nil(), |tail, i| {
+        eval::List::cons(eval::Value::Integer(i.into()), tail)
+    });
+    assert_eq!(list.iter().count(), 1_000_000);
+    drop(list);
+}
+
+#[test]
+fn test_factorial() {
+    let fact = "fn(f : (int -> int) -> int -> int) {
+                  fn(x : int) { f(fn(v : int) { x(x)(v) }) }(fn(x) { f(fn(v : int) { x(x)(v) }) })
+                }(fn(rec : int -> int, n : int) {
+                  if iszero(n) { 1 } else { mul(n, rec(dec(n))) }
+                })(50)";
+    assert_eq!(
+        run(fact),
+        Ok("30414093201713378043612608166064768844377641568960512000000000000".to_string())
+    );
+}
+
+#[test]
+fn test_prelude() {
+    let tests = vec![
+        ("div(7, 2)", "3"),
+        ("div(-7, 2)", "-4"),
+        ("div(7, -2)", "-4"),
+        ("mod(7, 2)", "1"),
+        ("mod(-7, 2)", "1"),
+        ("mod(7, -2)", "-1"),
+        ("neg(3)", "-3"),
+        ("abs(-3)", "3"),
+        ("inc(-1)", "0"),
+        ("min(2, -2)", "-2"),
+        ("max(2, -2)", "2"),
+        ("eq(2, 2)", "true"),
+        ("lt(1, 2)", "true"),
+        ("le(2, 2)", "true"),
+        ("gt(1, 2)", "false"),
+        ("ge(1, 2)", "false"),
+        ("and(true, false)", "false"),
+        ("or(true, false)", "true"),
+        ("xor(true, true)", "false"),
+    ];
+    for (src, want) in tests {
+        assert_eq!(run(src), Ok(want.to_string()), "eval({})", src);
+    }
+
+    let errors = vec![
+        ("div(5, 0)", "DivisionByZero 1"),
+        ("mod(5, 0)", "DivisionByZero 1"),
+        ("add(1, div(5, sub(2, 2)))", "DivisionByZero 8"),
+    ];
+    for (src, want) in errors {
+        assert_eq!(run(src), Err(want.to_string()), "eval({})", src);
+    }
+}
+
+/// Every primitive's type agrees with how many arguments it takes at
+/// runtime, and with the kinds of value it accepts and returns.
+#[test]
+fn test_prelude_types() {
+    use iron_golem::typecheck;
+
+    for prim in globals::primitives() {
+        let mut args = Vec::new();
+        let mut ty = prim.ty.to_string();
+        while let Some(arrow) = ty.find(" -> ") {
+            args.push(if ty.starts_with("int") { "3" } else { "true" });
+            ty = ty[arrow + 4..].to_string();
+        }
+        let src = format!("{}({})", prim.name, args.join(", "));
+        let ast = parser::parse("test", &src).unwrap();
+        let got = typecheck::typecheck(&globals::global_env(), &ast).unwrap();
+        assert_eq!(got.to_string(), ty, "tc({})", src);
+        let val = run(&src).unwrap();
+        let is_int = val.parse::<i64>().is_ok();
+        assert_eq!(is_int, ty == "int", "eval({}) = {}", src, val);
+    }
+}
+
+#[test]
+fn test_stack_trace() {
+    // Runaway recursion through a self-applied function, stopped by the
+    // recursion limit, leaves one frame per level.
+    let src = "(fn(f) { f(f) })(fn(self) { self(self) })";
+    let ast = parser::parse("test", src).unwrap();
+    let limits = eval::Limits {
+        max_depth: Some(30),
+        ..eval::Limits::default()
+    };
+    let failure = eval::eval_traced(&globals::global_values(), &ast, &limits).unwrap_err();
+    assert_eq!(failure.error.kind(), "RecursionLimit");
+    let names: Vec<String> = failure
+        .trace
+        .iter()
+        .map(|frame| frame.name.as_ref().map_or("-".to_string(), |name| name.to_string()))
+        .collect();
+    assert!(names.len() > 5, "{:?}", names);
+    assert_eq!(names.last().unwrap(), "-");
+    assert!(names[..names.len() - 2].iter().all(|name| name == "self"), "{:?}", names);
+    assert_eq!(names[names.len() - 2], "f");
+}
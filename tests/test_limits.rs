@@ -0,0 +1,188 @@
+extern crate iron_golem;
+
+use iron_golem::engine::Engine;
+use iron_golem::eval;
+use iron_golem::globals;
+use iron_golem::module::{Arena, Loader};
+use iron_golem::parser;
+use iron_golem::typecheck;
+
+use std::time::Duration;
+
+/// Diverges, and does not typecheck, so it must be evaluated directly.
+const OMEGA: &str = "fn(x) { x(x) }(fn(x) { x(x) })";
+
+fn eval_kind(src: &str, limits: eval::Limits) -> Result<String, &'static str> {
+    let ast = parser::parse("test", src).unwrap();
+    eval::eval_with(&globals::global_values(), &ast, &limits)
+        .map(|val| val.to_string())
+        .map_err(|e| e.kind())
+}
+
+fn check_kind(engine: &Engine, src: &str) -> Result<String, &'static str> {
+    engine
+        .check(src)
+        .map(|ty| ty.to_string())
+        .map_err(|e| e.diagnostics[0].kind)
+}
+
+#[test]
+fn test_fuel() {
+    let limits = eval::Limits {
+        fuel: Some(1000),
+        ..eval::Limits::default()
+    };
+    assert_eq!(eval_kind(OMEGA, limits.clone()), Err("OutOfFuel"));
+    assert_eq!(eval_kind("add(1, 2)", limits), Ok("3".to_string()));
+
+    let limits = eval::Limits {
+        fuel: Some(3),
+        ..eval::Limits::default()
+    };
+    assert_eq!(eval_kind("add(1, 2)", limits), Err("OutOfFuel"));
+}
+
+#[test]
+fn test_recursion_limit() {
+    let limits = eval::Limits {
+        max_depth: Some(100),
+        ..eval::Limits::default()
+    };
+    assert_eq!(eval_kind(OMEGA, limits), Err("RecursionLimit"));
+}
+
+#[test]
+fn test_deadline() {
+    let limits = eval::Limits {
+        timeout: Some(Duration::from_secs(0)),
+        ..eval::Limits::default()
+    };
+    assert_eq!(eval_kind(OMEGA, limits), Err("Deadline"));
+}
+
+#[test]
+fn test_heap_limit() {
+    // 2 squared 16 times is 2^65536, eight kilobytes of integer.
+    let src = "fn(sq : int -> int) {
+                 sq(sq(sq(sq(sq(sq(sq(sq(sq(sq(sq(sq(sq(sq(sq(sq(2))))))))))))))))
+               }(fn(x : int) { mul(x, x) })";
+    let mut engine = Engine::new();
+    engine.eval_limits(eval::Limits {
+        max_heap: Some(4096),
+        ..eval::Limits::default()
+    });
+    let err = engine.eval(src).unwrap_err();
+    assert_eq!(err.diagnostics[0].kind, "HeapLimit");
+    assert_eq!(
+        err.diagnostics[0].to_string(),
+        "<input>:3:32: runtime error: heap limit exceeded
+    in mul at <input>:3:32
+    in sq at <input>:2:27
+    in <input>:1:1"
+    );
+
+    engine.eval_limits(eval::Limits {
+        max_heap: Some(1 << 20),
+        ..eval::Limits::default()
+    });
+    assert!(engine.eval(src).is_ok());
+}
+
+#[test]
+fn test_check_limits() {
+    let mut engine = Engine::new();
+    engine.check_limits(typecheck::Limits {
+        max_type_size: Some(10),
+        max_depth: Some(50),
+    });
+
+    let nested = format!("{}1{}", "inc(".repeat(100), ")".repeat(100));
+    assert_eq!(check_kind(&engine, &nested), Err("TooDeep"));
+    assert_eq!(
+        check_kind(&engine, &format!("{}1{}", "inc(".repeat(20), ")".repeat(20))),
+        Ok("int".to_string())
+    );
+
+    let big = "fn(x : (int -> int) -> int -> int) { x } :
+                 ((int -> int) -> int -> int) -> (int -> int) -> int -> int";
+    assert_eq!(check_kind(&engine, big), Err("TypeTooLarge"));
+    assert_eq!(check_kind(&Engine::new(), big).map(|_| ()), Ok(()));
+}
+
+#[test]
+fn test_occurs_check() {
+    assert_eq!(check_kind(&Engine::new(), "fn(x) { x(x) }"), Err("Occur"));
+    assert_eq!(check_kind(&Engine::new(), OMEGA), Err("Occur"));
+}
+
+fn parse_kind(src: &str) -> Result<(), &'static str> {
+    parser::parse("test", src).map(|_| ()).map_err(|e| e.kind())
+}
+
+#[test]
+fn test_parse_nesting() {
+    let nested = |n| format!("{}1{}", "inc(".repeat(n), ")".repeat(n));
+    assert_eq!(parse_kind(&nested(parser::MAX_NESTING)), Ok(()));
+    assert_eq!(parse_kind(&nested(100_000)), Err("TooDeep"));
+
+    let conds = |n| format!("{}true{}", "if ".repeat(n), " { 1 } else { 2 }".repeat(n));
+    assert_eq!(parse_kind(&conds(50)), Ok(()));
+    assert_eq!(parse_kind(&conds(10_000)), Err("TooDeep"));
+
+    // Arrows do not nest, nor do brackets that have been closed.
+    assert_eq!(parse_kind(&format!("x : {}int", "int -> ".repeat(200))), Ok(()));
+    assert_eq!(parse_kind(&format!("[{}]", "(1), ".repeat(500))), Ok(()));
+}
+
+#[test]
+fn test_parse_depth() {
+    let list = format!("[{}]", "1, ".repeat(100_000));
+    assert_eq!(parse_kind(&list), Err("TooDeep"));
+    assert_eq!(parse_kind(&format!("[{}]", "1, ".repeat(100))), Ok(()));
+    let args = format!("f({})", "1, ".repeat(100_000));
+    assert_eq!(parse_kind(&args), Err("TooDeep"));
+}
+
+/// Church numerals: each application of `twice` to the next squares the
+/// count, so this applies `inc` 2^65536 times, nesting deeper at each step.
+fn tower() -> String {
+    let twice = "fn(f) { fn(x) { f(f(x)) } }";
+    format!("{0}({0})({0})({0})({0})(inc)(0)", twice)
+}
+
+/// Sums the integers up to `n` with `n` nested recursive calls.
+fn triangle(n: usize) -> String {
+    format!(
+        "fix(fn(self, n) {{ if iszero(n) {{ 0 }} else {{ add(n, self(dec(n))) }} }}, {})",
+        n
+    )
+}
+
+#[test]
+fn test_standard_limits() {
+    let engine = Engine::new();
+    assert_eq!(engine.eval(&triangle(100)).unwrap().to_string(), "5050");
+    assert_eq!(engine.eval(&triangle(2000)).unwrap().to_string(), "2001000");
+    let err = engine.eval(&triangle(5000)).unwrap_err();
+    assert_eq!(err.diagnostics[0].kind, "RecursionLimit");
+    let mut deeper = Engine::new();
+    deeper.eval_limits(eval::Limits {
+        max_depth: Some(100_000),
+        ..eval::Limits::standard()
+    });
+    assert_eq!(deeper.eval(&triangle(5000)).unwrap().to_string(), "12502500");
+
+    // Any tree the parser accepts can be checked and evaluated.
+    let list = format!("[{}]", "1, ".repeat(490));
+    assert!(parser::parse("test", &list).is_ok());
+    assert_eq!(engine.check(&list).unwrap().to_string(), "list<int>");
+    assert!(engine.eval(&list).is_ok());
+
+    let err = Engine::new().eval(&tower()).unwrap_err();
+    assert_eq!(err.diagnostics[0].kind, "RecursionLimit");
+
+    let arena = Arena::new();
+    let mut loader = Loader::new(&arena);
+    let module = loader.load_source("test", tower()).expect("load");
+    assert_eq!(module.eval().map(|_| ()).map_err(|e| e.error.kind()), Err("RecursionLimit"));
+}
@@ -0,0 +1,166 @@
+extern crate iron_golem;
+#[macro_use]
+extern crate serde_json;
+
+use std::io::Cursor;
+
+use iron_golem::lsp;
+
+use serde_json::Value;
+
+fn frame(msgs: &[Value]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for msg in msgs {
+        let body = msg.to_string();
+        out.extend(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes());
+    }
+    out
+}
+
+fn unframe(mut data: &str) -> Vec<Value> {
+    let mut msgs = Vec::new();
+    while !data.is_empty() {
+        let split = data.find("\r\n\r\n").expect("header terminator");
+        let len: usize = data[..split]
+            .trim_start_matches("Content-Length: ")
+            .parse()
+            .expect("content length");
+        let body = &data[split + 4..split + 4 + len];
+        msgs.push(serde_json::from_str(body).expect("valid json"));
+        data = &data[split + 4 + len..];
+    }
+    msgs
+}
+
+/// Run a session consisting of `msgs`, bracketed by the initialize and
+/// shutdown handshakes, and return everything the server sent except the
+/// handshake replies.
+fn session(msgs: Vec<Value>) -> Vec<Value> {
+    let mut script = vec![json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}})];
+    script.extend(msgs);
+    script.push(json!({"jsonrpc": "2.0", "id": 999, "method": "shutdown"}));
+    script.push(json!({"jsonrpc": "2.0", "method": "exit"}));
+
+    let mut output = Vec::new();
+    let clean = lsp::serve(Cursor::new(frame(&script)), &mut output).expect("serve");
+    assert!(clean, "server did not see a shutdown");
+
+    let mut replies = unframe(&String::from_utf8(output).unwrap());
+    let init = replies.remove(0);
+    assert_eq!(init["result"]["capabilities"]["hoverProvider"], json!(true));
+    let shutdown = replies.pop().unwrap();
+    assert_eq!(shutdown["id"], json!(999));
+    replies
+}
+
+fn open(uri: &str, text: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {"textDocument": {"uri": uri, "languageId": "gollum", "version": 1, "text": text}},
+    })
+}
+
+fn at(id: u64, method: &str, uri: &str, line: u64, character: u64) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": {
+            "textDocument": {"uri": uri},
+            "position": {"line": line, "character": character},
+        },
+    })
+}
+
+#[test]
+fn test_diagnostics() {
+    let replies = session(vec![
+        open("file:///a.gol", "add(1, true)"),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": {"uri": "file:///a.gol", "version": 2},
+                "contentChanges": [{"text": "add(1,"}],
+            },
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": {"uri": "file:///a.gol", "version": 3},
+                "contentChanges": [{"text": "add(1, 2)"}],
+            },
+        }),
+    ]);
+    assert_eq!(replies.len(), 3);
+    for reply in &replies {
+        assert_eq!(reply["method"], json!("textDocument/publishDiagnostics"));
+        assert_eq!(reply["params"]["uri"], json!("file:///a.gol"));
+    }
+
+    let type_err = &replies[0]["params"]["diagnostics"][0];
+    assert!(type_err["message"].as_str().unwrap().contains("mismatch"), "{}", type_err);
+
+    let parse_err = &replies[1]["params"]["diagnostics"][0];
+    assert_eq!(parse_err["range"]["start"], json!({"line": 0, "character": 6}));
+
+    assert_eq!(replies[2]["params"]["diagnostics"], json!([]));
+}
+
+#[test]
+fn test_hover_and_definition() {
+    let uri = "file:///b.gol";
+    let replies = session(vec![
+        open(uri, "fn(x : int, y) {\n  add(x, y)\n}"),
+        at(1, "textDocument/hover", uri, 1, 6),
+        at(2, "textDocument/hover", uri, 1, 2),
+        at(3, "textDocument/definition", uri, 1, 9),
+        at(4, "textDocument/definition", uri, 1, 2),
+    ]);
+    assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+
+    assert_eq!(replies[1]["id"], json!(1));
+    let hover = replies[1]["result"]["contents"]["value"].as_str().unwrap();
+    assert!(hover.contains("x : int"), "{}", hover);
+    assert_eq!(
+        replies[1]["result"]["range"],
+        json!({"start": {"line": 1, "character": 6}, "end": {"line": 1, "character": 7}})
+    );
+
+    let hover = replies[2]["result"]["contents"]["value"].as_str().unwrap();
+    assert!(hover.contains("add : int -> int -> int"), "{}", hover);
+
+    assert_eq!(
+        replies[3]["result"],
+        json!({
+            "uri": uri,
+            "range": {"start": {"line": 0, "character": 12}, "end": {"line": 0, "character": 13}},
+        })
+    );
+    assert_eq!(replies[4]["result"], Value::Null);
+}
+
+#[test]
+fn test_formatting() {
+    let uri = "file:///c.gol";
+    let replies = session(vec![
+        open(uri, "fn(x:int){\nadd(x,1)}"),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/formatting",
+            "params": {"textDocument": {"uri": uri}, "options": {"tabSize": 2, "insertSpaces": true}},
+        }),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/rename", "params": {}}),
+    ]);
+    assert_eq!(
+        replies[1]["result"],
+        json!([{
+            "range": {"start": {"line": 0, "character": 0}, "end": {"line": 1, "character": 9}},
+            "newText": "fn(x : int) { add(x, 1) }\n",
+        }])
+    );
+    assert_eq!(replies[2]["error"]["code"], json!(-32601));
+}
@@ -0,0 +1,172 @@
+extern crate iron_golem;
+
+use iron_golem::diagnostics::Diagnostic;
+use iron_golem::module::{Arena, Loader, Module, ModuleError};
+
+use std::fs;
+use std::rc::Rc;
+
+fn load<'a>(loader: &mut Loader<'a>, path: &str) -> Result<Rc<Module<'a>>, ModuleError<'a>> {
+    let src = fs::read_to_string(path).expect("read");
+    loader.load_source(path, src)
+}
+
+fn imported<'a>(module: &Module<'a>, alias: &str) -> Rc<Module<'a>> {
+    module
+        .imports
+        .iter()
+        .find(|(name, _)| name.to_string() == alias)
+        .map(|(_, module)| Rc::clone(module))
+        .expect("import")
+}
+
+#[test]
+fn test_import() {
+    let arena = Arena::new();
+    let mut loader = Loader::new(&arena);
+    let module = load(&mut loader, "tests/modules/main.gol").expect("load");
+    assert_eq!(module.ty.as_ref().unwrap().to_string(), "int");
+    assert_eq!(module.eval().expect("eval").unwrap().to_string(), "65536");
+}
+
+#[test]
+fn test_each_file_loaded_once() {
+    let arena = Arena::new();
+    let mut loader = Loader::new(&arena);
+    let module = load(&mut loader, "tests/modules/main.gol").expect("load");
+    let math = imported(&module, "math");
+    let both = imported(&module, "both");
+    assert!(Rc::ptr_eq(&math, &imported(&both, "math")));
+    assert_eq!(math.path, "tests/modules/lib/math.gol");
+}
+
+#[test]
+fn test_polymorphic_export() {
+    let arena = Arena::new();
+    let mut loader = Loader::new(&arena);
+    let module = load(&mut loader, "tests/modules/lib/combinators.gol").expect("load");
+    let exports: Vec<String> = module
+        .exports
+        .iter()
+        .map(|(name, ty)| format!("{} : {}", name, ty))
+        .collect();
+    assert_eq!(
+        exports,
+        vec![
+            "id : forall a. a -> a",
+            "twice : forall a. (a -> a) -> a -> a"
+        ]
+    );
+}
+
+#[test]
+fn test_imported_instances() {
+    let arena = Arena::new();
+    let mut loader = Loader::new(&arena);
+    let module = load(&mut loader, "tests/modules/classes.gol").expect("load");
+    let sized = imported(&module, "sized");
+    assert_eq!(
+        sized.exports[0].1.to_string(),
+        "forall a b. Size a, Size b => a -> b -> int"
+    );
+    assert_eq!(module.eval().expect("eval").unwrap().to_string(), "[4, 7]");
+}
+
+#[test]
+fn test_errors() {
+    let tests = vec![
+        (
+            "tests/modules/cycle_a.gol",
+            "tests/modules/cycle_b.gol:1:1: module error: import cycle: \
+             tests/modules/cycle_a.gol -> tests/modules/cycle_b.gol -> tests/modules/cycle_a.gol",
+        ),
+        (
+            "tests/modules/self.gol",
+            "tests/modules/self.gol:1:1: module error: import cycle: \
+             tests/modules/self.gol -> tests/modules/self.gol",
+        ),
+        (
+            "tests/modules/unknown.gol",
+            "tests/modules/unknown.gol:2:20: module error: module `math` does not define `quadruple`",
+        ),
+        (
+            "tests/modules/conflict.gol",
+            "tests/modules/conflict.gol:3:1: type error: class `Size` is already declared",
+        ),
+    ];
+    for (path, expected) in tests {
+        let arena = Arena::new();
+        let mut loader = Loader::new(&arena);
+        let err = match load(&mut loader, path) {
+            Ok(_) => panic!("load({}) succeeded", path),
+            Err(err) => err,
+        };
+        let src = loader.source(err.loc().file).expect("source");
+        assert_eq!(
+            Diagnostic::module(src, &err).to_string(),
+            expected,
+            "load({})",
+            path
+        );
+    }
+}
+
+#[test]
+fn test_std() {
+    let tests = vec![
+        ("compose(square, flip(sub, 1))(10)", "81"),
+        ("twice(const(0), 5)", "0"),
+        ("on(add, square, 3, 4)", "25"),
+        (
+            "if and(odd(3), divides(3, 12)) { sign(neg(cube(2))) } else { 0 }",
+            "-1",
+        ),
+        ("clamp(0, 10, 42)", "10"),
+        ("eq(eq(1, 2), eq(true, false))", "true"),
+        ("fold(add, 0, [mul(2, 3), sub(1, 2)])", "5"),
+        ("if between(1, 3, 4) { 1 } else { apply(id, 2) }", "2"),
+        (
+            "let square = fn(x : bool) { not(x) }; square(true)",
+            "false",
+        ),
+        ("fix(fn(fact, n) { if iszero(n) { 1 } else { mul(n, fact(dec(n))) } }, 5)", "120"),
+        ("range(2, 6)", "[2, 3, 4, 5]"),
+        ("[find(odd, range(4, 9)), find(odd, [2, 4])]", "[some(5), none]"),
+        ("withdefault(0, mapoption(square, nth(2, [5, 6, 7])))", "49"),
+        ("[issome(nth(3, [1])), isnone(none)]", "[false, true]"),
+        ("tolist(andthen(some(3), fn(x) { if odd(x) { some(x) } else { none } }))", "[3]"),
+    ];
+    for (src, expected) in tests {
+        let arena = Arena::new();
+        let mut loader = Loader::new(&arena);
+        let module = loader.load_source("test", src.to_string()).expect("load");
+        let val = module.eval().expect("eval").unwrap();
+        assert_eq!(val.to_string(), expected, "eval({})", src);
+    }
+}
+
+#[test]
+fn test_no_std() {
+    let arena = Arena::new();
+    let mut loader = Loader::new(&arena);
+    loader.std(false);
+    let err = match loader.load_source("test", "square(2)".to_string()) {
+        Ok(_) => panic!("square is bound without std"),
+        Err(err) => err,
+    };
+    assert_eq!(err.kind(), "UnboundVariable");
+
+    // The classes of the prelude do not come from the standard library.
+    let tests = vec![
+        ("let same = fn(x, y) { eq(x, y) }; [same(true, false), same(2, 2)]", "[false, true]"),
+        ("instance Num bool { add = or; sub = xor; mul = and } [add(true, false), mul(true, false)]", "[true, false]"),
+    ];
+    for (src, expected) in tests {
+        let arena = Arena::new();
+        let mut loader = Loader::new(&arena);
+        loader.std(false);
+        let module = loader.load_source("test", src.to_string()).expect("load");
+        let val = module.eval().expect("eval").unwrap();
+        assert_eq!(val.to_string(), expected, "eval({})", src);
+    }
+}
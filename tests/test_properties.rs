@@ -0,0 +1,186 @@
+extern crate iron_golem;
+
+use iron_golem::anf;
+use iron_golem::ast::AST;
+use iron_golem::eval;
+use iron_golem::gen;
+use iron_golem::gen::Generator;
+use iron_golem::lift;
+use iron_golem::names;
+use iron_golem::nbe;
+use iron_golem::globals;
+use iron_golem::parser;
+use iron_golem::peval;
+use iron_golem::pretty;
+use iron_golem::step::Stepper;
+use iron_golem::typecheck;
+use iron_golem::types::Type;
+
+use std::rc::Rc;
+
+const CASES: u64 = 500;
+
+/// Check `property` of the program generated from each of `CASES` seeds.
+/// It is passed the seed, for its failure messages, the program, and the
+/// type the program was built to have.
+fn for_each_program<F>(mut property: F)
+where
+    F: FnMut(u64, &AST<'static>, &Rc<Type<'static>>),
+{
+    for seed in 0..CASES {
+        let (ast, ty) = Generator::new(seed).program();
+        property(seed, &ast, &ty);
+    }
+}
+
+#[test]
+fn test_roundtrip_through_pretty() {
+    for_each_program(|seed, ast, _| {
+        let src = pretty::pretty(ast);
+        let parsed = parser::parse("gen.gol", &src)
+            .unwrap_or_else(|e| panic!("seed {}: parse({}): {}", seed, src, e));
+        assert_eq!(pretty::pretty(&parsed), src, "seed {}", seed);
+    });
+}
+
+#[test]
+fn test_generated_type() {
+    for_each_program(|seed, ast, want| {
+        let src = pretty::pretty(ast);
+        let parsed = parser::parse("gen.gol", &src).unwrap();
+        match typecheck::typecheck(&globals::global_env(), &parsed) {
+            Ok(got) => assert!(
+                got == *want,
+                "seed {}: tc({}) = {} != {}",
+                seed,
+                src,
+                got,
+                want
+            ),
+            Err(e) => panic!("seed {}: tc({}): {}", seed, src, e),
+        }
+    });
+}
+
+/// Well-typed programs do not go wrong.
+#[test]
+fn test_generated_eval() {
+    for_each_program(|seed, ast, _| {
+        let result = eval::eval(&globals::global_values(), ast).map(|_| ());
+        if let Err(e) = result {
+            panic!("seed {}: eval({}): {}", seed, pretty::pretty(ast), e);
+        }
+    });
+}
+
+/// Check that `expr`, in a function with `captures` environment slots (or in
+/// `main`, if `captures` is `None`), only refers to variables it has.
+fn well_formed(program: &lift::Program, expr: &lift::Expr, captures: Option<usize>) -> bool {
+    match *expr {
+        lift::Expr::Int(_) | lift::Expr::Bool(_) | lift::Expr::Global(_) => true,
+        lift::Expr::Param(_) => captures.is_some(),
+        lift::Expr::Env(i, _) => i < captures.unwrap_or(0),
+        lift::Expr::Closure(index, ref values) => {
+            index < program.functions.len()
+                && values.len() == program.functions[index].captures.len()
+                && values.iter().all(|val| well_formed(program, val, captures))
+        }
+        lift::Expr::Apply(ref func, ref arg) => {
+            well_formed(program, func, captures) && well_formed(program, arg, captures)
+        }
+        lift::Expr::If(ref cond, ref cons, ref alt) => {
+            well_formed(program, cond, captures)
+                && well_formed(program, cons, captures)
+                && well_formed(program, alt, captures)
+        }
+    }
+}
+
+#[test]
+fn test_lift_well_formed() {
+    for_each_program(|seed, ast, want| {
+        let program = lift::lift(&globals::global_env(), ast).unwrap();
+        assert!(program.ty == *want, "seed {}", seed);
+        assert!(well_formed(&program, &program.main, None), "seed {}:\n{}", seed, program);
+        for func in &program.functions {
+            assert!(
+                well_formed(&program, &func.body, Some(func.captures.len())),
+                "seed {}:\n{}",
+                seed,
+                program
+            );
+        }
+    });
+}
+
+/// Lowering to ANF and optimizing preserve a program's value.
+#[test]
+fn test_optimize_preserves_value() {
+    for_each_program(|seed, ast, _| {
+        let want = eval::eval(&globals::global_values(), ast).unwrap().to_string();
+        let lowered = anf::lower(&globals::global_env(), ast).unwrap();
+        let optimized = anf::optimize(lowered.clone());
+        for ir in &[lowered, optimized] {
+            let back = anf::to_ast(ir, &ast.loc());
+            let got = eval::eval(&globals::global_values(), &back).unwrap().to_string();
+            assert_eq!(got, want, "seed {}:\n{}", seed, ir);
+        }
+    });
+}
+
+#[test]
+fn test_specialize_preserves_meaning() {
+    for_each_program(|seed, ast, ty| {
+        let want = eval::eval(&globals::global_values(), ast).unwrap().to_string();
+        for &budget in &[0, 5, peval::DEFAULT_BUDGET] {
+            let residual = peval::specialize(ast, &[], budget);
+            let pretty = pretty::pretty(&residual);
+            let got = eval::eval(&globals::global_values(), &residual).unwrap().to_string();
+            assert_eq!(got, want, "seed {} budget {}:\n{}", seed, budget, pretty);
+            let got_ty = typecheck::typecheck(&globals::global_env(), &residual)
+                .unwrap_or_else(|e| panic!("seed {} budget {}: {:?}\n{}", seed, budget, e, pretty));
+            assert_eq!(got_ty, *ty, "seed {} budget {}:\n{}", seed, budget, pretty);
+        }
+    });
+}
+
+#[test]
+fn test_transformations_are_equivalent() {
+    let options = nbe::Options {
+        fold_primitives: true,
+        ..nbe::Options::default()
+    };
+    for_each_program(|seed, ast, _| {
+        let optimized = anf::optimize(anf::lower(&globals::global_env(), ast).unwrap());
+        let optimized = anf::to_ast(&optimized, &ast.loc());
+        let specialized = peval::specialize(ast, &[], peval::DEFAULT_BUDGET);
+        for other in &[optimized, specialized] {
+            let same = nbe::equivalent(ast, other, &options).unwrap();
+            assert!(same, "seed {}:\n{}\n{}", seed, pretty::pretty(ast), pretty::pretty(other));
+        }
+    });
+}
+
+#[test]
+fn test_stepper_agrees_with_eval() {
+    for_each_program(|seed, ast, _| {
+        let want = eval::eval(&globals::global_values(), ast).unwrap().to_string();
+        let mut stepper = Stepper::new(&globals::global_values(), ast, &eval::Limits::default());
+        while stepper.step().unwrap().is_some() {}
+        let got = stepper.value().unwrap().to_string();
+        assert_eq!(got, want, "seed {}:\n{}", seed, pretty::pretty(ast));
+    });
+}
+
+/// Type syntax built for a scheme prints as the scheme's signature.
+#[test]
+fn test_type_ast_of_schemes() {
+    let map = globals::global_env().lookup(&names::ident("map")).unwrap();
+    assert_eq!(pretty::pretty(&gen::type_ast(&map)), "(a -> b) -> list<a> -> list<b>");
+    let a = Rc::new(Type::Variable(names::typ("a")));
+    let eq = Type::Qualified(
+        vec![(names::typ("Eq"), Rc::clone(&a))],
+        Rc::new(Type::Function(Rc::clone(&a), globals::bool())),
+    );
+    assert_eq!(pretty::pretty(&gen::type_ast(&eq)), "Eq a => a -> bool");
+}